@@ -0,0 +1,62 @@
+//! Stream speech to a browser as it is synthesized.
+//!
+//! Run with `cargo run --example http_stream --features http-example` and
+//! open http://127.0.0.1:3000/?text=Hello+world
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server};
+use std::convert::Infallible;
+
+fn query_text(req: &Request<Body>) -> String {
+    let query = req.uri().query().unwrap_or_default();
+    let encoded = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("text="))
+        .unwrap_or("Hello from eSpeak NG");
+
+    let mut bytes = Vec::<u8>::new();
+    let mut iter = encoded.bytes();
+    while let Some(b) = iter.next() {
+        match b {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex: String = iter.by_ref().take(2).map(char::from).collect();
+                bytes.push(u8::from_str_radix(&hex, 16).unwrap_or(b'?'));
+            }
+            b => bytes.push(b),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+async fn speak(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let text = query_text(&req);
+    let (mut sender, body) = Body::channel();
+    let handle = tokio::runtime::Handle::current();
+
+    tokio::task::spawn_blocking(move || {
+        let speaker = espeak_rs::Speaker::new();
+        for chunk in speaker.speak(&text).into_chunked_wav_stream() {
+            // Sending fails once the client disconnects. Leaving the loop
+            // drops the stream, which stops synthesis.
+            if handle.block_on(sender.send_data(chunk.into())).is_err() {
+                println!("client went away");
+                break;
+            }
+        }
+    });
+
+    Ok(Response::builder()
+        .header("content-type", "audio/wav")
+        .header("cache-control", "no-store")
+        .body(body)
+        .unwrap())
+}
+
+#[tokio::main]
+async fn main() {
+    let addr = ([127, 0, 0, 1], 3000).into();
+    let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(speak)) });
+    println!("Listening on http://{}/?text=Hello+world", addr);
+    Server::bind(&addr).serve(make_svc).await.unwrap();
+}
@@ -0,0 +1,5 @@
+//! Worker process for `espeak_rs::pool::PooledSynthesizer`.
+
+fn main() -> std::io::Result<()> {
+    espeak_rs::pool::run_worker()
+}
@@ -0,0 +1,224 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Fan-out of a source's events to any number of independent subscribers.
+//!
+//! Each subscriber gets its own bounded queue. When a subscriber falls
+//! behind, its oldest events are discarded and counted rather than holding
+//! up the audio, and subscribers that have been dropped are forgotten the
+//! next time events are published.
+
+use crate::{Event, PoisonlessLock, TimedEvent};
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+/// Events a subscriber can fall behind by before the oldest are dropped,
+/// unless a capacity is given to [`EventBus::subscribe_with_capacity`].
+pub const DEFAULT_SUBSCRIBER_CAPACITY: usize = 1024;
+
+struct QueueState {
+    events: VecDeque<TimedEvent>,
+    dropped: u64,
+    closed: bool,
+}
+
+struct SubscriberQueue {
+    state: Mutex<QueueState>,
+    ready: Condvar,
+    capacity: usize,
+}
+
+impl SubscriberQueue {
+    fn push(&self, event: TimedEvent) {
+        let mut state = self.state.plock();
+        if state.events.len() == self.capacity {
+            state.events.pop_front();
+            state.dropped += 1;
+        }
+        state.events.push_back(event);
+        self.ready.notify_one();
+    }
+
+    fn close(&self) {
+        self.state.plock().closed = true;
+        self.ready.notify_all();
+    }
+}
+
+#[derive(Default)]
+struct Hub {
+    subscribers: Vec<Weak<SubscriberQueue>>,
+    closed: bool,
+}
+
+/// A handle for subscribing to a source's events, from
+/// [`SpeakerSource::events_broadcast`](crate::SpeakerSource::events_broadcast).
+///
+/// Events are delivered as the source's audio reaches them, and every
+/// subscriber sees every event published after it subscribed. The handle
+/// can be cloned and sent to other threads.
+#[derive(Clone)]
+pub struct EventBus {
+    hub: Arc<Mutex<Hub>>,
+}
+
+impl EventBus {
+    /// Subscribe with a queue of [`DEFAULT_SUBSCRIBER_CAPACITY`] events.
+    pub fn subscribe(&self) -> EventReceiver {
+        self.subscribe_with_capacity(DEFAULT_SUBSCRIBER_CAPACITY)
+    }
+
+    /// Subscribe with a queue holding at most `capacity` events. Once it is
+    /// full, each new event pushes out the oldest one.
+    pub fn subscribe_with_capacity(&self, capacity: usize) -> EventReceiver {
+        let mut hub = self.hub.plock();
+        let queue = Arc::new(SubscriberQueue {
+            state: Mutex::new(QueueState {
+                events: VecDeque::new(),
+                dropped: 0,
+                closed: hub.closed,
+            }),
+            ready: Condvar::new(),
+            capacity: capacity.max(1),
+        });
+        if !hub.closed {
+            hub.subscribers.push(Arc::downgrade(&queue));
+        }
+        EventReceiver { queue }
+    }
+}
+
+/// One subscriber's queue of events. Dropping it unsubscribes.
+pub struct EventReceiver {
+    queue: Arc<SubscriberQueue>,
+}
+
+impl EventReceiver {
+    /// Wait for the next event. Returns `None` once the source has ended,
+    /// or has been dropped, and every queued event has been received.
+    pub fn recv(&self) -> Option<TimedEvent> {
+        let mut state = self.queue.state.plock();
+        loop {
+            if let Some(event) = state.events.pop_front() {
+                return Some(event);
+            }
+            if state.closed {
+                return None;
+            }
+            state = match self.queue.ready.wait(state) {
+                Ok(state) => state,
+                Err(e) => e.into_inner(),
+            };
+        }
+    }
+
+    /// Like [`recv`](EventReceiver::recv), giving up after `timeout`.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<TimedEvent> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.queue.state.plock();
+        loop {
+            if let Some(event) = state.events.pop_front() {
+                return Some(event);
+            }
+            let now = Instant::now();
+            if state.closed || now >= deadline {
+                return None;
+            }
+            state = match self.queue.ready.wait_timeout(state, deadline - now) {
+                Ok((state, _)) => state,
+                Err(e) => e.into_inner().0,
+            };
+        }
+    }
+
+    /// Take the next event if one is already queued.
+    pub fn try_recv(&self) -> Option<TimedEvent> {
+        self.queue.state.plock().events.pop_front()
+    }
+
+    /// How many events were discarded because this subscriber's queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.queue.state.plock().dropped
+    }
+}
+
+impl Iterator for EventReceiver {
+    type Item = TimedEvent;
+
+    fn next(&mut self) -> Option<TimedEvent> {
+        self.recv()
+    }
+}
+
+/// The publishing side, owned by the source. Dropping it ends every
+/// subscription.
+#[derive(Default)]
+pub(crate) struct Broadcaster {
+    hub: Arc<Mutex<Hub>>,
+}
+
+impl Broadcaster {
+    pub(crate) fn bus(&self) -> EventBus {
+        EventBus {
+            hub: self.hub.clone(),
+        }
+    }
+
+    /// Deliver `events` to every live subscriber. Ending the source with
+    /// [`Event::End`] or [`Event::Cancelled`] closes the subscriptions, so
+    /// it is only delivered once.
+    pub(crate) fn publish(&self, events: &[TimedEvent]) {
+        let mut hub = self.hub.plock();
+        if hub.closed {
+            return;
+        }
+        hub.subscribers
+            .retain(|subscriber| match subscriber.upgrade() {
+                Some(queue) => {
+                    for event in events {
+                        queue.push(event.clone());
+                    }
+                    true
+                }
+                None => false,
+            });
+        if events
+            .iter()
+            .any(|timed| matches!(timed.event, Event::End | Event::Cancelled))
+        {
+            Self::close_hub(&mut hub);
+        }
+    }
+
+    fn close_hub(hub: &mut Hub) {
+        hub.closed = true;
+        for queue in hub.subscribers.drain(..).filter_map(|s| s.upgrade()) {
+            queue.close();
+        }
+    }
+}
+
+impl Drop for Broadcaster {
+    fn drop(&mut self) {
+        Self::close_hub(&mut self.hub.plock());
+    }
+}
@@ -0,0 +1,159 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Cancelling an utterance, whether it is still queued behind others for
+//! the espeak lock or already being synthesized.
+
+use crate::channel::ChunkSender;
+use crate::{EspeakError, PoisonlessLock};
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+
+const QUEUED: u8 = 0;
+const RUNNING: u8 = 1;
+const CANCELLED: u8 = 2;
+const FINISHED: u8 = 3;
+
+static NEXT_JOB_ID: AtomicU64 = AtomicU64::new(1);
+
+/// The state of one utterance's synthesis job, shared between its source,
+/// its synthesis thread and any [`CancelToken`]s.
+pub(crate) struct Job {
+    id: u64,
+    status: AtomicU8,
+    /// A sender for reporting a cancellation before the job started, taken
+    /// by whoever moves the job out of the queue.
+    tx: Mutex<Option<ChunkSender>>,
+}
+
+impl Default for Job {
+    fn default() -> Job {
+        Job {
+            id: NEXT_JOB_ID.fetch_add(1, Ordering::Relaxed),
+            status: AtomicU8::new(QUEUED),
+            tx: Mutex::new(None),
+        }
+    }
+}
+
+impl Job {
+    /// Queue the job, reporting to `tx` if it's cancelled before it starts.
+    pub(crate) fn queue(&self, tx: ChunkSender) {
+        *self.tx.plock() = Some(tx);
+    }
+
+    /// Move the job out of the queue. Called by the synthesis thread with
+    /// the espeak lock held and before touching espeak; `false` means the
+    /// job was cancelled and must be skipped entirely.
+    pub(crate) fn start(&self) -> bool {
+        let started = self
+            .status
+            .compare_exchange(QUEUED, RUNNING, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok();
+        if started {
+            self.tx.plock().take();
+        }
+        started
+    }
+
+    /// Mark the job finished, so that cancelling it no longer does anything.
+    pub(crate) fn finish(&self) {
+        let _ = self
+            .status
+            .compare_exchange(RUNNING, FINISHED, Ordering::SeqCst, Ordering::SeqCst);
+    }
+
+    /// Cancel the job because its source is gone. Unlike
+    /// [`CancelToken::cancel`], nothing is reported, since there's no one
+    /// to report to, and a full channel could block.
+    pub(crate) fn abandon(&self) {
+        let abandoned = |from| {
+            self.status
+                .compare_exchange(from, CANCELLED, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+        };
+        if abandoned(QUEUED) {
+            self.tx.plock().take();
+        } else {
+            abandoned(RUNNING);
+        }
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.status.load(Ordering::SeqCst) == CANCELLED
+    }
+}
+
+/// Cancels an utterance from any thread, through
+/// [`SpeakerSource::cancel_token`](crate::SpeakerSource::cancel_token).
+///
+/// An utterance waiting for others to finish is skipped without espeak
+/// being touched, and one being synthesized stops at espeak's next
+/// callback. Either way its source ends promptly with
+/// [`Event::Cancelled`](crate::Event::Cancelled) and reports
+/// [`EspeakError::Cancelled`].
+#[derive(Clone)]
+pub struct CancelToken {
+    job: Arc<Job>,
+}
+
+impl CancelToken {
+    pub(crate) fn new(job: Arc<Job>) -> CancelToken {
+        CancelToken { job }
+    }
+
+    /// The id of the utterance, unique within the process.
+    pub fn id(&self) -> u64 {
+        self.job.id
+    }
+
+    /// Cancel the utterance. Does nothing once it has finished or been
+    /// cancelled already.
+    pub fn cancel(&self) {
+        let job = &self.job;
+        let cancelled = |from| {
+            job.status
+                .compare_exchange(from, CANCELLED, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+        };
+        if cancelled(QUEUED) {
+            if let Some(tx) = job.tx.plock().take() {
+                let _ = tx.send_error(EspeakError::Cancelled);
+            }
+        } else {
+            cancelled(RUNNING);
+        }
+    }
+
+    /// Whether [`cancel`](CancelToken::cancel) took effect.
+    pub fn is_cancelled(&self) -> bool {
+        self.job.is_cancelled()
+    }
+}
+
+impl std::fmt::Debug for CancelToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CancelToken")
+            .field("id", &self.job.id)
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
@@ -0,0 +1,237 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Reading all-caps words as words.
+//!
+//! Depending on the capitals parameter, espeak either spells an all-caps
+//! word out or reads it with emphasis. With [`CapsPolicy::LowercaseLongRuns`]
+//! such words are lowercased in a copy of the text, unless they look like
+//! acronyms: short, containing digits, listed as acronyms, or without the
+//! vowels a word would need.
+
+use crate::text_map::TextMap;
+
+/// Acronyms that [`CapsPolicy::LowercaseLongRuns`] leaves alone by
+/// default, being long enough to be mistaken for words.
+pub const DEFAULT_ACRONYMS: &[&str] = &[
+    "ASCII", "CSV", "HTML", "HTTP", "HTTPS", "JSON", "NASA", "NATO", "UNESCO", "UNICEF", "USB",
+    "YAML",
+];
+
+/// What to do with words written in capitals in plain text.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(
+    any(feature = "serde", feature = "subprocess-pool"),
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[non_exhaustive]
+pub enum CapsPolicy {
+    /// Leave them to espeak and the capitals parameter.
+    #[default]
+    AsIs,
+    /// Lowercase all-caps words of at least this many letters, so they
+    /// are read as ordinary words. Words in
+    /// [`SpeakerParams::acronyms`](crate::SpeakerParams::acronyms) are
+    /// left alone.
+    LowercaseLongRuns(usize),
+}
+
+/// A word spoken with more consonants in a row than this is taken to be
+/// an acronym.
+const MAX_CONSONANT_RUN: usize = 5;
+
+const OPEN_PHONEMES: &str = "[[";
+const CLOSE_PHONEMES: &str = "]]";
+
+/// Lowercase the all-caps words of `text` that `policy` says should be
+/// read as words. `voice_name` selects the casing rules, and phoneme
+/// regions are skipped if `inline_phonemes` is set, their case mattering.
+pub(crate) fn lowercase_caps(
+    text: &str,
+    policy: CapsPolicy,
+    acronyms: &[String],
+    voice_name: &str,
+    inline_phonemes: bool,
+) -> (String, TextMap) {
+    let mut map = TextMap::default();
+    let CapsPolicy::LowercaseLongRuns(min_len) = policy else {
+        return (String::from(text), map);
+    };
+    let turkic = is_turkic(voice_name);
+    let mut rewritten = String::with_capacity(text.len());
+    let mut copied = 0;
+    for (start, end) in words(text, inline_phonemes) {
+        let word = &text[start..end];
+        if !reads_as_word(word, min_len, acronyms) {
+            continue;
+        }
+        rewritten.push_str(&text[copied..start]);
+        let spoken_start = rewritten.len();
+        rewritten.push_str(&lowercase(word, turkic));
+        map.push(spoken_start..rewritten.len(), start..end);
+        copied = end;
+    }
+    rewritten.push_str(&text[copied..]);
+    (rewritten, map)
+}
+
+/// Byte ranges of the runs of letters and digits in `text`, leaving out
+/// phoneme regions if `inline_phonemes` is set.
+fn words(text: &str, inline_phonemes: bool) -> Vec<(usize, usize)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if c.is_alphanumeric() {
+            start.get_or_insert(i);
+            continue;
+        }
+        if let Some(s) = start.take() {
+            words.push((s, i));
+        }
+        if inline_phonemes && text[i..].starts_with(OPEN_PHONEMES) {
+            // An unterminated region runs to the end of the text.
+            let Some(close) = text[i..].find(CLOSE_PHONEMES) else {
+                return words;
+            };
+            let resume = i + close + CLOSE_PHONEMES.len();
+            while chars.as_str().len() > text.len() - resume {
+                chars.next();
+            }
+        }
+    }
+    if let Some(s) = start {
+        words.push((s, text.len()));
+    }
+    words
+}
+
+fn reads_as_word(word: &str, min_len: usize, acronyms: &[String]) -> bool {
+    if word.chars().count() < min_len.max(2)
+        || !word.chars().all(char::is_uppercase)
+        || acronyms.iter().any(|acronym| acronym == word)
+    {
+        return false;
+    }
+    if !word.is_ascii() {
+        // Only Latin words are checked for vowels.
+        return true;
+    }
+    let mut consonants = 0;
+    let mut has_vowel = false;
+    for c in word.chars() {
+        if "AEIOUY".contains(c) {
+            has_vowel = true;
+            consonants = 0;
+        } else {
+            consonants += 1;
+            if consonants > MAX_CONSONANT_RUN {
+                return false;
+            }
+        }
+    }
+    has_vowel
+}
+
+/// Whether the voice's language has a dotted and a dotless i, which
+/// lowercase differently.
+fn is_turkic(voice_name: &str) -> bool {
+    let language = voice_name.rsplit('/').next().unwrap_or_default();
+    let language = language.split(['-', '+']).next().unwrap_or_default();
+    language.eq_ignore_ascii_case("tr") || language.eq_ignore_ascii_case("az")
+}
+
+fn lowercase(word: &str, turkic: bool) -> String {
+    if !turkic {
+        return word.to_lowercase();
+    }
+    word.chars()
+        .map(|c| match c {
+            'I' => String::from('ı'),
+            'İ' => String::from('i'),
+            c => c.to_lowercase().collect(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Event;
+
+    fn acronyms() -> Vec<String> {
+        DEFAULT_ACRONYMS.iter().map(|a| String::from(*a)).collect()
+    }
+
+    fn rewrite(text: &str, min_len: usize) -> String {
+        let policy = CapsPolicy::LowercaseLongRuns(min_len);
+        lowercase_caps(text, policy, &acronyms(), "en", false).0
+    }
+
+    #[test]
+    fn long_words_are_lowercased() {
+        assert_eq!(
+            rewrite("READ THIS IMPORTANT NOTICE from NASA", 4),
+            "read this important notice from NASA"
+        );
+        assert_eq!(rewrite("READ THIS", 5), "READ THIS");
+        assert_eq!(rewrite("A GOOD DAY", 1), "A good day");
+    }
+
+    #[test]
+    fn acronyms_are_kept() {
+        assert_eq!(
+            rewrite("THE HTTPS SPEC, USB3 AND MP3 FROM W3C", 3),
+            "the HTTPS spec, USB3 and MP3 from W3C"
+        );
+        assert_eq!(rewrite("XKCD TSKTSKS", 4), "XKCD TSKTSKS");
+        assert_eq!(rewrite("NOTICE Notice", 4), "notice Notice");
+    }
+
+    #[test]
+    fn casing_follows_the_voice() {
+        let policy = CapsPolicy::LowercaseLongRuns(3);
+        let (text, _) = lowercase_caps("İYİ BIR", policy, &[], "trk/tr", false);
+        assert_eq!(text, "iyi bır");
+        let (text, _) = lowercase_caps("İYİ BIR", policy, &[], "en", false);
+        assert_eq!(text, "i\u{307}yi\u{307} bir");
+    }
+
+    #[test]
+    fn phonemes_are_skipped() {
+        let policy = CapsPolicy::LowercaseLongRuns(3);
+        let (text, _) = lowercase_caps("SAY [[HELOU]] NOW", policy, &[], "en", true);
+        assert_eq!(text, "say [[HELOU]] now");
+        let (text, _) = lowercase_caps("SAY [[HELOU", policy, &[], "en", true);
+        assert_eq!(text, "say [[HELOU");
+    }
+
+    #[test]
+    fn positions_map_back() {
+        let text = "İYİ GÜN";
+        let (rewritten, map) =
+            lowercase_caps(text, CapsPolicy::LowercaseLongRuns(3), &[], "en", false);
+        let gun = rewritten.find('g').unwrap();
+        let mut word = Event::Word(gun, rewritten.len() - gun);
+        map.correct(&mut word);
+        assert_eq!(word, Event::Word(text.find('G').unwrap(), "GÜN".len()));
+    }
+}
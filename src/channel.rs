@@ -0,0 +1,259 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! The channel carrying audio chunks from the synthesis thread to a
+//! [`SpeakerSource`](crate::SpeakerSource), along with its backlog counters.
+
+use crate::{EspeakError, Event, PoisonlessLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender, TrySendError};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// How often a paced synthesis thread checks for room without being woken,
+/// in case a wakeup was missed.
+const PACING_POLL: Duration = Duration::from_millis(100);
+
+/// Samples and timed events delivered by one synth callback.
+pub(crate) type Chunk = (Vec<i16>, Vec<(u32, Event)>);
+
+/// What travels over the channel: audio, or the reason synthesis failed.
+pub(crate) type Message = Result<Chunk, EspeakError>;
+
+/// A snapshot of how much synthesized audio is waiting to be consumed.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct BacklogStats {
+    /// Chunks sent by the synthesis thread but not yet received.
+    pub chunks_queued: usize,
+    /// Samples contained in those chunks.
+    pub samples_queued: usize,
+    /// Whether the synthesis thread is waiting for room in a full channel.
+    pub producer_blocked: bool,
+}
+
+#[derive(Default)]
+pub(crate) struct Backlog {
+    chunks_queued: AtomicUsize,
+    samples_queued: AtomicUsize,
+    producer_blocked: AtomicBool,
+    pacing: Option<Pacing>,
+}
+
+/// Keeps the synthesis thread at most `limit` samples ahead of what the
+/// source has handed out.
+#[derive(Default)]
+struct Pacing {
+    limit: u64,
+    produced: AtomicU64,
+    consumed: AtomicU64,
+    /// Set while the synthesis thread waits for room.
+    waiting: AtomicBool,
+    /// Set once the source is gone, so nothing will make room.
+    closed: AtomicBool,
+    lock: Mutex<()>,
+    room: Condvar,
+}
+
+impl Pacing {
+    fn has_room(&self) -> bool {
+        let produced = self.produced.load(Ordering::Relaxed);
+        let consumed = self.consumed.load(Ordering::Relaxed);
+        produced.saturating_sub(consumed) < self.limit
+    }
+
+    fn wait_for_room(&self) {
+        let mut guard = self.lock.plock();
+        while !self.has_room() && !self.closed.load(Ordering::Relaxed) {
+            self.waiting.store(true, Ordering::Relaxed);
+            guard = match self.room.wait_timeout(guard, PACING_POLL) {
+                Ok((guard, _)) => guard,
+                Err(e) => e.into_inner().0,
+            };
+        }
+        self.waiting.store(false, Ordering::Relaxed);
+    }
+
+    fn wake(&self) {
+        let _guard = self.lock.plock();
+        self.room.notify_one();
+    }
+}
+
+impl Backlog {
+    fn paced(limit: u64) -> Backlog {
+        Backlog {
+            pacing: Some(Pacing {
+                limit,
+                ..Pacing::default()
+            }),
+            ..Backlog::default()
+        }
+    }
+
+    /// Record that the source handed out `samples` more samples.
+    pub(crate) fn consume(&self, samples: usize) {
+        if let Some(pacing) = &self.pacing {
+            pacing.consumed.fetch_add(samples as u64, Ordering::Relaxed);
+            if pacing.waiting.load(Ordering::Relaxed) && pacing.has_room() {
+                pacing.wake();
+            }
+        }
+    }
+
+    /// Record that the source is gone, releasing a paced synthesis thread.
+    pub(crate) fn close(&self) {
+        if let Some(pacing) = &self.pacing {
+            pacing.closed.store(true, Ordering::Relaxed);
+            pacing.wake();
+        }
+    }
+
+    /// Wait until a paced synthesis thread may send `samples` more.
+    fn pace(&self, samples: usize) {
+        if let Some(pacing) = &self.pacing {
+            if !pacing.has_room() {
+                self.set_blocked(true);
+                pacing.wait_for_room();
+                self.set_blocked(false);
+            }
+            pacing.produced.fetch_add(samples as u64, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn stats(&self) -> BacklogStats {
+        BacklogStats {
+            chunks_queued: self.chunks_queued.load(Ordering::Relaxed),
+            samples_queued: self.samples_queued.load(Ordering::Relaxed),
+            producer_blocked: self.producer_blocked.load(Ordering::Relaxed),
+        }
+    }
+
+    fn push(&self, samples: usize) {
+        self.chunks_queued.fetch_add(1, Ordering::Relaxed);
+        self.samples_queued.fetch_add(samples, Ordering::Relaxed);
+    }
+
+    pub(crate) fn pop(&self, samples: usize) {
+        self.chunks_queued.fetch_sub(1, Ordering::Relaxed);
+        self.samples_queued.fetch_sub(samples, Ordering::Relaxed);
+        self.trace();
+    }
+
+    fn set_blocked(&self, blocked: bool) {
+        self.producer_blocked.store(blocked, Ordering::Relaxed);
+        self.trace();
+    }
+
+    #[cfg(feature = "tracing")]
+    fn trace(&self) {
+        let stats = self.stats();
+        tracing::trace!(
+            target: "espeak_rs::backlog",
+            chunks_queued = stats.chunks_queued,
+            samples_queued = stats.samples_queued,
+            producer_blocked = stats.producer_blocked,
+            "backlog"
+        );
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    fn trace(&self) {}
+}
+
+#[derive(Clone)]
+enum ChunkTx {
+    Unbounded(Sender<Message>),
+    Bounded(SyncSender<Message>),
+}
+
+#[derive(Clone)]
+pub(crate) struct ChunkSender {
+    tx: ChunkTx,
+    backlog: Arc<Backlog>,
+}
+
+impl ChunkSender {
+    /// Send a chunk, blocking while a bounded channel is full or synthesis
+    /// is paced and far enough ahead.
+    /// Fails once the receiving source has been dropped.
+    pub(crate) fn send(&self, chunk: Chunk) -> Result<(), ()> {
+        let samples = chunk.0.len();
+        self.backlog.pace(samples);
+        // Count the chunk before it becomes visible to the receiver so the
+        // counters never underflow.
+        self.backlog.push(samples);
+        let result = self.send_message(Ok(chunk));
+        match result {
+            Ok(()) => self.backlog.trace(),
+            Err(()) => self.backlog.pop(samples),
+        }
+        result
+    }
+
+    /// Tell the receiving source why synthesis stopped.
+    pub(crate) fn send_error(&self, error: EspeakError) -> Result<(), ()> {
+        self.send_message(Err(error))
+    }
+
+    fn send_message(&self, message: Message) -> Result<(), ()> {
+        match &self.tx {
+            ChunkTx::Unbounded(tx) => tx.send(message).map_err(|_| ()),
+            ChunkTx::Bounded(tx) => match tx.try_send(message) {
+                Ok(()) => Ok(()),
+                Err(TrySendError::Full(message)) => {
+                    self.backlog.set_blocked(true);
+                    let result = tx.send(message).map_err(|_| ());
+                    self.backlog.set_blocked(false);
+                    result
+                }
+                Err(TrySendError::Disconnected(_)) => Err(()),
+            },
+        }
+    }
+}
+
+/// Create a chunk channel, bounded to `capacity` chunks if given, and
+/// letting the sender get at most `ahead` samples ahead of the source if
+/// given.
+pub(crate) fn chunk_channel(
+    capacity: Option<usize>,
+    ahead: Option<u64>,
+) -> (ChunkSender, Receiver<Message>, Arc<Backlog>) {
+    let (tx, rx) = match capacity {
+        None => {
+            let (tx, rx) = channel::<Message>();
+            (ChunkTx::Unbounded(tx), rx)
+        }
+        Some(capacity) => {
+            let (tx, rx) = sync_channel::<Message>(capacity);
+            (ChunkTx::Bounded(tx), rx)
+        }
+    };
+    let backlog = Arc::new(match ahead {
+        Some(limit) => Backlog::paced(limit),
+        None => Backlog::default(),
+    });
+    let sender = ChunkSender {
+        tx,
+        backlog: backlog.clone(),
+    };
+    (sender, rx, backlog)
+}
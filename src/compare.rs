@@ -0,0 +1,128 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Side-by-side renditions of one text in several voices.
+
+use crate::{init, EspeakError, Event, Speaker, SpeakerSource, TimedEvent, Voice};
+use std::time::Duration;
+
+/// Synthesized audio held in memory, with its events.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioBuffer {
+    /// 16-bit mono PCM.
+    pub samples: Vec<i16>,
+    pub sample_rate: u32,
+    /// Events in order of their offsets.
+    pub events: Vec<TimedEvent>,
+    /// For a buffer made by [`mix`](crate::mix), which buffer each of
+    /// `events` came from: 0 for the first, 1 for the second. Empty
+    /// otherwise.
+    pub event_origins: Vec<usize>,
+}
+
+impl AudioBuffer {
+    pub(crate) fn new(sample_rate: u32) -> AudioBuffer {
+        AudioBuffer {
+            samples: Vec::new(),
+            sample_rate,
+            events: Vec::new(),
+            event_origins: Vec::new(),
+        }
+    }
+
+    /// Length of the audio.
+    pub fn duration(&self) -> Duration {
+        self.offset_of(self.samples.len())
+    }
+
+    fn offset_of(&self, sample: usize) -> Duration {
+        Duration::from_secs_f64(sample as f64 / self.sample_rate as f64)
+    }
+
+    fn push_silence(&mut self, length: Duration) {
+        let samples = (length.as_secs_f64() * self.sample_rate as f64).round() as usize;
+        self.samples.resize(self.samples.len() + samples, 0);
+    }
+
+    /// Append all of `source`'s audio, and its events if `keep_events`.
+    fn append(&mut self, mut source: SpeakerSource, keep_events: bool) -> Result<(), EspeakError> {
+        loop {
+            let (sample, events) = source.next_sample_and_events();
+            if keep_events {
+                let offset = self.duration();
+                self.events.extend(
+                    events
+                        .into_iter()
+                        .flatten()
+                        .map(|event| TimedEvent { offset, event }),
+                );
+            }
+            match sample {
+                Some(sample) => self.samples.push(sample),
+                None => break,
+            }
+        }
+        match source.error() {
+            Some(error) => Err(error.clone()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Speak `text` in each of `voices`, one after another, for comparing them
+/// by ear.
+///
+/// Each rendition is preceded by a spoken "Voice: <name>" in that voice and
+/// separated from it and from the next rendition by `gap` of silence. An
+/// [`Event::Mark`] with the voice's name is placed where its intro starts,
+/// and the events of each rendition of `text` are kept, so `Word` and
+/// `Sentence` positions index into `text`.
+pub fn compare_voices(text: &str, voices: &[Voice], gap: Duration) -> AudioBuffer {
+    try_compare_voices(text, voices, gap).unwrap()
+}
+
+/// Like [`compare_voices`], but returns the first error from setting up or
+/// synthesizing a rendition.
+pub fn try_compare_voices(
+    text: &str,
+    voices: &[Voice],
+    gap: Duration,
+) -> Result<AudioBuffer, EspeakError> {
+    let mut speaker = Speaker::try_new()?;
+    let mut buffer = AudioBuffer::new(init()?);
+    for (i, voice) in voices.iter().enumerate() {
+        speaker.set_voice(voice);
+        let intro = speaker.try_speak(&format!("Voice: {}", voice.name))?;
+        let rendition = speaker.try_speak(text)?;
+
+        if i > 0 {
+            buffer.push_silence(gap);
+        }
+        buffer.events.push(TimedEvent {
+            offset: buffer.duration(),
+            event: Event::Mark(voice.name.clone()),
+        });
+        buffer.append(intro, false)?;
+        buffer.push_silence(gap);
+        buffer.append(rendition, true)?;
+    }
+    Ok(buffer)
+}
@@ -0,0 +1,257 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Golden corpus tooling for reviewing espeak-ng upgrades.
+//!
+//! Synthesize a fixed set of utterances with [`run`] and keep the resulting
+//! [`CorpusReport`] around as JSON. After bumping the vendored espeak-ng,
+//! run the corpus again and use [`diff`] to list the utterances whose
+//! timing, pronunciation or event stream changed:
+//! ```no_run
+//! use espeak_rs::corpus;
+//!
+//! let old = corpus::CorpusReport::from_json(&std::fs::read_to_string("golden.json").unwrap())
+//!     .unwrap();
+//! let new = corpus::run(&corpus::default_corpus());
+//! for item in corpus::diff(&old, &new).items {
+//!     println!("{:?}", item);
+//! }
+//! ```
+
+use crate::{text_to_phonemes, Event, SpeakerParams, SpeakerSource};
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+/// Relative duration change above which [`diff`] reports an item.
+pub const DURATION_TOLERANCE: f64 = 0.05;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CorpusItem {
+    pub voice: String,
+    pub text: String,
+}
+
+impl CorpusItem {
+    pub fn new(voice: &str, text: &str) -> CorpusItem {
+        CorpusItem {
+            voice: String::from(voice),
+            text: String::from(text),
+        }
+    }
+}
+
+/// What a single [`CorpusItem`] produced when it was synthesized.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CorpusEntry {
+    pub item: CorpusItem,
+    /// FNV-1a hash of the samples, for spotting any change in the audio.
+    pub fingerprint: String,
+    pub sample_count: usize,
+    pub duration_ms: u64,
+    pub word_events: usize,
+    pub sentence_events: usize,
+    pub phonemes: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct CorpusReport {
+    pub entries: Vec<CorpusEntry>,
+}
+
+impl CorpusReport {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Failed to serialize CorpusReport")
+    }
+
+    pub fn from_json(json: &str) -> Result<CorpusReport, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum Change {
+    Duration { old_ms: u64, new_ms: u64 },
+    Phonemes { old: String, new: String },
+    WordEvents { old: usize, new: usize },
+    SentenceEvents { old: usize, new: usize },
+    Added,
+    Removed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItemDiff {
+    pub item: CorpusItem,
+    pub changes: Vec<Change>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct DiffReport {
+    pub items: Vec<ItemDiff>,
+}
+
+impl DiffReport {
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+/// A short pangram-ish sentence in each of a dozen languages.
+pub fn default_corpus() -> Vec<CorpusItem> {
+    vec![
+        CorpusItem::new("en", "The quick brown fox jumps over the lazy dog."),
+        CorpusItem::new("en", "It costs $12.50, due on the 3rd of May 2024."),
+        CorpusItem::new("fr", "Portez ce vieux whisky au juge blond qui fume."),
+        CorpusItem::new(
+            "de",
+            "Zwölf Boxkämpfer jagen Viktor quer über den großen Sylter Deich.",
+        ),
+        CorpusItem::new(
+            "es",
+            "El veloz murciélago hindú comía feliz cardillo y kiwi.",
+        ),
+        CorpusItem::new(
+            "it",
+            "Quel vituperabile xenofobo zelante assaggia il whisky ed esclama alleluja.",
+        ),
+        CorpusItem::new("pt", "Um pequeno jabuti xereta viu dez cegonhas felizes."),
+        CorpusItem::new("nl", "Pa's wijze lynx bezag vroom het fikse aquaduct."),
+        CorpusItem::new("sv", "Flygande bäckasiner söka hwila på mjuka tuvor."),
+        CorpusItem::new("pl", "Pchnąć w tę łódź jeża lub ośm skrzyń fig."),
+        CorpusItem::new("cs", "Příliš žluťoučký kůň úpěl ďábelské ódy."),
+        CorpusItem::new(
+            "ru",
+            "Съешь же ещё этих мягких французских булок, да выпей чаю.",
+        ),
+        CorpusItem::new("fi", "Hyvää huomenta, mitä sinulle kuuluu tänään?"),
+    ]
+}
+
+/// Synthesize every item of the corpus in order.
+pub fn run(corpus: &[CorpusItem]) -> CorpusReport {
+    CorpusReport {
+        entries: corpus.iter().map(run_item).collect(),
+    }
+}
+
+fn run_item(item: &CorpusItem) -> CorpusEntry {
+    let source = SpeakerSource::new(&item.text, &item.voice, SpeakerParams::new());
+    let sample_rate = source.sample_rate();
+
+    let mut word_events = 0usize;
+    let mut sentence_events = 0usize;
+    let mut sample_count = 0usize;
+    let mut hash = FNV_OFFSET_BASIS;
+    let source = source.with_callback(|evt| match evt {
+        Event::Word(_, _) => word_events += 1,
+        Event::Sentence { .. } => sentence_events += 1,
+        _ => (),
+    });
+    for sample in source {
+        for byte in sample.to_le_bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+        sample_count += 1;
+    }
+
+    CorpusEntry {
+        item: item.clone(),
+        fingerprint: format!("{:016x}", hash),
+        sample_count,
+        duration_ms: sample_count as u64 * 1000 / sample_rate as u64,
+        word_events,
+        sentence_events,
+        phonemes: text_to_phonemes(&item.voice, &item.text),
+    }
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Compare two reports item by item.
+///
+/// Items are matched on their voice and text, so reordering the corpus
+/// doesn't produce spurious changes. Fingerprint differences alone are not
+/// reported since almost any espeak-ng change alters the waveform.
+pub fn diff(old: &CorpusReport, new: &CorpusReport) -> DiffReport {
+    let mut items = Vec::<ItemDiff>::new();
+
+    for old_entry in &old.entries {
+        let changes = match new.entries.iter().find(|e| e.item == old_entry.item) {
+            None => vec![Change::Removed],
+            Some(new_entry) => entry_changes(old_entry, new_entry),
+        };
+        if !changes.is_empty() {
+            items.push(ItemDiff {
+                item: old_entry.item.clone(),
+                changes,
+            });
+        }
+    }
+
+    for new_entry in &new.entries {
+        if !old.entries.iter().any(|e| e.item == new_entry.item) {
+            items.push(ItemDiff {
+                item: new_entry.item.clone(),
+                changes: vec![Change::Added],
+            });
+        }
+    }
+
+    DiffReport { items }
+}
+
+fn entry_changes(old: &CorpusEntry, new: &CorpusEntry) -> Vec<Change> {
+    let mut changes = Vec::<Change>::new();
+
+    let duration_changed = if old.duration_ms == 0 {
+        new.duration_ms != 0
+    } else {
+        let delta = old.duration_ms.abs_diff(new.duration_ms) as f64;
+        delta / old.duration_ms as f64 > DURATION_TOLERANCE
+    };
+    if duration_changed {
+        changes.push(Change::Duration {
+            old_ms: old.duration_ms,
+            new_ms: new.duration_ms,
+        });
+    }
+    if old.phonemes != new.phonemes {
+        changes.push(Change::Phonemes {
+            old: old.phonemes.clone(),
+            new: new.phonemes.clone(),
+        });
+    }
+    if old.word_events != new.word_events {
+        changes.push(Change::WordEvents {
+            old: old.word_events,
+            new: new.word_events,
+        });
+    }
+    if old.sentence_events != new.sentence_events {
+        changes.push(Change::SentenceEvents {
+            old: old.sentence_events,
+            new: new.sentence_events,
+        });
+    }
+    changes
+}
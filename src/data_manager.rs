@@ -0,0 +1,565 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Downloading espeak's data at runtime, only for the languages needed.
+//!
+//! Apps that don't want to ship all of `espeak-ng-data`, most of which is
+//! languages they never use, can fetch what they need with
+//! [`ensure_languages`] and pass the directory it returns to
+//! [`set_data_path`](crate::set_data_path).
+//!
+//! The files come from a [`DataSource`] set with [`set_data_source`].
+//! There is no default: this crate doesn't publish data, so apps host the
+//! files and sign the manifest with their own key. A source serves:
+//!
+//! - `{base_url}/manifest.json`, a [`Manifest`] listing every file with
+//!   its path in the data directory, size, SHA-256 and languages. Files
+//!   with no languages, such as `phontab`, `phonindex` and `phondata`, are
+//!   needed by all of them.
+//! - `{base_url}/manifest.json.sig`, the hex-encoded Ed25519 signature of
+//!   `manifest.json`, checked against [`DataSource::public_key`].
+//! - `{base_url}/{sha256}` for each file, named by its checksum so that
+//!   the layout is flat, as release assets on GitHub have to be.
+//!
+//! Downloads go to `partial/` in the cache directory and resume from there
+//! if interrupted. Only files that match their checksum are moved into
+//! place, and when everything asked for is already there, no request is
+//! made at all.
+
+use crate::{EspeakError, PoisonlessLock};
+use ed25519_dalek::{Signature, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+const MANIFEST: &str = "manifest.json";
+const SIGNATURE: &str = "manifest.json.sig";
+/// Which version of each file is installed, by path, as its SHA-256.
+const INSTALLED: &str = "installed.json";
+
+/// A server or directory of data files, laid out as described in the
+/// [module docs](self). `base_url` may be `http://`, `https://` or
+/// `file://`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataSource {
+    pub base_url: String,
+    /// The key the manifest is signed with.
+    pub public_key: [u8; 32],
+}
+
+impl DataSource {
+    /// A source at `base_url` whose manifest is signed with the key
+    /// `public_key`, e.g. the assets of a GitHub release at
+    /// `https://github.com/{owner}/{repo}/releases/download/{tag}`.
+    pub fn new(base_url: impl Into<String>, public_key: [u8; 32]) -> DataSource {
+        DataSource {
+            base_url: base_url.into(),
+            public_key,
+        }
+    }
+
+    fn url(&self, name: &str) -> String {
+        format!("{}/{}", self.base_url.trim_end_matches('/'), name)
+    }
+}
+
+static DATA_SOURCE: Mutex<Option<DataSource>> = Mutex::new(None);
+
+/// Fetch data from `source` in [`ensure_languages`]. With `None`, only
+/// what's already in the cache can be used.
+pub fn set_data_source(source: Option<DataSource>) {
+    *DATA_SOURCE.plock() = source;
+}
+
+/// The files a [`DataSource`] offers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestFile {
+    /// Where the file goes in the data directory, with `/` between
+    /// directories, e.g. `en_dict` or `lang/roa/fr`.
+    pub path: String,
+    pub size: u64,
+    /// Lowercase hex.
+    pub sha256: String,
+    /// The languages that need the file, or none if all of them do.
+    #[serde(default)]
+    pub languages: Vec<String>,
+}
+
+impl Manifest {
+    /// The files needed to speak `languages`. A language also gets the
+    /// files of the ones it's a variant of, so "en-us" gets those for
+    /// "en".
+    fn needed(&self, languages: &[&str]) -> Result<Vec<&ManifestFile>, EspeakError> {
+        let covers = |file_language: &str, language: &str| {
+            language == file_language
+                || (language.starts_with(file_language)
+                    && language[file_language.len()..].starts_with('-'))
+        };
+        if let Some(missing) = languages.iter().find(|language| {
+            !self.files.iter().any(|file| {
+                file.languages
+                    .iter()
+                    .any(|file_language| covers(file_language, language))
+            })
+        }) {
+            return Err(EspeakError::VoiceNotFound {
+                name: missing.to_string(),
+            });
+        }
+        Ok(self
+            .files
+            .iter()
+            .filter(|file| {
+                file.languages.is_empty()
+                    || file.languages.iter().any(|file_language| {
+                        languages
+                            .iter()
+                            .any(|language| covers(file_language, language))
+                    })
+            })
+            .collect())
+    }
+}
+
+/// Make sure `cache_dir` holds the data for speaking `languages`, such as
+/// `&["en", "fr"]`, downloading what's missing from the source given to
+/// [`set_data_source`]. Returns the data directory to pass to
+/// [`set_data_path`](crate::set_data_path).
+///
+/// Without a source, succeeds only if everything is already cached. A
+/// language no file is listed for is reported as
+/// [`EspeakError::VoiceNotFound`].
+pub fn ensure_languages(
+    languages: &[&str],
+    cache_dir: impl AsRef<Path>,
+) -> Result<PathBuf, EspeakError> {
+    let source = DATA_SOURCE.plock().clone();
+    ensure(source.as_ref(), languages, cache_dir.as_ref())
+}
+
+fn ensure(
+    source: Option<&DataSource>,
+    languages: &[&str],
+    cache_dir: &Path,
+) -> Result<PathBuf, EspeakError> {
+    let data_dir = cache_dir.join("espeak-ng-data");
+    let installed_path = cache_dir.join(INSTALLED);
+    let mut installed: BTreeMap<String, String> = fs::read(&installed_path)
+        .ok()
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .unwrap_or_default();
+    let is_installed = |installed: &BTreeMap<String, String>, file: &ManifestFile| {
+        installed.get(&file.path) == Some(&file.sha256) && data_dir.join(&file.path).is_file()
+    };
+
+    // The cached manifest was verified when it was downloaded.
+    let cached = fs::read(cache_dir.join(MANIFEST))
+        .ok()
+        .and_then(|json| serde_json::from_slice::<Manifest>(&json).ok());
+    if let Some(Ok(needed)) = cached.as_ref().map(|manifest| manifest.needed(languages)) {
+        if needed.iter().all(|file| is_installed(&installed, file)) {
+            return Ok(data_dir);
+        }
+    }
+
+    let source = source.ok_or_else(|| EspeakError::DataDownload {
+        url: String::from(MANIFEST),
+        message: String::from("not all of the data is cached, and no data source is set"),
+    })?;
+    let manifest_json = fetch(&source.url(MANIFEST))?;
+    let signature = fetch(&source.url(SIGNATURE))?;
+    verify_signature(&manifest_json, &signature, &source.public_key)?;
+    let manifest: Manifest =
+        serde_json::from_slice(&manifest_json).map_err(|e| verification_error(MANIFEST, e))?;
+    let needed = manifest.needed(languages)?;
+
+    let partial_dir = cache_dir.join("partial");
+    create_dir(&partial_dir)?;
+    for file in needed {
+        if is_installed(&installed, file) {
+            continue;
+        }
+        let target = relative_path(&file.path)
+            .map(|path| data_dir.join(path))
+            .ok_or_else(|| verification_error(&file.path, "not a relative path"))?;
+        if let Some(parent) = target.parent() {
+            create_dir(parent)?;
+        }
+        let partial = partial_dir.join(&file.sha256);
+        download(&source.url(&file.sha256), &partial, file.size)?;
+        if let Err(error) = verify_file(&partial, file) {
+            fs::remove_file(&partial).ok();
+            return Err(error);
+        }
+        fs::rename(&partial, &target).map_err(|e| not_writable(&target, e))?;
+        installed.insert(file.path.clone(), file.sha256.clone());
+        let json = serde_json::to_vec_pretty(&installed).expect("Failed to serialize paths");
+        fs::write(&installed_path, json).map_err(|e| not_writable(&installed_path, e))?;
+    }
+    let manifest_path = cache_dir.join(MANIFEST);
+    fs::write(&manifest_path, manifest_json).map_err(|e| not_writable(&manifest_path, e))?;
+    Ok(data_dir)
+}
+
+/// `path` as a path below the data directory, if it can't point outside
+/// it.
+fn relative_path(path: &str) -> Option<PathBuf> {
+    let path = Path::new(path);
+    let normal = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    (normal && !path.as_os_str().is_empty()).then(|| path.to_path_buf())
+}
+
+/// Open `url` for reading from byte `offset`. Also returns whether the
+/// server honoured the offset, rather than starting from the beginning.
+fn open(url: &str, offset: u64) -> Result<(Box<dyn Read>, bool), EspeakError> {
+    let download_error = |message: String| EspeakError::DataDownload {
+        url: String::from(url),
+        message,
+    };
+    if let Some(path) = url.strip_prefix("file://") {
+        let mut file = File::open(path).map_err(|e| download_error(e.to_string()))?;
+        file.seek(SeekFrom::Start(offset))
+            .map_err(|e| download_error(e.to_string()))?;
+        return Ok((Box::new(file), true));
+    }
+    let mut request = ureq::get(url);
+    if offset > 0 {
+        request = request.set("Range", &format!("bytes={}-", offset));
+    }
+    let response = request.call().map_err(|e| download_error(e.to_string()))?;
+    let resumed = response.status() == 206;
+    Ok((Box::new(response.into_reader()), resumed))
+}
+
+fn fetch(url: &str) -> Result<Vec<u8>, EspeakError> {
+    let (mut reader, _) = open(url, 0)?;
+    let mut bytes = Vec::new();
+    reader
+        .read_to_end(&mut bytes)
+        .map_err(|e| EspeakError::DataDownload {
+            url: String::from(url),
+            message: e.to_string(),
+        })?;
+    Ok(bytes)
+}
+
+/// Download `url` to `partial`, carrying on from what an interrupted
+/// download left there. Reads no more than the `size` bytes expected, and
+/// fails if the body is longer.
+fn download(url: &str, partial: &Path, size: u64) -> Result<(), EspeakError> {
+    let download_error = |message: String| EspeakError::DataDownload {
+        url: String::from(url),
+        message,
+    };
+    let have = fs::metadata(partial).map(|m| m.len()).unwrap_or(0);
+    if have == size {
+        return Ok(());
+    }
+    let offset = if have < size { have } else { 0 };
+    let (mut reader, resumed) = open(url, offset)?;
+    let start = if resumed { offset } else { 0 };
+    let mut out = if start > 0 {
+        OpenOptions::new().append(true).open(partial)
+    } else {
+        File::create(partial)
+    }
+    .map_err(|e| not_writable(partial, e))?;
+    io::copy(&mut reader.by_ref().take(size - start), &mut out)
+        .map_err(|e| download_error(e.to_string()))?;
+    let extra = reader
+        .read(&mut [0u8])
+        .map_err(|e| download_error(e.to_string()))?;
+    if extra > 0 {
+        return Err(download_error(format!(
+            "body is longer than the expected {} bytes",
+            size
+        )));
+    }
+    Ok(())
+}
+
+fn verify_signature(
+    manifest: &[u8],
+    signature: &[u8],
+    public_key: &[u8; 32],
+) -> Result<(), EspeakError> {
+    let signature = std::str::from_utf8(signature)
+        .ok()
+        .and_then(|hex| decode_hex(hex.trim()))
+        .and_then(|bytes| <[u8; 64]>::try_from(bytes).ok())
+        .ok_or_else(|| verification_error(SIGNATURE, "not a hex-encoded signature"))?;
+    let key =
+        VerifyingKey::from_bytes(public_key).map_err(|e| verification_error("public key", e))?;
+    key.verify_strict(manifest, &Signature::from_bytes(&signature))
+        .map_err(|e| verification_error(MANIFEST, e))
+}
+
+fn verify_file(path: &Path, file: &ManifestFile) -> Result<(), EspeakError> {
+    let mut hasher = Sha256::new();
+    let mut reader = File::open(path).map_err(|e| verification_error(&file.path, e))?;
+    let mut buffer = vec![0; 1 << 16];
+    let mut size = 0;
+    loop {
+        let n = reader
+            .read(&mut buffer)
+            .map_err(|e| verification_error(&file.path, e))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+        size += n as u64;
+    }
+    let sha256: String = hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    if size != file.size || sha256 != file.sha256.to_lowercase() {
+        return Err(verification_error(
+            &file.path,
+            format!(
+                "{} bytes with SHA-256 {}, not {} bytes with {}",
+                size, sha256, file.size, file.sha256
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 == 1 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn create_dir(path: &Path) -> Result<(), EspeakError> {
+    fs::create_dir_all(path).map_err(|e| not_writable(path, e))
+}
+
+fn not_writable(path: &Path, error: impl ToString) -> EspeakError {
+    EspeakError::DataNotWritable {
+        path: path.to_path_buf(),
+        message: error.to_string(),
+    }
+}
+
+fn verification_error(what: &str, error: impl ToString) -> EspeakError {
+    EspeakError::DataVerification {
+        what: String::from(what),
+        message: error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    const KEY: [u8; 32] = [7; 32];
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    /// A directory serving the data for "en" and "fr", and an empty cache.
+    struct Fixture {
+        root: PathBuf,
+        source: DataSource,
+        manifest: Manifest,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Fixture {
+            let root = std::env::temp_dir().join(format!(
+                "espeak-rs-data-manager-{}-{}",
+                name,
+                std::process::id()
+            ));
+            fs::remove_dir_all(&root).ok();
+            let served = root.join("served");
+            fs::create_dir_all(&served).unwrap();
+            let files = [
+                ("phontab", "shared phonemes", &[][..]),
+                ("en_dict", "english dictionary", &["en"][..]),
+                ("fr_dict", "french dictionary", &["fr"][..]),
+                ("lang/roa/fr", "name French", &["fr"][..]),
+            ];
+            let manifest = Manifest {
+                files: files
+                    .iter()
+                    .map(|(path, contents, languages)| {
+                        let sha256 = hex(&Sha256::digest(contents.as_bytes()));
+                        fs::write(served.join(&sha256), contents).unwrap();
+                        ManifestFile {
+                            path: path.to_string(),
+                            size: contents.len() as u64,
+                            sha256,
+                            languages: languages.iter().map(|l| l.to_string()).collect(),
+                        }
+                    })
+                    .collect(),
+            };
+            let json = serde_json::to_vec(&manifest).unwrap();
+            let key = SigningKey::from_bytes(&KEY);
+            fs::write(served.join(MANIFEST), &json).unwrap();
+            fs::write(served.join(SIGNATURE), hex(&key.sign(&json).to_bytes())).unwrap();
+            let source = DataSource::new(
+                format!("file://{}", served.display()),
+                key.verifying_key().to_bytes(),
+            );
+            Fixture {
+                root,
+                source,
+                manifest,
+            }
+        }
+
+        fn cache(&self) -> PathBuf {
+            self.root.join("cache")
+        }
+
+        /// Where the file installed at `path` is served from.
+        fn served(&self, path: &str) -> PathBuf {
+            let file = self.manifest.files.iter().find(|f| f.path == path);
+            self.root.join("served").join(&file.unwrap().sha256)
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.root).ok();
+        }
+    }
+
+    #[test]
+    fn only_requested_languages_are_installed() {
+        let fixture = Fixture::new("install");
+        let data_dir = ensure(Some(&fixture.source), &["en-us"], &fixture.cache()).unwrap();
+        assert_eq!(data_dir, fixture.cache().join("espeak-ng-data"));
+        assert_eq!(
+            fs::read_to_string(data_dir.join("en_dict")).unwrap(),
+            "english dictionary"
+        );
+        assert!(data_dir.join("phontab").is_file());
+        assert!(!data_dir.join("fr_dict").exists());
+
+        // What's cached needs no source; what isn't does.
+        assert_eq!(ensure(None, &["en"], &fixture.cache()).unwrap(), data_dir);
+        assert!(matches!(
+            ensure(None, &["fr"], &fixture.cache()),
+            Err(EspeakError::DataDownload { .. })
+        ));
+        ensure(Some(&fixture.source), &["fr"], &fixture.cache()).unwrap();
+        assert!(data_dir.join("lang/roa/fr").is_file());
+        assert!(ensure(None, &["en", "fr"], &fixture.cache()).is_ok());
+    }
+
+    #[test]
+    fn tampered_files_are_rejected() {
+        let fixture = Fixture::new("tampered");
+        fs::write(fixture.served("en_dict"), "english dictionarY").unwrap();
+        assert!(matches!(
+            ensure(Some(&fixture.source), &["en"], &fixture.cache()),
+            Err(EspeakError::DataVerification { what, .. }) if what == "en_dict"
+        ));
+        let cache = fixture.cache();
+        assert!(!cache.join("espeak-ng-data/en_dict").exists());
+        assert_eq!(fs::read_dir(cache.join("partial")).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn oversized_downloads_are_rejected() {
+        let fixture = Fixture::new("oversized");
+        fs::write(fixture.served("en_dict"), "english dictionary, and more").unwrap();
+        assert!(matches!(
+            ensure(Some(&fixture.source), &["en"], &fixture.cache()),
+            Err(EspeakError::DataDownload { message, .. }) if message.contains("longer")
+        ));
+        assert!(!fixture.cache().join("espeak-ng-data/en_dict").exists());
+    }
+
+    #[test]
+    fn bad_signatures_are_rejected() {
+        let fixture = Fixture::new("signature");
+        let source = DataSource {
+            public_key: SigningKey::from_bytes(&[8; 32]).verifying_key().to_bytes(),
+            ..fixture.source.clone()
+        };
+        assert!(matches!(
+            ensure(Some(&source), &["en"], &fixture.cache()),
+            Err(EspeakError::DataVerification { .. })
+        ));
+        assert!(!fixture.cache().join(MANIFEST).exists());
+    }
+
+    #[test]
+    fn interrupted_downloads_resume() {
+        let fixture = Fixture::new("resume");
+        let file = &fixture.manifest.files[1];
+        let partial = fixture.cache().join("partial");
+        fs::create_dir_all(&partial).unwrap();
+        fs::write(partial.join(&file.sha256), "english ").unwrap();
+        // Starting over would get this instead of what's already there.
+        fs::write(fixture.served("en_dict"), "XXXXXXX dictionary").unwrap();
+        let data_dir = ensure(Some(&fixture.source), &["en"], &fixture.cache()).unwrap();
+        assert_eq!(
+            fs::read_to_string(data_dir.join("en_dict")).unwrap(),
+            "english dictionary"
+        );
+    }
+
+    #[test]
+    fn unknown_languages_are_reported() {
+        let fixture = Fixture::new("unknown");
+        assert_eq!(
+            ensure(Some(&fixture.source), &["en", "xx"], &fixture.cache()),
+            Err(EspeakError::VoiceNotFound {
+                name: String::from("xx")
+            })
+        );
+    }
+
+    #[test]
+    fn paths_stay_inside_the_data_directory() {
+        assert_eq!(
+            relative_path("lang/roa/fr"),
+            Some(PathBuf::from("lang/roa/fr"))
+        );
+        assert_eq!(relative_path("../phontab"), None);
+        assert_eq!(relative_path("/etc/passwd"), None);
+        assert_eq!(relative_path(""), None);
+    }
+}
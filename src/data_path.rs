@@ -0,0 +1,216 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Choosing where espeak loads its data from.
+//!
+//! The data path can be given in several places. In order of precedence:
+//! [`set_data_path`], the `ESPEAK_DATA_PATH` environment variable, a path
+//! baked into the sys crate, and espeak's own default, which is
+//! `espeak-ng-data` in the home directory or else where espeak-ng was
+//! installed. They're resolved once each time espeak is initialized,
+//! while the espeak lock is held, and [`info`](crate::info) reports which
+//! was used. espeak would otherwise read the environment itself, again on
+//! every initialization.
+//!
+//! The sys crate doesn't bake a path in yet, so that source is always
+//! empty for now.
+
+use crate::{EspeakError, PoisonlessLock};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Where the data path espeak was initialized with came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum PathSource {
+    /// [`set_data_path`].
+    Explicit,
+    /// The `ESPEAK_DATA_PATH` environment variable.
+    Environment,
+    /// The path the sys crate was built with.
+    Baked,
+    /// None of the above, leaving espeak to find its data.
+    SystemDefault,
+}
+
+/// What to do when a data path that was given isn't a directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MissingDataPath {
+    /// Go on to the next source, as espeak does.
+    #[default]
+    Skip,
+    /// Fail initialization with [`EspeakError::DataNotFound`].
+    Fail,
+}
+
+/// The path and policy given to [`set_data_path`].
+static DATA_PATH: Mutex<(Option<PathBuf>, MissingDataPath)> =
+    Mutex::new((None, MissingDataPath::Skip));
+
+/// Load espeak's data from `path`, ahead of `ESPEAK_DATA_PATH`. It can be
+/// the `espeak-ng-data` directory or the one containing it, and `None`
+/// leaves it to the other sources again. `on_missing` applies to every
+/// source that's given but isn't a directory.
+///
+/// Takes effect when espeak is next initialized: on first use, or after
+/// [`shutdown`](crate::shutdown).
+pub fn set_data_path(path: Option<PathBuf>, on_missing: MissingDataPath) {
+    *DATA_PATH.plock() = (path, on_missing);
+}
+
+/// Pick the data path from the `explicit`, `env` and `baked` ones, in that
+/// order, along with where it came from. Sources that are `None` or empty
+/// are skipped, and so are ones that aren't directories unless `missing`
+/// says to fail. With no path left, the path returned is empty, for espeak
+/// to find its data itself.
+pub fn resolve_data_path(
+    explicit: Option<&Path>,
+    env: Option<&Path>,
+    baked: Option<&Path>,
+    missing: MissingDataPath,
+) -> Result<(PathBuf, PathSource), EspeakError> {
+    let sources = [
+        (explicit, PathSource::Explicit),
+        (env, PathSource::Environment),
+        (baked, PathSource::Baked),
+    ];
+    for (path, source) in sources {
+        let Some(path) = path.filter(|path| !path.as_os_str().is_empty()) else {
+            continue;
+        };
+        if path.is_dir() {
+            return Ok((path.to_path_buf(), source));
+        }
+        if missing == MissingDataPath::Fail {
+            return Err(EspeakError::DataNotFound {
+                searched_paths: vec![path.to_path_buf()],
+            });
+        }
+    }
+    Ok((PathBuf::new(), PathSource::SystemDefault))
+}
+
+/// Resolve the data path from what's configured now.
+pub(crate) fn configured_data_path() -> Result<(PathBuf, PathSource), EspeakError> {
+    let (explicit, missing) = DATA_PATH.plock().clone();
+    let env = std::env::var_os("ESPEAK_DATA_PATH").map(PathBuf::from);
+    resolve_data_path(explicit.as_deref(), env.as_deref(), None, missing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Existing directories for each source, and a path that doesn't exist.
+    struct Dirs {
+        root: PathBuf,
+        explicit: PathBuf,
+        env: PathBuf,
+        baked: PathBuf,
+        missing: PathBuf,
+    }
+
+    impl Dirs {
+        fn new(name: &str) -> Dirs {
+            let root = std::env::temp_dir().join(format!(
+                "espeak-rs-data-path-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let dirs = Dirs {
+                explicit: root.join("explicit"),
+                env: root.join("env"),
+                baked: root.join("baked"),
+                missing: root.join("missing"),
+                root,
+            };
+            for dir in [&dirs.explicit, &dirs.env, &dirs.baked] {
+                fs::create_dir_all(dir).unwrap();
+            }
+            dirs
+        }
+    }
+
+    impl Drop for Dirs {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+
+    #[test]
+    fn sources_take_precedence_in_order() {
+        let dirs = Dirs::new("order");
+        for mask in 0..8 {
+            let explicit = (mask & 1 != 0).then_some(dirs.explicit.as_path());
+            let env = (mask & 2 != 0).then_some(dirs.env.as_path());
+            let baked = (mask & 4 != 0).then_some(dirs.baked.as_path());
+            let expected = match (explicit, env, baked) {
+                (Some(path), _, _) => (path.to_path_buf(), PathSource::Explicit),
+                (None, Some(path), _) => (path.to_path_buf(), PathSource::Environment),
+                (None, None, Some(path)) => (path.to_path_buf(), PathSource::Baked),
+                (None, None, None) => (PathBuf::new(), PathSource::SystemDefault),
+            };
+            for missing in [MissingDataPath::Skip, MissingDataPath::Fail] {
+                let resolved = resolve_data_path(explicit, env, baked, missing).unwrap();
+                assert_eq!(resolved, expected, "{:03b} {:?}", mask, missing);
+            }
+        }
+    }
+
+    #[test]
+    fn missing_paths_fall_through_unless_failing() {
+        let dirs = Dirs::new("missing");
+        let missing = Some(dirs.missing.as_path());
+        let skip = MissingDataPath::Skip;
+        assert_eq!(
+            resolve_data_path(missing, Some(&dirs.env), None, skip).unwrap(),
+            (dirs.env.clone(), PathSource::Environment)
+        );
+        assert_eq!(
+            resolve_data_path(None, missing, Some(&dirs.baked), skip).unwrap(),
+            (dirs.baked.clone(), PathSource::Baked)
+        );
+        assert_eq!(
+            resolve_data_path(missing, missing, missing, skip).unwrap(),
+            (PathBuf::new(), PathSource::SystemDefault)
+        );
+
+        let fail = MissingDataPath::Fail;
+        assert_eq!(
+            resolve_data_path(None, missing, Some(&dirs.baked), fail),
+            Err(EspeakError::DataNotFound {
+                searched_paths: vec![dirs.missing.clone()]
+            })
+        );
+        // Only paths that are given count as missing.
+        assert_eq!(
+            resolve_data_path(Some(Path::new("")), None, Some(&dirs.baked), fail).unwrap(),
+            (dirs.baked.clone(), PathSource::Baked)
+        );
+        // A file isn't a data directory either.
+        let file = dirs.root.join("file");
+        fs::write(&file, "").unwrap();
+        assert!(resolve_data_path(Some(&file), None, None, fail).is_err());
+    }
+}
@@ -0,0 +1,64 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Process-wide default [`SpeakerParams`], e.g. for an app's settings screen.
+
+use crate::SpeakerParams;
+use std::sync::RwLock;
+
+/// `None` until first set, meaning [`SpeakerParams::new`].
+static GLOBAL_DEFAULTS: RwLock<Option<SpeakerParams>> = RwLock::new(None);
+
+/// A handle to the process-wide default params, from [`global_defaults`].
+///
+/// Speakers created with [`Speaker::new_with_global_defaults`](crate::Speaker::new_with_global_defaults)
+/// start from them, and speakers that
+/// [`follow_global`](crate::Speaker::follow_global) use them for every
+/// utterance. Each update replaces the whole set of params at once, so
+/// readers never see some fields from one update and some from another.
+#[derive(Clone, Copy)]
+pub struct GlobalDefaults {
+    _private: (),
+}
+
+/// The process-wide default params.
+pub fn global_defaults() -> GlobalDefaults {
+    GlobalDefaults { _private: () }
+}
+
+impl GlobalDefaults {
+    /// Replace the defaults. Utterances already started are unaffected.
+    pub fn set(&self, params: SpeakerParams) {
+        let mut defaults = match GLOBAL_DEFAULTS.write() {
+            Ok(defaults) => defaults,
+            Err(e) => e.into_inner(),
+        };
+        *defaults = Some(params);
+    }
+
+    pub fn get(&self) -> SpeakerParams {
+        let defaults = match GLOBAL_DEFAULTS.read() {
+            Ok(defaults) => defaults,
+            Err(e) => e.into_inner(),
+        };
+        defaults.clone().unwrap_or_else(SpeakerParams::new)
+    }
+}
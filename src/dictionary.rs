@@ -0,0 +1,157 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Compiling dictionaries from espeak-ng's source files.
+//!
+//! espeak writes a compiled dictionary into its data directory, which is
+//! often shared between users or read-only. So unless asked to replace the
+//! installed dictionary, the crate compiles into the temporary copy of the
+//! data it already makes for user voices.
+
+use crate::{espeak_lock, init, user_voices, EspeakError};
+use espeak_rs_sys::*;
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::io::{self, Write};
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+
+/// Written to check that a data directory has room for a dictionary.
+const SPACE_CHECK_FILE: &str = ".espeak-rs-space-check";
+
+/// Compile the dictionary `name` from `<name>_rules`, `<name>_list` and
+/// the other `<name>_*` files in `source`, returning the path of the
+/// compiled `<name>_dict`.
+///
+/// The dictionary is written to a copy of espeak's data in the temporary
+/// directory, which voices read from until [`shutdown`](crate::shutdown)
+/// removes it. The installed data is left alone unless `in_place` is set,
+/// in which case its dictionary is replaced too. Either way, the target
+/// directory is checked for write access and free space first.
+pub fn compile_dictionary<P: AsRef<Path>>(
+    name: &str,
+    source: P,
+    in_place: bool,
+) -> Result<PathBuf, EspeakError> {
+    let not_compiled = |message: String| EspeakError::DictionaryNotCompiled {
+        name: name.to_string(),
+        message,
+    };
+    let source = source.as_ref();
+    let rules = source.join(format!("{}_rules", name));
+    if !rules.is_file() {
+        return Err(not_compiled(format!("{} not found", rules.display())));
+    }
+    let needed = source_size(source, name).map_err(|e| not_compiled(e.to_string()))?;
+    let name_cstr = CString::new(name).map_err(|e| not_compiled(e.to_string()))?;
+    let source_cstr = CString::new(format!("{}{}", source.display(), MAIN_SEPARATOR))
+        .map_err(|e| not_compiled(e.to_string()))?;
+
+    init()?;
+    let mut lock = espeak_lock();
+    let state = lock.as_mut().ok_or(EspeakError::InitFailed(0))?;
+    let stock = user_voices::stock_data();
+    let data = user_voices::overlay_data(state)?;
+    check_space(&data, needed)?;
+    if in_place {
+        check_space(&stock, needed)?;
+    }
+
+    // The copy links to the installed dictionary, which espeak would
+    // write through.
+    let compiled = data.join(format!("{}_dict", name));
+    if fs::symlink_metadata(&compiled).is_ok_and(|m| m.file_type().is_symlink()) {
+        fs::remove_file(&compiled).map_err(|e| EspeakError::DataNotWritable {
+            path: data.clone(),
+            message: e.to_string(),
+        })?;
+    }
+    let status = unsafe {
+        espeak_ng_CompileDictionary(
+            source_cstr.as_ptr(),
+            name_cstr.as_ptr(),
+            std::ptr::null_mut(),
+            0,
+            std::ptr::null_mut(),
+        )
+    };
+    if status != espeak_ng_STATUS_ENS_OK {
+        return Err(not_compiled(status_message(status)));
+    }
+
+    if in_place {
+        let installed = stock.join(format!("{}_dict", name));
+        fs::copy(&compiled, &installed).map_err(|e| EspeakError::DataNotWritable {
+            path: stock,
+            message: e.to_string(),
+        })?;
+        return Ok(installed);
+    }
+    Ok(compiled)
+}
+
+/// The compiled dictionary is smaller than its sources, so their size is
+/// enough room.
+fn source_size(source: &Path, name: &str) -> io::Result<u64> {
+    let prefix = format!("{}_", name);
+    let mut size = 0;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with(&prefix) {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
+/// Check that `needed` bytes can be written to `data` by writing them.
+fn check_space(data: &Path, needed: u64) -> Result<(), EspeakError> {
+    let probe = data.join(SPACE_CHECK_FILE);
+    let result = fs::File::create(&probe).and_then(|mut file| {
+        let block = [0; 64 * 1024];
+        let mut left = needed;
+        while left > 0 {
+            let len = left.min(block.len() as u64) as usize;
+            file.write_all(&block[..len])?;
+            left -= len as u64;
+        }
+        file.sync_all()
+    });
+    let _ = fs::remove_file(&probe);
+    result.map_err(|e| match e.kind() {
+        io::ErrorKind::StorageFull => EspeakError::InsufficientSpace {
+            path: data.to_path_buf(),
+            needed,
+        },
+        _ => EspeakError::DataNotWritable {
+            path: data.to_path_buf(),
+            message: e.to_string(),
+        },
+    })
+}
+
+fn status_message(status: espeak_ng_STATUS) -> String {
+    let mut buffer = [0 as c_char; 512];
+    unsafe { espeak_ng_GetStatusCodeMessage(status, buffer.as_mut_ptr(), buffer.len()) };
+    unsafe { CStr::from_ptr(buffer.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
@@ -0,0 +1,134 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Reading numbers digit by digit, for
+//! [`SpeakerParams::say_digits`](crate::SpeakerParams::say_digits).
+//!
+//! A space goes between any two digits in a row, so "1234" is read "one
+//! two three four" rather than as a thousand and more. A decimal point
+//! stays attached to the digits either side, so "3.14" becomes "3.1 4",
+//! read "three point one four". A comma followed by exactly three digits
+//! is taken to separate thousands, and left out.
+
+use crate::text_map::TextMap;
+
+const OPEN_PHONEMES: &[u8] = b"[[";
+const CLOSE_PHONEMES: &[u8] = b"]]";
+
+/// Separate the digits of the numbers in `text`. Phoneme regions are
+/// skipped if `inline_phonemes` is set, digits in them being phonemes.
+pub(crate) fn separate_digits(text: &str, inline_phonemes: bool) -> (String, TextMap) {
+    let mut map = TextMap::default();
+    let bytes = text.as_bytes();
+    let mut rewritten = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        if inline_phonemes && bytes[i..].starts_with(OPEN_PHONEMES) {
+            // An unterminated region runs to the end of the text.
+            i = match bytes[i..]
+                .windows(CLOSE_PHONEMES.len())
+                .position(|window| window == CLOSE_PHONEMES)
+            {
+                Some(close) => i + close + CLOSE_PHONEMES.len(),
+                None => bytes.len(),
+            };
+            continue;
+        }
+        let separated = match bytes[i..] {
+            [digit, next, ..] if digit.is_ascii_digit() && next.is_ascii_digit() => 1,
+            [digit, b',', ref rest @ ..] if digit.is_ascii_digit() && is_thousands(rest) => 2,
+            _ => 0,
+        };
+        if separated == 0 {
+            i += 1;
+            continue;
+        }
+        // Digits are ASCII, so `i` is at a character boundary. The space
+        // maps to the comma, if any, so each digit still maps to itself.
+        rewritten.push_str(&text[copied..=i]);
+        let spoken_start = rewritten.len();
+        rewritten.push(' ');
+        map.push(spoken_start..rewritten.len(), i + 1..i + separated);
+        i += separated;
+        copied = i;
+    }
+    rewritten.push_str(&text[copied..]);
+    (rewritten, map)
+}
+
+/// Whether `rest`, following a comma after a digit, starts with a group of
+/// exactly three digits.
+fn is_thousands(rest: &[u8]) -> bool {
+    rest.len() >= 3
+        && rest[..3].iter().all(u8::is_ascii_digit)
+        && !rest.get(3).is_some_and(u8::is_ascii_digit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Event;
+
+    fn separated(text: &str) -> String {
+        separate_digits(text, false).0
+    }
+
+    #[test]
+    fn digits_are_separated() {
+        assert_eq!(separated("Your code is 1234."), "Your code is 1 2 3 4.");
+        assert_eq!(separated("7"), "7");
+        assert_eq!(separated("room 42b"), "room 4 2b");
+        assert_eq!(separated("A4 and B52"), "A4 and B5 2");
+        assert_eq!(separated("+1 555-0100"), "+1 5 5 5-0 1 0 0");
+        assert_eq!(separated("π ≈ 3.14159"), "π ≈ 3.1 4 1 5 9");
+    }
+
+    #[test]
+    fn separators() {
+        assert_eq!(separated("1,234,567 people"), "1 2 3 4 5 6 7 people");
+        assert_eq!(separated("12,345.67"), "1 2 3 4 5.6 7");
+        // Not thousands: a decimal comma, and a group of four.
+        assert_eq!(separated("0,25 and 1,2345"), "0,2 5 and 1,2 3 4 5");
+        assert_eq!(separated("1, 2, 3"), "1, 2, 3");
+    }
+
+    #[test]
+    fn phoneme_regions_are_skipped() {
+        let text = "[[h@l'oU2]] 12 [[3:";
+        assert_eq!(separate_digits(text, true).0, "[[h@l'oU2]] 1 2 [[3:");
+        assert_eq!(separate_digits("[[a12]]", false).0, "[[a1 2]]");
+    }
+
+    #[test]
+    fn words_map_to_digits() {
+        let (rewritten, map) = separate_digits("x 1,234", false);
+        assert_eq!(rewritten, "x 1 2 3 4");
+        let mut events = [Event::Word(2, 1), Event::Word(4, 1), Event::Word(8, 1)];
+        for event in &mut events {
+            map.correct(event);
+        }
+        assert_eq!(
+            events,
+            [Event::Word(2, 1), Event::Word(4, 1), Event::Word(6, 1)]
+        );
+    }
+}
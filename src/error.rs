@@ -0,0 +1,314 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use espeak_rs_sys::*;
+use std::ffi::CStr;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+/// Errors reported by this crate.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    any(feature = "serde", feature = "subprocess-pool"),
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[non_exhaustive]
+pub enum EspeakError {
+    /// `espeak_Initialize` failed even though its data was found. Holds
+    /// the value it returned.
+    InitFailed(i32),
+    /// espeak-ng's data directory wasn't found in any of the places espeak
+    /// looks. Setting `ESPEAK_DATA_PATH` to the directory containing
+    /// `espeak-ng-data`, or calling [`set_data_path`](crate::set_data_path),
+    /// tells it where to look.
+    DataNotFound { searched_paths: Vec<PathBuf> },
+    /// The text to speak contains a NUL byte, which espeak can't accept.
+    InvalidText { nul_position: usize },
+    /// The voice name contains a NUL byte.
+    InvalidVoiceName { nul_position: usize },
+    /// espeak couldn't find or load the named voice.
+    VoiceNotFound { name: String },
+    /// The text is longer than the per-call budget and has no point where
+    /// it can be split, e.g. SSML wrapped in a single element.
+    TextTooLong { len: usize, budget: usize },
+    /// espeak's internal buffers are full.
+    BufferFull,
+    /// espeak returned an unexpected error code.
+    Internal(i32),
+    /// A [`SpeakerParams`](crate::SpeakerParams) field is outside the
+    /// range espeak accepts.
+    ParamOutOfRange {
+        #[cfg_attr(
+            any(feature = "serde", feature = "subprocess-pool"),
+            serde(deserialize_with = "static_name")
+        )]
+        name: ParamName,
+        value: i32,
+        range: RangeInclusive<i32>,
+    },
+    /// The synthesis thread panicked, with the given message.
+    SynthesisPanicked { message: String },
+    /// The user voice directories couldn't be merged with espeak's data
+    /// in `path`.
+    VoiceDirectory { path: PathBuf, message: String },
+    /// espeak produced no audio for non-empty text, even after
+    /// [`SpeakerParams::retry_on_empty`](crate::SpeakerParams::retry_on_empty)
+    /// tried again.
+    SilentOutput,
+    /// espeak refused some params and
+    /// [`SpeakerParams::strict_params`](crate::SpeakerParams::strict_params)
+    /// is set.
+    ParamsRejected(Vec<ParamApplyError>),
+    /// The utterance was cancelled with a
+    /// [`CancelToken`](crate::CancelToken).
+    Cancelled,
+    /// [`compile_dictionary`](crate::compile_dictionary) can't write to
+    /// the data directory `path`.
+    DataNotWritable { path: PathBuf, message: String },
+    /// The data directory `path` doesn't have the `needed` bytes free
+    /// that compiling a dictionary may take.
+    InsufficientSpace { path: PathBuf, needed: u64 },
+    /// espeak couldn't compile the dictionary `name`.
+    DictionaryNotCompiled { name: String, message: String },
+    /// Buffers given to [`mix`](crate::mix) have different sample rates.
+    SampleRateMismatch { a: u32, b: u32 },
+    /// espeak can't take `c` as a single character on this platform,
+    /// where its `wchar_t` has 16 bits.
+    UnsupportedChar { c: char },
+    /// The WAV file `path` couldn't be read or written, or isn't 16-bit
+    /// mono PCM with the header
+    /// [`AudioBuffer::save_wav`](crate::AudioBuffer::save_wav) writes.
+    AudioFile { path: PathBuf, message: String },
+    /// There is no default audio output to play to, or it couldn't be
+    /// opened.
+    #[cfg(feature = "playback")]
+    NoOutputDevice { message: String },
+    /// The audio output was opened but couldn't be played to.
+    #[cfg(feature = "playback")]
+    PlaybackFailed { message: String },
+    /// Data couldn't be downloaded from `url` by
+    /// [`ensure_languages`](crate::data_manager::ensure_languages).
+    #[cfg(feature = "online")]
+    DataDownload { url: String, message: String },
+    /// Downloaded data didn't match its signature or checksum. `what` is
+    /// the file's path, or the manifest.
+    #[cfg(feature = "online")]
+    DataVerification { what: String, message: String },
+}
+
+/// A param espeak refused to set.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    any(feature = "serde", feature = "subprocess-pool"),
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct ParamApplyError {
+    /// The [`SpeakerParams`](crate::SpeakerParams) field.
+    #[cfg_attr(
+        any(feature = "serde", feature = "subprocess-pool"),
+        serde(deserialize_with = "static_name")
+    )]
+    pub param: ParamName,
+    /// The value it was to be set to.
+    pub value: i32,
+    /// What `espeak_SetParameter` returned.
+    pub status: EspeakError,
+}
+
+impl fmt::Display for ParamApplyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} of {}: {}", self.param, self.value, self.status)
+    }
+}
+
+impl fmt::Display for EspeakError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EspeakError::InitFailed(code) => write!(f, "espeak failed to initialize ({})", code),
+            EspeakError::DataNotFound { searched_paths } => {
+                write!(f, "espeak-ng-data not found; searched")?;
+                for path in searched_paths {
+                    write!(f, " {}", path.display())?;
+                }
+                Ok(())
+            }
+            EspeakError::InvalidText { nul_position } => {
+                write!(f, "text contains a NUL byte at position {}", nul_position)
+            }
+            EspeakError::InvalidVoiceName { nul_position } => {
+                write!(
+                    f,
+                    "voice name contains a NUL byte at position {}",
+                    nul_position
+                )
+            }
+            EspeakError::VoiceNotFound { name } => write!(f, "voice {:?} not found", name),
+            EspeakError::TextTooLong { len, budget } => write!(
+                f,
+                "text of {} bytes can't be split into pieces of {} bytes",
+                len, budget
+            ),
+            EspeakError::BufferFull => write!(f, "espeak's buffers are full"),
+            EspeakError::Internal(code) => write!(f, "espeak error {}", code),
+            EspeakError::ParamOutOfRange { name, value, range } => write!(
+                f,
+                "{} of {} is outside {}..={}",
+                name,
+                value,
+                range.start(),
+                range.end()
+            ),
+            EspeakError::SynthesisPanicked { message } => {
+                write!(f, "synthesis thread panicked: {}", message)
+            }
+            EspeakError::VoiceDirectory { path, message } => write!(
+                f,
+                "can't merge user voices into {}: {}",
+                path.display(),
+                message
+            ),
+            EspeakError::SilentOutput => write!(f, "espeak produced no audio"),
+            EspeakError::Cancelled => write!(f, "utterance cancelled"),
+            EspeakError::DataNotWritable { path, message } => {
+                write!(f, "can't write to {}: {}", path.display(), message)
+            }
+            EspeakError::InsufficientSpace { path, needed } => {
+                write!(f, "{} has less than {} bytes free", path.display(), needed)
+            }
+            EspeakError::DictionaryNotCompiled { name, message } => {
+                write!(f, "dictionary {:?} not compiled: {}", name, message)
+            }
+            EspeakError::SampleRateMismatch { a, b } => {
+                write!(f, "can't mix audio at {} Hz and {} Hz", a, b)
+            }
+            EspeakError::UnsupportedChar { c } => {
+                write!(f, "espeak can't take {:?} as a single character", c)
+            }
+            EspeakError::AudioFile { path, message } => {
+                write!(f, "audio file {}: {}", path.display(), message)
+            }
+            #[cfg(feature = "playback")]
+            EspeakError::NoOutputDevice { message } => {
+                write!(f, "no audio output device: {}", message)
+            }
+            #[cfg(feature = "playback")]
+            EspeakError::PlaybackFailed { message } => write!(f, "can't play audio: {}", message),
+            #[cfg(feature = "online")]
+            EspeakError::DataDownload { url, message } => {
+                write!(f, "can't download {}: {}", url, message)
+            }
+            #[cfg(feature = "online")]
+            EspeakError::DataVerification { what, message } => {
+                write!(f, "{} failed verification: {}", what, message)
+            }
+            EspeakError::ParamsRejected(errors) => {
+                write!(f, "espeak refused params:")?;
+                for error in errors {
+                    write!(f, " {};", error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for EspeakError {}
+
+/// The name of a param. Spelled through an alias so that serde's derive
+/// doesn't borrow it from the input, which would only deserialize from
+/// `'static` input; `static_name` picks it from `PARAM_NAMES` instead.
+type ParamName = &'static str;
+
+/// Every name [`EspeakError::ParamOutOfRange`] and [`ParamApplyError`]
+/// give a param by.
+#[cfg(any(feature = "serde", feature = "subprocess-pool"))]
+const PARAM_NAMES: &[&str] = &[
+    "rate",
+    "volume",
+    "pitch",
+    "range",
+    "punctuation",
+    "punctuation_chars",
+    "capitals",
+    "word_gap",
+    "line_length",
+    "raw_params",
+];
+
+/// Deserialize the name of a param as the `&'static str` errors hold.
+#[cfg(any(feature = "serde", feature = "subprocess-pool"))]
+fn static_name<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<&'static str, D::Error> {
+    use serde::de::Error;
+    use serde::Deserialize;
+
+    let name = String::deserialize(deserializer)?;
+    PARAM_NAMES
+        .iter()
+        .find(|known| **known == name)
+        .copied()
+        .ok_or_else(|| D::Error::unknown_variant(&name, PARAM_NAMES))
+}
+
+/// Map an `espeak_ERROR` return code. Every call into espeak that reports
+/// one should go through here, or [`check_voice`] for voice selection.
+#[allow(non_upper_case_globals)]
+pub(crate) fn check(code: espeak_ERROR) -> Result<(), EspeakError> {
+    match code {
+        espeak_ERROR_EE_OK => Ok(()),
+        espeak_ERROR_EE_BUFFER_FULL => Err(EspeakError::BufferFull),
+        code => Err(EspeakError::Internal(code)),
+    }
+}
+
+/// Like [`check`], for the result of selecting the voice `name`.
+#[allow(non_upper_case_globals)]
+pub(crate) fn check_voice(code: espeak_ERROR, name: &CStr) -> Result<(), EspeakError> {
+    match code {
+        espeak_ERROR_EE_NOT_FOUND => Err(EspeakError::VoiceNotFound {
+            name: name.to_string_lossy().into_owned(),
+        }),
+        code => check(code),
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn param_names_round_trip() {
+        for name in PARAM_NAMES {
+            let error = EspeakError::ParamsRejected(vec![ParamApplyError {
+                param: name,
+                value: 7,
+                status: EspeakError::Internal(-1),
+            }]);
+            let json = serde_json::to_string(&error).unwrap();
+            assert_eq!(serde_json::from_str::<EspeakError>(&json).unwrap(), error);
+        }
+        let unknown =
+            r#"{"ParamOutOfRange":{"name":"speed","value":1,"range":{"start":0,"end":9}}}"#;
+        assert!(serde_json::from_str::<EspeakError>(unknown).is_err());
+    }
+}
@@ -0,0 +1,152 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Reading strings out of the `id` union of an `espeak_EVENT`.
+//!
+//! Mark and play events name something with `id.name`, and phoneme events
+//! spell the mnemonic in `id.string`. Depending on the espeak-ng headers,
+//! such a field is either a pointer to a string espeak owns, valid only
+//! during the callback, or a fixed array inside the event. Which
+//! [`EventString`] impl applies follows from the type bindgen generated,
+//! so the choice is made by the compiler: a header declaring anything else
+//! fails to build here instead of reading garbage. Either way the string is
+//! copied before the callback returns.
+
+use espeak_rs_sys::*;
+use std::ffi::CStr;
+use std::mem::size_of;
+use std::os::raw::c_char;
+
+// The union holds an int, a string pointer and an 8 byte array. A header
+// adding a larger member changes its size, and needs looking at.
+const _: () = assert!(
+    size_of::<espeak_EVENT__bindgen_ty_1>()
+        == if size_of::<*const c_char>() > 8 {
+            size_of::<*const c_char>()
+        } else {
+            8
+        }
+);
+
+/// A string field of the event union, in one of its representations.
+pub(crate) trait EventString {
+    /// Copy the string out. `None` if there is no string.
+    ///
+    /// # Safety
+    ///
+    /// A pointer must be null or point to a NUL-terminated string.
+    unsafe fn to_owned_string(&self) -> Option<String>;
+}
+
+impl EventString for *const c_char {
+    unsafe fn to_owned_string(&self) -> Option<String> {
+        if self.is_null() {
+            return None;
+        }
+        Some(CStr::from_ptr(*self).to_string_lossy().into_owned())
+    }
+}
+
+/// The array is NUL-terminated only when the string is shorter than it.
+impl<const N: usize> EventString for [c_char; N] {
+    unsafe fn to_owned_string(&self) -> Option<String> {
+        let bytes: Vec<u8> = self
+            .iter()
+            .map(|c| *c as u8)
+            .take_while(|b| *b != 0)
+            .collect();
+        Some(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+/// The name of a mark or play event.
+///
+/// # Safety
+///
+/// `event` must come from espeak, during the callback that reported it.
+pub(crate) unsafe fn event_name(event: &espeak_EVENT) -> Option<String> {
+    event.id.name.to_owned_string()
+}
+
+/// The mnemonic of a phoneme event.
+///
+/// # Safety
+///
+/// As for [`event_name`].
+pub(crate) unsafe fn event_mnemonic(event: &espeak_EVENT) -> String {
+    event.id.string.to_owned_string().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(bytes: &[u8]) -> Vec<c_char> {
+        bytes.iter().map(|b| *b as c_char).collect()
+    }
+
+    #[test]
+    fn pointer_strings() {
+        let name = chars(b"mid\xff\0");
+        let pointer = name.as_ptr();
+        assert_eq!(
+            unsafe { pointer.to_owned_string() },
+            Some(String::from("mid\u{fffd}"))
+        );
+        let null: *const c_char = std::ptr::null();
+        assert_eq!(unsafe { null.to_owned_string() }, None);
+    }
+
+    #[test]
+    fn array_strings() {
+        let mut array = [0 as c_char; 8];
+        array[..2].copy_from_slice(&chars(b"@U"));
+        assert_eq!(unsafe { array.to_owned_string() }, Some(String::from("@U")));
+
+        // A string filling the array has no terminator.
+        let full: [c_char; 8] = chars(b"abcdefgh").try_into().unwrap();
+        assert_eq!(
+            unsafe { full.to_owned_string() },
+            Some(String::from("abcdefgh"))
+        );
+        assert_eq!(
+            unsafe { [0 as c_char; 4].to_owned_string() },
+            Some(String::new())
+        );
+    }
+
+    #[test]
+    fn union_fields() {
+        let mut event: espeak_EVENT = unsafe { std::mem::zeroed() };
+        assert_eq!(unsafe { event_name(&event) }, None);
+        assert_eq!(unsafe { event_mnemonic(&event) }, "");
+
+        event.id.string = chars(b"h@\0\0\0\0\0\0").try_into().unwrap();
+        assert_eq!(unsafe { event_mnemonic(&event) }, "h@");
+
+        let name = c"intro.wav";
+        event.id.name = name.as_ptr();
+        assert_eq!(
+            unsafe { event_name(&event) },
+            Some(String::from("intro.wav"))
+        );
+    }
+}
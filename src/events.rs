@@ -0,0 +1,167 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Storage for events that have been received but not yet dispatched.
+//!
+//! espeak reports event positions as `u32` milliseconds from the start of
+//! the current utterance. They are turned into `u64` milliseconds from the
+//! start of the source, so positions keep increasing across utterance
+//! boundaries and past the point where a `u32` would wrap.
+
+use crate::Event;
+use std::collections::VecDeque;
+
+/// Capacity the queue is allowed to keep around once it has drained.
+const RETAINED_CAPACITY: usize = 64;
+
+pub(crate) struct EventQueue {
+    sample_rate: u32,
+    events: VecDeque<(u64, Event)>,
+    /// Absolute position of the current utterance's start.
+    base_ms: u64,
+    /// Last position espeak reported within the current utterance.
+    last_ms: u32,
+    samples_received: u64,
+    started: bool,
+}
+
+impl EventQueue {
+    pub(crate) fn new(sample_rate: u32) -> EventQueue {
+        EventQueue {
+            sample_rate,
+            events: VecDeque::new(),
+            base_ms: 0,
+            last_ms: 0,
+            samples_received: 0,
+            started: false,
+        }
+    }
+
+    /// Queue the events of a chunk, followed by `samples` samples of audio.
+    pub(crate) fn push_chunk(&mut self, events: Vec<(u32, Event)>, samples: usize) {
+        for (audio_position, event) in events {
+            let position = self.absolute_position(audio_position, &event);
+            self.events.push_back((position, event));
+        }
+        self.samples_received += samples as u64;
+    }
+
+    fn absolute_position(&mut self, audio_position: u32, event: &Event) -> u64 {
+        if let Event::Start = event {
+            // A new utterance restarts espeak's positions at zero.
+            if self.started {
+                self.base_ms = self.samples_received * 1000 / self.sample_rate as u64;
+            }
+            self.started = true;
+        } else if audio_position < self.last_ms && self.last_ms - audio_position > u32::MAX / 2 {
+            // Positions only go forward within an utterance, so a large
+            // step backwards means the u32 wrapped around.
+            self.base_ms += 1 << 32;
+        }
+        self.last_ms = audio_position;
+        self.base_ms + audio_position as u64
+    }
+
+    /// Remove and return the events due at or before the given sample,
+    /// along with their positions in milliseconds.
+    pub(crate) fn pop_due(&mut self, sample_index: u64) -> Vec<(u64, Event)> {
+        let mut due = Vec::<(u64, Event)>::new();
+        while let Some((position, _)) = self.events.front() {
+            let at_sample = position * self.sample_rate as u64 / 1000;
+            if at_sample > sample_index {
+                break;
+            }
+            if let Some(event) = self.events.pop_front() {
+                due.push(event);
+            }
+        }
+        if !due.is_empty() && self.events.capacity() > RETAINED_CAPACITY * 4 {
+            self.events
+                .shrink_to(RETAINED_CAPACITY.max(self.events.len() * 2));
+        }
+        due
+    }
+
+    /// Remove and return all remaining events, for when the audio has
+    /// ended before reaching them.
+    pub(crate) fn drain(&mut self) -> Vec<(u64, Event)> {
+        self.events.drain(..).collect()
+    }
+
+    #[cfg(test)]
+    fn capacity(&self) -> usize {
+        self.events.capacity()
+    }
+
+    #[cfg(test)]
+    fn positions(&self) -> Vec<u64> {
+        self.events.iter().map(|(position, _)| *position).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn storage_does_not_grow() {
+        // One sample per millisecond keeps the arithmetic easy to follow.
+        let mut queue = EventQueue::new(1000);
+        queue.push_chunk(vec![(0, Event::Start)], 0);
+        assert_eq!(queue.pop_due(0), vec![(0, Event::Start)]);
+        let mut sample_index = 0u64;
+        let mut peak_capacity = 0usize;
+        for i in 0..100_000u32 {
+            let events = vec![(i * 10, Event::Word(0, 1)), (i * 10 + 5, Event::Word(2, 1))];
+            queue.push_chunk(events, 10);
+            sample_index += 10;
+            assert_eq!(queue.pop_due(sample_index).len(), 2);
+            peak_capacity = peak_capacity.max(queue.capacity());
+        }
+        assert!(peak_capacity <= RETAINED_CAPACITY * 4);
+    }
+
+    #[test]
+    fn positions_survive_wraparound() {
+        let mut queue = EventQueue::new(1000);
+        let near_end = u32::MAX - 10;
+        queue.push_chunk(vec![(0, Event::Start)], 0);
+        queue.push_chunk(vec![(near_end, Event::Sentence { start: 0, len: 0 })], 0);
+        queue.push_chunk(vec![(5, Event::Word(0, 1))], 0);
+        queue.push_chunk(vec![(20, Event::Word(2, 1))], 0);
+        assert_eq!(
+            queue.positions(),
+            vec![0, near_end as u64, (1 << 32) + 5, (1 << 32) + 20]
+        );
+
+        assert_eq!(queue.pop_due(near_end as u64).len(), 2);
+        assert!(queue.pop_due(u32::MAX as u64).is_empty());
+        assert_eq!(queue.pop_due((1 << 32) + 20).len(), 2);
+    }
+
+    #[test]
+    fn utterance_boundary_resets_base() {
+        let mut queue = EventQueue::new(1000);
+        queue.push_chunk(vec![(0, Event::Start), (300, Event::Word(0, 1))], 500);
+        queue.push_chunk(vec![(0, Event::Start), (100, Event::Word(0, 1))], 500);
+        assert_eq!(queue.positions(), vec![0, 300, 500, 600]);
+    }
+}
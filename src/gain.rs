@@ -0,0 +1,97 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Caller-controlled gain, e.g. for ducking speech under music.
+
+use crate::SpeakerSource;
+use rodio::Source;
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
+
+/// How long a gain change takes to fully apply. Ramping rather than
+/// jumping avoids audible "zipper" noise.
+pub const GAIN_RAMP: Duration = Duration::from_millis(10);
+
+/// A source whose gain follows the latest value received on a channel.
+/// Created with [`SpeakerSource::with_gain_control`].
+pub struct GainControlledSource {
+    inner: SpeakerSource,
+    rx: Receiver<f32>,
+    target: f32,
+    current: f32,
+    /// Largest gain change allowed between two samples.
+    step: f32,
+}
+
+impl GainControlledSource {
+    pub(crate) fn new(inner: SpeakerSource, rx: Receiver<f32>) -> GainControlledSource {
+        let ramp_samples = inner.sample_rate() as f32 * GAIN_RAMP.as_secs_f32();
+        GainControlledSource {
+            inner,
+            rx,
+            target: 1.0,
+            current: 1.0,
+            step: 1.0 / ramp_samples.max(1.0),
+        }
+    }
+
+    /// The gain applied to the most recent sample.
+    pub fn current_gain(&self) -> f32 {
+        self.current
+    }
+}
+
+impl Source for GainControlledSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+impl Iterator for GainControlledSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        while let Ok(gain) = self.rx.try_recv() {
+            self.target = gain.clamp(0.0, 1.0);
+        }
+        let sample = self.inner.next()?;
+
+        let delta = self.target - self.current;
+        self.current += delta.clamp(-self.step, self.step);
+        Some((sample as f32 * self.current).round() as i16)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
@@ -0,0 +1,314 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use crate::transform::transform;
+use crate::{espeak_version, ParamValue, Speaker, TextMode};
+use std::sync::OnceLock;
+
+/// Bumped whenever the serialization below changes, so old keys can't
+/// collide with new ones.
+const KEY_FORMAT: &str = "espeak-rs synthesis key 1";
+
+/// A key for caching what `speaker` would produce for `text`: the audio
+/// and the events.
+///
+/// The key covers the text as handed to espeak along with the text the
+/// events refer to, the voice name, the text mode, the `espeak_Synth`
+/// flags, every parameter that reaches espeak and the versions of this
+/// crate and the linked espeak-ng. It changes when, and only when, any of
+/// them does. Params that only pace synthesis, like
+/// [`SpeakerParams::channel_capacity`](crate::SpeakerParams::channel_capacity)
+/// and [`SpeakerParams::synth_ahead`](crate::SpeakerParams::synth_ahead),
+/// are left out.
+///
+/// An unset parameter keys differently from one set to espeak's default,
+/// though both sound the same. Voices are identified by name, so editing a
+/// voice file in place isn't noticed.
+pub fn synthesis_key(speaker: &Speaker, text: &str) -> [u8; 32] {
+    static ESPEAK_VERSION: OnceLock<String> = OnceLock::new();
+    let params = speaker.current_params();
+    let text_mode = TextMode::from_params(&params);
+    let report = transform(text, &speaker.voice_name, &params, text_mode);
+    #[cfg(feature = "test-tone")]
+    let test_tone = params.test_tone_mode;
+    #[cfg(not(feature = "test-tone"))]
+    let test_tone = false;
+
+    let mut key = KeyWriter::default();
+    key.str("format", KEY_FORMAT);
+    key.str("crate", env!("CARGO_PKG_VERSION"));
+    key.str("espeak", ESPEAK_VERSION.get_or_init(espeak_version));
+    key.str("voice", &speaker.voice_name);
+    key.flag("ssml", text_mode == TextMode::Ssml);
+    key.int("flags", Some(params.synth_flags(text_mode) as i64));
+    key.param("rate", params.rate);
+    key.param("volume", params.volume);
+    key.param("pitch", params.pitch);
+    key.param("range", params.range);
+    key.int(
+        "punctuation",
+        params.punctuation.map(|p| i64::from(p.as_raw())),
+    );
+    if let Some(chars) = &params.punctuation_chars {
+        key.str("punctuation_chars", chars);
+    }
+    key.int("capitals", params.capitals.map(|c| i64::from(c.as_raw())));
+    key.int("word_gap", params.word_gap.map(i64::from));
+    key.int("line_length", params.line_length.map(i64::from));
+    for &(param, value, relative) in &params.raw_params {
+        key.int("raw_param", Some(i64::from(param)));
+        key.int("raw_value", Some(i64::from(value)));
+        key.flag("raw_relative", relative);
+    }
+    key.int("synth_text_budget", Some(params.synth_text_budget as i64));
+    key.flag("phoneme_events", params.phoneme_events);
+    key.flag("strict_params", params.strict_params);
+    key.flag("retry_on_empty", params.retry_on_empty);
+    key.flag("test_tone", test_tone);
+    key.str("text", text);
+    key.str("effective_text", &report.effective_text);
+    key.finish()
+}
+
+/// Writes named fields unambiguously: every field is tagged with its name
+/// and type, and strings are length-prefixed, so no two different sets of
+/// inputs serialize the same way.
+#[derive(Default)]
+struct KeyWriter {
+    hasher: Sha256,
+}
+
+impl KeyWriter {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.hasher.update(&(bytes.len() as u64).to_le_bytes());
+        self.hasher.update(bytes);
+    }
+
+    fn str(&mut self, name: &str, value: &str) {
+        self.bytes(name.as_bytes());
+        self.hasher.update(b"s");
+        self.bytes(value.as_bytes());
+    }
+
+    fn int(&mut self, name: &str, value: Option<i64>) {
+        self.bytes(name.as_bytes());
+        match value {
+            Some(value) => {
+                self.hasher.update(b"i");
+                self.hasher.update(&value.to_le_bytes());
+            }
+            None => self.hasher.update(b"n"),
+        }
+    }
+
+    /// A relative value tags differently from an absolute one, which
+    /// hashes like any int.
+    fn param(&mut self, name: &str, value: Option<ParamValue>) {
+        match value {
+            Some(ParamValue::Relative(percent)) => {
+                self.bytes(name.as_bytes());
+                self.hasher.update(b"%");
+                self.hasher.update(&i64::from(percent).to_le_bytes());
+            }
+            Some(ParamValue::Absolute(value)) => self.int(name, Some(i64::from(value))),
+            None => self.int(name, None),
+        }
+    }
+
+    fn flag(&mut self, name: &str, value: bool) {
+        self.bytes(name.as_bytes());
+        self.hasher.update(if value { b"t" } else { b"f" });
+    }
+
+    fn finish(self) -> [u8; 32] {
+        self.hasher.finish()
+    }
+}
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// A plain SHA-256, to avoid a dependency for hashing a few hundred bytes.
+struct Sha256 {
+    state: [u32; 8],
+    block: Vec<u8>,
+    len: u64,
+}
+
+impl Default for Sha256 {
+    fn default() -> Sha256 {
+        Sha256 {
+            state: [
+                0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+                0x5be0cd19,
+            ],
+            block: Vec::with_capacity(64),
+            len: 0,
+        }
+    }
+}
+
+impl Sha256 {
+    fn update(&mut self, mut data: &[u8]) {
+        self.len += data.len() as u64;
+        while !data.is_empty() {
+            let take = (64 - self.block.len()).min(data.len());
+            self.block.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.block.len() == 64 {
+                self.compress();
+            }
+        }
+    }
+
+    fn compress(&mut self) {
+        let mut w = [0u32; 64];
+        for (i, word) in self.block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *state = state.wrapping_add(value);
+        }
+        self.block.clear();
+    }
+
+    fn finish(mut self) -> [u8; 32] {
+        let bits = self.len * 8;
+        self.block.push(0x80);
+        if self.block.len() > 56 {
+            self.block.resize(64, 0);
+            self.compress();
+        }
+        self.block.resize(56, 0);
+        self.block.extend_from_slice(&bits.to_be_bytes());
+        self.compress();
+        let mut digest = [0u8; 32];
+        for (bytes, word) in digest.chunks_exact_mut(4).zip(self.state) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(digest: [u8; 32]) -> String {
+        digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    fn sha256(data: &[u8]) -> String {
+        let mut hasher = Sha256::default();
+        hasher.update(data);
+        hex(hasher.finish())
+    }
+
+    #[test]
+    fn sha256_matches_known_digests() {
+        assert_eq!(
+            sha256(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            sha256(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        let mut hasher = Sha256::default();
+        for _ in 0..1000 {
+            hasher.update(&[b'a'; 1000]);
+        }
+        assert_eq!(
+            hex(hasher.finish()),
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+        );
+    }
+
+    fn key(write: impl FnOnce(&mut KeyWriter)) -> [u8; 32] {
+        let mut key = KeyWriter::default();
+        write(&mut key);
+        key.finish()
+    }
+
+    #[test]
+    fn fields_are_unambiguous() {
+        assert_ne!(
+            key(|k| k.int("rate", None)),
+            key(|k| k.int("rate", Some(0)))
+        );
+        assert_ne!(
+            key(|k| k.int("rate", Some(1))),
+            key(|k| k.int("pitch", Some(1)))
+        );
+        assert_ne!(
+            key(|k| {
+                k.str("a", "bc");
+                k.str("d", "");
+            }),
+            key(|k| {
+                k.str("a", "b");
+                k.str("cd", "");
+            })
+        );
+        assert_eq!(key(|k| k.flag("ssml", true)), key(|k| k.flag("ssml", true)));
+    }
+}
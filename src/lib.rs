@@ -28,7 +28,7 @@
 //! ```no_run
 //! use rodio::{OutputStream, Sink};
 //!
-//! let speaker = espeaking::Speaker::new();
+//! let speaker = espeak_rs::Speaker::new();
 //! let source = speaker.speak("Hello, world!");
 //! let (_stream, stream_handle) = OutputStream::try_default().unwrap();
 //! let sink = Sink::try_new(&stream_handle).unwrap();
@@ -36,69 +36,430 @@
 //! sink.sleep_until_end();
 //! ```
 //!
-//! You can tweak the speaker's parameters via [`Speaker::params`].
-//! Each change will only affect the given speaker. This is unlike
-//! eSpeak NG's API where a parameter change is global:
+//! Configure a speaker with [`Speaker::builder`], which checks the
+//! parameters and the voice when it builds the speaker. The configuration
+//! only affects the given speaker. This is unlike eSpeak NG's API where a
+//! parameter change is global:
 //! ```no_run
-//! let mut speaker = espeaking::Speaker::new();
-//! speaker.params.pitch = Some(400);
-//! speaker.params.rate = Some(80);
+//! let speaker = espeak_rs::Speaker::builder()
+//!     .voice_name("en-GB")
+//!     .pitch(60)
+//!     .rate(espeak_rs::Rate::wpm(180).unwrap())
+//!     .build()
+//!     .unwrap();
 //! ```
 //!
+//! The parameters can also be changed later via [`Speaker::params`],
+//! unchecked until the speaker speaks.
+//!
+//! The public enums, such as [`Event`], [`EspeakError`] and [`Gender`],
+//! are `#[non_exhaustive]`, and so is [`SpeakerParams`], so that new
+//! events, errors and params can be added in minor releases. The one
+//! exception is [`viseme::Viseme`], the fixed set of mouth shapes avatars
+//! are animated with, which a `match` may cover exactly. Otherwise a
+//! `match` needs a wildcard arm:
+//! ```
+//! use espeak_rs::Event;
+//!
+//! fn is_boundary(event: &Event) -> bool {
+//!     match event {
+//!         Event::Word(..) | Event::Sentence { .. } => true,
+//!         _ => false,
+//!     }
+//! }
+//! ```
+//! without which it doesn't compile:
+//! ```compile_fail,E0004
+//! fn name(gender: espeak_rs::Gender) -> &'static str {
+//!     match gender {
+//!         espeak_rs::Gender::Female => "female",
+//!         espeak_rs::Gender::Male => "male",
+//!         espeak_rs::Gender::NonBinary => "non-binary",
+//!     }
+//! }
+//! ```
+//! and params are built with methods rather than as a struct literal:
+//! ```compile_fail,E0639
+//! let params = espeak_rs::SpeakerParams {
+//!     rate: None,
+//!     ..Default::default()
+//! };
+//! ```
+//!
+//! espeak synthesizes one utterance at a time, so utterances from all
+//! speakers and threads take turns, in the order they were spoken.
+//!
 //! This library also supports callbacks that can be used when certain
 //! speech landmarks like words or sentences are spoken.
 //! Use the [`SpeakerSource::with_callback`] method to create a new source
 //! that dispatches the callback:
 //! ```no_run
-//! let mut speaker = espeaking::Speaker::new();
-//! speaker.params.rate = Some(280);
+//! let mut speaker = espeak_rs::Speaker::new();
+//! speaker.params.rate = Some(280.into());
 //! let source = speaker.speak("Hello world, goodbye!");
 //! let source = source.with_callback(move |evt| match evt {
-//!     espeaking::Event::Word(start, _len) => {
+//!     espeak_rs::Event::Word(start, _len) => {
 //!         println!("'Word at {}'", start);
 //!     }
-//!     espeaking::Event::Sentence(_) => (),
-//!     espeaking::Event::Start => {
+//!     espeak_rs::Event::Sentence { .. } => (),
+//!     espeak_rs::Event::Start => {
 //!         println!("'Start!")
 //!     }
-//!     espeaking::Event::End => {
+//!     espeak_rs::Event::End => {
 //!         println!("'End!");
 //!     }
+//!     _ => (),
 //! });
 //! ```
+//!
+//! Audio post-processing and analysis, such as gain control, loudness
+//! normalization, WAV encoding and `SpeakerSource::duration_breakdown`, are
+//! behind the `dsp` feature, which is on by default. Build with
+//! `--no-default-features --features core` for just speakers, sources,
+//! events and voices.
+#![cfg_attr(
+    not(feature = "dsp"),
+    doc = "```compile_fail\nuse espeak_rs::GainControlledSource;\n```",
+    doc = "```compile_fail\nuse espeak_rs::WavChunkStream;\n```",
+    doc = "```compile_fail\nuse espeak_rs::compare_voices;\n```",
+    doc = "```compile_fail\nlet source = espeak_rs::Speaker::new().speak(\"\");\nsource.duration_breakdown();\n```"
+)]
 
 use espeak_rs_sys::*;
-use lazy_static::lazy_static;
 use rodio::Source;
+use std::cell::OnceCell;
+use std::collections::{BTreeMap, VecDeque};
 use std::ffi::{c_void, CStr, CString};
+use std::fmt;
+use std::ops::{ControlFlow, RangeInclusive};
 use std::os::raw::{c_char, c_int, c_short};
-use std::sync::mpsc::{channel, Receiver, Sender};
-use std::sync::{Mutex, MutexGuard};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::thread;
 use std::time::Duration;
 
-lazy_static! {
-    static ref ESPEAK_INIT: Mutex<u32> = Mutex::new(0);
+mod broadcast;
+mod cancel;
+mod caps;
+mod channel;
+#[cfg(feature = "dsp")]
+mod compare;
+#[cfg(feature = "corpus-tools")]
+pub mod corpus;
+#[cfg(feature = "online")]
+pub mod data_manager;
+mod data_path;
+mod defaults;
+mod dictionary;
+mod digits;
+mod error;
+mod event_id;
+mod events;
+#[cfg(feature = "dsp")]
+mod gain;
+mod key;
+#[cfg(feature = "dsp")]
+mod loudness;
+#[cfg(feature = "dsp")]
+mod mix;
+#[cfg(feature = "mmap")]
+mod mmap;
+mod offsets;
+mod phonemes;
+#[cfg(feature = "playback")]
+pub mod playback;
+#[cfg(feature = "subprocess-pool")]
+pub mod pool;
+mod positions;
+pub mod prelude;
+mod pronunciations;
+mod punctuation;
+mod recording;
+mod ring;
+pub mod schedule;
+mod sentences;
+#[cfg(feature = "dsp")]
+mod silence;
+mod snapshot;
+pub mod spelling;
+mod split;
+mod ssml;
+mod stream;
+mod table;
+mod text_map;
+#[cfg(feature = "test-tone")]
+mod tone;
+mod transform;
+mod turns;
+mod urls;
+mod user_voices;
+pub mod viseme;
+#[cfg(feature = "dsp")]
+mod wav;
+mod word_stats;
+
+use broadcast::Broadcaster;
+pub use broadcast::{EventBus, EventReceiver, DEFAULT_SUBSCRIBER_CAPACITY};
+pub use cancel::CancelToken;
+use cancel::Job;
+pub use caps::{CapsPolicy, DEFAULT_ACRONYMS};
+pub use channel::BacklogStats;
+use channel::{chunk_channel, Backlog, Chunk, ChunkSender, Message};
+#[cfg(feature = "dsp")]
+pub use compare::{compare_voices, try_compare_voices, AudioBuffer};
+pub use data_path::{resolve_data_path, set_data_path, MissingDataPath, PathSource};
+pub use defaults::{global_defaults, GlobalDefaults};
+pub use dictionary::compile_dictionary;
+pub use error::{EspeakError, ParamApplyError};
+use event_id::{event_mnemonic, event_name};
+use events::EventQueue;
+#[cfg(feature = "dsp")]
+pub use gain::{GainControlledSource, GAIN_RAMP};
+pub use key::synthesis_key;
+#[cfg(feature = "dsp")]
+pub use loudness::{normalize_loudness_batch, DEFAULT_LOUDNESS_TARGET, LIMITER_CEILING};
+#[cfg(feature = "dsp")]
+pub use mix::mix;
+#[cfg(feature = "mmap")]
+pub use mmap::MappedAudio;
+use offsets::OffsetCorrector;
+use positions::PositionIndex;
+pub use positions::WordPosition;
+pub use punctuation::{PunctuationEdit, PunctuationRepair, DEFAULT_CONJUNCTIONS};
+pub use recording::{EventRecording, Replay};
+pub use ring::{RingConsumer, RingProducerHandle};
+use sentences::{sentence_len, split_sentences};
+#[cfg(feature = "dsp")]
+pub use silence::{DurationBreakdown, SILENCE_THRESHOLD_DB, SILENCE_WINDOW};
+pub use snapshot::{debug_snapshot, ParameterSnapshot, StateSnapshot};
+use split::split_text;
+pub use split::DEFAULT_SYNTH_TEXT_BUDGET;
+pub use ssml::{probe_ssml, sanitize_ssml, ssml_capabilities, RemovedTag, SsmlCapabilities};
+pub use stream::STREAM_FLUSH_TIMEOUT;
+use table::cell_event;
+pub use table::{TableSpeech, DEFAULT_CELL_TEMPLATE};
+use text_map::TextMap;
+use transform::transform;
+pub use transform::{dry_run_transform, OffsetMap, TransformReport};
+use turns::Turn;
+pub use urls::UrlPolicy;
+pub use user_voices::{add_user_voice_dir, reload_voices, user_voice_warnings};
+#[cfg(feature = "dsp")]
+pub use wav::WavChunkStream;
+pub use word_stats::WordStat;
+
+/// The rodio this crate was built against. Using its types rather than
+/// depending on rodio separately avoids mixing two versions of `Source`.
+pub use rodio;
+
+/// State of the process-wide espeak instance, `None` until initialized.
+pub(crate) struct InitState {
+    sample_rate: u32,
+    output_mode: OutputMode,
+    data_path_source: PathSource,
 }
 
-fn init() -> u32 {
-    let mut lock = ESPEAK_INIT.plock();
-    if *lock == 0 {
-        *lock = unsafe {
-            espeak_Initialize(
-                espeak_AUDIO_OUTPUT_AUDIO_OUTPUT_SYNCHRONOUS,
-                0,
-                std::ptr::null(),
-                0,
-            )
-            .try_into()
-            .unwrap()
-        };
+static ESPEAK: Mutex<Option<InitState>> = Mutex::new(None);
+
+/// The mode espeak is to be initialized with, set by [`set_output_mode`].
+static OUTPUT_MODE: Mutex<OutputMode> = Mutex::new(OutputMode::Synchronous);
+
+/// How espeak hands synthesized audio to the crate. Sources behave the
+/// same in either mode; some espeak-ng builds report events more
+/// accurately in one than the other.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum OutputMode {
+    /// `AUDIO_OUTPUT_SYNCHRONOUS`: espeak synthesizes on the thread that
+    /// asks it to.
+    #[default]
+    Synchronous,
+    /// `AUDIO_OUTPUT_RETRIEVAL`: espeak synthesizes on its own thread,
+    /// which the synthesis thread waits for.
+    Retrieval,
+}
+
+impl OutputMode {
+    fn espeak_output(self) -> espeak_AUDIO_OUTPUT {
+        match self {
+            OutputMode::Synchronous => espeak_AUDIO_OUTPUT_AUDIO_OUTPUT_SYNCHRONOUS,
+            OutputMode::Retrieval => espeak_AUDIO_OUTPUT_AUDIO_OUTPUT_RETRIEVAL,
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            OutputMode::Synchronous => "synchronous",
+            OutputMode::Retrieval => "retrieval",
+        }
+    }
+}
+
+/// Initialize espeak in `mode` from now on. If it is already initialized
+/// in another mode, it is initialized again once the utterance being
+/// synthesized, if any, is done.
+pub fn set_output_mode(mode: OutputMode) -> Result<(), EspeakError> {
+    let mut lock = espeak_lock();
+    *OUTPUT_MODE.plock() = mode;
+    let Some(state) = lock.as_mut() else {
+        return Ok(());
+    };
+    if state.output_mode == mode {
+        return Ok(());
+    }
+    let data_path = current_data_path();
+    let root = data_path.parent().unwrap_or(&data_path);
+    let root = CString::new(root.to_string_lossy().into_owned()).unwrap_or_default();
+    let result = unsafe {
+        espeak_Terminate();
+        espeak_Initialize(
+            mode.espeak_output(),
+            0,
+            root.as_ptr(),
+            espeakINITIALIZE_DONT_EXIT as c_int,
+        )
+    };
+    match u32::try_from(result) {
+        Ok(sample_rate) => {
+            state.sample_rate = sample_rate;
+            state.output_mode = mode;
+            Ok(())
+        }
+        Err(_) => {
+            // Start over on next use.
+            *lock = None;
+            Err(EspeakError::InitFailed(result))
+        }
+    }
+}
+
+/// Terminate espeak and remove the temporary copy of its data made for
+/// user voices and compiled dictionaries. Waits for the utterance being
+/// synthesized, if any. espeak is initialized again on next use, with the
+/// user voice directories but without the dictionaries compiled since.
+pub fn shutdown() -> Result<(), EspeakError> {
+    let mut lock = espeak_lock();
+    if lock.take().is_some() {
+        unsafe { espeak_Terminate() };
+    }
+    user_voices::remove_overlay()
+}
+
+/// What espeak was initialized with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EspeakInfo {
+    /// Version reported by the linked espeak-ng.
+    pub version: String,
+    /// Where espeak-ng loads its voices and dictionaries from.
+    pub data_path: PathBuf,
+    /// Where `data_path` came from.
+    pub data_path_source: PathSource,
+    pub sample_rate: u32,
+    pub output_mode: OutputMode,
+}
+
+/// Initialize espeak if needed, and report how.
+pub fn info() -> Result<EspeakInfo, EspeakError> {
+    init()?;
+    let version = espeak_version();
+    let lock = espeak_lock();
+    let state = lock.as_ref().ok_or(EspeakError::InitFailed(0))?;
+    Ok(EspeakInfo {
+        version,
+        data_path: current_data_path(),
+        data_path_source: state.data_path_source,
+        sample_rate: state.sample_rate,
+        output_mode: state.output_mode,
+    })
+}
+
+/// Synthesis threads that have been spawned and haven't finished yet.
+static ACTIVE_SYNTHESES: AtomicUsize = AtomicUsize::new(0);
+
+/// Lock the process-wide espeak instance. espeak keeps its state in
+/// globals, so every call into it must happen while holding this lock.
+/// A panic while holding it doesn't make espeak unusable, so poisoning
+/// is ignored.
+fn espeak_lock() -> MutexGuard<'static, Option<InitState>> {
+    ESPEAK.plock()
+}
+
+/// Initialize espeak on first use, returning its sample rate.
+fn init() -> Result<u32, EspeakError> {
+    let mut lock = espeak_lock();
+    if let Some(state) = lock.as_ref() {
+        return Ok(state.sample_rate);
+    }
+    // espeak reports missing data by exiting the process, or with
+    // DONT_EXIT, by carrying on without it. So look for the data first.
+    let (path, data_path_source) = data_path::configured_data_path()?;
+    let path = CString::new(path.to_string_lossy().into_owned()).unwrap_or_default();
+    // Given the same path, espeak_Initialize doesn't look any further.
+    let path_ptr = match path.as_bytes() {
+        [] => std::ptr::null(),
+        _ => path.as_ptr(),
+    };
+    unsafe { espeak_ng_InitializePath(path_ptr) };
+    let data_path = current_data_path();
+    if !data_path.join("phontab").is_file() {
+        return Err(EspeakError::DataNotFound {
+            searched_paths: searched_data_paths(data_path),
+        });
+    }
+    let output_mode = *OUTPUT_MODE.plock();
+    let result = unsafe {
+        espeak_Initialize(
+            output_mode.espeak_output(),
+            0,
+            path_ptr,
+            espeakINITIALIZE_DONT_EXIT as c_int,
+        )
+    };
+    // espeak_Initialize returns the sample rate, or an espeak_ERROR.
+    let sample_rate: u32 = result
+        .try_into()
+        .map_err(|_| EspeakError::InitFailed(result))?;
+    let state = lock.insert(InitState {
+        sample_rate,
+        output_mode,
+        data_path_source,
+    });
+    user_voices::merge(state)?;
+    Ok(state.sample_rate)
+}
+
+/// The data directory espeak has settled on, as `espeak-ng-data` itself.
+fn current_data_path() -> PathBuf {
+    let mut data_path: *const c_char = std::ptr::null();
+    unsafe { espeak_Info(&mut data_path) };
+    if data_path.is_null() {
+        return PathBuf::new();
+    }
+    let data_path = unsafe { CStr::from_ptr(data_path) };
+    PathBuf::from(data_path.to_string_lossy().into_owned())
+}
+
+/// Where espeak looks for its data, in order, ending with `chosen`, which
+/// it falls back to when none of the others is a directory.
+fn searched_data_paths(chosen: PathBuf) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = ["ESPEAK_DATA_PATH", "HOME"]
+        .iter()
+        .filter_map(std::env::var_os)
+        .map(|dir| PathBuf::from(dir).join("espeak-ng-data"))
+        .collect();
+    if !paths.contains(&chosen) {
+        paths.push(chosen);
     }
-    *lock
+    paths
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub enum Gender {
     Female,
     Male,
@@ -107,6 +468,7 @@ pub enum Gender {
 
 #[allow(dead_code)]
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Language {
     pub priority: i8,
     pub name: String,
@@ -114,6 +476,7 @@ pub struct Language {
 
 #[allow(dead_code)]
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Voice {
     pub name: String,
     pub identifier: String,
@@ -122,20 +485,32 @@ pub struct Voice {
     pub languages: Vec<Language>,
 }
 
+/// The name, then the identifier and each language with its priority, e.g.
+/// `French (Switzerland) [roa/fr-CH, fr-ch(5), fr(8)]`.
+impl fmt::Display for Voice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{}", self.name, self.identifier)?;
+        for language in &self.languages {
+            write!(f, ", {}({})", language.name, language.priority)?;
+        }
+        write!(f, "]")
+    }
+}
+
 impl Voice {
     pub(crate) fn from_espeak_voice(v: espeak_VOICE) -> Voice {
         let name = if v.name.is_null() {
             String::default()
         } else {
             let name_cstr = unsafe { CStr::from_ptr(v.name) };
-            String::from(name_cstr.to_str().unwrap())
+            name_cstr.to_string_lossy().into_owned()
         };
 
         let identifier = if v.identifier.is_null() {
             String::default()
         } else {
             let identifier_cstr = unsafe { CStr::from_ptr(v.identifier) };
-            String::from(identifier_cstr.to_str().unwrap())
+            identifier_cstr.to_string_lossy().into_owned()
         };
 
         let age: u8 = v.age;
@@ -154,8 +529,10 @@ impl Voice {
                 let priority = unsafe { *langs_ptr };
                 langs_ptr = langs_ptr.wrapping_add(1);
                 let lang_cstr = unsafe { CStr::from_ptr(langs_ptr) };
-                let name = String::from(lang_cstr.to_str().unwrap());
-                let name_len = name.bytes().count();
+                // Voice files from third parties aren't always UTF-8, so
+                // step over the raw bytes rather than the converted name.
+                let name_len = lang_cstr.to_bytes().len();
+                let name = lang_cstr.to_string_lossy().into_owned();
                 languages.push(Language {
                     priority: priority.try_into().unwrap(),
                     name,
@@ -174,332 +551,2738 @@ impl Voice {
     }
 }
 
+/// List the installed voices. Panics if espeak can't be initialized;
+/// see [`try_list_voices`].
 pub fn list_voices() -> Vec<Voice> {
-    init();
-    {
-        let _lock = ESPEAK_INIT.plock();
-        let mut result = Vec::<Voice>::new();
-        let mut voice_arr = unsafe { espeak_ListVoices(std::ptr::null_mut()) };
+    try_list_voices().expect("Failed to initialize espeak")
+}
+
+pub fn try_list_voices() -> Result<Vec<Voice>, EspeakError> {
+    list_voices_matching(std::ptr::null_mut())
+}
+
+/// List the installed voice variants, which can be added to a voice name
+/// after a `+`, e.g. `en+klatt`.
+pub fn list_variants() -> Vec<Voice> {
+    let language = CString::new("variant").unwrap();
+    let mut spec = espeak_VOICE {
+        name: std::ptr::null(),
+        languages: language.as_ptr(),
+        identifier: std::ptr::null(),
+        gender: 0,
+        age: 0,
+        variant: 0,
+        xx1: 0,
+        score: 0,
+        spare: std::ptr::null_mut(),
+    };
+    list_voices_matching(&mut spec).expect("Failed to initialize espeak")
+}
 
-        while unsafe { !(*voice_arr).is_null() } {
-            let voice = unsafe { Voice::from_espeak_voice(**voice_arr) };
-            result.push(voice);
-            voice_arr = voice_arr.wrapping_add(1);
+fn list_voices_matching(spec: *mut espeak_VOICE) -> Result<Vec<Voice>, EspeakError> {
+    init()?;
+    {
+        let _lock = espeak_lock();
+        let voice_arr = unsafe { espeak_ListVoices(spec) };
+        if voice_arr.is_null() {
+            return Ok(Vec::new());
         }
-        result
+        Ok(unsafe { walk_voices(|i| *voice_arr.wrapping_add(i)) })
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub enum Event {
-    Start,
-    Word(usize, usize),
-    Sentence(usize),
-    End,
+/// More voices than any espeak install has. Reaching it means the array
+/// being walked wasn't terminated.
+const MAX_LISTED_VOICES: usize = 4096;
+
+/// Convert the voices of a null-terminated array, `entry` giving its
+/// element at an index. Entries with no name, identifier or language are
+/// skipped as garbage, and the walk gives up after [`MAX_LISTED_VOICES`].
+///
+/// # Safety
+///
+/// Each non-null pointer `entry` returns before the first null one must
+/// point to a valid `espeak_VOICE`.
+unsafe fn walk_voices<F>(mut entry: F) -> Vec<Voice>
+where
+    F: FnMut(usize) -> *const espeak_VOICE,
+{
+    let mut voices = Vec::<Voice>::new();
+    for i in 0..MAX_LISTED_VOICES {
+        let voice = entry(i);
+        if voice.is_null() {
+            return voices;
+        }
+        let voice = *voice;
+        let no_languages = voice.languages.is_null() || *voice.languages == 0;
+        if voice.name.is_null() && voice.identifier.is_null() && no_languages {
+            continue;
+        }
+        voices.push(Voice::from_espeak_voice(voice));
+    }
+    #[cfg(feature = "tracing")]
+    tracing::error!(
+        limit = MAX_LISTED_VOICES,
+        "espeak's voice list isn't terminated; ignoring the rest of it"
+    );
+    voices
 }
 
-#[derive(Clone)]
-pub struct SpeakerParams {
-    pub rate: Option<i32>,
-    pub volume: Option<i32>,
-    pub pitch: Option<i32>,
-    pub range: Option<i32>,
-    pub punctuation: Option<i32>,
-    pub capitals: Option<i32>,
-    pub word_gap: Option<i32>,
-    pub is_ssml: bool,
+/// The version of the linked espeak-ng library.
+pub fn espeak_version() -> String {
+    init().expect("Failed to initialize espeak");
+    let _lock = espeak_lock();
+    let version = unsafe { espeak_Info(std::ptr::null_mut()) };
+    if version.is_null() {
+        String::default()
+    } else {
+        let version_cstr = unsafe { CStr::from_ptr(version) };
+        version_cstr.to_string_lossy().into_owned()
+    }
 }
 
-impl SpeakerParams {
-    pub fn new() -> SpeakerParams {
-        SpeakerParams {
-            rate: None,
-            volume: None,
-            pitch: None,
-            range: None,
-            punctuation: None,
-            capitals: None,
-            word_gap: None,
-            is_ssml: false,
-        }
+pub(crate) fn text_to_phonemes(voice_name: &str, text: &str) -> String {
+    init().expect("Failed to initialize espeak");
+    let voice_name_cstr = CString::new(if voice_name.is_empty() {
+        "en"
+    } else {
+        voice_name
+    })
+    .expect("Failed to convert &str to CString");
+    let text_cstr = CString::new(text).expect("Failed to convert &str to CString");
+
+    let _lock = espeak_lock();
+    unsafe {
+        espeak_SetVoiceByName(voice_name_cstr.as_ptr() as *const c_char);
     }
 
-    pub(crate) fn apply_params(self: SpeakerParams) {
-        fn apply_param(param_enum: u32, value: Option<i32>) {
-            unsafe {
-                match value {
-                    Some(value) => espeak_SetParameter(param_enum, value, 0),
-                    None => espeak_SetParameter(param_enum, espeak_GetParameter(param_enum, 0), 0),
-                };
-            };
+    // espeak_TextToPhonemes translates one clause per call and advances
+    // the text pointer, setting it to null once the text is exhausted.
+    let mut clauses = Vec::<String>::new();
+    let mut text_ptr = text_cstr.as_ptr() as *const c_void;
+    while !text_ptr.is_null() {
+        let phonemes = unsafe {
+            espeak_TextToPhonemes(
+                &mut text_ptr,
+                espeakCHARS_AUTO as c_int,
+                espeakPHONEMES_IPA as c_int,
+            )
+        };
+        if phonemes.is_null() {
+            break;
         }
-
-        apply_param(espeak_PARAMETER_espeakRATE, self.rate);
-        apply_param(espeak_PARAMETER_espeakVOLUME, self.volume);
-        apply_param(espeak_PARAMETER_espeakPITCH, self.pitch);
-        apply_param(espeak_PARAMETER_espeakRANGE, self.range);
-        apply_param(espeak_PARAMETER_espeakPUNCTUATION, self.punctuation);
-        apply_param(espeak_PARAMETER_espeakCAPITALS, self.capitals);
-        apply_param(espeak_PARAMETER_espeakWORDGAP, self.word_gap);
+        let phonemes_cstr = unsafe { CStr::from_ptr(phonemes) };
+        clauses.push(phonemes_cstr.to_string_lossy().into_owned());
     }
+    clauses.join(" ")
 }
 
-pub struct Speaker {
-    pub params: SpeakerParams,
-    voice_name: String,
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    any(feature = "serde", feature = "subprocess-pool"),
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[non_exhaustive]
+pub enum Event {
+    Start,
+    /// A word starts: its byte position and length in the text spoken.
+    /// [`Event::word_in`] slices it out and [`Event::char_range`] gives it
+    /// in characters.
+    Word(usize, usize),
+    /// A sentence starts: its byte position and length in the text spoken.
+    /// espeak only reports the start; the end is found in the text, at the
+    /// sentence-ending punctuation.
+    Sentence {
+        start: usize,
+        len: usize,
+    },
+    /// The utterance was spoken to the end. Always the last event, unless
+    /// it is [`Event::Cancelled`].
+    End,
+    /// A phoneme starts, reported when [`SpeakerParams::phoneme_events`]
+    /// is set. `name` is espeak's mnemonic for it and `id` the raw number
+    /// espeak reported alongside.
+    Phoneme {
+        name: String,
+        id: i32,
+    },
+    /// A named position: an SSML `<mark name="..."/>` reached in speech,
+    /// or where each voice starts in [`compare_voices`]' output.
+    Mark(String),
+    /// An SSML `<audio src="..."/>` was reached, and the named sound should
+    /// be played. espeak doesn't play it itself.
+    Play(String),
+    /// espeak finished the utterance, whose audio is `audio_ms` long. Comes
+    /// just before [`Event::End`] when synthesis wasn't cut short.
+    Terminated {
+        audio_ms: u32,
+    },
+    /// A table cell starts being read, when speaking a [`TableSpeech`].
+    /// Rows and columns count from zero, and the headers aren't a row.
+    Cell {
+        row: usize,
+        col: usize,
+    },
+    /// Synthesis stopped before the end of the utterance. Comes last in
+    /// place of [`Event::End`], and [`SpeakerSource::error`] tells why if
+    /// it was an error.
+    Cancelled,
+    /// An event of a kind this crate doesn't know, with its fields as
+    /// espeak reported them: the `espeak_EVENT_TYPE` number, the audio
+    /// position in milliseconds, and the text position and length in
+    /// characters, counting from 1. Positions are relative to the piece of
+    /// text being synthesized when long text is split.
+    ///
+    /// This is a stopgap for event kinds newer espeak-ng versions add;
+    /// once the crate handles a kind, it gets its own variant instead.
+    Other {
+        kind: u32,
+        audio_position: u32,
+        text_position: usize,
+        length: usize,
+    },
 }
 
-impl Speaker {
-    pub fn new() -> Speaker {
-        Speaker {
-            params: SpeakerParams::new(),
-            voice_name: String::default(),
+impl Event {
+    /// The part of `text` a [`Event::Word`] covers, `text` being what was
+    /// spoken. `None` for other events, or if the positions don't fall on
+    /// character boundaries of `text`.
+    pub fn word_in<'a>(&self, text: &'a str) -> Option<&'a str> {
+        match self {
+            Event::Word(start, len) => text.get(*start..start.checked_add(*len)?),
+            _ => None,
         }
     }
 
-    pub fn speak(&self, text: &str) -> SpeakerSource {
-        SpeakerSource::new(text, &self.voice_name, self.params.clone())
+    /// The characters of `text` a [`Event::Word`] covers, as a range of
+    /// character indices rather than bytes. This counts from the start of
+    /// `text` every time; [`SpeakerSource::position`] doesn't.
+    pub fn char_range(&self, text: &str) -> Option<std::ops::Range<usize>> {
+        let word = self.word_in(text)?;
+        let Event::Word(start, _) = self else {
+            return None;
+        };
+        let char_start = text[..*start].chars().count();
+        Some(char_start..char_start + word.chars().count())
     }
 
-    pub fn set_voice(&mut self, voice: &Voice) {
-        self.voice_name = voice.name.clone();
+    /// Like the [`Display`](fmt::Display) form, but with the text a word
+    /// or sentence covers in `text`, e.g. `word 'Goodbye' [13..20]`.
+    pub fn describe(&self, text: &str) -> String {
+        let (kind, start, len) = match self {
+            Event::Word(start, len) => ("word", *start, *len),
+            Event::Sentence { start, len } => ("sentence", *start, *len),
+            _ => return self.to_string(),
+        };
+        match start.checked_add(len).and_then(|end| text.get(start..end)) {
+            Some(covered) if len > 0 => {
+                format!("{} '{}' [{}..{}]", kind, covered, start, start + len)
+            }
+            _ => self.to_string(),
+        }
     }
 }
 
-pub struct SpeakerSource {
-    rx: Receiver<(Vec<i16>, Vec<(u32, Event)>)>,
-    sample_rate: u32,
-    data: Vec<i16>,
-    events: Vec<(u32, Event)>,
-    iter_index: Option<usize>,
+/// A compact form for logs: `start`, `word[13..20]`, `sentence@13`,
+/// `mark("intro")` and so on. The format is stable.
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Start => write!(f, "start"),
+            Event::Word(start, len) => write!(f, "word[{}..{}]", start, start.saturating_add(*len)),
+            Event::Sentence { start, .. } => write!(f, "sentence@{}", start),
+            Event::End => write!(f, "end"),
+            Event::Phoneme { name, id } => write!(f, "phoneme({:?}, {})", name, id),
+            Event::Mark(name) => write!(f, "mark({:?})", name),
+            Event::Play(name) => write!(f, "play({:?})", name),
+            Event::Terminated { audio_ms } => write!(f, "terminated@{}ms", audio_ms),
+            Event::Cell { row, col } => write!(f, "cell({}, {})", row, col),
+            Event::Cancelled => write!(f, "cancelled"),
+            Event::Other {
+                kind,
+                audio_position,
+                text_position,
+                length,
+            } => write!(
+                f,
+                "other({})[{}+{}]@{}ms",
+                kind, text_position, length, audio_position
+            ),
+        }
+    }
 }
 
-impl SpeakerSource {
-    pub fn new(text: &str, voice_name: &str, params: SpeakerParams) -> SpeakerSource {
-        let (mut tx, rx) = channel::<(Vec<i16>, Vec<(u32, Event)>)>();
-        let sample_rate = init();
+/// An [`Event`] along with where it falls in the audio.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimedEvent {
+    /// Time from the start of the source's audio.
+    pub offset: Duration,
+    pub event: Event,
+}
 
-        let voice_name_cstr = CString::new(if voice_name.is_empty() {
-            "en"
-        } else {
-            voice_name
-        })
-        .expect("Failed to convert &str to CString");
-        let text_cstr = CString::new(text).expect("Failed to convert &str to CString");
-        thread::spawn(move || {
-            let _lock = ESPEAK_INIT.plock();
-            let flags = if params.is_ssml {
-                espeakSSML | espeakCHARS_AUTO
-            } else {
-                espeakCHARS_AUTO
-            };
-            params.apply_params();
-            let tx_ptr: *mut c_void = &mut tx as *mut _ as *mut c_void;
+/// How a [`Speaker`] speaks. Unset params use espeak's defaults, see
+/// [`SpeakerParams::espeak_defaults`].
+///
+/// Params start from [`SpeakerParams::new`], and are set with the `with_`
+/// methods:
+/// ```
+/// use espeak_rs::SpeakerParams;
+///
+/// let params = SpeakerParams::new().with_rate(200).with_pitch(60);
+/// assert_eq!(params.rate, Some(200.into()));
+/// ```
+/// or by assigning the public fields:
+/// ```
+/// use espeak_rs::{ParamValue, SpeakerParams};
+///
+/// let mut params = SpeakerParams::new();
+/// params.rate = Some(ParamValue::Relative(120));
+/// params.phoneme_events = true;
+/// ```
+/// New params are added as fields, so the struct can't be written as a
+/// literal outside this crate, not even with struct update syntax.
+#[derive(Clone)]
+#[cfg_attr(
+    any(feature = "serde", feature = "subprocess-pool"),
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[non_exhaustive]
+pub struct SpeakerParams {
+    pub rate: Option<ParamValue>,
+    pub volume: Option<ParamValue>,
+    pub pitch: Option<ParamValue>,
+    pub range: Option<ParamValue>,
+    pub punctuation: Option<Punctuation>,
+    /// The characters [`Punctuation::Some`] reads aloud. `None` leaves
+    /// espeak's list as it was last set.
+    pub punctuation_chars: Option<String>,
+    pub capitals: Option<Capitals>,
+    /// Pause between words, in units of 10 ms. See
+    /// [`SpeakerParams::set_word_gap`] for setting it from a [`Duration`].
+    pub word_gap: Option<i32>,
+    /// Break clauses in text running longer than this many characters
+    /// without punctuation, such as log lines, so that it still gets
+    /// [`Event::Sentence`]s now and then. `0` never breaks.
+    pub line_length: Option<i32>,
+    /// How `espeak_Synth` reads the text: as SSML, with phoneme input,
+    /// with a pause at the end, and in which character set.
+    pub flags: SynthFlags,
+    /// Bound the number of audio chunks buffered ahead of the consumer.
+    /// Synthesis pauses while the buffer is full. `None` means unbounded.
+    pub channel_capacity: Option<usize>,
+    /// Keep synthesis at most this much audio ahead of what the source has
+    /// handed out, e.g. to save power on slow devices. Synthesis idles
+    /// while playback is paused, holding the espeak lock like a full
+    /// channel does. `None` means as far ahead as the channel allows.
+    pub synth_ahead: Option<Duration>,
+    /// Raw `espeakCHARS_*`/`espeakSSML`/... bits OR-ed into the flags passed
+    /// to `espeak_Synth`. Unstable and unchecked: the bits go to espeak as-is.
+    pub extra_synth_flags: u32,
+    /// Replace the computed `espeak_Synth` flags entirely, ignoring the
+    /// text mode and `extra_synth_flags`. Unstable and unchecked.
+    pub override_synth_flags: Option<u32>,
+    /// espeak params by number, each with its value and whether that is
+    /// relative, for params without a field here. Unstable and unchecked.
+    /// See [`SpeakerParams::set_raw`].
+    pub raw_params: Vec<(u32, i32, bool)>,
+    /// Run espeak for timing and events but replace its audio with a
+    /// deterministic tone keyed to the current word, for stable tests.
+    #[cfg(feature = "test-tone")]
+    pub test_tone_mode: bool,
+    /// The most bytes of text passed to espeak at once. Longer text is
+    /// split at sentence boundaries and synthesized piece by piece.
+    pub synth_text_budget: usize,
+    /// What to say in place of URLs and email addresses in plain text.
+    /// Events still report the positions of the URLs themselves.
+    pub url_policy: UrlPolicy,
+    /// Read text between `[[` and `]]` in plain text as espeak phoneme
+    /// mnemonics, e.g. `[[h@'loU]]`. A literal `[[` is then written `\[[`.
+    pub allow_inline_phonemes: bool,
+    /// Take the tags the linked espeak-ng doesn't support out of SSML, as
+    /// [`sanitize_ssml`] does, so that they aren't read aloud. Support is
+    /// probed the first time it's needed.
+    pub sanitize_ssml: bool,
+    /// Whether to read all-caps words in plain text as ordinary words
+    /// rather than leaving them to the `capitals` parameter.
+    pub caps_policy: CapsPolicy,
+    /// All-caps words that [`CapsPolicy::LowercaseLongRuns`] leaves alone.
+    /// Defaults to [`DEFAULT_ACRONYMS`].
+    pub acronyms: Vec<String>,
+    /// Read numbers in plain text digit by digit, "one two three four"
+    /// for `1234`, as for codes and phone numbers.
+    pub say_digits: bool,
+    /// Restore sentence and clause punctuation to plain text that has
+    /// none, such as speech recognizer output, so that it isn't read as
+    /// one long sentence. Runs before the other rewrites; the changes are
+    /// listed in [`TransformReport::punctuation_edits`].
+    pub punctuation_repair: Option<PunctuationRepair>,
+    /// What to say in place of words in plain text, keyed by the word in
+    /// lowercase. Set with [`Speaker::add_pronunciation`].
+    pub pronunciations: BTreeMap<String, String>,
+    /// Report an [`Event::Phoneme`] for each phoneme spoken, e.g. for
+    /// lip-sync.
+    pub phoneme_events: bool,
+    /// Fail synthesis with [`EspeakError::ParamsRejected`] before any
+    /// audio if espeak refuses any of the params. Otherwise the refusals
+    /// are only reported by [`SpeakerSource::param_errors`], and espeak
+    /// keeps the previous values of the params it refused.
+    pub strict_params: bool,
+    /// If espeak produces no audio for non-empty text, which happens now
+    /// and then with some voices or right after the data path changed,
+    /// select the voice again and retry once before reporting
+    /// [`EspeakError::SilentOutput`]. Nothing reaches the source until the
+    /// first audio does, so a retry isn't visible in its events.
+    pub retry_on_empty: bool,
+    /// How many of the synthesis attempts of each utterance to drop the
+    /// audio of, to test retries.
+    #[cfg(test)]
+    drop_attempts: usize,
+}
 
-            unsafe {
-                espeak_SetVoiceByName(voice_name_cstr.as_ptr() as *const c_char);
-            }
+/// Options passed to `espeak_Synth` as flags, in [`SpeakerParams::flags`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(
+    any(feature = "serde", feature = "subprocess-pool"),
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[non_exhaustive]
+pub struct SynthFlags {
+    /// Read [`Speaker::speak`]'s text as SSML. [`Speaker::speak_ssml`]
+    /// and [`Speaker::speak_plain`] choose for themselves.
+    pub ssml: bool,
+    /// Read text between `[[` and `]]` as espeak phoneme mnemonics, e.g.
+    /// `[[h@'loU]]`. Unlike
+    /// [`SpeakerParams::allow_inline_phonemes`], the rest of the text
+    /// isn't prepared for it: rewrites such as
+    /// [`SpeakerParams::caps_policy`] may change the phonemes, and there's
+    /// no escape for a literal `[[`.
+    pub phonemes_input: bool,
+    /// Pause at the end of the text as at the end of a sentence, adding
+    /// silence after the last word. Off by default, so that the audio
+    /// ends right after the last word.
+    pub end_pause: bool,
+    /// How espeak is to decode the text's bytes.
+    pub charset: Charset,
+}
 
-            unsafe {
-                espeak_SetSynthCallback(Some(Self::synth_callback));
-            }
+impl SynthFlags {
+    pub fn new() -> SynthFlags {
+        SynthFlags::default()
+    }
 
-            let position = 0u32;
-            let position_type: espeak_POSITION_TYPE = 0;
-            let end_position = 0u32;
+    /// The `espeak_Synth` flags, for text in `text_mode` rather than as
+    /// `ssml` says.
+    fn bits(self, text_mode: TextMode) -> u32 {
+        let mut flags = self.charset.bits();
+        if text_mode == TextMode::Ssml {
+            flags |= espeakSSML;
+        }
+        if self.phonemes_input {
+            flags |= espeakPHONEMES;
+        }
+        if self.end_pause {
+            flags |= espeakENDPAUSE;
+        }
+        flags
+    }
+}
 
-            let identifier = std::ptr::null_mut();
-            unsafe {
-                espeak_Synth(
-                    text_cstr.as_ptr() as *const c_void,
-                    500,
-                    position,
-                    position_type,
-                    end_position,
-                    flags,
-                    identifier,
-                    tx_ptr,
-                );
-            }
-        });
+/// How espeak decodes the bytes of the text, in [`SynthFlags::charset`].
+/// Text is always handed over as UTF-8.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(
+    any(feature = "serde", feature = "subprocess-pool"),
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[non_exhaustive]
+pub enum Charset {
+    /// UTF-8, unless the text isn't valid UTF-8. espeak's default.
+    #[default]
+    Auto,
+    /// Always UTF-8.
+    Utf8,
+    /// Each byte is a character in the voice's 8-bit character set, such
+    /// as ISO 8859-1. Only text that is ASCII reads the same as it would
+    /// as UTF-8.
+    EightBit,
+}
 
-        SpeakerSource {
-            rx,
-            sample_rate,
-            data: Vec::new(),
-            events: Vec::new(),
-            iter_index: Some(0),
+impl Charset {
+    fn bits(self) -> u32 {
+        match self {
+            Charset::Auto => espeakCHARS_AUTO,
+            Charset::Utf8 => espeakCHARS_UTF8,
+            Charset::EightBit => espeakCHARS_8BIT,
         }
     }
+}
 
-    pub fn with_callback<F>(self, callback: F) -> SpeakerSourceWithCallback<F>
-    where
-        F: FnMut(Event),
-    {
-        SpeakerSourceWithCallback {
-            inner: self,
-            callback,
+/// Which punctuation espeak reads aloud, e.g. "comma" for `,`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    any(feature = "serde", feature = "subprocess-pool"),
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[non_exhaustive]
+pub enum Punctuation {
+    None,
+    All,
+    /// The characters in [`SpeakerParams::punctuation_chars`].
+    Some,
+}
+
+impl Punctuation {
+    /// From espeak's `espeakPUNCT_*` value.
+    #[allow(non_upper_case_globals)]
+    pub fn from_raw(raw: i32) -> Option<Punctuation> {
+        match u32::try_from(raw).ok()? {
+            espeak_PUNCT_TYPE_espeakPUNCT_NONE => Some(Punctuation::None),
+            espeak_PUNCT_TYPE_espeakPUNCT_ALL => Some(Punctuation::All),
+            espeak_PUNCT_TYPE_espeakPUNCT_SOME => Some(Punctuation::Some),
+            _ => None,
         }
     }
 
-    pub fn iter_audio_and_events(self) -> IterAudioAndEvents {
-        IterAudioAndEvents { inner: self }
+    /// espeak's `espeakPUNCT_*` value.
+    pub fn as_raw(self) -> i32 {
+        let raw = match self {
+            Punctuation::None => espeak_PUNCT_TYPE_espeakPUNCT_NONE,
+            Punctuation::All => espeak_PUNCT_TYPE_espeakPUNCT_ALL,
+            Punctuation::Some => espeak_PUNCT_TYPE_espeakPUNCT_SOME,
+        };
+        raw as i32
     }
+}
 
-    fn next_sample_and_events(&mut self) -> (Option<i16>, Option<Vec<Event>>) {
-        match self.iter_index {
-            None => (None, None),
-            Some(i) => {
-                while i >= self.data.len() {
-                    match self.rx.recv() {
-                        Err(_) => {
-                            return (None, Some(vec![Event::End]));
-                        }
-                        Ok((mut wav_vec, mut events_vec)) => {
-                            self.data.append(&mut wav_vec);
-                            self.events.append(&mut events_vec);
-                        }
-                    }
-                }
-                let mut events = Vec::<Event>::new();
-                while let Some((audio_position, _)) = self.events.first() {
-                    let at_sample = (audio_position * self.sample_rate / 1000) as usize;
-                    if at_sample > i {
-                        break;
-                    }
-                    let (_, event) = self.events.remove(0);
-                    events.push(event);
-                }
+/// How espeak announces capital letters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    any(feature = "serde", feature = "subprocess-pool"),
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[non_exhaustive]
+pub enum Capitals {
+    Off,
+    /// Play a sound before each capital letter.
+    SoundIcon,
+    /// Say "capital" before each capital letter.
+    Spelling,
+    /// Raise the pitch of capital letters by this many Hz, within
+    /// [`SpeakerParams::CAPITALS_PITCH_RANGE`].
+    PitchRaise(u16),
+}
 
-                let sample = if i < self.data.len() {
-                    self.iter_index = Some(i + 1usize);
-                    Some(self.data[i])
-                } else {
-                    None
-                };
-                (
-                    sample,
-                    if events.is_empty() {
-                        None
-                    } else {
-                        Some(events)
-                    },
-                )
-            }
+impl Capitals {
+    /// From espeak's value of the capitals parameter.
+    pub fn from_raw(raw: i32) -> Option<Capitals> {
+        match raw {
+            0 => Some(Capitals::Off),
+            1 => Some(Capitals::SoundIcon),
+            2 => Some(Capitals::Spelling),
+            _ => u16::try_from(raw).ok().map(Capitals::PitchRaise),
         }
     }
 
-    #[allow(non_upper_case_globals)]
-    #[allow(non_snake_case)]
-    extern "C" fn synth_callback(
-        wav: *mut c_short,
-        sample_count: c_int,
-        events: *mut espeak_EVENT,
-    ) -> c_int {
-        let mut events_copy = events.clone();
-        let mut events_vec = Vec::<(u32, Event)>::new();
-        while unsafe { (*events_copy).type_ != espeak_EVENT_TYPE_espeakEVENT_LIST_TERMINATED } {
-            // let at_sample = audio_position * self.sample_rate * 1000;
-            let evt = match unsafe { (*events_copy).type_ } {
-                espeak_EVENT_TYPE_espeakEVENT_SAMPLERATE => {
-                    // This is the start of the utterance
-                    Some(Event::Start)
-                }
-                espeak_EVENT_TYPE_espeakEVENT_WORD => {
-                    let text_position: usize =
-                        unsafe { (*events_copy).text_position.try_into().unwrap() };
-                    let length: usize = unsafe { (*events_copy).length.try_into().unwrap() };
-                    Some(Event::Word(text_position.saturating_sub(1), length))
-                }
-                espeak_EVENT_TYPE_espeakEVENT_SENTENCE => {
-                    let text_position: usize =
-                        unsafe { (*events_copy).text_position.try_into().unwrap() };
-                    Some(Event::Sentence(text_position.saturating_sub(1)))
-                }
-                _ => None,
-            };
-            if let Some(evt) = evt {
-                let audio_position: u32 =
-                    unsafe { (*events_copy).audio_position.try_into().unwrap() };
-                events_vec.push((audio_position, evt));
-            }
-            events_copy = events_copy.wrapping_add(1);
+    /// espeak's value of the capitals parameter. A pitch raise of 0 to 2
+    /// Hz would read back as another mode, and is refused by
+    /// [`SpeakerParams::validate`].
+    pub fn as_raw(self) -> i32 {
+        match self {
+            Capitals::Off => 0,
+            Capitals::SoundIcon => 1,
+            Capitals::Spelling => 2,
+            Capitals::PitchRaise(hz) => i32::from(hz),
         }
+    }
+}
 
-        let tx_ptr = unsafe { (*events).user_data };
-        let tx: &mut Sender<(Vec<i16>, Vec<(u32, Event)>)> =
-            unsafe { &mut *(tx_ptr as *mut Sender<(Vec<i16>, Vec<(u32, Event)>)>) };
-        let mut wav_vec: Vec<i16> = Vec::new();
-        if !wav.is_null() {
-            let wav_slice = unsafe { std::slice::from_raw_parts(wav, sample_count as usize) };
-            wav_vec = wav_slice
-                .into_iter()
-                .map(|f| f.clone() as i16)
-                .collect::<Vec<i16>>();
-        }
-        match tx.send((wav_vec, events_vec)) {
-            Err(_) => 1,
-            Ok(_) => 0,
+/// A value for one of the numeric params. espeak only takes relative
+/// values for the rate, volume, pitch and range.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    any(feature = "serde", feature = "subprocess-pool"),
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[non_exhaustive]
+pub enum ParamValue {
+    Absolute(i32),
+    /// A percentage of espeak's default for the param, see
+    /// [`SpeakerParams::espeak_defaults`]: `Relative(120)` for the rate is
+    /// 20% faster than normal. Within [`SpeakerParams::RELATIVE_RANGE`].
+    Relative(i32),
+}
+
+impl ParamValue {
+    /// The absolute value, given espeak's `default` for the param and the
+    /// `range` it accepts, which a relative value is clamped to.
+    ///
+    /// espeak's own relative values are changes in percent, `default +
+    /// default * value / 100`, so they are worked out here instead.
+    fn to_absolute(self, default: i32, range: RangeInclusive<i32>) -> i32 {
+        match self {
+            ParamValue::Absolute(value) => value,
+            ParamValue::Relative(percent) => {
+                let value = i64::from(default) * i64::from(percent) / 100;
+                value.clamp(i64::from(*range.start()), i64::from(*range.end())) as i32
+            }
         }
     }
 }
 
-impl Source for SpeakerSource {
-    fn current_frame_len(&self) -> Option<usize> {
-        None
+impl From<i32> for ParamValue {
+    fn from(value: i32) -> ParamValue {
+        ParamValue::Absolute(value)
     }
+}
 
-    fn channels(&self) -> u16 {
-        1
+impl From<Rate> for ParamValue {
+    fn from(rate: Rate) -> ParamValue {
+        ParamValue::Absolute(rate.into())
+    }
+}
+
+/// A speaking rate in words per minute, within
+/// [`SpeakerParams::RATE_RANGE`]. espeak would clamp a rate outside it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Rate(u16);
+
+impl Rate {
+    pub fn wpm(wpm: u16) -> Result<Rate, EspeakError> {
+        Rate::try_from(i32::from(wpm))
     }
 
-    fn sample_rate(&self) -> u32 {
-        self.sample_rate
+    /// The rate closest to `wpm` within [`SpeakerParams::RATE_RANGE`].
+    pub fn clamped(wpm: i32) -> Rate {
+        let range = SpeakerParams::RATE_RANGE;
+        Rate(wpm.clamp(*range.start(), *range.end()) as u16)
     }
 
-    fn total_duration(&self) -> Option<Duration> {
-        None
+    pub fn as_wpm(self) -> u16 {
+        self.0
     }
 }
 
-impl Iterator for SpeakerSource {
-    type Item = i16;
+impl TryFrom<i32> for Rate {
+    type Error = EspeakError;
 
-    fn next(&mut self) -> Option<i16> {
-        let (sample, _) = self.next_sample_and_events();
-        return sample;
+    /// Fails with [`EspeakError::ParamOutOfRange`] outside
+    /// [`SpeakerParams::RATE_RANGE`].
+    fn try_from(wpm: i32) -> Result<Rate, EspeakError> {
+        let range = SpeakerParams::RATE_RANGE;
+        if range.contains(&wpm) {
+            Ok(Rate(wpm as u16))
+        } else {
+            Err(EspeakError::ParamOutOfRange {
+                name: "rate",
+                value: wpm,
+                range,
+            })
+        }
     }
+}
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        (0, None)
+impl From<Rate> for i32 {
+    fn from(rate: Rate) -> i32 {
+        i32::from(rate.0)
     }
 }
 
-pub struct SpeakerSourceWithCallback<F> {
-    inner: SpeakerSource,
-    callback: F,
+/// How the text of a single utterance should be interpreted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum TextMode {
+    Plain,
+    Ssml,
 }
 
-impl<F> SpeakerSourceWithCallback<F> where F: FnMut(Event) {}
+/// A part of what [`Speaker::speak_segments`] says.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Segment<'a> {
+    /// Plain text, spoken right after the segment before it.
+    Text(&'a str),
+    /// A pause of this length.
+    Silence(Duration),
+}
 
-impl<F> Source for SpeakerSourceWithCallback<F>
-where
-    F: FnMut(Event),
-{
-    fn current_frame_len(&self) -> Option<usize> {
-        self.inner.current_frame_len()
+impl TextMode {
+    pub(crate) fn from_params(params: &SpeakerParams) -> TextMode {
+        if params.flags.ssml {
+            TextMode::Ssml
+        } else {
+            TextMode::Plain
+        }
     }
+}
 
-    fn channels(&self) -> u16 {
-        self.inner.channels()
+impl SpeakerParams {
+    /// Speaking rate in words per minute.
+    pub const RATE_RANGE: RangeInclusive<i32> = 80..=450;
+    /// Volume as a percentage; above 100 may distort.
+    pub const VOLUME_RANGE: RangeInclusive<i32> = 0..=200;
+    /// Base pitch, 50 being normal.
+    pub const PITCH_RANGE: RangeInclusive<i32> = 0..=99;
+    /// Pitch range, from 0 for monotone up to 99; 50 is normal.
+    pub const RANGE_RANGE: RangeInclusive<i32> = 0..=99;
+    /// Percentages accepted for a [`ParamValue::Relative`] value, enough
+    /// to reach the top of every param's range from espeak's default.
+    /// What they come to is clamped to the param's range, so the rate
+    /// goes no higher than 450 even at 300%.
+    pub const RELATIVE_RANGE: RangeInclusive<i32> = 0..=300;
+    /// Pitch raise in Hz for [`Capitals::PitchRaise`]; lower values mean
+    /// the other modes to espeak.
+    pub const CAPITALS_PITCH_RANGE: RangeInclusive<i32> = 3..=u16::MAX as i32;
+    /// Word gaps, in units of 10 ms, that [`SpeakerParams::set_word_gap`]
+    /// sets; longer durations are capped at about five minutes.
+    pub const WORD_GAP_RANGE: RangeInclusive<i32> = 0..=i16::MAX as i32;
+
+    pub fn new() -> SpeakerParams {
+        SpeakerParams {
+            rate: None,
+            volume: None,
+            pitch: None,
+            range: None,
+            punctuation: None,
+            punctuation_chars: None,
+            capitals: None,
+            word_gap: None,
+            line_length: None,
+            flags: SynthFlags::new(),
+            channel_capacity: None,
+            synth_ahead: None,
+            extra_synth_flags: 0,
+            override_synth_flags: None,
+            raw_params: Vec::new(),
+            #[cfg(feature = "test-tone")]
+            test_tone_mode: false,
+            synth_text_budget: DEFAULT_SYNTH_TEXT_BUDGET,
+            url_policy: UrlPolicy::ReadFull,
+            allow_inline_phonemes: false,
+            sanitize_ssml: false,
+            caps_policy: CapsPolicy::AsIs,
+            acronyms: DEFAULT_ACRONYMS.iter().map(|a| String::from(*a)).collect(),
+            say_digits: false,
+            punctuation_repair: None,
+            pronunciations: BTreeMap::new(),
+            phoneme_events: false,
+            strict_params: false,
+            retry_on_empty: false,
+            #[cfg(test)]
+            drop_attempts: 0,
+        }
     }
 
-    fn sample_rate(&self) -> u32 {
-        self.inner.sample_rate()
+    pub const fn with_rate(mut self, rate: i32) -> SpeakerParams {
+        self.rate = Some(ParamValue::Absolute(rate));
+        self
     }
 
-    fn total_duration(&self) -> Option<Duration> {
-        self.inner.total_duration()
+    pub const fn with_volume(mut self, volume: i32) -> SpeakerParams {
+        self.volume = Some(ParamValue::Absolute(volume));
+        self
+    }
+
+    pub const fn with_pitch(mut self, pitch: i32) -> SpeakerParams {
+        self.pitch = Some(ParamValue::Absolute(pitch));
+        self
+    }
+
+    pub const fn with_range(mut self, range: i32) -> SpeakerParams {
+        self.range = Some(ParamValue::Absolute(range));
+        self
+    }
+
+    pub const fn with_punctuation(mut self, punctuation: Punctuation) -> SpeakerParams {
+        self.punctuation = Some(punctuation);
+        self
+    }
+
+    pub const fn with_capitals(mut self, capitals: Capitals) -> SpeakerParams {
+        self.capitals = Some(capitals);
+        self
+    }
+
+    pub const fn with_word_gap(mut self, word_gap: i32) -> SpeakerParams {
+        self.word_gap = Some(word_gap);
+        self
+    }
+
+    pub const fn with_line_length(mut self, line_length: i32) -> SpeakerParams {
+        self.line_length = Some(line_length);
+        self
+    }
+
+    /// Whether [`Speaker::speak`] reads its text as SSML.
+    #[deprecated(note = "use flags.ssml, or Speaker::speak_ssml or Speaker::speak_plain")]
+    pub fn is_ssml(&self) -> bool {
+        self.flags.ssml
+    }
+
+    #[deprecated(note = "use flags.ssml, or Speaker::speak_ssml or Speaker::speak_plain")]
+    pub fn set_ssml(&mut self, ssml: bool) {
+        self.flags.ssml = ssml;
+    }
+
+    /// Pause for `gap` between words, rounded to the nearest 10 ms.
+    pub fn set_word_gap(&mut self, gap: Duration) {
+        let units = (gap.as_millis() + 5) / 10;
+        let max = *Self::WORD_GAP_RANGE.end();
+        self.word_gap = Some(units.min(max as u128) as i32);
+    }
+
+    /// The pause between words, if set.
+    pub fn word_gap_duration(&self) -> Option<Duration> {
+        self.word_gap
+            .map(|units| Duration::from_millis(units.max(0) as u64 * 10))
+    }
+
+    /// The values espeak is using now, all filled in. Speaking applies a
+    /// speaker's params, so this is what the last utterance used.
+    pub fn current() -> SpeakerParams {
+        Self::from_espeak(1)
+    }
+
+    /// espeak's own defaults, e.g. a rate of 175 and a pitch of 50, all
+    /// filled in.
+    pub fn espeak_defaults() -> SpeakerParams {
+        Self::from_espeak(0)
+    }
+
+    /// Read the params from `espeak_GetParameter`, which gives the current
+    /// values when `current` is 1 and the defaults when it's 0.
+    fn from_espeak(current: c_int) -> SpeakerParams {
+        init().expect("Failed to initialize espeak");
+        let _lock = espeak_lock();
+        let get = |param| Some(unsafe { espeak_GetParameter(param, current) });
+        let absolute = |param| get(param).map(ParamValue::Absolute);
+        SpeakerParams {
+            rate: absolute(espeak_PARAMETER_espeakRATE),
+            volume: absolute(espeak_PARAMETER_espeakVOLUME),
+            pitch: absolute(espeak_PARAMETER_espeakPITCH),
+            range: absolute(espeak_PARAMETER_espeakRANGE),
+            punctuation: get(espeak_PARAMETER_espeakPUNCTUATION).and_then(Punctuation::from_raw),
+            capitals: get(espeak_PARAMETER_espeakCAPITALS).and_then(Capitals::from_raw),
+            word_gap: get(espeak_PARAMETER_espeakWORDGAP),
+            line_length: get(espeak_PARAMETER_espeakLINELENGTH),
+            ..SpeakerParams::new()
+        }
+    }
+
+    /// A copy of the current [`global_defaults`].
+    pub fn from_global() -> SpeakerParams {
+        global_defaults().get()
+    }
+
+    /// Check that every set parameter is within the range espeak accepts.
+    pub fn validate(&self) -> Result<(), EspeakError> {
+        let checks = [
+            ("rate", self.rate, Self::RATE_RANGE),
+            ("volume", self.volume, Self::VOLUME_RANGE),
+            ("pitch", self.pitch, Self::PITCH_RANGE),
+            ("range", self.range, Self::RANGE_RANGE),
+            (
+                "capitals",
+                self.capitals.and_then(|capitals| match capitals {
+                    Capitals::PitchRaise(hz) => Some(ParamValue::Absolute(i32::from(hz))),
+                    _ => None,
+                }),
+                Self::CAPITALS_PITCH_RANGE,
+            ),
+        ];
+        for (name, value, range) in checks {
+            let (value, range) = match value {
+                Some(ParamValue::Absolute(value)) => (value, range),
+                Some(ParamValue::Relative(percent)) => (percent, Self::RELATIVE_RANGE),
+                None => continue,
+            };
+            if !range.contains(&value) {
+                return Err(EspeakError::ParamOutOfRange { name, value, range });
+            }
+        }
+        Ok(())
+    }
+
+    /// Hand the params to espeak, returning the ones it refused. An unset
+    /// param is set again to espeak's default for it.
+    pub(crate) fn apply_params(self: SpeakerParams) -> Result<Vec<ParamApplyError>, EspeakError> {
+        self.validate()?;
+        let params = [
+            ("rate", espeak_PARAMETER_espeakRATE, self.rate),
+            ("volume", espeak_PARAMETER_espeakVOLUME, self.volume),
+            ("pitch", espeak_PARAMETER_espeakPITCH, self.pitch),
+            ("range", espeak_PARAMETER_espeakRANGE, self.range),
+            (
+                "punctuation",
+                espeak_PARAMETER_espeakPUNCTUATION,
+                self.punctuation.map(|p| ParamValue::Absolute(p.as_raw())),
+            ),
+            (
+                "capitals",
+                espeak_PARAMETER_espeakCAPITALS,
+                self.capitals.map(|c| ParamValue::Absolute(c.as_raw())),
+            ),
+            (
+                "word_gap",
+                espeak_PARAMETER_espeakWORDGAP,
+                self.word_gap.map(ParamValue::Absolute),
+            ),
+            (
+                "line_length",
+                espeak_PARAMETER_espeakLINELENGTH,
+                self.line_length.map(ParamValue::Absolute),
+            ),
+        ];
+        let mut errors = Vec::new();
+        for (param, param_enum, value) in params {
+            let default = unsafe { espeak_GetParameter(param_enum, 0) };
+            let range = match param {
+                "rate" => Self::RATE_RANGE,
+                "volume" => Self::VOLUME_RANGE,
+                "pitch" => Self::PITCH_RANGE,
+                "range" => Self::RANGE_RANGE,
+                _ => i32::MIN..=i32::MAX,
+            };
+            let value = value.map_or(default, |value| value.to_absolute(default, range));
+            let result = unsafe { espeak_SetParameter(param_enum, value, 0) };
+            #[cfg(test)]
+            let result = if value == REJECTED_PARAM_VALUE {
+                espeak_ERROR_EE_INTERNAL_ERROR
+            } else {
+                result
+            };
+            if let Err(status) = error::check(result) {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(param, value, %status, "espeak refused a parameter");
+                errors.push(ParamApplyError {
+                    param,
+                    value,
+                    status,
+                });
+            }
+        }
+        if let Some(chars) = &self.punctuation_chars {
+            let list: Vec<wchar_t> = chars
+                .chars()
+                .map(|c| c as u32 as wchar_t)
+                .chain(std::iter::once(0))
+                .collect();
+            let result = unsafe { espeak_SetPunctuationList(list.as_ptr()) };
+            if let Err(status) = error::check(result) {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(chars, %status, "espeak refused the punctuation list");
+                errors.push(ParamApplyError {
+                    param: "punctuation_chars",
+                    value: chars.chars().count() as i32,
+                    status,
+                });
+            }
+        }
+        self.apply_raw_params(&mut errors);
+        Ok(errors)
+    }
+
+    /// Hand the raw params to espeak, after resetting the ones an earlier
+    /// utterance set and this one doesn't to espeak's defaults.
+    fn apply_raw_params(&self, errors: &mut Vec<ParamApplyError>) {
+        /// The raw params set by the last utterance.
+        static RAW_PARAMS_SET: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+        let mut set = RAW_PARAMS_SET.plock();
+        for param in set.iter() {
+            if !self.raw_params.iter().any(|(p, ..)| p == param) {
+                unsafe {
+                    espeak_SetParameter(*param, espeak_GetParameter(*param, 0), 0);
+                }
+            }
+        }
+        *set = self.raw_params.iter().map(|(param, ..)| *param).collect();
+        for &(param, value, relative) in &self.raw_params {
+            let result = unsafe { espeak_SetParameter(param, value, relative as c_int) };
+            if let Err(status) = error::check(result) {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(param, value, %status, "espeak refused a raw parameter");
+                errors.push(ParamApplyError {
+                    param: "raw_params",
+                    value,
+                    status,
+                });
+            }
+        }
+    }
+
+    /// Set an espeak param that has no field here, such as
+    /// `espeakEMPHASIS`, by its number. The value goes to espeak
+    /// unchecked, after the other params. Like them it only applies to
+    /// this speaker's utterances: the next utterance without it gets
+    /// espeak's default back.
+    pub fn set_raw(&mut self, param: u32, value: i32, relative: bool) {
+        self.raw_params.retain(|(p, ..)| *p != param);
+        self.raw_params.push((param, value, relative));
+    }
+
+    /// The value espeak is using now for the param numbered `param`,
+    /// including ones set with [`set_raw`](SpeakerParams::set_raw).
+    pub fn current_raw(param: u32) -> i32 {
+        init().expect("Failed to initialize espeak");
+        let _lock = espeak_lock();
+        unsafe { espeak_GetParameter(param, 1) }
+    }
+
+    /// The flags to pass to `espeak_Synth` for text in `text_mode`.
+    pub(crate) fn synth_flags(&self, text_mode: TextMode) -> u32 {
+        match self.override_synth_flags {
+            Some(flags) => flags,
+            None => {
+                let mut flags = self.flags.bits(text_mode) | self.extra_synth_flags;
+                if self.allow_inline_phonemes && text_mode == TextMode::Plain {
+                    flags |= espeakPHONEMES;
+                }
+                flags
+            }
+        }
+    }
+}
+
+impl Default for SpeakerParams {
+    fn default() -> SpeakerParams {
+        SpeakerParams::new()
+    }
+}
+
+/// Speaks text with a voice and params of its own.
+///
+/// Each utterance takes a copy of the params and voice, and they're handed
+/// to espeak under the espeak lock just before it's synthesized, so other
+/// speakers' utterances can't change them. A speaker can be shared between
+/// threads, e.g. in an `Arc`, and spoken with from all of them at once.
+pub struct Speaker {
+    pub params: SpeakerParams,
+    voice_name: String,
+    follow_global: bool,
+}
+
+// Speakers are shared between threads, so they must stay `Send + Sync`.
+const _: () = {
+    const fn shareable<T: Send + Sync>() {}
+    shareable::<Speaker>()
+};
+
+impl Speaker {
+    /// Configure a speaker, checking everything at once in
+    /// [`SpeakerBuilder::build`].
+    pub fn builder() -> SpeakerBuilder {
+        SpeakerBuilder {
+            params: SpeakerParams::new(),
+            voice_name: String::default(),
+        }
+    }
+
+    /// Like [`Speaker::new`], but first makes sure espeak can be
+    /// initialized, so that missing data is reported up front.
+    pub fn try_new() -> Result<Speaker, EspeakError> {
+        init()?;
+        Ok(Speaker::new())
+    }
+
+    pub fn new() -> Speaker {
+        Speaker {
+            params: SpeakerParams::new(),
+            voice_name: String::default(),
+            follow_global: false,
+        }
+    }
+
+    /// Create a speaker whose params start as a copy of the
+    /// [`global_defaults`].
+    pub fn new_with_global_defaults() -> Speaker {
+        Speaker {
+            params: SpeakerParams::from_global(),
+            ..Speaker::new()
+        }
+    }
+
+    /// When `follow` is true, each utterance uses the [`global_defaults`]
+    /// as they are at that moment, and `params` is ignored.
+    pub fn follow_global(&mut self, follow: bool) {
+        self.follow_global = follow;
+    }
+
+    fn current_params(&self) -> SpeakerParams {
+        if self.follow_global {
+            global_defaults().get()
+        } else {
+            self.params.clone()
+        }
+    }
+
+    /// Speak `text`, removing any NUL bytes from it first.
+    /// Use [`Speaker::try_speak`] to have them reported instead.
+    pub fn speak(&self, text: &str) -> SpeakerSource {
+        SpeakerSource::new(text, &self.voice_name, self.current_params())
+    }
+
+    pub fn try_speak(&self, text: &str) -> Result<SpeakerSource, EspeakError> {
+        let params = self.current_params();
+        params.validate()?;
+        let text_mode = TextMode::from_params(&params);
+        SpeakerSource::try_with_text_mode(text, &self.voice_name, params, text_mode)
+    }
+
+    /// Speak the single character `c`, as when echoing what's typed. This
+    /// skips the sentence processing of [`Speaker::speak`], which makes it
+    /// quicker to start, and the pauses around a sentence.
+    pub fn speak_char(&self, c: char) -> SpeakerSource {
+        self.try_speak_char(c).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Like [`Speaker::speak_char`], but reports a NUL character, or one
+    /// espeak can't take on this platform, instead of panicking.
+    pub fn try_speak_char(&self, c: char) -> Result<SpeakerSource, EspeakError> {
+        let params = self.current_params();
+        params.validate()?;
+        SpeakerSource::try_char(c, &self.voice_name, params)
+    }
+
+    /// Speak `text` as SSML, regardless of the speaker's params.
+    pub fn speak_ssml(&self, text: &str) -> SpeakerSource {
+        SpeakerSource::with_text_mode(
+            text,
+            &self.voice_name,
+            self.current_params(),
+            TextMode::Ssml,
+        )
+    }
+
+    /// Read out the cells of `table`, reporting an [`Event::Cell`] as each
+    /// one is reached. Other events refer to the table's generated SSML.
+    pub fn speak_table(&self, table: &TableSpeech) -> SpeakerSource {
+        let mut source = self.speak_ssml(&table.ssml());
+        source.table_cells = true;
+        source
+    }
+
+    /// Spell out `text` with the phonetic alphabet for the voice's
+    /// language, e.g. "Alpha, Bravo, 1" for `AB1`. Each word is reported
+    /// as an [`Event::Word`] covering the character it spells.
+    pub fn speak_phonetic_alphabet(&self, text: &str) -> SpeakerSource {
+        let text = text.replace('\0', "");
+        let voice_name = self.voice_name.replace('\0', "");
+        let spell = |text: &str, voice_name: &str, _: &SpeakerParams| {
+            let alphabet = spelling::language_alphabet(&viseme::voice_language(voice_name));
+            TransformReport::rewritten("phonetic_alphabet", spelling::spell(text, &alphabet))
+        };
+        SpeakerSource::try_with_rewrite(
+            &text,
+            &voice_name,
+            self.current_params(),
+            TextMode::Plain,
+            spell,
+            &[],
+        )
+        .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Spell out `text` by naming each character, "R, U, S, T" for `Rust`,
+    /// with [`DEFAULT_SPELLING_GAP`](spelling::DEFAULT_SPELLING_GAP)
+    /// between them. Each character is reported as an [`Event::Word`]
+    /// covering it, with accents and emoji modifiers counted as part of
+    /// the character they go with. Whitespace is skipped, and
+    /// [`SpeakerParams::capitals`] says how capitals are announced.
+    pub fn spell(&self, text: &str) -> SpeakerSource {
+        self.spell_with_gap(text, spelling::DEFAULT_SPELLING_GAP)
+    }
+
+    /// Like [`Speaker::spell`], with `gap` between characters.
+    pub fn spell_with_gap(&self, text: &str, gap: Duration) -> SpeakerSource {
+        let text = text.replace('\0', "");
+        let voice_name = self.voice_name.replace('\0', "");
+        let spell = |text: &str, _: &str, _: &SpeakerParams| {
+            TransformReport::rewritten("spelling", spelling::spell_characters(text, gap))
+        };
+        let mut params = self.current_params();
+        // The SSML is a single element, which can't be split.
+        params.synth_text_budget = usize::MAX;
+        let mut source =
+            SpeakerSource::try_with_rewrite(&text, &voice_name, params, TextMode::Ssml, spell, &[])
+                .unwrap_or_else(|e| panic!("{}", e));
+        source.spelling = true;
+        source
+    }
+
+    /// Speak `text` literally, so any markup in it is read aloud.
+    pub fn speak_plain(&self, text: &str) -> SpeakerSource {
+        SpeakerSource::with_text_mode(
+            text,
+            &self.voice_name,
+            self.current_params(),
+            TextMode::Plain,
+        )
+    }
+
+    /// Speak `segments` one after another as a single source, with the
+    /// text segments read as plain text. Events refer to the text segments
+    /// joined together, as given.
+    ///
+    /// ```no_run
+    /// use espeak_rs::{Segment, Speaker};
+    /// use std::time::Duration;
+    ///
+    /// let source = Speaker::new().speak_segments(&[
+    ///     Segment::Text("Your code is "),
+    ///     Segment::Silence(Duration::from_millis(800)),
+    ///     Segment::Text("1 2 3 4"),
+    /// ]);
+    /// ```
+    pub fn speak_segments(&self, segments: &[Segment]) -> SpeakerSource {
+        let mut text = String::new();
+        let mut pauses = Vec::new();
+        for segment in segments {
+            match segment {
+                Segment::Text(segment) => text.push_str(&segment.replace('\0', "")),
+                Segment::Silence(length) => pauses.push((text.len(), *length)),
+            }
+        }
+        let voice_name = self.voice_name.replace('\0', "");
+        let rewrite = |text: &str, voice_name: &str, params: &SpeakerParams| {
+            transform(text, voice_name, params, TextMode::Plain)
+        };
+        SpeakerSource::try_with_rewrite(
+            &text,
+            &voice_name,
+            self.current_params(),
+            TextMode::Plain,
+            rewrite,
+            &pauses,
+        )
+        .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Speak each sentence of the plain `text` with a source of its own,
+    /// e.g. to pause, repeat or skip them one by one. Sentences end where
+    /// [`Event::Sentence`]s do, and come with their text.
+    pub fn speak_sentences(&self, text: &str) -> Vec<(String, SpeakerSource)> {
+        split_sentences(text, TextMode::Plain)
+            .into_iter()
+            .map(|range| {
+                let sentence = &text[range];
+                (sentence.to_string(), self.speak_plain(sentence))
+            })
+            .collect()
+    }
+
+    /// Speak text as it arrives on `rx`, starting before the whole of it
+    /// is known, e.g. while a log is being written. Fragments are joined
+    /// as given and read as plain text, without the rewrites the params
+    /// turn on. Each sentence is spoken once the text after it arrives, or
+    /// once none has for [`STREAM_FLUSH_TIMEOUT`], and the source ends when
+    /// the sender is dropped and the rest has been spoken.
+    ///
+    /// Events refer to the text spoken so far, which
+    /// [`SpeakerSource::effective_text`] returns and which grows as the
+    /// source is read.
+    ///
+    /// ```no_run
+    /// use espeak_rs::Speaker;
+    /// use std::sync::mpsc;
+    ///
+    /// let (tx, rx) = mpsc::channel();
+    /// let source = Speaker::new().speak_stream(rx);
+    /// tx.send(String::from("Build started. ")).unwrap();
+    /// ```
+    pub fn speak_stream(&self, rx: Receiver<String>) -> SpeakerSource {
+        self.speak_stream_with_timeout(rx, STREAM_FLUSH_TIMEOUT)
+    }
+
+    /// Like [`Speaker::speak_stream`], waiting `timeout` for more text
+    /// before speaking an unfinished sentence.
+    pub fn speak_stream_with_timeout(
+        &self,
+        rx: Receiver<String>,
+        timeout: Duration,
+    ) -> SpeakerSource {
+        let voice_name = self.voice_name.replace('\0', "");
+        stream::stream_source(rx, &voice_name, self.current_params(), timeout)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Speak `text` without playing it, and report the phonemes spoken for
+    /// each word. Words with phonemes foreign to the voice were likely
+    /// read in another language, as espeak does with text in another
+    /// script; see [`WordStat::foreign_phonemes`].
+    pub fn word_stats(&self, text: &str) -> Result<Vec<WordStat>, EspeakError> {
+        let inventory = word_stats::phoneme_inventory(&self.voice_name)?;
+        let mut params = self.current_params();
+        params.phoneme_events = true;
+        let text_mode = TextMode::from_params(&params);
+        let source = SpeakerSource::try_with_text_mode(text, &self.voice_name, params, text_mode)?;
+        let mut events = Vec::new();
+        let mut source = source.with_callback(|event| events.push(event));
+        source.by_ref().for_each(drop);
+        if let Some(error) = source.error() {
+            return Err(error.clone());
+        }
+        drop(source);
+        Ok(word_stats::word_stats(text, events, &inventory))
+    }
+
+    /// How long `text` takes to speak, found by synthesizing it and
+    /// counting the samples. The audio is discarded, so this takes as long
+    /// as synthesis, but not as long as playing it.
+    pub fn estimate_duration(&self, text: &str) -> Duration {
+        let mut source = self.speak(text);
+        let samples = source.by_ref().count();
+        Duration::from_secs_f64(samples as f64 / source.sample_rate() as f64)
+    }
+
+    /// A rough guess at how long `text` takes to speak, from its number of
+    /// words, the rate and the word gap, without calling espeak. Pauses
+    /// for punctuation and how long each word is aren't counted, so it may
+    /// be off by a third or more; use
+    /// [`estimate_duration`](Speaker::estimate_duration) when that matters.
+    pub fn estimate_duration_fast(&self, text: &str) -> Duration {
+        let params = self.current_params();
+        let normal = espeakRATE_NORMAL as i32;
+        let words_per_minute = params
+            .rate
+            .map_or(normal, |rate| {
+                rate.to_absolute(normal, SpeakerParams::RATE_RANGE)
+            })
+            .max(1);
+        let words = text.split_whitespace().count() as u32;
+        let word_gap = Duration::from_millis(params.word_gap.unwrap_or(0).max(0) as u64 * 10);
+        Duration::from_secs_f64(words as f64 * 60.0 / words_per_minute as f64)
+            + word_gap * words.saturating_sub(1)
+    }
+
+    /// Say `replacement` in place of `word` in plain text, e.g. "engine x"
+    /// for "nginx". Words are matched regardless of case and only as whole
+    /// words, so "cat" doesn't change "category". Events still refer to
+    /// the text as given. Replaces any pronunciation `word` had.
+    pub fn add_pronunciation(&mut self, word: &str, replacement: &str) {
+        self.params
+            .pronunciations
+            .insert(word.to_lowercase(), String::from(replacement));
+    }
+
+    pub fn set_voice(&mut self, voice: &Voice) {
+        self.voice_name = voice.name.clone();
+    }
+}
+
+impl Default for Speaker {
+    fn default() -> Speaker {
+        Speaker::new()
+    }
+}
+
+/// Builds a [`Speaker`], from [`Speaker::builder`]. Unset parameters use
+/// espeak's defaults, as with [`SpeakerParams::new`].
+#[derive(Clone)]
+pub struct SpeakerBuilder {
+    params: SpeakerParams,
+    voice_name: String,
+}
+
+impl SpeakerBuilder {
+    /// Start from `params` rather than [`SpeakerParams::new`].
+    pub fn params(mut self, params: SpeakerParams) -> SpeakerBuilder {
+        self.params = params;
+        self
+    }
+
+    pub fn voice(mut self, voice: &Voice) -> SpeakerBuilder {
+        self.voice_name = voice.name.clone();
+        self
+    }
+
+    /// Select a voice by anything espeak accepts for it: its name, its
+    /// identifier such as `gmw/en-GB`, or a language such as `en-GB`.
+    pub fn voice_name(mut self, name: &str) -> SpeakerBuilder {
+        self.voice_name = String::from(name);
+        self
+    }
+
+    /// See [`Rate`].
+    pub fn rate(mut self, rate: impl Into<Rate>) -> SpeakerBuilder {
+        self.params.rate = Some(rate.into().into());
+        self
+    }
+
+    /// See [`SpeakerParams::VOLUME_RANGE`].
+    pub fn volume(mut self, volume: i32) -> SpeakerBuilder {
+        self.params.volume = Some(ParamValue::Absolute(volume));
+        self
+    }
+
+    /// See [`SpeakerParams::PITCH_RANGE`].
+    pub fn pitch(mut self, pitch: i32) -> SpeakerBuilder {
+        self.params.pitch = Some(ParamValue::Absolute(pitch));
+        self
+    }
+
+    /// See [`SpeakerParams::RANGE_RANGE`].
+    pub fn range(mut self, range: i32) -> SpeakerBuilder {
+        self.params.range = Some(ParamValue::Absolute(range));
+        self
+    }
+
+    pub fn word_gap(mut self, word_gap: i32) -> SpeakerBuilder {
+        self.params.word_gap = Some(word_gap);
+        self
+    }
+
+    /// See [`SpeakerParams::line_length`].
+    pub fn line_length(mut self, line_length: i32) -> SpeakerBuilder {
+        self.params.line_length = Some(line_length);
+        self
+    }
+
+    /// See [`SpeakerParams::strict_params`].
+    pub fn strict_params(mut self, strict: bool) -> SpeakerBuilder {
+        self.params.strict_params = strict;
+        self
+    }
+
+    /// Whether [`Speaker::speak`] reads its text as SSML.
+    pub fn ssml(mut self, ssml: bool) -> SpeakerBuilder {
+        self.params.flags.ssml = ssml;
+        self
+    }
+
+    /// Check the parameters' ranges and that the voice exists.
+    pub fn build(self) -> Result<Speaker, EspeakError> {
+        self.params.validate()?;
+        if !self.voice_name.is_empty() {
+            let name = CString::new(self.voice_name.as_str()).map_err(|e| {
+                EspeakError::InvalidVoiceName {
+                    nul_position: e.nul_position(),
+                }
+            })?;
+            init()?;
+            let _lock = espeak_lock();
+            let result = unsafe { espeak_SetVoiceByName(name.as_ptr()) };
+            error::check_voice(result, &name)?;
+        }
+        Ok(Speaker {
+            params: self.params,
+            voice_name: self.voice_name,
+            follow_global: false,
+        })
+    }
+}
+
+pub struct SpeakerSource {
+    rx: Receiver<Message>,
+    error: Option<EspeakError>,
+    backlog: Arc<Backlog>,
+    sample_rate: u32,
+    data: VecDeque<i16>,
+    events: EventQueue,
+    iter_index: Option<u64>,
+    /// Whether espeak reported the end of the utterance.
+    completed: bool,
+    offsets: OffsetCorrector,
+    /// The text as given by the caller, which event positions refer to.
+    text: String,
+    voice_name: String,
+    text_mode: TextMode,
+    /// The text as handed to espeak, after all rewrites.
+    effective_text: String,
+    offset_map: OffsetMap,
+    /// Whether marks are turned into [`Event::Cell`].
+    table_cells: bool,
+    /// Whether each grapheme is reported as a single word, for
+    /// [`Speaker::spell`].
+    spelling: bool,
+    /// Where the last word reported while spelling starts.
+    last_spelled: Option<usize>,
+    /// The text of [`Speaker::speak_stream`] so far, which grows as the
+    /// synthesis thread takes on more of it.
+    streamed_text: Option<Arc<Mutex<String>>>,
+    /// Built from `text` the first time a position is asked for.
+    positions: OnceCell<PositionIndex>,
+    /// Filled in by the synthesis thread before it sends any audio.
+    param_errors: Arc<Mutex<Vec<ParamApplyError>>>,
+    job: Arc<Job>,
+    broadcaster: Broadcaster,
+    #[cfg(feature = "test-tone")]
+    tone: Option<tone::ToneGenerator>,
+}
+
+/// Corrections the crate made to what espeak reported for an utterance.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Diagnostics {
+    /// Bytes added to every text position after the first word's reported
+    /// position didn't match the text. `0` when no correction was needed.
+    pub text_offset_correction: isize,
+}
+
+/// Everything the synthesis thread needs for one utterance.
+struct SynthJob {
+    /// The text to speak, split into pieces along with their byte offsets.
+    pieces: Vec<(usize, CString)>,
+    /// Silence to play before the piece at each index, or after the last
+    /// piece for an index past it.
+    pauses: Vec<(usize, Duration)>,
+    voice_name: CString,
+    params: SpeakerParams,
+    text_mode: TextMode,
+    sample_rate: u32,
+    /// Where to report the params espeak refused.
+    param_errors: Arc<Mutex<Vec<ParamApplyError>>>,
+    job: Arc<Job>,
+    /// The job's place in the synthesis queue, held until it's done.
+    turn: Turn,
+    /// Whether more text follows in the same source, so the end of the
+    /// last piece isn't the end of the utterance.
+    continued: bool,
+    /// A character to speak with `espeak_Char`, in place of the only
+    /// piece, which is what espeak makes of it.
+    character: Option<wchar_t>,
+}
+
+/// What `synth_callback` receives as its user data.
+struct SynthContext {
+    tx: ChunkSender,
+    sample_rate: u32,
+    /// Byte offset of the current piece within the whole text.
+    text_offset: usize,
+    /// [`char_starts`] of the current piece.
+    char_starts: Vec<usize>,
+    /// Audio position at which the current piece starts.
+    audio_offset_ms: u32,
+    samples_sent: u64,
+    /// Whether a piece has already reported the start of the utterance.
+    started: bool,
+    /// Set once the receiving source is gone or the job is cancelled.
+    cancelled: bool,
+    job: Arc<Job>,
+    /// Whether the end of the last piece has been reported.
+    terminated: bool,
+    /// Whether espeak synthesizes on its own thread, in
+    /// [`OutputMode::Retrieval`], so `espeak_Synth` must be waited for.
+    asynchronous: bool,
+    /// Whether the current piece is the last, the only one whose end is
+    /// the end of the utterance.
+    last_piece: bool,
+    /// Chunks held back until one has audio in it, so that an attempt
+    /// which turns out silent can be retried unseen. `None` once released,
+    /// or when not retrying.
+    held: Option<Vec<Chunk>>,
+    /// Drop the audio of this attempt, to test retries.
+    #[cfg(test)]
+    drop_audio: bool,
+}
+
+impl SynthContext {
+    /// Send a chunk to the source, or hold it back if the attempt hasn't
+    /// produced audio yet.
+    fn send(&mut self, chunk: Chunk) {
+        if let Some(held) = &mut self.held {
+            if chunk.0.is_empty() {
+                held.push(chunk);
+                return;
+            }
+            for chunk in std::mem::take(held) {
+                self.send_now(chunk);
+            }
+            self.held = None;
+        }
+        self.send_now(chunk);
+    }
+
+    fn send_now(&mut self, chunk: Chunk) {
+        if !self.cancelled && self.tx.send(chunk).is_err() {
+            self.cancelled = true;
+        }
+    }
+
+    /// Send `length` of silence, held back like espeak's audio would be.
+    fn send_silence(&mut self, length: Duration) {
+        let samples = (length.as_secs_f64() * self.sample_rate as f64).round() as usize;
+        self.samples_sent += samples as u64;
+        let chunk = (vec![0; samples], Vec::new());
+        match &mut self.held {
+            Some(held) => held.push(chunk),
+            None => self.send_now(chunk),
+        }
+    }
+}
+
+/// A param value espeak is made to refuse, to test refusals.
+#[cfg(test)]
+const REJECTED_PARAM_VALUE: i32 = -4242;
+
+/// Initialize espeak again if [`shutdown`] was called since it was, and
+/// return the espeak lock.
+fn reinit(
+    lock: MutexGuard<'static, Option<InitState>>,
+) -> Result<MutexGuard<'static, Option<InitState>>, EspeakError> {
+    if lock.is_some() {
+        return Ok(lock);
+    }
+    drop(lock);
+    init()?;
+    Ok(espeak_lock())
+}
+
+/// Whether espeak synthesizes on its own thread, so `espeak_Synth` must be
+/// waited for.
+fn is_asynchronous(lock: &Option<InitState>) -> bool {
+    matches!(lock, Some(state) if state.output_mode == OutputMode::Retrieval)
+}
+
+/// Run `work` on a new synthesis thread. A panic is reported to the source
+/// as an error, so it ends rather than waiting for audio that won't come.
+fn spawn_synthesis<F>(tx: ChunkSender, work: F)
+where
+    F: FnOnce(ChunkSender) + Send + 'static,
+{
+    ACTIVE_SYNTHESES.fetch_add(1, Ordering::SeqCst);
+    thread::spawn(move || {
+        let panic_tx = tx.clone();
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| work(tx))) {
+            let message = panic_message(&*payload);
+            let _ = panic_tx.send_error(EspeakError::SynthesisPanicked { message });
+        }
+        ACTIVE_SYNTHESES.fetch_sub(1, Ordering::SeqCst);
+    });
+}
+
+impl SynthJob {
+    fn synth_flags(&self) -> u32 {
+        let flags = self.params.synth_flags(self.text_mode);
+        #[cfg(feature = "tracing")]
+        if (flags & espeakSSML != 0) != (self.text_mode == TextMode::Ssml) {
+            tracing::warn!(
+                flags,
+                text_mode = ?self.text_mode,
+                "espeak_Synth flags disagree with the utterance's text mode"
+            );
+        }
+        flags
+    }
+
+    /// Apply the params and select the voice. Synthesizing after an error
+    /// would silently use the previous settings.
+    fn configure(&self) -> Result<(), EspeakError> {
+        let param_errors = self.params.clone().apply_params()?;
+        *self.param_errors.plock() = param_errors.clone();
+        if self.params.strict_params && !param_errors.is_empty() {
+            return Err(EspeakError::ParamsRejected(param_errors));
+        }
+        unsafe {
+            espeak_ng_SetPhonemeEvents(self.params.phoneme_events as c_int, 0);
+        }
+        let result = unsafe { espeak_SetVoiceByName(self.voice_name.as_ptr() as *const c_char) };
+        error::check_voice(result, &self.voice_name)?;
+        unsafe {
+            espeak_SetSynthCallback(Some(SpeakerSource::synth_callback));
+        }
+        Ok(())
+    }
+
+    fn run(self, tx: ChunkSender) {
+        self.turn.wait();
+        let lock = espeak_lock();
+        if !self.job.start() {
+            // Cancelled while waiting for the lock, and already reported.
+            return;
+        }
+        let lock = match reinit(lock) {
+            Ok(lock) => lock,
+            Err(error) => {
+                let _ = tx.send_error(error);
+                self.job.finish();
+                return;
+            }
+        };
+        let asynchronous = is_asynchronous(&lock);
+        let flags = self.synth_flags();
+        if let Err(error) = self.configure() {
+            let _ = tx.send_error(error);
+            self.job.finish();
+            return;
+        }
+
+        let retry = self.params.retry_on_empty
+            && self
+                .pieces
+                .iter()
+                .any(|(_, text)| !text.as_bytes().is_empty());
+        let mut context = SynthContext {
+            tx,
+            sample_rate: self.sample_rate,
+            text_offset: 0,
+            char_starts: Vec::new(),
+            audio_offset_ms: 0,
+            samples_sent: 0,
+            started: false,
+            cancelled: false,
+            terminated: false,
+            job: self.job.clone(),
+            asynchronous,
+            last_piece: false,
+            held: retry.then(Vec::new),
+            #[cfg(test)]
+            drop_audio: self.params.drop_attempts > 0,
+        };
+
+        let mut completed = self.synthesize(&mut context, flags);
+        if completed && context.held.is_some() {
+            #[cfg(feature = "tracing")]
+            tracing::warn!("espeak produced no audio; selecting the voice again and retrying");
+            context.held = Some(Vec::new());
+            context.samples_sent = 0;
+            context.started = false;
+            context.terminated = false;
+            #[cfg(test)]
+            {
+                context.drop_audio = self.params.drop_attempts > 1;
+            }
+            completed = match self.configure() {
+                Ok(()) => self.synthesize(&mut context, flags),
+                Err(error) => {
+                    let _ = context.tx.send_error(error);
+                    false
+                }
+            };
+        }
+        match context.held.take() {
+            Some(held) => {
+                for chunk in held {
+                    context.send_now(chunk);
+                }
+                if completed {
+                    let _ = context.tx.send_error(EspeakError::SilentOutput);
+                }
+            }
+            None if completed && !context.terminated => {
+                // espeak doesn't report the end of every utterance, e.g.
+                // of empty text, but it was spoken all the same.
+                let audio_ms = (context.samples_sent * 1000 / self.sample_rate as u64) as u32;
+                let terminated = Event::Terminated { audio_ms };
+                context.send_now((Vec::new(), vec![(audio_ms, terminated)]));
+            }
+            None => (),
+        }
+        if self.job.is_cancelled() {
+            let _ = context.tx.send_error(EspeakError::Cancelled);
+        }
+        self.job.finish();
+    }
+
+    /// Synthesize each piece in turn. Returns whether all of them were,
+    /// rather than stopping on an error or cancellation.
+    fn synthesize(&self, context: &mut SynthContext, flags: u32) -> bool {
+        let pause = |context: &mut SynthContext, index: usize| {
+            for (_, length) in self.pauses.iter().filter(|(at, _)| *at == index) {
+                context.send_silence(*length);
+            }
+        };
+        // Whether this returns or panics, espeak must not be left pointing
+        // at `context`, which is about to go away.
+        let _clear = ClearSynthContext;
+        // After a trailing pause, the end is reported once it's played.
+        let trailing_pause = self.pauses.iter().any(|(at, _)| *at == self.pieces.len());
+        for (i, (text_offset, text)) in self.pieces.iter().enumerate() {
+            pause(context, i);
+            context.text_offset = *text_offset;
+            context.char_starts = char_starts(text.to_str().unwrap_or_default());
+            context.last_piece = i + 1 == self.pieces.len() && !trailing_pause && !self.continued;
+            context.audio_offset_ms =
+                (context.samples_sent * 1000 / context.sample_rate as u64) as u32;
+            let context_ptr: *mut c_void = context as *mut SynthContext as *mut c_void;
+            SYNTH_CONTEXT.store(context_ptr as *mut SynthContext, Ordering::SeqCst);
+
+            let position = 0u32;
+            let position_type: espeak_POSITION_TYPE = 0;
+            let end_position = 0u32;
+
+            let identifier = std::ptr::null_mut();
+            let result = match self.character {
+                // The callback gets no user data, and falls back on
+                // SYNTH_CONTEXT.
+                Some(character) => unsafe { espeak_Char(character) },
+                None => unsafe {
+                    espeak_Synth(
+                        text.as_ptr() as *const c_void,
+                        text.as_bytes_with_nul().len(),
+                        position,
+                        position_type,
+                        end_position,
+                        flags,
+                        identifier,
+                        context_ptr,
+                    )
+                },
+            };
+            if result == espeak_ERROR_EE_OK && context.asynchronous {
+                // The callback runs on espeak's thread until this returns.
+                unsafe { espeak_Synchronize() };
+            }
+            if let Err(error) = error::check(result) {
+                let _ = context.tx.send_error(error);
+                return false;
+            }
+            if context.cancelled {
+                return false;
+            }
+        }
+        pause(context, self.pieces.len());
+        !context.cancelled
+    }
+}
+
+impl SpeakerSource {
+    pub fn new(text: &str, voice_name: &str, params: SpeakerParams) -> SpeakerSource {
+        let text_mode = TextMode::from_params(&params);
+        Self::with_text_mode(text, voice_name, params, text_mode)
+    }
+
+    /// Like [`SpeakerSource::try_with_text_mode`], but NUL bytes are
+    /// removed from the text and voice name rather than reported.
+    pub fn with_text_mode(
+        text: &str,
+        voice_name: &str,
+        params: SpeakerParams,
+        text_mode: TextMode,
+    ) -> SpeakerSource {
+        let text = text.replace('\0', "");
+        let voice_name = voice_name.replace('\0', "");
+        match Self::try_with_text_mode(&text, &voice_name, params.clone(), text_mode) {
+            Ok(source) => source,
+            Err(EspeakError::TextTooLong { .. }) => {
+                // SSML that can't be split is handed to espeak whole.
+                let mut params = params;
+                params.synth_text_budget = usize::MAX;
+                Self::try_with_text_mode(&text, &voice_name, params, text_mode)
+                    .expect("text fits the budget")
+            }
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    pub fn try_with_text_mode(
+        text: &str,
+        voice_name: &str,
+        params: SpeakerParams,
+        text_mode: TextMode,
+    ) -> Result<SpeakerSource, EspeakError> {
+        let rewrite = |text: &str, voice_name: &str, params: &SpeakerParams| {
+            transform(text, voice_name, params, text_mode)
+        };
+        Self::try_with_rewrite(text, voice_name, params, text_mode, rewrite, &[])
+    }
+
+    /// Like [`SpeakerSource::try_with_text_mode`], with `rewrite` in place
+    /// of the rewrites the params enable, and `pauses` of silence played
+    /// at byte positions of the text.
+    fn try_with_rewrite<R>(
+        text: &str,
+        voice_name: &str,
+        params: SpeakerParams,
+        text_mode: TextMode,
+        rewrite: R,
+        pauses: &[(usize, Duration)],
+    ) -> Result<SpeakerSource, EspeakError>
+    where
+        R: FnOnce(&str, &str, &SpeakerParams) -> TransformReport,
+    {
+        Self::try_synthesize(text, voice_name, params, text_mode, rewrite, pauses, None)
+    }
+
+    /// Speak `c` with `espeak_Char`, which takes it without any sentence
+    /// processing. Events refer to the SSML espeak makes of it.
+    fn try_char(
+        c: char,
+        voice_name: &str,
+        params: SpeakerParams,
+    ) -> Result<SpeakerSource, EspeakError> {
+        let character =
+            wchar_t::try_from(u32::from(c)).map_err(|_| EspeakError::UnsupportedChar { c })?;
+        let text = c.to_string();
+        let rewrite = |text: &str, _: &str, _: &SpeakerParams| {
+            // What espeak_Char hands to espeak_Synth.
+            let ssml = format!(
+                "<say-as interpret-as=\"tts:char\">&#{};</say-as>",
+                u32::from(c)
+            );
+            let mut map = TextMap::default();
+            map.push(0..ssml.len(), 0..text.len());
+            TransformReport::rewritten("char", (ssml, map))
+        };
+        let mut params = params;
+        params.synth_text_budget = usize::MAX;
+        Self::try_synthesize(
+            &text,
+            voice_name,
+            params,
+            TextMode::Ssml,
+            rewrite,
+            &[],
+            Some(character),
+        )
+    }
+
+    /// Like [`SpeakerSource::try_with_rewrite`], speaking `character` with
+    /// `espeak_Char` in place of the rewritten text, if given.
+    fn try_synthesize<R>(
+        text: &str,
+        voice_name: &str,
+        params: SpeakerParams,
+        text_mode: TextMode,
+        rewrite: R,
+        pauses: &[(usize, Duration)],
+        character: Option<wchar_t>,
+    ) -> Result<SpeakerSource, EspeakError>
+    where
+        R: FnOnce(&str, &str, &SpeakerParams) -> TransformReport,
+    {
+        let voice_name = CString::new(if voice_name.is_empty() {
+            "en"
+        } else {
+            voice_name
+        })
+        .map_err(|e| EspeakError::InvalidVoiceName {
+            nul_position: e.nul_position(),
+        })?;
+        if let Some(nul_position) = text.find('\0') {
+            return Err(EspeakError::InvalidText { nul_position });
+        }
+        let original = text;
+        let report = rewrite(text, voice_name.to_str().unwrap_or_default(), &params);
+        let text = &report.effective_text;
+        if let Some(position) = text.find('\0') {
+            // Put there by a pronunciation or URL placeholder.
+            return Err(EspeakError::InvalidText {
+                nul_position: report.offset_map.to_original(position),
+            });
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            effective_text = transform::truncated(text, 200),
+            stages = ?report.applied_stages,
+            "text prepared for espeak"
+        );
+        // The text is also split where each pause goes, and the pause
+        // noted along with the piece it comes before.
+        let mut pieces = Vec::new();
+        let mut piece_pauses = Vec::new();
+        let mut start = 0;
+        let ends = pauses
+            .iter()
+            .map(|(position, _)| report.offset_map.to_effective(*position))
+            .chain([text.len()]);
+        for (i, end) in ends.enumerate() {
+            let end = end.clamp(start, text.len());
+            if end > start || (i == pauses.len() && pieces.is_empty()) {
+                for (offset, piece) in
+                    split_text(&text[start..end], params.synth_text_budget, text_mode)?
+                {
+                    let piece = CString::new(piece).expect("text has no NUL bytes");
+                    pieces.push((start + offset, piece));
+                }
+            }
+            if let Some((_, length)) = pauses.get(i) {
+                piece_pauses.push((pieces.len(), *length));
+            }
+            start = end;
+        }
+        let offsets = OffsetCorrector::new(text, text_mode);
+
+        let sample_rate = init()?;
+        let ahead = params
+            .synth_ahead
+            .map(|ahead| (ahead.as_secs_f64() * sample_rate as f64) as u64);
+        let (tx, rx, backlog) = chunk_channel(params.channel_capacity, ahead);
+        let mut source = SpeakerSource::from_channel(rx, backlog, sample_rate, offsets);
+        source.text = String::from(original);
+        source.voice_name = String::from(voice_name.to_str().unwrap_or_default());
+        source.text_mode = text_mode;
+        source.effective_text = report.effective_text;
+        source.offset_map = report.offset_map;
+        #[cfg(feature = "test-tone")]
+        {
+            source.tone = params
+                .test_tone_mode
+                .then(|| tone::ToneGenerator::new(sample_rate));
+        }
+
+        let job = SynthJob {
+            pieces,
+            pauses: piece_pauses,
+            voice_name,
+            params,
+            text_mode,
+            sample_rate,
+            param_errors: source.param_errors.clone(),
+            job: source.job.clone(),
+            turn: Turn::take(),
+            continued: false,
+            character,
+        };
+        source.job.queue(tx.clone());
+        spawn_synthesis(tx, move |tx| job.run(tx));
+        Ok(source)
+    }
+
+    fn from_channel(
+        rx: Receiver<Message>,
+        backlog: Arc<Backlog>,
+        sample_rate: u32,
+        offsets: OffsetCorrector,
+    ) -> SpeakerSource {
+        SpeakerSource {
+            rx,
+            error: None,
+            backlog,
+            sample_rate,
+            data: VecDeque::new(),
+            events: EventQueue::new(sample_rate),
+            iter_index: Some(0),
+            completed: false,
+            offsets,
+            text: String::new(),
+            voice_name: String::new(),
+            text_mode: TextMode::Plain,
+            effective_text: String::new(),
+            offset_map: OffsetMap::default(),
+            table_cells: false,
+            spelling: false,
+            last_spelled: None,
+            streamed_text: None,
+            positions: OnceCell::new(),
+            param_errors: Arc::default(),
+            job: Arc::default(),
+            broadcaster: Broadcaster::default(),
+            #[cfg(feature = "test-tone")]
+            tone: None,
+        }
+    }
+
+    pub fn with_callback<F>(self, callback: F) -> SpeakerSourceWithCallback<F>
+    where
+        F: FnMut(Event),
+    {
+        SpeakerSourceWithCallback {
+            inner: self,
+            callback,
+        }
+    }
+
+    /// Like [`with_callback`](SpeakerSource::with_callback), but the
+    /// callback can stop the speech by returning `ControlFlow::Break`. The
+    /// source then ends without the rest of the audio, and synthesis is
+    /// abandoned.
+    pub fn with_callback_control<F>(self, callback: F) -> SpeakerSourceWithCallbackControl<F>
+    where
+        F: FnMut(Event) -> ControlFlow<()>,
+    {
+        SpeakerSourceWithCallbackControl {
+            inner: self,
+            callback,
+        }
+    }
+
+    /// Like [`with_callback`](SpeakerSource::with_callback), but each event
+    /// comes with its offset into the audio, as reported by espeak rather
+    /// than counted from the samples read.
+    pub fn with_timed_callback<F>(self, callback: F) -> SpeakerSourceWithTimedCallback<F>
+    where
+        F: FnMut(TimedEvent),
+    {
+        SpeakerSourceWithTimedCallback {
+            inner: self,
+            callback,
+        }
+    }
+
+    /// Like [`with_callback`](SpeakerSource::with_callback), but each event
+    /// goes to the callback, on a timer thread, once `clock` reports a
+    /// playback position within this source's audio at or past it. Made
+    /// for players that pull samples ahead of playing them, such as a
+    /// rodio `Sink`, whose `get_pos` is such a clock: while the sink is
+    /// paused the position stands still and so do the events.
+    ///
+    /// The [`Schedule`](schedule::Schedule) cancels the events that haven't
+    /// fired when dropped, so keep it until the speech has been played.
+    /// ```no_run
+    /// use espeak_rs::rodio::{OutputStream, Sink};
+    /// use std::sync::Arc;
+    ///
+    /// let (_stream, handle) = OutputStream::try_default().unwrap();
+    /// let sink = Arc::new(Sink::try_new(&handle).unwrap());
+    /// let position = sink.clone();
+    /// let source = espeak_rs::Speaker::new().speak("Hello world");
+    /// let (source, schedule) =
+    ///     source.with_paced_callback(move || position.get_pos(), |event| println!("{}", event));
+    /// sink.append(source);
+    /// schedule.join();
+    /// ```
+    pub fn with_paced_callback<C, F>(
+        self,
+        clock: C,
+        callback: F,
+    ) -> (schedule::PacedCallback, schedule::Schedule)
+    where
+        C: FnMut() -> Duration + Send + 'static,
+        F: FnMut(Event) + Send + 'static,
+    {
+        schedule::PacedCallback::new(self, clock, callback)
+    }
+
+    /// Call `callback` with the offset of each mouth shape as the audio
+    /// reaches it, for lip sync. Visemes come from phoneme events, so
+    /// [`SpeakerParams::phoneme_events`] must have been set when speaking,
+    /// and the mouth closes with [`Viseme::Sil`](viseme::Viseme::Sil) at
+    /// the end. Phonemes are mapped with the voice language's
+    /// [`language_map`](viseme::language_map).
+    pub fn with_viseme_callback<F>(
+        self,
+        mut callback: F,
+    ) -> SpeakerSourceWithTimedCallback<impl FnMut(TimedEvent)>
+    where
+        F: FnMut(Duration, viseme::Viseme),
+    {
+        let map = viseme::language_map(&viseme::voice_language(&self.voice_name));
+        self.with_timed_callback(move |timed| match timed.event {
+            Event::Phoneme { name, .. } => callback(timed.offset, map.viseme(&name)),
+            Event::End | Event::Cancelled => callback(timed.offset, viseme::Viseme::Sil),
+            _ => (),
+        })
+    }
+
+    pub fn iter_audio_and_events(self) -> IterAudioAndEvents {
+        IterAudioAndEvents {
+            inner: self,
+            callback: ignore_event,
+        }
+    }
+
+    /// Discard the audio and yield only the events, with their offsets
+    /// into it. Audio is dropped a chunk at a time as it arrives, so this
+    /// runs as fast as espeak can synthesize.
+    pub fn into_events(self) -> IntoEvents {
+        IntoEvents {
+            inner: self,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Share this source's events with any number of subscribers, each
+    /// receiving them as the audio reaches them. This works alongside
+    /// [`with_callback`](SpeakerSource::with_callback) and the other adapters.
+    pub fn events_broadcast(&self) -> EventBus {
+        self.broadcaster.bus()
+    }
+
+    /// Scale samples by the latest gain (0.0 to 1.0) received on `rx`,
+    /// ramping to each new value over [`GAIN_RAMP`].
+    #[cfg(feature = "dsp")]
+    pub fn with_gain_control(self, rx: Receiver<f32>) -> GainControlledSource {
+        GainControlledSource::new(self, rx)
+    }
+
+    /// Move the source into a ring buffer holding up to `capacity` of
+    /// audio, for real-time callbacks that must never block. A helper
+    /// thread keeps the ring filled.
+    pub fn into_ring(self, capacity: Duration) -> (RingProducerHandle, RingConsumer) {
+        ring::into_ring(self, capacity)
+    }
+
+    /// Encode the source as a streaming WAV file, for example to send as a
+    /// chunked HTTP response body while it is still being synthesized.
+    #[cfg(feature = "dsp")]
+    pub fn into_chunked_wav_stream(self) -> WavChunkStream {
+        WavChunkStream::new(self)
+    }
+
+    /// Why synthesis stopped early, if it did. Once an error is reported
+    /// the source yields no more audio.
+    pub fn error(&self) -> Option<&EspeakError> {
+        self.error.as_ref()
+    }
+
+    /// Take the error [`error`](SpeakerSource::error) would return, e.g.
+    /// to hand it on once the source has ended with [`Event::Cancelled`].
+    pub fn take_error(&mut self) -> Option<EspeakError> {
+        self.error.take()
+    }
+
+    /// Synthesize all of the audio and break its duration down into
+    /// leading silence, sentences and trailing silence. Blocks until
+    /// synthesis is done.
+    #[cfg(feature = "dsp")]
+    pub fn duration_breakdown(mut self) -> Result<DurationBreakdown, EspeakError> {
+        let mut samples = Vec::new();
+        let mut sentence_starts = Vec::new();
+        loop {
+            let (sample, events) = self.next_sample_and_events();
+            for event in events.into_iter().flatten() {
+                if let Event::Sentence { .. } = event {
+                    sentence_starts.push(samples.len());
+                }
+            }
+            match sample {
+                Some(sample) => samples.push(sample),
+                None => break,
+            }
+        }
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => Ok(DurationBreakdown::new(
+                &samples,
+                self.sample_rate,
+                &sentence_starts,
+            )),
+        }
+    }
+
+    /// The part of the text this source speaks that `event` covers. Only
+    /// words and sentences cover any text. For SSML this is a slice of the
+    /// markup, so an entity such as `&amp;` comes back as written.
+    pub fn resolve(&self, event: &Event) -> Option<&str> {
+        match event {
+            Event::Sentence { start, len } => self.text.get(*start..start.checked_add(*len)?),
+            _ => event.word_in(&self.text),
+        }
+    }
+
+    /// Where the text `event` covers is, in characters and UTF-16 code
+    /// units as well as bytes. Only words and sentences cover any text.
+    pub fn position(&self, event: &Event) -> Option<WordPosition> {
+        self.positions
+            .get_or_init(|| PositionIndex::new(&self.text))
+            .position(event)
+    }
+
+    /// Whether espeak reported finishing the utterance, as opposed to
+    /// synthesis stopping early on an error or panic. Only meaningful once
+    /// the source has ended.
+    pub fn completed(&self) -> bool {
+        self.completed
+    }
+
+    /// The text espeak was given, after the rewrites enabled in the
+    /// params. Useful when checking why something was mispronounced.
+    pub fn effective_text(&self) -> &str {
+        &self.effective_text
+    }
+
+    pub fn diagnostics(&self) -> Diagnostics {
+        Diagnostics {
+            text_offset_correction: self.offsets.offset(),
+        }
+    }
+
+    /// The params espeak refused for this utterance, if any. Complete once
+    /// the source has yielded a sample or an event, or has ended.
+    pub fn param_errors(&self) -> Vec<ParamApplyError> {
+        self.param_errors.plock().clone()
+    }
+
+    /// A token for cancelling this utterance from another thread, even
+    /// while it waits for earlier utterances to finish. See [`CancelToken`].
+    pub fn cancel_token(&self) -> CancelToken {
+        CancelToken::new(self.job.clone())
+    }
+
+    /// How much synthesized audio is queued between the synthesis thread
+    /// and this source. Samples already received by the source aren't counted.
+    pub fn backlog(&self) -> BacklogStats {
+        self.backlog.stats()
+    }
+
+    fn next_sample_and_events(&mut self) -> (Option<i16>, Option<Vec<Event>>) {
+        let (sample, events) = self.next_sample_and_timed_events();
+        let events = events.map(|events| events.into_iter().map(|timed| timed.event).collect());
+        (sample, events)
+    }
+
+    fn next_sample_and_timed_events(&mut self) -> (Option<i16>, Option<Vec<TimedEvent>>) {
+        match self.iter_index {
+            None => (None, None),
+            Some(i) => {
+                while self.data.is_empty() {
+                    match self.rx.recv() {
+                        Err(_) => return (None, Some(self.end_events(i))),
+                        Ok(Ok(chunk)) => {
+                            let samples = self.receive_chunk(chunk);
+                            self.data.extend(samples);
+                            if self.data.capacity() > self.data.len() * 4 {
+                                self.data.shrink_to_fit();
+                            }
+                        }
+                        Ok(Err(error)) => self.receive_error(error),
+                    }
+                }
+                let events: Vec<TimedEvent> = self
+                    .events
+                    .pop_due(i)
+                    .into_iter()
+                    .map(|(position, event)| TimedEvent {
+                        offset: Duration::from_millis(position),
+                        event,
+                    })
+                    .collect();
+                if !events.is_empty() {
+                    self.broadcaster.publish(&events);
+                }
+
+                let sample = self.data.pop_front();
+                if sample.is_some() {
+                    self.iter_index = Some(i + 1);
+                    self.backlog.consume(1);
+                }
+                (
+                    sample,
+                    if events.is_empty() {
+                        None
+                    } else {
+                        Some(events)
+                    },
+                )
+            }
+        }
+    }
+
+    /// The events of the next chunk, skipping over its audio. Returns
+    /// `None` once the channel has closed and the final events were given.
+    fn next_chunk_events(&mut self) -> Option<Vec<TimedEvent>> {
+        let i = self.iter_index?;
+        match self.rx.recv() {
+            Err(_) => {
+                self.iter_index = None;
+                Some(self.end_events(i))
+            }
+            Ok(Ok(chunk)) => {
+                let samples = self.receive_chunk(chunk).len();
+                self.backlog.consume(samples);
+                let i = i + samples as u64;
+                self.iter_index = Some(i);
+                let events: Vec<TimedEvent> = self
+                    .events
+                    .pop_due(i)
+                    .into_iter()
+                    .map(|(position, event)| TimedEvent {
+                        offset: Duration::from_millis(position),
+                        event,
+                    })
+                    .collect();
+                self.broadcaster.publish(&events);
+                Some(events)
+            }
+            Ok(Err(error)) => {
+                self.receive_error(error);
+                Some(Vec::new())
+            }
+        }
+    }
+
+    /// Events still queued once the channel has closed, followed by
+    /// `Event::End` if the utterance was completed and `Event::Cancelled`
+    /// otherwise. Events past the last sample, such as `Terminated`, are
+    /// due now.
+    fn end_events(&mut self, sample_index: u64) -> Vec<TimedEvent> {
+        let mut end: Vec<TimedEvent> = self
+            .events
+            .drain()
+            .into_iter()
+            .map(|(position, event)| TimedEvent {
+                offset: Duration::from_millis(position),
+                event,
+            })
+            .collect();
+        let offset = Duration::from_millis(self.position_ms(sample_index));
+        let offset = end.last().map_or(offset, |last| last.offset.max(offset));
+        let event = if self.completed && self.error.is_none() {
+            Event::End
+        } else {
+            Event::Cancelled
+        };
+        end.push(TimedEvent { offset, event });
+        self.broadcaster.publish(&end);
+        end
+    }
+
+    /// Record why synthesis failed. A cancelled job may not have started,
+    /// so the source ends now rather than waiting for its thread.
+    fn receive_error(&mut self, error: EspeakError) {
+        if error == EspeakError::Cancelled {
+            self.stop();
+        }
+        self.error = Some(error);
+    }
+
+    /// End the source early. Dropping the receiver makes the synthesis
+    /// thread's next send fail, upon which it tells espeak to stop.
+    fn stop(&mut self) {
+        let (_, closed) = mpsc::channel();
+        self.rx = closed;
+        self.iter_index = None;
+        self.data.clear();
+        self.backlog.close();
+    }
+
+    fn position_ms(&self, sample_index: u64) -> u64 {
+        sample_index * 1000 / self.sample_rate as u64
+    }
+
+    /// Take in a chunk's events and return its audio.
+    fn receive_chunk(&mut self, (mut wav_vec, mut events_vec): Chunk) -> Vec<i16> {
+        self.backlog.pop(wav_vec.len());
+        self.catch_up_text();
+        #[cfg(feature = "test-tone")]
+        if let Some(tone) = self.tone.as_mut() {
+            tone.process(&mut wav_vec, &events_vec);
+        }
+        for (_, event) in events_vec.iter_mut() {
+            self.offsets.correct(event);
+            self.offset_map.correct(event);
+            if let Event::Sentence { start, len } = event {
+                *len = sentence_len(&self.text, *start, self.text_mode);
+            }
+            if let Event::Terminated { .. } = event {
+                self.completed = true;
+            }
+            if self.table_cells {
+                cell_event(event);
+            }
+        }
+        if self.spelling {
+            // espeak may read a grapheme of several characters as several
+            // words, which all map back to it.
+            let last = &mut self.last_spelled;
+            events_vec.retain(|(_, event)| match event {
+                Event::Word(start, _) => last.replace(*start) != Some(*start),
+                _ => true,
+            });
+        }
+        self.events.push_chunk(events_vec, wav_vec.len());
+        wav_vec
+    }
+
+    /// Take on the text a stream has grown by, which the chunk's events
+    /// may refer to.
+    fn catch_up_text(&mut self) {
+        let Some(streamed) = &self.streamed_text else {
+            return;
+        };
+        let streamed = streamed.plock();
+        if streamed.len() > self.text.len() {
+            self.text.push_str(&streamed[self.text.len()..]);
+            self.effective_text.clone_from(&self.text);
+            self.positions = OnceCell::new();
+        }
+    }
+
+    extern "C" fn synth_callback(
+        wav: *mut c_short,
+        sample_count: c_int,
+        events: *mut espeak_EVENT,
+    ) -> c_int {
+        let context_ptr = unsafe { callback_context(events) };
+        if context_ptr.is_null() {
+            // Nowhere to deliver the audio to, so let espeak carry on.
+            return 0;
+        }
+        let context: &mut SynthContext = unsafe { &mut *context_ptr };
+
+        // Unwinding into espeak's C code is undefined behavior, so a panic
+        // stops synthesis and is reported on the source instead.
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let events = unsafe { event_slice(events) };
+            let wav = if wav.is_null() {
+                &[]
+            } else {
+                unsafe { std::slice::from_raw_parts(wav, sample_count.max(0) as usize) }
+            };
+            Self::handle_synth_callback(context, wav, events)
+        }));
+        match result {
+            Ok(result) => result,
+            Err(payload) => {
+                let message = panic_message(&*payload);
+                let _ = context
+                    .tx
+                    .send_error(EspeakError::SynthesisPanicked { message });
+                context.cancelled = true;
+                1
+            }
+        }
+    }
+
+    fn handle_synth_callback(
+        context: &mut SynthContext,
+        wav: &[c_short],
+        events: &[espeak_EVENT],
+    ) -> c_int {
+        let mut events_vec = parse_events(
+            events,
+            &context.char_starts,
+            context.text_offset,
+            context.audio_offset_ms,
+            &mut context.started,
+        );
+        if !context.last_piece {
+            events_vec.retain(|(_, event)| !matches!(event, Event::Terminated { .. }));
+        } else if events_vec
+            .iter()
+            .any(|(_, event)| matches!(event, Event::Terminated { .. }))
+        {
+            context.terminated = true;
+        }
+        #[cfg(test)]
+        let wav = if context.drop_audio { &[] } else { wav };
+        let wav_vec: Vec<i16> = wav.to_vec();
+        context.samples_sent += wav_vec.len() as u64;
+        context.send((wav_vec, events_vec));
+        context.cancelled |= context.job.is_cancelled();
+        context.cancelled as c_int
+    }
+}
+
+/// The context of the synthesis in progress, for when espeak calls back
+/// without the user data. Only one synthesis runs at a time, under the
+/// espeak lock.
+static SYNTH_CONTEXT: AtomicPtr<SynthContext> = AtomicPtr::new(std::ptr::null_mut());
+
+/// Clears [`SYNTH_CONTEXT`] when dropped. Dropped with the espeak lock
+/// still held, so it can't clear the context of the next synthesis.
+struct ClearSynthContext;
+
+impl Drop for ClearSynthContext {
+    fn drop(&mut self) {
+        SYNTH_CONTEXT.store(std::ptr::null_mut(), Ordering::SeqCst);
+    }
+}
+
+/// The user data passed to `espeak_Synth`, taken from the event list if
+/// there is one.
+///
+/// # Safety
+///
+/// `events` must be null or point to at least one event.
+unsafe fn callback_context(events: *const espeak_EVENT) -> *mut SynthContext {
+    if !events.is_null() && !(*events).user_data.is_null() {
+        return (*events).user_data as *mut SynthContext;
+    }
+    SYNTH_CONTEXT.load(Ordering::SeqCst)
+}
+
+/// The events espeak passed to a callback, up to the terminating one.
+/// espeak may pass no list at all when it only has audio.
+///
+/// # Safety
+///
+/// `events` must be null or point to a list of events ending with
+/// `espeakEVENT_LIST_TERMINATED`, which outlives the returned slice.
+unsafe fn event_slice<'a>(events: *const espeak_EVENT) -> &'a [espeak_EVENT] {
+    if events.is_null() {
+        return &[];
+    }
+    let mut len = 0;
+    while (*events.add(len)).type_ != espeak_EVENT_TYPE_espeakEVENT_LIST_TERMINATED {
+        len += 1;
+    }
+    std::slice::from_raw_parts(events, len)
+}
+
+/// The byte position of each character of `text`, followed by its length.
+fn char_starts(text: &str) -> Vec<usize> {
+    text.char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(text.len()))
+        .collect()
+}
+
+/// Byte position of the character at `chars`, given the text's
+/// [`char_starts`]. Positions past the end are clamped to it, so that
+/// words stay within the text.
+fn byte_position(char_starts: &[usize], chars: usize) -> usize {
+    match char_starts.get(chars) {
+        Some(position) => *position,
+        None => char_starts.last().copied().unwrap_or(0),
+    }
+}
+
+/// Convert the events espeak reported for one callback. espeak counts
+/// text positions in characters of the piece being spoken, whose
+/// [`char_starts`] turn them into bytes; they are then shifted by
+/// `text_offset`, and audio positions by `audio_offset_ms`. Only the first
+/// utterance start is reported, tracked with `started`. Out of range
+/// values are clamped rather than trusted.
+#[allow(non_upper_case_globals)]
+fn parse_events(
+    events: &[espeak_EVENT],
+    char_starts: &[usize],
+    text_offset: usize,
+    audio_offset_ms: u32,
+    started: &mut bool,
+) -> Vec<(u32, Event)> {
+    let char_position =
+        |event: &espeak_EVENT| usize::try_from(event.text_position.saturating_sub(1)).unwrap_or(0);
+    let mut events_vec = Vec::<(u32, Event)>::new();
+    for event in events {
+        let audio_position = u32::try_from(event.audio_position)
+            .unwrap_or(0)
+            .wrapping_add(audio_offset_ms);
+        let evt = match event.type_ {
+            espeak_EVENT_TYPE_espeakEVENT_SAMPLERATE => {
+                // This is the start of the utterance, or of a later
+                // piece of it, which callers don't need to know about.
+                if *started {
+                    None
+                } else {
+                    *started = true;
+                    Some(Event::Start)
+                }
+            }
+            espeak_EVENT_TYPE_espeakEVENT_WORD => {
+                let chars = char_position(event);
+                let len = usize::try_from(event.length).unwrap_or(0);
+                let start = byte_position(char_starts, chars);
+                let end = byte_position(char_starts, chars.saturating_add(len));
+                Some(Event::Word(start.saturating_add(text_offset), end - start))
+            }
+            // The length is filled in by the source, which has the text.
+            espeak_EVENT_TYPE_espeakEVENT_SENTENCE => Some(Event::Sentence {
+                start: byte_position(char_starts, char_position(event)).saturating_add(text_offset),
+                len: 0,
+            }),
+            espeak_EVENT_TYPE_espeakEVENT_PHONEME => Some(Event::Phoneme {
+                name: unsafe { event_mnemonic(event) },
+                id: unsafe { event.id.number },
+            }),
+            espeak_EVENT_TYPE_espeakEVENT_MSG_TERMINATED => Some(Event::Terminated {
+                audio_ms: audio_position,
+            }),
+            espeak_EVENT_TYPE_espeakEVENT_MARK => unsafe { event_name(event) }.map(Event::Mark),
+            espeak_EVENT_TYPE_espeakEVENT_PLAY => unsafe { event_name(event) }.map(Event::Play),
+            // The end of a clause, which the sentence events already cover.
+            espeak_EVENT_TYPE_espeakEVENT_END => None,
+            kind => Some(Event::Other {
+                kind,
+                audio_position: u32::try_from(event.audio_position).unwrap_or(0),
+                text_position: usize::try_from(event.text_position).unwrap_or(0),
+                length: usize::try_from(event.length).unwrap_or(0),
+            }),
+        };
+        if let Some(evt) = evt {
+            events_vec.push((audio_position, evt));
+        }
+    }
+    events_vec
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => payload
+            .downcast_ref::<String>()
+            .cloned()
+            .unwrap_or_default(),
+    }
+}
+
+impl Drop for SpeakerSource {
+    fn drop(&mut self) {
+        // Nothing will read what's synthesized from here on, so a queued
+        // job is skipped and a running one stops at the next callback.
+        self.job.abandon();
+        self.backlog.close();
+    }
+}
+
+impl Source for SpeakerSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl Iterator for SpeakerSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let (sample, _) = self.next_sample_and_events();
+        return sample;
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, None)
+    }
+}
+
+pub struct SpeakerSourceWithCallback<F> {
+    inner: SpeakerSource,
+    callback: F,
+}
+
+impl<F> SpeakerSourceWithCallback<F>
+where
+    F: FnMut(Event),
+{
+    pub fn error(&self) -> Option<&EspeakError> {
+        self.inner.error()
+    }
+
+    pub fn resolve(&self, event: &Event) -> Option<&str> {
+        self.inner.resolve(event)
+    }
+
+    pub fn position(&self, event: &Event) -> Option<WordPosition> {
+        self.inner.position(event)
+    }
+
+    /// Iterate over samples paired with their events, which keep going to
+    /// the callback as well. Only the callback gets the events after the
+    /// last sample, such as [`Event::End`]; see
+    /// [`IterAudioAndEvents::with_callback`].
+    pub fn iter_audio_and_events(self) -> IterAudioAndEvents<F> {
+        IterAudioAndEvents {
+            inner: self.inner,
+            callback: self.callback,
+        }
+    }
+
+    /// The source without the callback, with whatever audio and events it
+    /// has received but not yet handed out.
+    pub fn into_inner(self) -> SpeakerSource {
+        self.inner
+    }
+}
+
+impl<F> Source for SpeakerSourceWithCallback<F>
+where
+    F: FnMut(Event),
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
     }
 }
 
@@ -529,15 +3312,197 @@ where
     }
 }
 
-pub struct IterAudioAndEvents {
+pub struct SpeakerSourceWithCallbackControl<F> {
+    inner: SpeakerSource,
+    callback: F,
+}
+
+impl<F> SpeakerSourceWithCallbackControl<F>
+where
+    F: FnMut(Event) -> ControlFlow<()>,
+{
+    pub fn error(&self) -> Option<&EspeakError> {
+        self.inner.error()
+    }
+
+    pub fn resolve(&self, event: &Event) -> Option<&str> {
+        self.inner.resolve(event)
+    }
+
+    pub fn position(&self, event: &Event) -> Option<WordPosition> {
+        self.inner.position(event)
+    }
+}
+
+impl<F> Source for SpeakerSourceWithCallbackControl<F>
+where
+    F: FnMut(Event) -> ControlFlow<()>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+impl<F> Iterator for SpeakerSourceWithCallbackControl<F>
+where
+    F: FnMut(Event) -> ControlFlow<()>,
+{
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let (sample, events) = self.inner.next_sample_and_events();
+        for event in events.unwrap_or_default() {
+            if (self.callback)(event).is_break() {
+                self.inner.stop();
+                return None;
+            }
+        }
+        sample
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+pub struct SpeakerSourceWithTimedCallback<F> {
+    inner: SpeakerSource,
+    callback: F,
+}
+
+impl<F> SpeakerSourceWithTimedCallback<F>
+where
+    F: FnMut(TimedEvent),
+{
+    pub fn error(&self) -> Option<&EspeakError> {
+        self.inner.error()
+    }
+
+    pub fn resolve(&self, event: &Event) -> Option<&str> {
+        self.inner.resolve(event)
+    }
+
+    pub fn position(&self, event: &Event) -> Option<WordPosition> {
+        self.inner.position(event)
+    }
+
+    /// The source without the callback, with whatever audio and events it
+    /// has received but not yet handed out.
+    pub fn into_inner(self) -> SpeakerSource {
+        self.inner
+    }
+}
+
+impl<F> Source for SpeakerSourceWithTimedCallback<F>
+where
+    F: FnMut(TimedEvent),
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+impl<F> Iterator for SpeakerSourceWithTimedCallback<F>
+where
+    F: FnMut(TimedEvent),
+{
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let (sample, events) = self.inner.next_sample_and_timed_events();
+        for event in events.into_iter().flatten() {
+            (self.callback)(event);
+        }
+        sample
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/// Samples paired with the events due at them. Events are also passed to
+/// the callback, if one was attached with
+/// [`with_callback`](IterAudioAndEvents::with_callback) or this was made
+/// from a [`SpeakerSourceWithCallback`].
+pub struct IterAudioAndEvents<F = fn(Event)> {
     inner: SpeakerSource,
+    callback: F,
+}
+
+fn ignore_event(_: Event) {}
+
+impl IterAudioAndEvents {
+    /// Also pass each event to `callback`.
+    ///
+    /// Events after the last sample, such as [`Event::End`], have no
+    /// sample to be paired with, so only the callback gets them: the
+    /// iterator ends without yielding them.
+    pub fn with_callback<F>(self, callback: F) -> IterAudioAndEvents<F>
+    where
+        F: FnMut(Event),
+    {
+        IterAudioAndEvents {
+            inner: self.inner,
+            callback,
+        }
+    }
+}
+
+impl<F> IterAudioAndEvents<F>
+where
+    F: FnMut(Event),
+{
+    pub fn resolve(&self, event: &Event) -> Option<&str> {
+        self.inner.resolve(event)
+    }
+
+    pub fn position(&self, event: &Event) -> Option<WordPosition> {
+        self.inner.position(event)
+    }
+
+    /// The source being iterated, with whatever audio and events it has
+    /// received but not yet handed out.
+    pub fn into_inner(self) -> SpeakerSource {
+        self.inner
+    }
 }
 
-impl Iterator for IterAudioAndEvents {
+impl<F> Iterator for IterAudioAndEvents<F>
+where
+    F: FnMut(Event),
+{
     type Item = (i16, Option<Vec<Event>>);
 
     fn next(&mut self) -> Option<(i16, Option<Vec<Event>>)> {
         let (sample, events) = self.inner.next_sample_and_events();
+        for event in events.iter().flatten() {
+            (self.callback)(event.clone());
+        }
 
         match sample {
             None => None,
@@ -550,6 +3515,38 @@ impl Iterator for IterAudioAndEvents {
     }
 }
 
+/// The events of a [`SpeakerSource`], without its audio. See
+/// [`SpeakerSource::into_events`].
+pub struct IntoEvents {
+    inner: SpeakerSource,
+    pending: VecDeque<TimedEvent>,
+}
+
+impl IntoEvents {
+    pub fn error(&self) -> Option<&EspeakError> {
+        self.inner.error()
+    }
+
+    pub fn resolve(&self, event: &Event) -> Option<&str> {
+        self.inner.resolve(event)
+    }
+
+    pub fn position(&self, event: &Event) -> Option<WordPosition> {
+        self.inner.position(event)
+    }
+}
+
+impl Iterator for IntoEvents {
+    type Item = TimedEvent;
+
+    fn next(&mut self) -> Option<TimedEvent> {
+        while self.pending.is_empty() {
+            self.pending.extend(self.inner.next_chunk_events()?);
+        }
+        self.pending.pop_front()
+    }
+}
+
 trait PoisonlessLock<T> {
     fn plock(&self) -> MutexGuard<T>;
 }
@@ -562,3 +3559,422 @@ impl<T> PoisonlessLock<T> for Mutex<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn voice_names_need_not_be_utf8() {
+        let name = b"Fran\xe7ais\0";
+        let identifier = b"mb/mb-fr\xe71\0";
+        let languages = b"\x05fr\xe7\0\x07fr\0\0";
+        let voice = Voice::from_espeak_voice(espeak_VOICE {
+            name: name.as_ptr() as *const c_char,
+            languages: languages.as_ptr() as *const c_char,
+            identifier: identifier.as_ptr() as *const c_char,
+            gender: 2,
+            age: 0,
+            variant: 0,
+            xx1: 0,
+            score: 0,
+            spare: std::ptr::null_mut(),
+        });
+        assert_eq!(voice.name, "Fran\u{fffd}ais");
+        assert_eq!(voice.identifier, "mb/mb-fr\u{fffd}1");
+        assert_eq!(voice.gender, Gender::Female);
+        let languages: Vec<(i8, &str)> = voice
+            .languages
+            .iter()
+            .map(|language| (language.priority, language.name.as_str()))
+            .collect();
+        assert_eq!(languages, [(5, "fr\u{fffd}"), (7, "fr")]);
+    }
+
+    #[test]
+    fn voice_lists_are_bounded() {
+        let voice = |name: *const c_char, languages: *const c_char| espeak_VOICE {
+            name,
+            languages,
+            identifier: std::ptr::null(),
+            gender: 0,
+            age: 0,
+            variant: 0,
+            xx1: 0,
+            score: 0,
+            spare: std::ptr::null_mut(),
+        };
+        let name = c"English";
+        let languages = b"\x05en\0\0";
+        let english = voice(name.as_ptr(), languages.as_ptr() as *const c_char);
+        let unnamed = voice(std::ptr::null(), languages.as_ptr() as *const c_char);
+        let empty = voice(std::ptr::null(), c"".as_ptr());
+        let blank = voice(std::ptr::null(), std::ptr::null());
+
+        let names = |voices: Vec<Voice>| -> Vec<String> {
+            voices.into_iter().map(|voice| voice.name).collect()
+        };
+        let terminated: [*const espeak_VOICE; 3] = [&english, &unnamed, std::ptr::null()];
+        let voices = unsafe { walk_voices(|i| terminated[i]) };
+        assert_eq!(names(voices), ["English", ""]);
+
+        let garbage: [*const espeak_VOICE; 4] = [&blank, &english, &empty, std::ptr::null()];
+        let voices = unsafe { walk_voices(|i| garbage[i]) };
+        assert_eq!(names(voices), ["English"]);
+
+        let mut reads = 0;
+        let voices = unsafe {
+            walk_voices(|_| {
+                reads += 1;
+                &english
+            })
+        };
+        assert_eq!(voices.len(), MAX_LISTED_VOICES);
+        assert_eq!(reads, MAX_LISTED_VOICES);
+    }
+
+    #[test]
+    fn pathological_events_are_clamped() {
+        let event = |type_, text_position, length, audio_position| {
+            let mut event: espeak_EVENT = unsafe { std::mem::zeroed() };
+            event.type_ = type_;
+            event.text_position = text_position;
+            event.length = length;
+            event.audio_position = audio_position;
+            event
+        };
+        let events = [
+            event(espeak_EVENT_TYPE_espeakEVENT_SAMPLERATE, 0, 0, 0),
+            event(espeak_EVENT_TYPE_espeakEVENT_WORD, 1, -3, 10),
+            event(espeak_EVENT_TYPE_espeakEVENT_WORD, i32::MIN, 4, -1),
+            event(
+                espeak_EVENT_TYPE_espeakEVENT_SENTENCE,
+                i32::MAX,
+                0,
+                i32::MAX,
+            ),
+            event(espeak_EVENT_TYPE_espeakEVENT_SAMPLERATE, 0, 0, 0),
+            event(espeak_EVENT_TYPE_espeakEVENT_MARK, 5, 5, 5),
+            {
+                let mut mark = event(espeak_EVENT_TYPE_espeakEVENT_MARK, 5, 0, 5);
+                mark.id.name = c"mid".as_ptr();
+                mark
+            },
+            event(espeak_EVENT_TYPE_espeakEVENT_MSG_TERMINATED, 0, 0, 7),
+        ];
+        let mut started = false;
+        let parsed = parse_events(&events, &[], usize::MAX - 1, u32::MAX, &mut started);
+        assert!(started);
+        assert_eq!(
+            parsed,
+            [
+                (u32::MAX, Event::Start),
+                (9, Event::Word(usize::MAX - 1, 0)),
+                // Without text, every position clamps to its start.
+                (u32::MAX, Event::Word(usize::MAX - 1, 0)),
+                (
+                    i32::MAX as u32 - 1,
+                    Event::Sentence {
+                        start: usize::MAX - 1,
+                        len: 0,
+                    },
+                ),
+                (4, Event::Mark(String::from("mid"))),
+                (6, Event::Terminated { audio_ms: 6 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_events_are_passed_through() {
+        let event = |type_, text_position, length, audio_position| {
+            let mut event: espeak_EVENT = unsafe { std::mem::zeroed() };
+            event.type_ = type_;
+            event.text_position = text_position;
+            event.length = length;
+            event.audio_position = audio_position;
+            event
+        };
+        let events = [
+            event(espeak_EVENT_TYPE_espeakEVENT_WORD, 1, 5, 10),
+            event(espeak_EVENT_TYPE_espeakEVENT_END, 6, 0, 300),
+            event(42, 3, 2, 20),
+            event(43, -1, -1, -1),
+        ];
+        let parsed = parse_events(&events, &[0, 1, 2, 3, 4, 5], 0, 100, &mut true);
+        assert_eq!(
+            parsed,
+            [
+                (110, Event::Word(0, 5)),
+                (
+                    120,
+                    Event::Other {
+                        kind: 42,
+                        audio_position: 20,
+                        text_position: 3,
+                        length: 2,
+                    }
+                ),
+                (
+                    100,
+                    Event::Other {
+                        kind: 43,
+                        audio_position: 0,
+                        text_position: 0,
+                        length: 0,
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_event_lists() {
+        let event = |type_| {
+            let mut event: espeak_EVENT = unsafe { std::mem::zeroed() };
+            event.type_ = type_;
+            event
+        };
+        assert!(unsafe { event_slice(std::ptr::null()) }.is_empty());
+        assert!(unsafe { callback_context(std::ptr::null()) }.is_null());
+
+        let empty = [event(espeak_EVENT_TYPE_espeakEVENT_LIST_TERMINATED)];
+        assert!(unsafe { event_slice(empty.as_ptr()) }.is_empty());
+        assert!(unsafe { callback_context(empty.as_ptr()) }.is_null());
+
+        let unknown = [
+            event(espeak_EVENT_TYPE_espeakEVENT_MARK),
+            event(99),
+            event(espeak_EVENT_TYPE_espeakEVENT_WORD),
+            event(espeak_EVENT_TYPE_espeakEVENT_LIST_TERMINATED),
+        ];
+        let events = unsafe { event_slice(unknown.as_ptr()) };
+        assert_eq!(events.len(), 3);
+        let mut started = false;
+        assert_eq!(
+            parse_events(events, &[], 0, 0, &mut started),
+            [(0, Event::Word(0, 0))]
+        );
+    }
+
+    #[test]
+    fn only_completed_utterances_end() {
+        let ending = |chunks: Vec<Message>| {
+            let (tx, rx, backlog) = chunk_channel(None, None);
+            let offsets = OffsetCorrector::new("", TextMode::Plain);
+            let mut source = SpeakerSource::from_channel(rx, backlog, 22050, offsets);
+            for chunk in chunks {
+                match chunk {
+                    Ok(chunk) => tx.send(chunk).unwrap(),
+                    Err(error) => tx.send_error(error).unwrap(),
+                }
+            }
+            drop(tx);
+            let mut events = Vec::new();
+            loop {
+                let (sample, due) = source.next_sample_and_events();
+                events.extend(due.unwrap_or_default());
+                if sample.is_none() {
+                    break;
+                }
+            }
+            (events.pop(), source.take_error())
+        };
+        let start = || Ok((vec![0; 10], vec![(0, Event::Start)]));
+        let terminated = || Ok((vec![], vec![(0, Event::Terminated { audio_ms: 0 })]));
+
+        assert_eq!(
+            ending(vec![start(), terminated()]),
+            (Some(Event::End), None)
+        );
+        assert_eq!(ending(vec![start()]), (Some(Event::Cancelled), None));
+        assert_eq!(
+            ending(vec![start(), terminated(), Err(EspeakError::SilentOutput)]),
+            (Some(Event::Cancelled), Some(EspeakError::SilentOutput))
+        );
+    }
+
+    #[test]
+    fn synthesis_panic_ends_source() {
+        let (tx, rx, backlog) = chunk_channel(None, None);
+        let offsets = OffsetCorrector::new("", TextMode::Plain);
+        let mut source = SpeakerSource::from_channel(rx, backlog, 22050, offsets);
+        spawn_synthesis(tx, |tx| {
+            tx.send((vec![0; 10], vec![(0, Event::Start)])).unwrap();
+            panic!("synthesis failed");
+        });
+
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        thread::spawn(move || {
+            let mut samples = 0;
+            let mut events = Vec::new();
+            loop {
+                let (sample, due) = source.next_sample_and_events();
+                events.extend(due.unwrap_or_default());
+                match sample {
+                    Some(_) => samples += 1,
+                    None => break,
+                }
+            }
+            let _ = done_tx.send((samples, events, source.take_error()));
+        });
+
+        let (samples, events, error) = done_rx
+            .recv_timeout(Duration::from_secs(5))
+            .expect("source should end after the synthesis thread panics");
+        assert_eq!(samples, 10);
+        assert_eq!(events, [Event::Start, Event::Cancelled]);
+        assert_eq!(
+            error,
+            Some(EspeakError::SynthesisPanicked {
+                message: String::from("synthesis failed"),
+            })
+        );
+    }
+
+    #[test]
+    fn silent_attempt_is_retried() {
+        let speak = |speaker: &mut Speaker, dropped: usize| {
+            speaker.params.drop_attempts = dropped;
+            let mut events = Vec::new();
+            let mut source = speaker
+                .speak("Hello world")
+                .with_callback(|event| events.push(event));
+            let samples = source.by_ref().count();
+            let error = source.error().cloned();
+            drop(source);
+            (samples, events, error)
+        };
+
+        let mut speaker = Speaker::new();
+        let (samples, events, _) = speak(&mut speaker, 0);
+        assert!(samples > 0);
+        assert_eq!(speak(&mut speaker, 1).0, 0);
+
+        speaker.params.retry_on_empty = true;
+        assert_eq!(speak(&mut speaker, 1), (samples, events.clone(), None));
+        assert_eq!(events.iter().filter(|e| **e == Event::Start).count(), 1);
+
+        let (silent, events, error) = speak(&mut speaker, 2);
+        assert_eq!(silent, 0);
+        assert_eq!(events.iter().filter(|e| **e == Event::Start).count(), 1);
+        assert_eq!(error, Some(EspeakError::SilentOutput));
+    }
+
+    #[test]
+    fn refused_params_are_reported() {
+        let mut speaker = Speaker::new();
+        let mut source = speaker.speak("Hello");
+        assert!(source.by_ref().count() > 0);
+        assert_eq!(source.param_errors(), []);
+
+        speaker.params.word_gap = Some(REJECTED_PARAM_VALUE);
+        let mut source = speaker.speak("Hello");
+        assert!(source.by_ref().count() > 0);
+        let refused = ParamApplyError {
+            param: "word_gap",
+            value: REJECTED_PARAM_VALUE,
+            status: EspeakError::Internal(espeak_ERROR_EE_INTERNAL_ERROR),
+        };
+        assert_eq!(source.param_errors(), std::slice::from_ref(&refused));
+        assert_eq!(source.error(), None);
+
+        speaker.params.strict_params = true;
+        let mut source = speaker.speak("Hello");
+        assert_eq!(source.by_ref().count(), 0);
+        assert_eq!(
+            source.error(),
+            Some(&EspeakError::ParamsRejected(vec![refused.clone()]))
+        );
+        assert_eq!(source.param_errors(), [refused]);
+    }
+
+    #[test]
+    fn events_are_displayed() {
+        let events = [
+            (Event::Start, "start"),
+            (Event::Word(13, 7), "word[13..20]"),
+            (Event::Sentence { start: 13, len: 13 }, "sentence@13"),
+            (Event::End, "end"),
+            (
+                Event::Phoneme {
+                    name: String::from("h@"),
+                    id: 7,
+                },
+                "phoneme(\"h@\", 7)",
+            ),
+            (Event::Mark(String::from("intro")), "mark(\"intro\")"),
+            (Event::Play(String::from("a.wav")), "play(\"a.wav\")"),
+            (Event::Terminated { audio_ms: 1849 }, "terminated@1849ms"),
+            (Event::Cell { row: 1, col: 2 }, "cell(1, 2)"),
+            (Event::Cancelled, "cancelled"),
+            (
+                Event::Other {
+                    kind: 42,
+                    audio_position: 20,
+                    text_position: 3,
+                    length: 2,
+                },
+                "other(42)[3+2]@20ms",
+            ),
+        ];
+        for (event, displayed) in events {
+            assert_eq!(event.to_string(), displayed);
+        }
+
+        let text = "Hello world. Goodbye world";
+        assert_eq!(Event::Word(13, 7).describe(text), "word 'Goodbye' [13..20]");
+        assert_eq!(
+            Event::Sentence { start: 13, len: 13 }.describe(text),
+            "sentence 'Goodbye world' [13..26]"
+        );
+        assert_eq!(Event::Word(13, 70).describe(text), "word[13..83]");
+        assert_eq!(
+            Event::Sentence { start: 13, len: 0 }.describe(text),
+            "sentence@13"
+        );
+        assert_eq!(Event::End.describe(text), "end");
+    }
+
+    #[test]
+    fn voices_are_displayed() {
+        let voice = Voice {
+            name: String::from("French (Switzerland)"),
+            identifier: String::from("roa/fr-CH"),
+            age: 0,
+            gender: Gender::Male,
+            languages: vec![
+                Language {
+                    priority: 5,
+                    name: String::from("fr-ch"),
+                },
+                Language {
+                    priority: 8,
+                    name: String::from("fr"),
+                },
+            ],
+        };
+        assert_eq!(
+            voice.to_string(),
+            "French (Switzerland) [roa/fr-CH, fr-ch(5), fr(8)]"
+        );
+    }
+
+    #[test]
+    fn character_positions_become_bytes() {
+        let starts = char_starts("aé😀 b");
+        assert_eq!(starts, [0, 1, 3, 7, 8, 9]);
+        assert_eq!(byte_position(&starts, 3), 7);
+        assert_eq!(byte_position(&starts, 7), 9);
+
+        let mut word: espeak_EVENT = unsafe { std::mem::zeroed() };
+        word.type_ = espeak_EVENT_TYPE_espeakEVENT_WORD;
+        word.text_position = 2;
+        word.length = 2;
+        let mut started = false;
+        assert_eq!(
+            parse_events(&[word], &starts, 100, 0, &mut started),
+            [(0, Event::Word(101, 6))]
+        );
+    }
+}
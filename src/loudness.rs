@@ -0,0 +1,303 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Loudness measurement and normalization, after ITU-R BS.1770 and
+//! EBU R128.
+//!
+//! Loudness is measured in LUFS: the audio is K-weighted, which roughly
+//! follows how loud each frequency sounds, its power is averaged over
+//! overlapping 400 ms blocks, and blocks of near silence are left out so
+//! that pauses don't make speech measure quieter.
+
+use crate::AudioBuffer;
+
+/// The EBU R128 target, for [`AudioBuffer::normalize_loudness`].
+pub const DEFAULT_LOUDNESS_TARGET: f32 = -23.0;
+
+/// The highest peak normalization leaves, as a fraction of full scale.
+/// Louder peaks are brought down by a limiter rather than clipped.
+pub const LIMITER_CEILING: f32 = 0.98;
+
+/// How long the limiter takes to ramp its gain fully up or down.
+const LIMITER_RAMP_SECS: f64 = 0.01;
+
+const BLOCK_SECS: f64 = 0.4;
+/// Blocks overlap by 75%.
+const BLOCK_STEP_SECS: f64 = 0.1;
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+
+/// A biquad filter, in direct form I.
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 3],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 3]) -> Biquad {
+        Biquad {
+            b,
+            a,
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[1] * self.y[0]
+            - self.a[2] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
+
+/// The two stages of the K-weighting filter, a high shelf for the head's
+/// effect and a high pass, designed for `sample_rate` the way libebur128
+/// does so any rate works.
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let rate = sample_rate as f64;
+
+    let f0 = 1681.974450955533;
+    let gain_db = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (std::f64::consts::PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::new(
+        [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (std::f64::consts::PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::new(
+        [1.0, -2.0, 1.0],
+        [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+    [shelf, high_pass]
+}
+
+fn to_lufs(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+/// The gated integrated loudness of mono `samples`, in LUFS. Audio
+/// shorter than a block is measured as a single block. Silence measures
+/// as negative infinity.
+pub(crate) fn integrated_loudness(samples: &[i16], sample_rate: u32) -> f32 {
+    let [mut shelf, mut high_pass] = k_weighting(sample_rate);
+    let squares: Vec<f64> = samples
+        .iter()
+        .map(|sample| {
+            let weighted = high_pass.process(shelf.process(*sample as f64 / 32768.0));
+            weighted * weighted
+        })
+        .collect();
+
+    let block = ((BLOCK_SECS * sample_rate as f64) as usize).max(1);
+    let step = ((BLOCK_STEP_SECS * sample_rate as f64) as usize).max(1);
+    let block = block.min(squares.len());
+    if block == 0 {
+        return f32::NEG_INFINITY;
+    }
+    let blocks: Vec<f64> = (0..=squares.len() - block)
+        .step_by(step)
+        .map(|start| squares[start..start + block].iter().sum::<f64>() / block as f64)
+        .filter(|power| to_lufs(*power) > ABSOLUTE_GATE)
+        .collect();
+    if blocks.is_empty() {
+        return f32::NEG_INFINITY;
+    }
+    let mean = |blocks: &mut dyn Iterator<Item = f64>| {
+        let (sum, count) = blocks.fold((0.0, 0usize), |(sum, count), p| (sum + p, count + 1));
+        sum / count as f64
+    };
+    let gate = to_lufs(mean(&mut blocks.iter().copied())) + RELATIVE_GATE;
+    let gated = mean(&mut blocks.iter().copied().filter(|p| to_lufs(*p) > gate));
+    to_lufs(gated) as f32
+}
+
+/// `samples` scaled by `gain`, with a limiter holding peaks to
+/// [`LIMITER_CEILING`]. The limiter's gain ramps down ahead of a peak and
+/// back up after it, at most one unit per `LIMITER_RAMP_SECS`.
+pub(crate) fn apply_gain(samples: &[i16], sample_rate: u32, gain: f64) -> Vec<i16> {
+    let ceiling = LIMITER_CEILING as f64 * i16::MAX as f64;
+    let scaled: Vec<f64> = samples.iter().map(|s| *s as f64 * gain).collect();
+    let mut limit: Vec<f64> = scaled
+        .iter()
+        .map(|s| (ceiling / s.abs()).min(1.0))
+        .collect();
+    let step = 1.0 / (LIMITER_RAMP_SECS * sample_rate as f64).max(1.0);
+    for i in 1..limit.len() {
+        limit[i] = limit[i].min(limit[i - 1] + step);
+    }
+    for i in (0..limit.len().saturating_sub(1)).rev() {
+        limit[i] = limit[i].min(limit[i + 1] + step);
+    }
+    scaled
+        .iter()
+        .zip(limit)
+        .map(|(s, limit)| (s * limit).round() as i16)
+        .collect()
+}
+
+impl AudioBuffer {
+    /// The integrated loudness of the audio in LUFS, negative infinity for
+    /// silence.
+    pub fn loudness_lufs(&self) -> f32 {
+        integrated_loudness(&self.samples, self.sample_rate)
+    }
+
+    /// A copy with the audio scaled to `target_lufs`, such as
+    /// [`DEFAULT_LOUDNESS_TARGET`]. Peaks that would go past
+    /// [`LIMITER_CEILING`] are limited. Silence is left as it is.
+    pub fn normalize_loudness(&self, target_lufs: f32) -> AudioBuffer {
+        let loudness = self.loudness_lufs();
+        if !loudness.is_finite() {
+            return self.clone();
+        }
+        let gain = 10f64.powf((target_lufs - loudness) as f64 / 20.0);
+        AudioBuffer {
+            samples: apply_gain(&self.samples, self.sample_rate, gain),
+            ..self.clone()
+        }
+    }
+}
+
+/// Normalize each of `buffers` to `target_lufs`, so clips from different
+/// voices sound equally loud together.
+pub fn normalize_loudness_batch(buffers: &[AudioBuffer], target_lufs: f32) -> Vec<AudioBuffer> {
+    buffers
+        .iter()
+        .map(|buffer| buffer.normalize_loudness(target_lufs))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 22050;
+
+    fn sine(frequency: f64, amplitude: f64, secs: f64) -> AudioBuffer {
+        let samples = (0..(secs * RATE as f64) as usize)
+            .map(|i| {
+                let phase = 2.0 * std::f64::consts::PI * frequency * i as f64 / RATE as f64;
+                (phase.sin() * amplitude * 32767.0).round() as i16
+            })
+            .collect();
+        buffer(samples)
+    }
+
+    fn noise(amplitude: f64, secs: f64) -> AudioBuffer {
+        let mut state = 0x2545f491u32;
+        let samples = (0..(secs * RATE as f64) as usize)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                let unit = state as f64 / u32::MAX as f64 * 2.0 - 1.0;
+                (unit * amplitude * 32767.0).round() as i16
+            })
+            .collect();
+        buffer(samples)
+    }
+
+    fn buffer(samples: Vec<i16>) -> AudioBuffer {
+        AudioBuffer {
+            samples,
+            ..AudioBuffer::new(RATE)
+        }
+    }
+
+    fn peak(buffer: &AudioBuffer) -> i16 {
+        buffer
+            .samples
+            .iter()
+            .map(|s| s.saturating_abs())
+            .max()
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn sine_loudness_is_known() {
+        // A full scale 1 kHz sine measures -3.01 LUFS.
+        let full = sine(1000.0, 1.0, 2.0).loudness_lufs();
+        assert!((full + 3.01).abs() < 0.5, "{}", full);
+        let quieter = sine(1000.0, 0.1, 2.0).loudness_lufs();
+        assert!((quieter + 23.01).abs() < 0.5, "{}", quieter);
+        // K-weighting makes low tones count for less.
+        assert!(sine(40.0, 0.1, 2.0).loudness_lufs() < quieter - 0.5);
+    }
+
+    #[test]
+    fn silence_is_gated() {
+        assert_eq!(buffer(vec![0; 44100]).loudness_lufs(), f32::NEG_INFINITY);
+        assert_eq!(buffer(Vec::new()).loudness_lufs(), f32::NEG_INFINITY);
+        let mut padded = sine(1000.0, 0.1, 1.0);
+        padded.samples.extend(vec![0; 3 * RATE as usize]);
+        // Blocks straddling the end of the tone pull it down a little,
+        // but without gating it would measure 6 LU quieter.
+        let padded = padded.loudness_lufs();
+        assert!((padded + 23.01).abs() < 1.0, "{}", padded);
+        let short = sine(1000.0, 0.1, 0.2).loudness_lufs();
+        assert!((short + 23.01).abs() < 0.5, "{}", short);
+        let silent = buffer(vec![0; 100]);
+        assert_eq!(silent.normalize_loudness(-23.0), silent);
+    }
+
+    #[test]
+    fn buffers_are_normalized_together() {
+        let clips = [
+            sine(440.0, 0.02, 1.0),
+            noise(0.5, 1.0),
+            sine(3000.0, 0.3, 1.0),
+        ];
+        let normalized = normalize_loudness_batch(&clips, DEFAULT_LOUDNESS_TARGET);
+        for clip in &normalized {
+            let loudness = clip.loudness_lufs();
+            assert!(
+                (loudness - DEFAULT_LOUDNESS_TARGET).abs() < 0.2,
+                "{}",
+                loudness
+            );
+        }
+    }
+
+    #[test]
+    fn peaks_are_limited() {
+        let loud = noise(0.5, 1.0).normalize_loudness(-3.0);
+        let ceiling = (LIMITER_CEILING * i16::MAX as f32) as i16;
+        assert!(peak(&loud) <= ceiling, "{}", peak(&loud));
+        assert!(loud.loudness_lufs() > -8.0);
+    }
+}
@@ -0,0 +1,157 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Mixing two buffers into one, e.g. a chime under an announcement.
+
+use crate::{AudioBuffer, EspeakError, TimedEvent};
+use std::time::Duration;
+
+/// Mix `b`, scaled by `gain_b`, into `a` starting `offset` into it. The
+/// result is as long as the longer of the two, and sums saturate rather
+/// than wrap. Both buffers' events are kept, `b`'s shifted by `offset`,
+/// and [`AudioBuffer::event_origins`] tells them apart.
+///
+/// The buffers must have the same sample rate.
+pub fn mix(
+    a: AudioBuffer,
+    b: AudioBuffer,
+    offset: Duration,
+    gain_b: f32,
+) -> Result<AudioBuffer, EspeakError> {
+    if a.sample_rate != b.sample_rate {
+        return Err(EspeakError::SampleRateMismatch {
+            a: a.sample_rate,
+            b: b.sample_rate,
+        });
+    }
+    let start = (offset.as_secs_f64() * a.sample_rate as f64).round() as usize;
+    let mut samples = a.samples;
+    samples.resize(samples.len().max(start + b.samples.len()), 0);
+    for (mixed, sample) in samples[start..].iter_mut().zip(&b.samples) {
+        let sum = *mixed as f32 + *sample as f32 * gain_b;
+        *mixed = sum.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+    }
+
+    // The offset the samples were actually moved by.
+    let shift = Duration::from_secs_f64(start as f64 / a.sample_rate as f64);
+    let shifted = b.events.into_iter().map(|timed| TimedEvent {
+        offset: timed.offset + shift,
+        event: timed.event,
+    });
+    let mut events: Vec<(TimedEvent, usize)> = a
+        .events
+        .into_iter()
+        .map(|timed| (timed, 0))
+        .chain(shifted.map(|timed| (timed, 1)))
+        .collect();
+    // Stable, so that on a tie `a`'s events come first.
+    events.sort_by_key(|(timed, _)| timed.offset);
+    let (events, event_origins) = events.into_iter().unzip();
+    Ok(AudioBuffer {
+        samples,
+        sample_rate: a.sample_rate,
+        events,
+        event_origins,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Event;
+
+    const RATE: u32 = 1000;
+
+    fn buffer(samples: Vec<i16>, events: Vec<(u64, Event)>) -> AudioBuffer {
+        AudioBuffer {
+            samples,
+            events: events
+                .into_iter()
+                .map(|(ms, event)| TimedEvent {
+                    offset: Duration::from_millis(ms),
+                    event,
+                })
+                .collect(),
+            ..AudioBuffer::new(RATE)
+        }
+    }
+
+    #[test]
+    fn mixing_in_silence_changes_nothing() {
+        let a = buffer(
+            vec![1, -2, 3, i16::MAX],
+            vec![(0, Event::Start), (3, Event::End)],
+        );
+        let silence = buffer(vec![0; 3], Vec::new());
+        let mixed = mix(a.clone(), silence, Duration::from_millis(1), 1.0).unwrap();
+        assert_eq!(mixed.samples, a.samples);
+        assert_eq!(mixed.events, a.events);
+        assert_eq!(mixed.event_origins, [0, 0]);
+    }
+
+    #[test]
+    fn buffers_are_aligned_and_merged() {
+        let a = buffer(
+            vec![100, 100, 100, 100, 30000],
+            vec![(0, Event::Start), (4, Event::End)],
+        );
+        let b = buffer(
+            vec![10, 20000, 30, 40],
+            vec![(0, Event::Word(0, 4)), (1, Event::Mark("ding".into()))],
+        );
+        let mixed = mix(a, b, Duration::from_millis(3), 0.5).unwrap();
+        assert_eq!(mixed.samples, [100, 100, 100, 105, i16::MAX, 15, 20]);
+        let events: Vec<_> = mixed
+            .events
+            .iter()
+            .map(|timed| (timed.offset.as_millis(), timed.event.clone()))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                (0, Event::Start),
+                (3, Event::Word(0, 4)),
+                (4, Event::End),
+                (4, Event::Mark("ding".into())),
+            ]
+        );
+        assert_eq!(mixed.event_origins, [0, 1, 0, 1]);
+
+        // b can start past the end of a.
+        let a = buffer(vec![1; 2], Vec::new());
+        let b = buffer(vec![2; 2], Vec::new());
+        let mixed = mix(a, b, Duration::from_millis(4), 1.0).unwrap();
+        assert_eq!(mixed.samples, [1, 1, 0, 0, 2, 2]);
+    }
+
+    #[test]
+    fn sample_rates_must_match() {
+        let a = AudioBuffer::new(RATE);
+        let b = AudioBuffer::new(RATE * 2);
+        assert_eq!(
+            mix(a, b, Duration::ZERO, 1.0),
+            Err(EspeakError::SampleRateMismatch {
+                a: RATE,
+                b: RATE * 2
+            })
+        );
+    }
+}
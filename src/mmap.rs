@@ -0,0 +1,291 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Memory-mapped replay of WAV files, for serving cached audio without
+//! reading it all into memory first.
+
+use crate::wav::{file_error, load_sidecar, parse_wav_header, WAV_HEADER_LEN};
+use crate::{AudioBuffer, EspeakError, TimedEvent};
+use memmap2::Mmap;
+use rodio::Source;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Samples read between checks that the file hasn't been truncated.
+const SAMPLES_PER_CHUNK: usize = 4096;
+
+struct Mapping {
+    path: PathBuf,
+    file: File,
+    map: Mmap,
+    sample_rate: u32,
+    /// Number of samples, from the header or the file's length when it was
+    /// opened.
+    len: usize,
+}
+
+impl Mapping {
+    /// Check that the file still holds the samples up to `end`, so reading
+    /// them from the map doesn't fault.
+    fn check(&self, end: usize) -> Result<(), EspeakError> {
+        let file_len = self
+            .file
+            .metadata()
+            .map_err(|e| file_error(&self.path, e))?
+            .len();
+        let needed = (WAV_HEADER_LEN + end * 2) as u64;
+        if file_len < needed {
+            return Err(file_error(
+                &self.path,
+                format!("truncated to {} bytes, {} expected", file_len, needed),
+            ));
+        }
+        Ok(())
+    }
+
+    fn sample(&self, index: usize) -> i16 {
+        // The data may not be aligned for i16, so read it byte by byte.
+        let i = WAV_HEADER_LEN + index * 2;
+        i16::from_le_bytes([self.map[i], self.map[i + 1]])
+    }
+}
+
+/// Audio replayed straight from a memory-mapped WAV file. See
+/// [`AudioBuffer::open_mmap`].
+///
+/// Clones share the map and iterate independently. Every few thousand
+/// samples the file's length is checked against its header; if it was
+/// truncated, iteration ends and [`error`](MappedAudio::error) says why.
+#[derive(Clone)]
+pub struct MappedAudio {
+    mapping: Arc<Mapping>,
+    /// Events from the sidecar, in order of their offsets.
+    events: Arc<[TimedEvent]>,
+    position: usize,
+    /// Samples before this one have been checked to be in the file.
+    checked: usize,
+    error: Option<EspeakError>,
+}
+
+impl MappedAudio {
+    /// Events saved beside the file, or none without the `serde` feature.
+    pub fn events(&self) -> &[TimedEvent] {
+        &self.events
+    }
+
+    /// Number of samples in the file.
+    pub fn len(&self) -> usize {
+        self.mapping.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mapping.len == 0
+    }
+
+    /// Why iteration ended early, if the file was truncated or its length
+    /// couldn't be read.
+    pub fn error(&self) -> Option<&EspeakError> {
+        self.error.as_ref()
+    }
+}
+
+impl std::fmt::Debug for MappedAudio {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MappedAudio")
+            .field("path", &self.mapping.path)
+            .field("sample_rate", &self.mapping.sample_rate)
+            .field("len", &self.mapping.len)
+            .field("position", &self.position)
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+impl Iterator for MappedAudio {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.position >= self.mapping.len || self.error.is_some() {
+            return None;
+        }
+        if self.position >= self.checked {
+            let end = (self.position + SAMPLES_PER_CHUNK).min(self.mapping.len);
+            if let Err(error) = self.mapping.check(end) {
+                self.error = Some(error);
+                return None;
+            }
+            self.checked = end;
+        }
+        let sample = self.mapping.sample(self.position);
+        self.position += 1;
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.mapping.len - self.position.min(self.mapping.len);
+        (0, Some(remaining))
+    }
+}
+
+impl Source for MappedAudio {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.mapping.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f64(
+            self.mapping.len as f64 / self.mapping.sample_rate as f64,
+        ))
+    }
+}
+
+impl AudioBuffer {
+    /// Memory-map a WAV file written by
+    /// [`save_wav`](AudioBuffer::save_wav), for replaying it without
+    /// loading it. The header is checked here; the events come from the
+    /// sidecar, as with [`load_wav`](AudioBuffer::load_wav).
+    pub fn open_mmap(path: impl AsRef<Path>) -> Result<MappedAudio, EspeakError> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| file_error(path, e))?;
+        // Safety: the map is only read through `Mapping::sample`, after
+        // `Mapping::check` has found the file long enough.
+        let map = unsafe { Mmap::map(&file) }.map_err(|e| file_error(path, e))?;
+        let (sample_rate, data_len) = parse_wav_header(&map).map_err(|e| file_error(path, e))?;
+        let available = map.len() - WAV_HEADER_LEN;
+        let data_len = match data_len {
+            Some(len) if len > available => {
+                return Err(file_error(
+                    path,
+                    format!("{} bytes of data, not {}", available, len),
+                ))
+            }
+            Some(len) => len,
+            None => available,
+        };
+        let events = load_sidecar(path)?;
+        Ok(MappedAudio {
+            mapping: Arc::new(Mapping {
+                path: path.to_path_buf(),
+                file,
+                map,
+                sample_rate,
+                len: data_len / 2,
+            }),
+            events: events.into(),
+            position: 0,
+            checked: 0,
+            error: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+
+    fn saved(name: &str, samples: Vec<i16>) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "espeak-rs-mmap-{}-{}.wav",
+            name,
+            std::process::id()
+        ));
+        let mut buffer = AudioBuffer::new(22050);
+        buffer.samples = samples;
+        buffer.save_wav(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn mapped_samples_match_loaded_ones() {
+        let samples: Vec<i16> = (0..10_000)
+            .map(|i| (i * 37 % 65536 - 32768) as i16)
+            .collect();
+        let path = saved("match", samples.clone());
+        let loaded = AudioBuffer::load_wav(&path).unwrap();
+        let mapped = AudioBuffer::open_mmap(&path).unwrap();
+        assert_eq!(loaded.samples, samples);
+        assert_eq!(mapped.len(), samples.len());
+        assert_eq!(mapped.sample_rate(), 22050);
+        assert_eq!(mapped.collect::<Vec<_>>(), samples);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn clones_iterate_independently() {
+        let path = saved("clones", vec![1, 2, 3, 4]);
+        let mut a = AudioBuffer::open_mmap(&path).unwrap();
+        assert_eq!(a.next(), Some(1));
+        let mut b = a.clone();
+        assert_eq!(a.next(), Some(2));
+        assert_eq!(a.next(), Some(3));
+        assert_eq!(b.next(), Some(2));
+        assert_eq!(a.collect::<Vec<_>>(), vec![4]);
+        assert_eq!(b.collect::<Vec<_>>(), vec![3, 4]);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn truncation_ends_iteration_with_an_error() {
+        let path = saved("truncated", vec![7; SAMPLES_PER_CHUNK * 3]);
+        let mut mapped = AudioBuffer::open_mmap(&path).unwrap();
+        assert_eq!(mapped.next(), Some(7));
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len((WAV_HEADER_LEN + SAMPLES_PER_CHUNK * 2) as u64)
+            .unwrap();
+        // The first chunk was checked before the truncation and is still
+        // readable; the next one isn't.
+        assert_eq!(mapped.by_ref().count(), SAMPLES_PER_CHUNK - 1);
+        assert!(matches!(
+            mapped.error(),
+            Some(EspeakError::AudioFile { .. })
+        ));
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn bad_headers_are_rejected() {
+        let path = saved("header", vec![1, 2]);
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[22] = 2;
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(
+            AudioBuffer::open_mmap(&path),
+            Err(EspeakError::AudioFile { .. })
+        ));
+        std::fs::write(&path, &bytes[..20]).unwrap();
+        assert!(AudioBuffer::open_mmap(&path).is_err());
+        std::fs::remove_file(path).ok();
+    }
+}
@@ -0,0 +1,107 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Sanity checks for the text positions espeak reports.
+//!
+//! espeak's positions are 1-based and can drift by a constant amount when
+//! the text starts with whitespace, punctuation or markup. The first word
+//! of an utterance is checked against the text, and any offset found there
+//! is applied to every later event of the same utterance.
+
+use crate::{Event, TextMode};
+
+pub(crate) struct OffsetCorrector {
+    text: String,
+    text_mode: TextMode,
+    offset: Option<isize>,
+}
+
+impl OffsetCorrector {
+    pub(crate) fn new(text: &str, text_mode: TextMode) -> OffsetCorrector {
+        OffsetCorrector {
+            text: String::from(text),
+            text_mode,
+            offset: None,
+        }
+    }
+
+    /// The correction applied to this utterance's positions so far.
+    pub(crate) fn offset(&self) -> isize {
+        self.offset.unwrap_or(0)
+    }
+
+    pub(crate) fn correct(&mut self, event: &mut Event) {
+        match event {
+            Event::Word(start, _) => {
+                if self.offset.is_none() {
+                    self.offset = Some(self.first_word_offset(*start));
+                }
+                *start = self.shift(*start);
+            }
+            Event::Sentence { start, .. } => *start = self.shift(*start),
+            _ => (),
+        }
+    }
+
+    fn shift(&self, position: usize) -> usize {
+        position.saturating_add_signed(self.offset())
+    }
+
+    /// Compare the first reported word with where the text's first word
+    /// actually is. A report that already lands on a word start is trusted.
+    fn first_word_offset(&self, reported: usize) -> isize {
+        if self.is_word_start(reported) {
+            return 0;
+        }
+        match first_word_position(&self.text, self.text_mode) {
+            Some(expected) => expected as isize - reported as isize,
+            None => 0,
+        }
+    }
+
+    fn is_word_start(&self, position: usize) -> bool {
+        let starts_word = self
+            .text
+            .get(position..)
+            .and_then(|rest| rest.chars().next())
+            .is_some_and(char::is_alphanumeric);
+        let follows_word = self
+            .text
+            .get(..position)
+            .and_then(|before| before.chars().next_back())
+            .is_some_and(char::is_alphanumeric);
+        starts_word && !follows_word
+    }
+}
+
+/// Byte position of the first alphanumeric character outside of markup.
+fn first_word_position(text: &str, text_mode: TextMode) -> Option<usize> {
+    let mut in_tag = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '<' if text_mode == TextMode::Ssml => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag && c.is_alphanumeric() => return Some(i),
+            _ => (),
+        }
+    }
+    None
+}
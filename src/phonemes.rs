@@ -0,0 +1,92 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! espeak's inline phoneme escape, `[[...]]`, in plain text.
+//!
+//! With [`SpeakerParams::allow_inline_phonemes`](crate::SpeakerParams::allow_inline_phonemes)
+//! set, text between `[[` and `]]` is read as espeak phoneme mnemonics,
+//! e.g. `say [[h@'loU]] now`. Words espeak reports inside the brackets are
+//! mapped to the whole bracketed token. A literal `[[` is written `\[[`,
+//! which espeak is given as `[ [`.
+
+use crate::text_map::TextMap;
+
+const ESCAPED_OPEN: &str = "\\[[";
+const OPEN: &str = "[[";
+const CLOSE: &str = "]]";
+
+/// Replace escaped brackets in `text`, and map each phoneme region to itself
+/// so that positions inside it snap to the whole region.
+pub(crate) fn inline_phonemes(text: &str) -> (String, TextMap) {
+    let mut map = TextMap::default();
+    let mut rewritten = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        if rest.starts_with(ESCAPED_OPEN) {
+            let spoken_start = rewritten.len();
+            rewritten.push_str("[ [");
+            map.push(spoken_start..rewritten.len(), i..i + ESCAPED_OPEN.len());
+            i += ESCAPED_OPEN.len();
+        } else if let Some(inner) = rest.strip_prefix(OPEN) {
+            // An unterminated escape runs to the end of the text, as it
+            // does for espeak.
+            let len = match inner.find(CLOSE) {
+                Some(close) => OPEN.len() + close + CLOSE.len(),
+                None => rest.len(),
+            };
+            let spoken_start = rewritten.len();
+            rewritten.push_str(&rest[..len]);
+            map.push(spoken_start..rewritten.len(), i..i + len);
+            i += len;
+        } else {
+            let c = rest.chars().next().unwrap_or_default();
+            rewritten.push(c);
+            i += c.len_utf8();
+        }
+    }
+    (rewritten, map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Event;
+
+    #[test]
+    fn words_cover_the_brackets() {
+        let text = "say [[h@'loU]] now \\[[1]]";
+        let (rewritten, map) = inline_phonemes(text);
+        assert_eq!(rewritten, "say [[h@'loU]] now [ [1]]");
+
+        let mut hello = Event::Word(6, 6);
+        map.correct(&mut hello);
+        assert_eq!(hello, Event::Word(4, "[[h@'loU]]".len()));
+
+        let mut now = Event::Word(15, 3);
+        map.correct(&mut now);
+        assert_eq!(now, Event::Word(15, 3));
+
+        let mut one = Event::Word(rewritten.find('1').unwrap(), 1);
+        map.correct(&mut one);
+        assert_eq!(one, Event::Word(text.find('1').unwrap(), 1));
+    }
+}
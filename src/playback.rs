@@ -0,0 +1,215 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Helpers for playing speech on the default audio output.
+//!
+//! Machines without an audio device, such as headless CI runners, get a
+//! [`PlaybackError`] instead of a panic, and
+//! [`speak_and_wait_or_discard`] lets code paths that play speech still
+//! run there. [`Speaker::play`] does it all in one call.
+
+use crate::{
+    EspeakError, Event, PoisonlessLock, Speaker, SpeakerSource, SpeakerSourceWithCallback,
+};
+use rodio::{OutputStream, PlayError, Sink, Source, StreamError};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Why speech couldn't be played.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PlaybackError {
+    /// There is no default audio output, or it couldn't be opened.
+    NoOutputDevice(StreamError),
+    /// The output was opened but couldn't be played to.
+    Play(PlayError),
+}
+
+impl fmt::Display for PlaybackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlaybackError::NoOutputDevice(e) => write!(f, "no audio output device: {}", e),
+            PlaybackError::Play(e) => write!(f, "can't play audio: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PlaybackError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PlaybackError::NoOutputDevice(e) => Some(e),
+            PlaybackError::Play(e) => Some(e),
+        }
+    }
+}
+
+impl From<PlaybackError> for EspeakError {
+    fn from(error: PlaybackError) -> EspeakError {
+        match error {
+            PlaybackError::NoOutputDevice(e) => EspeakError::NoOutputDevice {
+                message: e.to_string(),
+            },
+            PlaybackError::Play(e) => EspeakError::PlaybackFailed {
+                message: e.to_string(),
+            },
+        }
+    }
+}
+
+/// Open the default audio output and a sink playing to it. The stream
+/// must be kept alive for as long as the sink is used.
+pub fn open_sink() -> Result<(OutputStream, Sink), PlaybackError> {
+    let (stream, handle) = OutputStream::try_default().map_err(PlaybackError::NoOutputDevice)?;
+    let sink = Sink::try_new(&handle).map_err(PlaybackError::Play)?;
+    Ok((stream, sink))
+}
+
+/// Play `source` on the default audio output, returning once it has finished.
+pub fn speak_and_wait<S>(source: S) -> Result<(), PlaybackError>
+where
+    S: Source<Item = i16> + Send + 'static,
+{
+    let (_stream, sink) = open_sink()?;
+    sink.append(source);
+    sink.sleep_until_end();
+    Ok(())
+}
+
+/// Like [`speak_and_wait`], but without an audio output the source is
+/// consumed as fast as it can be synthesized instead. Callbacks still fire,
+/// in order, just not paced to real time. Returns whether the source was
+/// played.
+pub fn speak_and_wait_or_discard<S>(source: S) -> Result<bool, PlaybackError>
+where
+    S: Source<Item = i16> + Send + 'static,
+{
+    match open_sink() {
+        Ok((_stream, sink)) => {
+            sink.append(source);
+            sink.sleep_until_end();
+            Ok(true)
+        }
+        Err(PlaybackError::NoOutputDevice(_)) => {
+            source.for_each(drop);
+            Ok(false)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+impl Speaker {
+    /// Speak `text` on the default audio output, returning once it has
+    /// been played. Without an audio device this fails with
+    /// [`EspeakError::NoOutputDevice`] before synthesizing anything.
+    pub fn play(&self, text: &str) -> Result<(), EspeakError> {
+        let (_stream, sink) = open_sink()?;
+        play_on(sink, self.try_speak(text)?)
+    }
+
+    /// Like [`play`](Speaker::play), passing each event to `callback` as
+    /// its audio is reached.
+    pub fn play_with_callback<F>(&self, text: &str, callback: F) -> Result<(), EspeakError>
+    where
+        F: FnMut(Event) + Send + 'static,
+    {
+        let (_stream, sink) = open_sink()?;
+        play_on(sink, self.try_speak(text)?.with_callback(callback))
+    }
+}
+
+/// Play `source` on `sink` until it ends, then report the error that
+/// ended it, if any.
+fn play_on<S>(sink: Sink, source: S) -> Result<(), EspeakError>
+where
+    S: Source<Item = i16> + SourceError + Send + 'static,
+{
+    let error = Arc::new(Mutex::new(None));
+    sink.append(KeepError {
+        source,
+        error: error.clone(),
+    });
+    sink.sleep_until_end();
+    let error = error.plock().take();
+    match error {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+trait SourceError {
+    fn error(&self) -> Option<&EspeakError>;
+}
+
+impl SourceError for SpeakerSource {
+    fn error(&self) -> Option<&EspeakError> {
+        SpeakerSource::error(self)
+    }
+}
+
+impl<F: FnMut(Event)> SourceError for SpeakerSourceWithCallback<F> {
+    fn error(&self) -> Option<&EspeakError> {
+        SpeakerSourceWithCallback::error(self)
+    }
+}
+
+/// Passes a source through, keeping its error once it ends, since the sink
+/// owns the source by then.
+struct KeepError<S> {
+    source: S,
+    error: Arc<Mutex<Option<EspeakError>>>,
+}
+
+impl<S> Iterator for KeepError<S>
+where
+    S: Source<Item = i16> + SourceError,
+{
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let sample = self.source.next();
+        if sample.is_none() {
+            *self.error.plock() = self.source.error().cloned();
+        }
+        sample
+    }
+}
+
+impl<S> Source for KeepError<S>
+where
+    S: Source<Item = i16> + SourceError,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
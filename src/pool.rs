@@ -0,0 +1,283 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Real parallel synthesis through a pool of worker processes.
+//!
+//! espeak-ng keeps its state in globals, so a process can only synthesize
+//! one utterance at a time. A [`PooledSynthesizer`] spawns helper processes
+//! running [`run_worker`] (the `espeak-rs-worker` binary by default) and
+//! hands each job to an idle one. Jobs and results travel over the
+//! workers' stdin and stdout as length-prefixed bincode frames.
+//!
+//! ```no_run
+//! use espeak_rs::pool::PooledSynthesizer;
+//!
+//! let pool = PooledSynthesizer::new(4).unwrap();
+//! let speaker = espeak_rs::Speaker::new();
+//! let synthesis = pool.synthesize(&speaker, "Hello, world").unwrap();
+//! println!("{} samples", synthesis.samples.len());
+//! ```
+
+use crate::{EspeakError, Event, PoisonlessLock, Speaker, SpeakerParams, SpeakerSource, TextMode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::cell::Cell;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::{Condvar, Mutex};
+
+/// Overrides where [`PooledSynthesizer::new`] looks for the worker binary.
+pub const WORKER_PATH_ENV: &str = "ESPEAK_RS_WORKER";
+
+const WORKER_BIN: &str = "espeak-rs-worker";
+
+/// The audio and events of one utterance synthesized by a worker.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Synthesis {
+    pub sample_rate: u32,
+    pub samples: Vec<i16>,
+    /// Each event along with the index of the sample it occurred at.
+    pub events: Vec<(usize, Event)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Job {
+    voice_name: String,
+    text: String,
+    params: SpeakerParams,
+}
+
+fn write_frame<T: Serialize>(writer: &mut impl Write, value: &T) -> io::Result<()> {
+    let bytes =
+        bincode::serialize(value).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let len: u32 = bytes
+        .len()
+        .try_into()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "frame too large"))?;
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(&bytes)?;
+    writer.flush()
+}
+
+/// Read the next frame, or `None` if the stream ended between frames.
+fn read_frame<T: DeserializeOwned>(reader: &mut impl Read) -> io::Result<Option<T>> {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => (),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let mut bytes = vec![0u8; u32::from_le_bytes(len) as usize];
+    reader.read_exact(&mut bytes)?;
+    bincode::deserialize(&bytes)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Serve jobs from stdin until it is closed. This is the body of the
+/// `espeak-rs-worker` binary, exposed so applications can embed the
+/// worker in their own executable.
+pub fn run_worker() -> io::Result<()> {
+    let mut stdin = BufReader::new(io::stdin().lock());
+    let mut stdout = BufWriter::new(io::stdout().lock());
+    while let Some(job) = read_frame::<Job>(&mut stdin)? {
+        write_frame(&mut stdout, &synthesize_job(job))?;
+    }
+    Ok(())
+}
+
+/// Synthesize `job` as [`Speaker::try_speak`] would in this process.
+fn synthesize_job(job: Job) -> Result<Synthesis, EspeakError> {
+    job.params.validate()?;
+    let text_mode = TextMode::from_params(&job.params);
+    let source =
+        SpeakerSource::try_with_text_mode(&job.text, &job.voice_name, job.params, text_mode)?;
+    let sample_rate = rodio::Source::sample_rate(&source);
+
+    let current_sample = Cell::new(0usize);
+    let mut events = Vec::<(usize, Event)>::new();
+    let mut samples = Vec::<i16>::new();
+    let mut source = source.with_callback(|evt| events.push((current_sample.get(), evt)));
+    for sample in source.by_ref() {
+        samples.push(sample);
+        current_sample.set(current_sample.get() + 1);
+    }
+    if let Some(error) = source.error() {
+        return Err(error.clone());
+    }
+    drop(source);
+    Ok(Synthesis {
+        sample_rate,
+        samples,
+        events,
+    })
+}
+
+struct Worker {
+    child: Child,
+    stdin: BufWriter<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Worker {
+    fn spawn(path: &Path) -> io::Result<Worker> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = BufWriter::new(child.stdin.take().expect("worker stdin is piped"));
+        let stdout = BufReader::new(child.stdout.take().expect("worker stdout is piped"));
+        Ok(Worker {
+            child,
+            stdin,
+            stdout,
+        })
+    }
+
+    fn run(&mut self, job: &Job) -> io::Result<Result<Synthesis, EspeakError>> {
+        write_frame(&mut self.stdin, job)?;
+        read_frame(&mut self.stdout)?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "worker exited mid-job"))
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// A fixed-size pool of synthesis worker processes.
+///
+/// [`PooledSynthesizer::synthesize`] blocks until a worker is free, so at
+/// most `n_workers` utterances are in flight at once. A worker that dies
+/// is replaced and the job is retried once on the replacement. If no
+/// replacement can be spawned its slot stays empty, and the next job to
+/// take that slot tries again.
+pub struct PooledSynthesizer {
+    worker_path: PathBuf,
+    /// One entry per worker slot; `None` once a worker died and could not
+    /// be replaced.
+    idle: Mutex<Vec<Option<Worker>>>,
+    available: Condvar,
+}
+
+impl PooledSynthesizer {
+    /// Spawn `n_workers` copies of the `espeak-rs-worker` binary, found via
+    /// the `ESPEAK_RS_WORKER` environment variable or next to the current
+    /// executable.
+    pub fn new(n_workers: usize) -> io::Result<PooledSynthesizer> {
+        let worker_path = match std::env::var_os(WORKER_PATH_ENV) {
+            Some(path) => PathBuf::from(path),
+            None => {
+                let exe = std::env::current_exe()?;
+                let dir = exe.parent().unwrap_or(Path::new("."));
+                dir.join(WORKER_BIN)
+                    .with_extension(std::env::consts::EXE_EXTENSION)
+            }
+        };
+        Self::with_worker_path(worker_path, n_workers)
+    }
+
+    /// Spawn `n_workers` copies of the given executable, which must call
+    /// [`run_worker`].
+    pub fn with_worker_path<P: Into<PathBuf>>(
+        worker_path: P,
+        n_workers: usize,
+    ) -> io::Result<PooledSynthesizer> {
+        let worker_path = worker_path.into();
+        let workers = (0..n_workers.max(1))
+            .map(|_| Worker::spawn(&worker_path).map(Some))
+            .collect::<io::Result<Vec<Option<Worker>>>>()?;
+        Ok(PooledSynthesizer {
+            worker_path,
+            idle: Mutex::new(workers),
+            available: Condvar::new(),
+        })
+    }
+
+    /// Process ids of the currently idle workers.
+    pub fn worker_ids(&self) -> Vec<u32> {
+        let idle = self.idle.plock();
+        idle.iter()
+            .flatten()
+            .map(|worker| worker.child.id())
+            .collect()
+    }
+
+    /// Synthesize `text` with the speaker's voice and params on the next
+    /// free worker, blocking until the whole utterance is available.
+    ///
+    /// An [`EspeakError`] from the worker, such as a param out of range,
+    /// comes back as an [`io::Error`] of kind
+    /// [`Other`](io::ErrorKind::Other) wrapping it.
+    pub fn synthesize(&self, speaker: &Speaker, text: &str) -> io::Result<Synthesis> {
+        let job = Job {
+            voice_name: speaker.voice_name.clone(),
+            text: String::from(text),
+            params: speaker.current_params(),
+        };
+
+        let mut slot = self.checkout();
+        let result = self.run_in(&mut slot, &job);
+        self.checkin(slot);
+        result?.map_err(io::Error::other)
+    }
+
+    /// Run `job` on the slot's worker, spawning one if the slot is empty.
+    /// A worker that fails is dropped rather than put back in the slot.
+    fn run_in(
+        &self,
+        slot: &mut Option<Worker>,
+        job: &Job,
+    ) -> io::Result<Result<Synthesis, EspeakError>> {
+        if let Some(worker) = slot {
+            match worker.run(job) {
+                Ok(result) => return Ok(result),
+                // The worker crashed or was killed; replace it and retry once.
+                Err(_) => *slot = None,
+            }
+        }
+        let worker = slot.insert(Worker::spawn(&self.worker_path)?);
+        let result = worker.run(job);
+        if result.is_err() {
+            *slot = None;
+        }
+        result
+    }
+
+    fn checkout(&self) -> Option<Worker> {
+        let mut idle = self.idle.plock();
+        loop {
+            if let Some(slot) = idle.pop() {
+                return slot;
+            }
+            idle = self.available.wait(idle).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    fn checkin(&self, slot: Option<Worker>) {
+        let mut idle = self.idle.plock();
+        idle.push(slot);
+        self.available.notify_one();
+    }
+}
@@ -0,0 +1,131 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Text positions in characters and UTF-16 code units as well as bytes,
+//! for consumers that index strings differently, e.g. JavaScript.
+
+use crate::Event;
+use std::ops::Range;
+
+/// Where a word or sentence is in the spoken text, in bytes, in
+/// characters and in UTF-16 code units. Each range selects the same text.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct WordPosition {
+    pub byte: Range<usize>,
+    pub char: Range<usize>,
+    pub utf16: Range<usize>,
+}
+
+/// The character and UTF-16 offset of every character boundary of a
+/// text, built once so that each lookup is a binary search.
+#[derive(Debug, Clone)]
+pub(crate) struct PositionIndex {
+    /// The byte and UTF-16 offset of each character, indexed by character,
+    /// followed by those of the end of the text.
+    starts: Vec<(usize, usize)>,
+}
+
+impl PositionIndex {
+    pub(crate) fn new(text: &str) -> PositionIndex {
+        let mut starts = Vec::with_capacity(text.len() + 1);
+        let mut utf16 = 0;
+        for (byte, c) in text.char_indices() {
+            starts.push((byte, utf16));
+            utf16 += c.len_utf16();
+        }
+        starts.push((text.len(), utf16));
+        PositionIndex { starts }
+    }
+
+    /// The character and UTF-16 offset of the byte offset `byte`, if it's a
+    /// character boundary.
+    fn locate(&self, byte: usize) -> Option<(usize, usize)> {
+        let char = self
+            .starts
+            .binary_search_by_key(&byte, |(start, _)| *start)
+            .ok()?;
+        Some((char, self.starts[char].1))
+    }
+
+    /// The position of a [`Event::Word`] or [`Event::Sentence`]. `None` for
+    /// other events, or if the event doesn't fall on character boundaries.
+    pub(crate) fn position(&self, event: &Event) -> Option<WordPosition> {
+        let (start, len) = match event {
+            Event::Word(start, len) => (*start, *len),
+            Event::Sentence { start, len } => (*start, *len),
+            _ => return None,
+        };
+        let end = start.checked_add(len)?;
+        let (char_start, utf16_start) = self.locate(start)?;
+        let (char_end, utf16_end) = self.locate(end)?;
+        Some(WordPosition {
+            byte: start..end,
+            char: char_start..char_end,
+            utf16: utf16_start..utf16_end,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "Hi café 東京 🎉 naïve 😀x";
+
+    #[test]
+    fn ranges_select_the_same_word() {
+        let index = PositionIndex::new(TEXT);
+        let chars: Vec<char> = TEXT.chars().collect();
+        let utf16: Vec<u16> = TEXT.encode_utf16().collect();
+        let mut words = 0;
+        for word in TEXT.split(' ') {
+            let start = word.as_ptr() as usize - TEXT.as_ptr() as usize;
+            let position = index.position(&Event::Word(start, word.len())).unwrap();
+            assert_eq!(&TEXT[position.byte.clone()], word);
+            assert_eq!(chars[position.char].iter().collect::<String>(), word);
+            assert_eq!(String::from_utf16(&utf16[position.utf16]).unwrap(), word);
+            words += 1;
+        }
+        assert_eq!(words, 6);
+
+        let all = Event::Sentence {
+            start: 0,
+            len: TEXT.len(),
+        };
+        let position = index.position(&all).unwrap();
+        assert_eq!(position.char, 0..chars.len());
+        assert_eq!(position.utf16, 0..utf16.len());
+    }
+
+    #[test]
+    fn positions_off_boundaries_are_refused() {
+        let index = PositionIndex::new(TEXT);
+        let cafe = TEXT.find("café").unwrap();
+        assert_eq!(index.position(&Event::Word(cafe, 4)), None);
+        assert_eq!(index.position(&Event::Word(TEXT.len(), 1)), None);
+        assert_eq!(index.position(&Event::Word(usize::MAX, 2)), None);
+        assert_eq!(index.position(&Event::End), None);
+
+        let empty = PositionIndex::new("");
+        let position = empty.position(&Event::Word(0, 0)).unwrap();
+        assert_eq!((position.char, position.utf16), (0..0, 0..0));
+    }
+}
@@ -0,0 +1,41 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! The items most programs need, for a glob import.
+//!
+//! Along with this crate's own types it brings in rodio's [`Source`]
+//! trait, from the same rodio as [`crate::rodio`], so that it is always the
+//! one [`SpeakerSource`](crate::SpeakerSource) implements:
+//!
+//! ```no_run
+//! use espeak_rs::prelude::*;
+//! use espeak_rs::rodio::{OutputStream, Sink};
+//!
+//! let (_stream, handle) = OutputStream::try_default().unwrap();
+//! let sink = Sink::try_new(&handle).unwrap();
+//! let source = Speaker::new().speak("Hello world");
+//! println!("{}Hz", source.sample_rate());
+//! sink.append(source);
+//! sink.sleep_until_end();
+//! ```
+
+pub use crate::{list_voices, Event, Speaker, SpeakerParams, Voice};
+pub use rodio::Source;
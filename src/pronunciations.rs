@@ -0,0 +1,207 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Replacing words with how they should be said, for
+//! [`Speaker::add_pronunciation`](crate::Speaker::add_pronunciation).
+//!
+//! Words are matched regardless of case, and only as whole words: a match
+//! can't start or end in the middle of a run of letters, digits and
+//! underscores, so "cat" leaves "category" alone. Where matches overlap,
+//! the one starting first wins, and the longest of those starting at the
+//! same place. Replacements aren't matched again.
+
+use crate::text_map::TextMap;
+use std::collections::BTreeMap;
+
+const OPEN_PHONEMES: &str = "[[";
+const CLOSE_PHONEMES: &str = "]]";
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Length in bytes of the start of `text` that is `word`, ignoring case.
+fn match_len(text: &str, word: &str) -> Option<usize> {
+    let mut text_chars = text.char_indices();
+    for expected in word.chars() {
+        let (_, c) = text_chars.next()?;
+        if !c.to_lowercase().eq(expected.to_lowercase()) {
+            return None;
+        }
+    }
+    Some(text_chars.next().map_or(text.len(), |(i, _)| i))
+}
+
+/// Replace the words of `text` that are in `pronunciations`. Phoneme
+/// regions are skipped if `inline_phonemes` is set.
+pub(crate) fn apply_pronunciations(
+    text: &str,
+    pronunciations: &BTreeMap<String, String>,
+    inline_phonemes: bool,
+) -> (String, TextMap) {
+    let mut map = TextMap::default();
+    if pronunciations.is_empty() {
+        return (String::from(text), map);
+    }
+    let mut rewritten = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut i = 0;
+    let mut previous = None;
+    while let Some(c) = text[i..].chars().next() {
+        if inline_phonemes && text[i..].starts_with(OPEN_PHONEMES) {
+            // An unterminated region runs to the end of the text.
+            i = match text[i..].find(CLOSE_PHONEMES) {
+                Some(close) => i + close + CLOSE_PHONEMES.len(),
+                None => text.len(),
+            };
+            previous = None;
+            continue;
+        }
+        let at_boundary = !previous.is_some_and(is_word_char) || !is_word_char(c);
+        let found = at_boundary
+            .then(|| {
+                pronunciations
+                    .iter()
+                    .filter(|(word, _)| !word.is_empty())
+                    .filter_map(|(word, replacement)| {
+                        let len = match_len(&text[i..], word)?;
+                        let ends_word = text[i..i + len].chars().next_back().map(is_word_char)
+                            == Some(true)
+                            && text[i + len..].chars().next().is_some_and(is_word_char);
+                        (!ends_word).then_some((len, replacement))
+                    })
+                    .max_by_key(|(len, _)| *len)
+            })
+            .flatten();
+        match found {
+            Some((len, replacement)) => {
+                rewritten.push_str(&text[copied..i]);
+                let spoken_start = rewritten.len();
+                rewritten.push_str(replacement);
+                map.push(spoken_start..rewritten.len(), i..i + len);
+                i += len;
+                copied = i;
+                previous = text[..i].chars().next_back();
+            }
+            None => {
+                i += c.len_utf8();
+                previous = Some(c);
+            }
+        }
+    }
+    rewritten.push_str(&text[copied..]);
+    (rewritten, map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Event;
+
+    fn pronunciations(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(word, replacement)| (word.to_lowercase(), replacement.to_string()))
+            .collect()
+    }
+
+    fn applied(text: &str, pairs: &[(&str, &str)]) -> String {
+        apply_pronunciations(text, &pronunciations(pairs), false).0
+    }
+
+    #[test]
+    fn whole_words_are_replaced() {
+        let pairs = [("nginx", "engine x"), ("cat", "kitty")];
+        assert_eq!(
+            applied("NGINX serves the cat category, Cat!", &pairs),
+            "engine x serves the kitty category, kitty!"
+        );
+        assert_eq!(applied("concat cats cat_x", &pairs), "concat cats cat_x");
+        assert_eq!(applied("Straße", &[("STRASSE", "x")]), "Straße");
+        assert_eq!(
+            applied("ÉCOLE école", &[("école", "school")]),
+            "school school"
+        );
+    }
+
+    #[test]
+    fn overlapping_words() {
+        let pairs = [
+            ("postgres", "post gress"),
+            ("postgresql", "post gress Q L"),
+            ("sql server", "sequel server"),
+            ("server", "serva"),
+        ];
+        assert_eq!(
+            applied("PostgreSQL or SQL Server, not postgres server", &pairs),
+            "post gress Q L or sequel server, not post gress serva"
+        );
+        // The first match wins, even if a longer one starts inside it.
+        let pairs = [("a b", "1"), ("b c", "2")];
+        assert_eq!(applied("a b c", &pairs), "1 c");
+        // Replacements aren't replaced again.
+        let pairs = [("x", "y"), ("y", "z")];
+        assert_eq!(applied("x y", &pairs), "y z");
+        // Symbols are never in the middle of a word.
+        let pairs = [("c++", "C plus plus")];
+        assert_eq!(applied("(c++, c#)", &pairs), "(C plus plus, c#)");
+    }
+
+    #[test]
+    fn phoneme_regions_are_skipped() {
+        let pairs = pronunciations(&[("hello", "hi")]);
+        let text = "[[hello]] hello [[hello";
+        assert_eq!(
+            apply_pronunciations(text, &pairs, true).0,
+            "[[hello]] hi [[hello"
+        );
+    }
+
+    #[test]
+    fn words_map_back_to_the_original() {
+        let text = "Use nginx with PostgreSQL.";
+        let pairs = pronunciations(&[("nginx", "engine x"), ("postgresql", "post gress")]);
+        let (rewritten, map) = apply_pronunciations(text, &pairs, false);
+        assert_eq!(rewritten, "Use engine x with post gress.");
+        let mut events = [
+            Event::Word(0, 3),
+            Event::Word(4, 6),
+            Event::Word(11, 1),
+            Event::Word(13, 4),
+            Event::Word(18, 4),
+            Event::Word(23, 5),
+        ];
+        for event in &mut events {
+            map.correct(event);
+        }
+        let words: Vec<_> = events
+            .iter()
+            .map(|event| match event {
+                Event::Word(start, len) => &text[*start..start + len],
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            words,
+            ["Use", "nginx", "nginx", "with", "PostgreSQL", "PostgreSQL"]
+        );
+    }
+}
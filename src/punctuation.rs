@@ -0,0 +1,370 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Restoring punctuation to text that has none, as speech recognizers
+//! produce, for [`SpeakerParams::punctuation_repair`].
+//!
+//! Without punctuation espeak reads a whole transcript as one sentence,
+//! with one intonation contour and no pauses. The repair is heuristic:
+//! a pause marker ends a sentence, a sentence running past
+//! [`PunctuationRepair::max_words`] is ended before the next conjunction,
+//! or regardless once half as long again, and a clause running past half
+//! that gets a comma before its next conjunction. Sentence starts are
+//! capitalized. Punctuation already in the text is respected.
+//!
+//! [`SpeakerParams::punctuation_repair`]: crate::SpeakerParams::punctuation_repair
+
+use crate::text_map::TextMap;
+use std::ops::Range;
+
+/// Conjunctions [`PunctuationRepair`] breaks sentences and clauses before
+/// by default.
+pub const DEFAULT_CONJUNCTIONS: &[&str] = &[
+    "although", "and", "because", "but", "however", "or", "so", "then", "though", "which", "while",
+];
+
+/// How to restore punctuation to unpunctuated plain text. See
+/// [`SpeakerParams::punctuation_repair`](crate::SpeakerParams::punctuation_repair).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    any(feature = "serde", feature = "subprocess-pool"),
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[non_exhaustive]
+pub struct PunctuationRepair {
+    /// Text marking a long pause, which ends a sentence. Defaults to a
+    /// line break. A marker other than whitespace is replaced by a space;
+    /// an empty one never matches.
+    pub pause_marker: String,
+    /// The most words in a sentence before it is ended at the next
+    /// conjunction. Defaults to 20.
+    pub max_words: usize,
+    /// Lowercase words to end sentences and clauses before. Defaults to
+    /// [`DEFAULT_CONJUNCTIONS`], which are English.
+    pub conjunctions: Vec<String>,
+}
+
+impl Default for PunctuationRepair {
+    fn default() -> Self {
+        PunctuationRepair {
+            pause_marker: String::from("\n"),
+            max_words: 20,
+            conjunctions: DEFAULT_CONJUNCTIONS
+                .iter()
+                .map(|c| String::from(*c))
+                .collect(),
+        }
+    }
+}
+
+/// A change [`PunctuationRepair`] made, at a byte position in the
+/// caller's text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PunctuationEdit {
+    /// `mark` was inserted at `position`, after a word.
+    Inserted { position: usize, mark: char },
+    /// The letter at `position` was capitalized to start a sentence.
+    Capitalized { position: usize },
+}
+
+const OPEN_PHONEMES: &str = "[[";
+const CLOSE_PHONEMES: &str = "]]";
+
+enum Token {
+    Word(Range<usize>),
+    Pause(Range<usize>),
+}
+
+/// Restore punctuation to `text` according to `repair`. Phoneme regions
+/// are kept within words if `inline_phonemes` is set, and never
+/// capitalized.
+pub(crate) fn restore_punctuation(
+    text: &str,
+    repair: &PunctuationRepair,
+    inline_phonemes: bool,
+) -> (String, TextMap, Vec<PunctuationEdit>) {
+    let mut out = Rewriter {
+        text,
+        rewritten: String::with_capacity(text.len() + text.len() / 16),
+        copied: 0,
+        map: TextMap::default(),
+        edits: Vec::new(),
+    };
+    let clause_limit = (repair.max_words / 2).max(1);
+    let sentence_limit = repair.max_words + repair.max_words / 2;
+    let mut sentence_words = 0;
+    let mut clause_words = 0;
+    let mut sentence_start = true;
+    // The end of the previous word, if a mark can follow it.
+    let mut open_end = None;
+    for token in tokens(text, &repair.pause_marker, inline_phonemes) {
+        let word = match token {
+            Token::Pause(marker) => {
+                if !sentence_start {
+                    if let Some(end) = open_end {
+                        out.insert(end, '.');
+                    }
+                    sentence_start = true;
+                }
+                if !repair.pause_marker.chars().all(char::is_whitespace) {
+                    out.replace(marker, " ");
+                }
+                continue;
+            }
+            Token::Word(word) => word,
+        };
+        if let Some(end) = open_end.filter(|_| !sentence_start) {
+            let conjunction = is_conjunction(&text[word.clone()], &repair.conjunctions);
+            if (conjunction && sentence_words >= repair.max_words)
+                || sentence_words >= sentence_limit
+            {
+                out.insert(end, '.');
+                sentence_start = true;
+            } else if conjunction && clause_words >= clause_limit {
+                out.insert(end, ',');
+                clause_words = 0;
+            }
+        }
+        if sentence_start {
+            out.capitalize(word.clone(), inline_phonemes);
+            sentence_start = false;
+            sentence_words = 0;
+            clause_words = 0;
+        }
+        sentence_words += 1;
+        clause_words += 1;
+        let word_text = &text[word.clone()];
+        open_end = is_open(word_text).then_some(word.end);
+        match word_text.chars().next_back() {
+            Some('.' | '!' | '?' | '…') => sentence_start = true,
+            Some(',' | ';' | ':') => clause_words = 0,
+            _ => (),
+        }
+    }
+    out.rewritten.push_str(&text[out.copied..]);
+    (out.rewritten, out.map, out.edits)
+}
+
+struct Rewriter<'a> {
+    text: &'a str,
+    rewritten: String,
+    copied: usize,
+    map: TextMap,
+    edits: Vec<PunctuationEdit>,
+}
+
+impl Rewriter<'_> {
+    fn insert(&mut self, position: usize, mark: char) {
+        self.rewritten.push_str(&self.text[self.copied..position]);
+        let spoken_start = self.rewritten.len();
+        self.rewritten.push(mark);
+        self.map
+            .push(spoken_start..self.rewritten.len(), position..position);
+        self.copied = position;
+        self.edits
+            .push(PunctuationEdit::Inserted { position, mark });
+    }
+
+    fn replace(&mut self, original: Range<usize>, spoken: &str) {
+        self.rewritten
+            .push_str(&self.text[self.copied..original.start]);
+        let spoken_start = self.rewritten.len();
+        self.rewritten.push_str(spoken);
+        self.copied = original.end;
+        self.map.push(spoken_start..self.rewritten.len(), original);
+    }
+
+    /// Capitalize the first letter of `word`, unless it starts with
+    /// something other than a letter or quote.
+    fn capitalize(&mut self, word: Range<usize>, inline_phonemes: bool) {
+        let word_text = &self.text[word.clone()];
+        if inline_phonemes && word_text.starts_with(OPEN_PHONEMES) {
+            return;
+        }
+        let Some((offset, letter)) = word_text
+            .char_indices()
+            .find(|(_, c)| !matches!(c, '"' | '\'' | '(' | '“' | '‘'))
+        else {
+            return;
+        };
+        if !letter.is_lowercase() {
+            return;
+        }
+        let position = word.start + offset;
+        let upper: String = letter.to_uppercase().collect();
+        self.replace(position..position + letter.len_utf8(), &upper);
+        self.edits.push(PunctuationEdit::Capitalized { position });
+    }
+}
+
+/// Whether `word` ends without punctuation, so that a mark can follow it.
+fn is_open(word: &str) -> bool {
+    word.chars()
+        .next_back()
+        .is_some_and(|c| c.is_alphanumeric() || c == ']')
+}
+
+fn is_conjunction(word: &str, conjunctions: &[String]) -> bool {
+    let word = word.trim_end_matches(|c: char| !c.is_alphanumeric());
+    conjunctions
+        .iter()
+        .any(|conjunction| conjunction.eq_ignore_ascii_case(word))
+}
+
+/// The words of `text`, split at whitespace and pause markers, and the
+/// pause markers. Phoneme regions are kept whole if `inline_phonemes` is
+/// set.
+fn tokens(text: &str, pause_marker: &str, inline_phonemes: bool) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let is_marker = |i: usize| !pause_marker.is_empty() && text[i..].starts_with(pause_marker);
+    let mut i = 0;
+    while let Some(c) = text[i..].chars().next() {
+        if is_marker(i) {
+            tokens.push(Token::Pause(i..i + pause_marker.len()));
+            i += pause_marker.len();
+            continue;
+        }
+        if c.is_whitespace() {
+            i += c.len_utf8();
+            continue;
+        }
+        let start = i;
+        while let Some(c) = text[i..].chars().next() {
+            if c.is_whitespace() || is_marker(i) {
+                break;
+            }
+            if inline_phonemes && text[i..].starts_with(OPEN_PHONEMES) {
+                // An unterminated region runs to the end of the text.
+                i = match text[i..].find(CLOSE_PHONEMES) {
+                    Some(close) => i + close + CLOSE_PHONEMES.len(),
+                    None => text.len(),
+                };
+                continue;
+            }
+            i += c.len_utf8();
+        }
+        tokens.push(Token::Word(start..i));
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Event;
+
+    fn repaired(text: &str, max_words: usize) -> String {
+        let repair = PunctuationRepair {
+            max_words,
+            ..PunctuationRepair::default()
+        };
+        restore_punctuation(text, &repair, false).0
+    }
+
+    #[test]
+    fn pauses_end_sentences() {
+        assert_eq!(
+            repaired("so we met there\nit was raining\n", 20),
+            "So we met there.\nIt was raining.\n"
+        );
+        assert_eq!(
+            repaired("we met there.\n\nit was raining", 20),
+            "We met there.\n\nIt was raining"
+        );
+        let repair = PunctuationRepair {
+            pause_marker: String::from("<pause>"),
+            ..PunctuationRepair::default()
+        };
+        assert_eq!(
+            restore_punctuation("we met<pause>it rained", &repair, false).0,
+            "We met. It rained"
+        );
+    }
+
+    #[test]
+    fn long_runs_are_split() {
+        assert_eq!(
+            repaired("we walked to the shop and then we bought some bread", 4),
+            "We walked to the shop. And then we bought some bread"
+        );
+        assert_eq!(
+            repaired("one two three four five six seven eight", 4),
+            "One two three four five six. Seven eight"
+        );
+        assert_eq!(
+            repaired("we walked for hours but we never got there", 6),
+            "We walked for hours, but we never got there"
+        );
+        assert_eq!(
+            repaired("we walked, for hours but we never got there", 6),
+            "We walked, for hours but we never got there"
+        );
+    }
+
+    #[test]
+    fn sentence_starts_are_capitalized() {
+        assert_eq!(
+            repaired("yes. \"no\" said élodie! ok? [[h@'loU]]", 20),
+            "Yes. \"No\" said élodie! Ok? [[h@'loU]]"
+        );
+        let repair = PunctuationRepair::default();
+        let (text, _, edits) = restore_punctuation("hi [[h@'loU\n]] x\n[[b]] y", &repair, true);
+        assert_eq!(text, "Hi [[h@'loU\n]] x.\n[[b]] y");
+        assert_eq!(
+            edits,
+            [
+                PunctuationEdit::Capitalized { position: 0 },
+                PunctuationEdit::Inserted {
+                    position: 16,
+                    mark: '.'
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn events_map_to_the_original() {
+        let text = "ok\nthen go";
+        let (rewritten, map, _) = restore_punctuation(text, &PunctuationRepair::default(), false);
+        assert_eq!(rewritten, "Ok.\nThen go");
+        let mut events = [
+            Event::Sentence { start: 0, len: 3 },
+            Event::Word(0, 2),
+            Event::Sentence { start: 4, len: 7 },
+            Event::Word(4, 4),
+            Event::Word(9, 2),
+        ];
+        for event in &mut events {
+            map.correct(event);
+        }
+        assert_eq!(
+            events,
+            [
+                Event::Sentence { start: 0, len: 3 },
+                Event::Word(0, 2),
+                Event::Sentence { start: 3, len: 7 },
+                Event::Word(3, 4),
+                Event::Word(8, 2),
+            ]
+        );
+    }
+}
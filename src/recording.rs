@@ -0,0 +1,142 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Recorded event streams, for working on a UI without espeak.
+//!
+//! [`EventRecording::capture`] keeps the events of a synthesis along with
+//! its audio. A recording can be saved as JSON with the `serde` feature,
+//! and [`EventRecording::replay`] plays its events back in real time, at
+//! any speed.
+
+#[cfg(feature = "dsp")]
+use crate::{AudioBuffer, SpeakerSource};
+use crate::{Event, TimedEvent};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The events of one utterance, with their offsets into its audio.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EventRecording {
+    /// The text spoken, which word and sentence positions refer to.
+    pub text: String,
+    /// Length of the audio.
+    pub duration: Duration,
+    /// Events in order of their offsets.
+    pub events: Vec<TimedEvent>,
+}
+
+impl EventRecording {
+    /// Synthesize all of `source`, keeping its audio and its events. If
+    /// synthesis fails part way, both hold what was produced until then.
+    #[cfg(feature = "dsp")]
+    pub fn capture(mut source: SpeakerSource) -> (AudioBuffer, EventRecording) {
+        let mut buffer = AudioBuffer::new(source.sample_rate);
+        loop {
+            let (sample, events) = source.next_sample_and_timed_events();
+            buffer.events.extend(events.into_iter().flatten());
+            match sample {
+                Some(sample) => buffer.samples.push(sample),
+                None => break,
+            }
+        }
+        let recording = EventRecording {
+            text: source.text.clone(),
+            duration: buffer.duration(),
+            events: buffer.events.clone(),
+        };
+        (buffer, recording)
+    }
+
+    /// Play the events back, each one yielded when it is due. `speed` scales
+    /// time, so at `2.0` the events come twice as fast as they were spoken.
+    /// Panics unless `speed` is positive.
+    pub fn replay(&self, speed: f32) -> Replay {
+        assert!(speed > 0.0, "replay speed must be positive, not {}", speed);
+        Replay {
+            events: self.events.clone(),
+            next: 0,
+            speed,
+            base: Duration::ZERO,
+            started: None,
+        }
+    }
+
+    /// The part of the text that `event` covers, as
+    /// [`SpeakerSource::resolve`](crate::SpeakerSource::resolve) would
+    /// give it.
+    pub fn resolve(&self, event: &Event) -> Option<&str> {
+        match event {
+            Event::Sentence { start, len } => self.text.get(*start..start.checked_add(*len)?),
+            _ => event.word_in(&self.text),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Failed to serialize EventRecording")
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> Result<EventRecording, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+}
+
+/// Events of an [`EventRecording`] yielded in real time. See
+/// [`EventRecording::replay`].
+pub struct Replay {
+    events: Vec<TimedEvent>,
+    next: usize,
+    speed: f32,
+    /// The offset that playback started from.
+    base: Duration,
+    /// When the first event was asked for, since the start or last seek.
+    started: Option<Instant>,
+}
+
+impl Replay {
+    /// Continue from `offset` into the recording, with the events due at
+    /// or after it. Timing starts over from the next call to `next`.
+    pub fn seek(&mut self, offset: Duration) {
+        self.next = self.events.partition_point(|timed| timed.offset < offset);
+        self.base = offset;
+        self.started = None;
+    }
+}
+
+impl Iterator for Replay {
+    type Item = TimedEvent;
+
+    fn next(&mut self) -> Option<TimedEvent> {
+        let timed = self.events.get(self.next)?.clone();
+        self.next += 1;
+        let started = *self.started.get_or_insert_with(Instant::now);
+        let due = started + timed.offset.saturating_sub(self.base).div_f32(self.speed);
+        thread::sleep(due.saturating_duration_since(Instant::now()));
+        Some(timed)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.events.len() - self.next;
+        (remaining, Some(remaining))
+    }
+}
@@ -0,0 +1,315 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Handing audio to a real-time callback that must never block.
+//!
+//! A helper thread moves samples from the source into a fixed-size
+//! single-producer, single-consumer ring, and events into a second one
+//! allocated up front alongside it. The consumer side only touches
+//! atomics, so reading from it never waits on synthesis: when the ring is
+//! short, the rest of the buffer is filled with silence and an underrun
+//! is counted. When the event ring is full, new events are dropped and
+//! counted rather than holding up the audio.
+
+use crate::{Event, SpeakerSource};
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicBool, AtomicI16, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How long the helper thread waits for room when the ring is full.
+const FULL_RING_WAIT: Duration = Duration::from_millis(1);
+
+/// Room in the event ring per second of audio the sample ring holds, well
+/// above the rate espeak emits events at, phoneme events included.
+const EVENTS_PER_SECOND: f64 = 64.0;
+
+/// The fewest events the event ring has room for.
+const MIN_EVENTS: usize = 64;
+
+struct Ring {
+    samples: Box<[AtomicI16]>,
+    /// Total samples ever written and read. Their difference is how many
+    /// are waiting in the ring.
+    written: AtomicUsize,
+    read: AtomicUsize,
+    /// Set once the source has ended and everything has been written.
+    finished: AtomicBool,
+}
+
+impl Ring {
+    fn new(capacity: usize) -> Ring {
+        Ring {
+            samples: (0..capacity.max(1)).map(|_| AtomicI16::new(0)).collect(),
+            written: AtomicUsize::new(0),
+            read: AtomicUsize::new(0),
+            finished: AtomicBool::new(false),
+        }
+    }
+
+    /// Write as many of `samples` as fit, returning how many did.
+    fn push(&self, samples: &[i16]) -> usize {
+        let written = self.written.load(Ordering::Relaxed);
+        let read = self.read.load(Ordering::Acquire);
+        let count = samples.len().min(self.samples.len() - (written - read));
+        for (i, sample) in samples[..count].iter().enumerate() {
+            self.samples[(written + i) % self.samples.len()].store(*sample, Ordering::Relaxed);
+        }
+        self.written.store(written + count, Ordering::Release);
+        count
+    }
+
+    /// Read as many samples as are available into `out`, returning how many.
+    fn pop(&self, out: &mut [i16]) -> usize {
+        let read = self.read.load(Ordering::Relaxed);
+        let written = self.written.load(Ordering::Acquire);
+        let count = out.len().min(written - read);
+        for (i, sample) in out[..count].iter_mut().enumerate() {
+            *sample = self.samples[(read + i) % self.samples.len()].load(Ordering::Relaxed);
+        }
+        self.read.store(read + count, Ordering::Release);
+        count
+    }
+}
+
+/// An event along with the sample position it falls at.
+type PositionedEvent = (u64, Event);
+
+/// Events in a fixed-size single-producer, single-consumer ring like
+/// [`Ring`]'s samples.
+struct EventRing {
+    slots: Box<[UnsafeCell<Option<PositionedEvent>>]>,
+    /// Total events ever written and read, as in [`Ring`].
+    written: AtomicUsize,
+    read: AtomicUsize,
+    /// Events that didn't fit.
+    dropped: AtomicUsize,
+}
+
+// SAFETY: the producer only touches slots outside `read..written` and the
+// consumer only ones inside it, and a slot passes from one to the other
+// through a release store and acquire load of those counters.
+unsafe impl Sync for EventRing {}
+
+impl EventRing {
+    fn new(capacity: usize) -> EventRing {
+        EventRing {
+            slots: (0..capacity.max(1))
+                .map(|_| UnsafeCell::new(None))
+                .collect(),
+            written: AtomicUsize::new(0),
+            read: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    /// Write `event` at `position`, or drop it if the ring is full. Only
+    /// called from the helper thread.
+    fn push(&self, position: u64, event: Event) {
+        let written = self.written.load(Ordering::Relaxed);
+        let read = self.read.load(Ordering::Acquire);
+        if written - read == self.slots.len() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let slot = self.slots[written % self.slots.len()].get();
+        // SAFETY: the slot is outside `read..written`, so the consumer is
+        // done with it.
+        unsafe { *slot = Some((position, event)) };
+        self.written.store(written + 1, Ordering::Release);
+    }
+
+    /// Take the next event if it falls within the first `consumed`
+    /// samples. Only called from the consumer.
+    fn pop_due(&self, consumed: u64) -> Option<PositionedEvent> {
+        let read = self.read.load(Ordering::Relaxed);
+        let written = self.written.load(Ordering::Acquire);
+        if read == written {
+            return None;
+        }
+        // SAFETY: the slot is inside `read..written`, so the producer has
+        // finished writing it and won't touch it until `read` moves past.
+        let slot = unsafe { &mut *self.slots[read % self.slots.len()].get() };
+        match slot {
+            Some((position, _)) if *position <= consumed => {
+                let event = slot.take();
+                self.read.store(read + 1, Ordering::Release);
+                event
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The helper thread filling the ring, from [`SpeakerSource::into_ring`].
+/// Dropping it stops the thread, which stops synthesis.
+pub struct RingProducerHandle {
+    ring: Arc<Ring>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl RingProducerHandle {
+    /// Whether the whole source has been written to the ring.
+    pub fn is_finished(&self) -> bool {
+        self.ring.finished.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for RingProducerHandle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The real-time side of the ring, from [`SpeakerSource::into_ring`].
+pub struct RingConsumer {
+    ring: Arc<Ring>,
+    events: Arc<EventRing>,
+    consumed: u64,
+    underruns: u64,
+    sample_rate: u32,
+}
+
+impl RingConsumer {
+    /// Fill `out` without blocking, returning how many samples came from
+    /// the source. If fewer are ready, the rest of `out` is silence and,
+    /// unless the source has ended, an underrun is counted.
+    pub fn read(&mut self, out: &mut [i16]) -> usize {
+        // Check before reading so samples written in between aren't missed.
+        let finished = self.ring.finished.load(Ordering::Acquire);
+        let count = self.ring.pop(out);
+        out[count..].fill(0);
+        if count < out.len() && !finished {
+            self.underruns += 1;
+        }
+        self.consumed += count as u64;
+        count
+    }
+
+    /// The next event whose audio has been read, along with its position
+    /// in samples read from the source, not counting underrun silence.
+    pub fn poll_event(&mut self) -> Option<(u64, Event)> {
+        self.events.pop_due(self.consumed)
+    }
+
+    /// How many events were dropped because the event ring was full,
+    /// which happens when events aren't polled as the audio is read.
+    pub fn dropped_events(&self) -> usize {
+        self.events.dropped.load(Ordering::Relaxed)
+    }
+
+    /// How many reads came up short while the source was still going.
+    pub fn underruns(&self) -> u64 {
+        self.underruns
+    }
+
+    /// Whether the source has ended and all of it has been read.
+    pub fn is_finished(&self) -> bool {
+        self.ring.finished.load(Ordering::Acquire)
+            && self.ring.read.load(Ordering::Relaxed) == self.ring.written.load(Ordering::Acquire)
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+}
+
+pub(crate) fn into_ring(
+    source: SpeakerSource,
+    capacity: Duration,
+) -> (RingProducerHandle, RingConsumer) {
+    let sample_rate = source.sample_rate;
+    let seconds = capacity.as_secs_f64();
+    let ring = Arc::new(Ring::new((sample_rate as f64 * seconds).ceil() as usize));
+    let events = Arc::new(EventRing::new(
+        ((EVENTS_PER_SECOND * seconds).ceil() as usize).max(MIN_EVENTS),
+    ));
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let thread = {
+        let ring = ring.clone();
+        let events = events.clone();
+        let stop = stop.clone();
+        thread::spawn(move || fill(source, &ring, &events, &stop))
+    };
+    let producer = RingProducerHandle {
+        ring: ring.clone(),
+        stop,
+        thread: Some(thread),
+    };
+    let consumer = RingConsumer {
+        ring,
+        events,
+        consumed: 0,
+        underruns: 0,
+        sample_rate,
+    };
+    (producer, consumer)
+}
+
+fn fill(mut source: SpeakerSource, ring: &Ring, events: &EventRing, stop: &AtomicBool) {
+    let mut position = 0u64;
+    while !stop.load(Ordering::Relaxed) {
+        let (sample, due) = source.next_sample_and_events();
+        for event in due.into_iter().flatten() {
+            events.push(position, event);
+        }
+        let Some(sample) = sample else {
+            ring.finished.store(true, Ordering::Release);
+            return;
+        };
+        while ring.push(&[sample]) == 0 {
+            if stop.load(Ordering::Relaxed) {
+                return;
+            }
+            thread::sleep(FULL_RING_WAIT);
+        }
+        position += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_ring_holds_events_until_due_and_drops_overflow() {
+        let events = EventRing::new(2);
+        events.push(0, Event::Start);
+        events.push(10, Event::End);
+        events.push(20, Event::End);
+        assert_eq!(events.dropped.load(Ordering::Relaxed), 1);
+
+        assert_eq!(events.pop_due(0), Some((0, Event::Start)));
+        assert_eq!(events.pop_due(9), None);
+        // The freed slot takes new events again.
+        events.push(30, Event::End);
+        assert_eq!(events.pop_due(10), Some((10, Event::End)));
+        assert_eq!(events.pop_due(30), Some((30, Event::End)));
+        assert_eq!(events.pop_due(u64::MAX), None);
+        assert_eq!(events.dropped.load(Ordering::Relaxed), 1);
+    }
+}
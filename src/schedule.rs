@@ -0,0 +1,319 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Firing events at wall-clock times, for audio played by something else.
+//!
+//! When the audio goes to an external player, events can't be delivered
+//! as samples are consumed. Instead, [`plan`] turns their offsets into
+//! instants and [`run`] calls back at those instants on a timer thread.
+//! [`run_with_clock`] follows a caller-supplied playback position
+//! instead, such as the one a player reports, so that the callbacks stay
+//! in step when the player drifts, stalls or seeks.
+//!
+//! A player such as a rodio `Sink` pulls samples ahead of playing them,
+//! and in bursts around a pause, so events delivered as samples are
+//! pulled run ahead of the speech. A [`PacedCallback`], from
+//! [`SpeakerSource::with_paced_callback`], is a source for such a player
+//! that schedules its events against the player's position as they are
+//! pulled.
+
+use crate::{Event, PoisonlessLock, SpeakerSource, TimedEvent};
+use rodio::Source;
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// The longest the timer thread sleeps before checking the clock again.
+/// Bounds how long a drifting clock goes uncorrected.
+pub const MAX_CLOCK_POLL: Duration = Duration::from_millis(10);
+
+/// The instant at which each event is due, for audio starting at `start`.
+pub fn plan<I>(events: I, start: Instant) -> Vec<(Instant, Event)>
+where
+    I: IntoIterator<Item = TimedEvent>,
+{
+    events
+        .into_iter()
+        .map(|timed| (start + timed.offset, timed.event))
+        .collect()
+}
+
+/// Call `f` with each event at its instant, using the system clock.
+/// `start` is the instant the audio started, as given to [`plan`].
+pub fn run<F>(events: Vec<(Instant, Event)>, start: Instant, f: F) -> Schedule
+where
+    F: FnMut(Event) + Send + 'static,
+{
+    let events = events
+        .into_iter()
+        .map(|(at, event)| (at.saturating_duration_since(start), event))
+        .collect();
+    run_with_clock(events, move || start.elapsed(), f)
+}
+
+/// Call `f` with each event once `clock` reports a playback position at or
+/// past the event's offset.
+///
+/// Events fire in order and at most once each: if the clock jumps
+/// backward, nothing fires again until it passes the next unfired event.
+/// If it jumps forward, every event it skipped fires at once.
+pub fn run_with_clock<C, F>(events: Vec<(Duration, Event)>, clock: C, f: F) -> Schedule
+where
+    C: FnMut() -> Duration + Send + 'static,
+    F: FnMut(Event) + Send + 'static,
+{
+    let shared = Arc::new(Shared::default());
+    for (offset, event) in events {
+        shared.push(offset, event);
+    }
+    shared.close();
+    start(shared, clock, f)
+}
+
+fn start<C, F>(shared: Arc<Shared>, clock: C, f: F) -> Schedule
+where
+    C: FnMut() -> Duration + Send + 'static,
+    F: FnMut(Event) + Send + 'static,
+{
+    let thread_shared = shared.clone();
+    let handle = thread::spawn(move || fire(&thread_shared, clock, f));
+    Schedule {
+        shared,
+        handle: Some(handle),
+    }
+}
+
+/// The events a timer thread has yet to fire, shared with whoever adds
+/// them and with its [`Schedule`].
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    wake: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    /// In order of offset.
+    pending: VecDeque<(Duration, Event)>,
+    /// Set once no more events will be added.
+    closed: bool,
+    cancelled: bool,
+}
+
+impl Shared {
+    fn push(&self, offset: Duration, event: Event) {
+        let mut state = self.state.plock();
+        let at = state.pending.partition_point(|(due, _)| *due <= offset);
+        state.pending.insert(at, (offset, event));
+        self.wake.notify_all();
+    }
+
+    fn close(&self) {
+        self.state.plock().closed = true;
+        self.wake.notify_all();
+    }
+
+    fn cancel(&self) {
+        self.state.plock().cancelled = true;
+        self.wake.notify_all();
+    }
+
+    /// When the next event is due, waiting for one to be added if need be.
+    /// `None` once cancelled, or closed with every event fired.
+    fn next_due(&self) -> Option<Duration> {
+        let mut state = self.state.plock();
+        loop {
+            if state.cancelled {
+                return None;
+            }
+            match state.pending.front() {
+                Some((due, _)) => return Some(*due),
+                None if state.closed => return None,
+                None => {
+                    state = self
+                        .wake
+                        .wait(state)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                }
+            }
+        }
+    }
+
+    fn pop(&self) -> Option<Event> {
+        self.state
+            .plock()
+            .pending
+            .pop_front()
+            .map(|(_, event)| event)
+    }
+
+    /// Sleep for up to `timeout`, or until woken, returning whether
+    /// cancellation was requested.
+    fn wait(&self, timeout: Duration) -> bool {
+        let state = self.state.plock();
+        if state.cancelled {
+            return true;
+        }
+        match self.wake.wait_timeout(state, timeout) {
+            Ok((state, _)) => state.cancelled,
+            Err(e) => e.into_inner().0.cancelled,
+        }
+    }
+}
+
+fn fire<C, F>(shared: &Shared, mut clock: C, mut f: F)
+where
+    C: FnMut() -> Duration,
+    F: FnMut(Event),
+{
+    while let Some(due) = shared.next_due() {
+        let position = clock();
+        if position >= due {
+            if let Some(event) = shared.pop() {
+                f(event);
+            }
+            continue;
+        }
+        // Re-read the clock at least every MAX_CLOCK_POLL rather than
+        // trusting a single long sleep, so drift is corrected.
+        if shared.wait((due - position).min(MAX_CLOCK_POLL)) {
+            return;
+        }
+    }
+}
+
+/// A running schedule, from [`run`] or [`run_with_clock`]. Dropping it
+/// cancels any events that haven't fired yet.
+#[must_use = "dropping a Schedule cancels it"]
+pub struct Schedule {
+    shared: Arc<Shared>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Schedule {
+    /// Stop firing events. An event being delivered when this is called
+    /// still finishes.
+    pub fn cancel(&self) {
+        self.shared.cancel();
+    }
+
+    /// Whether every event has fired, or the schedule was cancelled.
+    pub fn is_finished(&self) -> bool {
+        match &self.handle {
+            Some(handle) => handle.is_finished(),
+            None => true,
+        }
+    }
+
+    /// Wait until every event has fired, or the schedule is cancelled. For
+    /// a [`PacedCallback`], that includes the events it has yet to pull.
+    pub fn join(mut self) {
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for Schedule {
+    fn drop(&mut self) {
+        if self.handle.is_some() {
+            self.cancel();
+        }
+    }
+}
+
+/// A [`SpeakerSource`] whose events go to a callback once a playback
+/// clock reaches them, rather than as its samples are pulled. From
+/// [`SpeakerSource::with_paced_callback`].
+pub struct PacedCallback {
+    inner: SpeakerSource,
+    shared: Arc<Shared>,
+}
+
+impl PacedCallback {
+    pub(crate) fn new<C, F>(inner: SpeakerSource, clock: C, f: F) -> (PacedCallback, Schedule)
+    where
+        C: FnMut() -> Duration + Send + 'static,
+        F: FnMut(Event) + Send + 'static,
+    {
+        let shared = Arc::new(Shared::default());
+        let schedule = start(shared.clone(), clock, f);
+        (PacedCallback { inner, shared }, schedule)
+    }
+
+    pub fn error(&self) -> Option<&crate::EspeakError> {
+        self.inner.error()
+    }
+
+    pub fn resolve(&self, event: &Event) -> Option<&str> {
+        self.inner.resolve(event)
+    }
+
+    pub fn position(&self, event: &Event) -> Option<crate::WordPosition> {
+        self.inner.position(event)
+    }
+}
+
+impl Source for PacedCallback {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+impl Iterator for PacedCallback {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let (sample, events) = self.inner.next_sample_and_timed_events();
+        for timed in events.into_iter().flatten() {
+            self.shared.push(timed.offset, timed.event);
+        }
+        if sample.is_none() {
+            self.shared.close();
+        }
+        sample
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for PacedCallback {
+    /// A player drops the source once it has pulled every sample, before
+    /// playing them all, so the events already scheduled are left to fire.
+    fn drop(&mut self) {
+        self.shared.close();
+    }
+}
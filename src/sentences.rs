@@ -0,0 +1,202 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Finding where a sentence ends, for the length of `Event::Sentence`.
+//!
+//! espeak only reports where each sentence starts. It is taken to end at
+//! the first sentence-ending punctuation followed by whitespace or the end
+//! of the text, along with any closing quotes or brackets after it, so
+//! that "3.14" or "example.com" don't end one. Full-width punctuation, as
+//! used in Chinese and Japanese, ends a sentence wherever it is. In SSML,
+//! punctuation inside tags doesn't count, and neither do trailing tags.
+//!
+//! Like in espeak, a full stop or ellipsis followed by a lowercase letter
+//! doesn't end a sentence, and nor does the full stop of a common English
+//! abbreviation such as "Dr.".
+
+use crate::TextMode;
+use std::ops::Range;
+
+const TERMINATORS: &[char] = &['.', '!', '?', '…', '؟', '।', '。', '！', '？'];
+const FULL_WIDTH_TERMINATORS: &[char] = &['。', '！', '？'];
+const CLOSERS: &[char] = &['"', '\'', '”', '’', '»', ')', ']', '」', '』'];
+/// Words whose full stop doesn't end a sentence, in lowercase.
+const ABBREVIATIONS: &[&str] = &[
+    "dr", "mr", "mrs", "ms", "prof", "st", "jr", "sr", "mt", "vs", "etc", "e.g", "i.e", "no",
+];
+
+/// Length in bytes of the sentence starting at byte `start` of `text`.
+pub(crate) fn sentence_len(text: &str, start: usize, text_mode: TextMode) -> usize {
+    let Some(rest) = text.get(start..) else {
+        return 0;
+    };
+    let mut in_tag = false;
+    // End of the last character that was part of the text.
+    let mut last_end = 0;
+    let mut chars = rest.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '<' if text_mode == TextMode::Ssml => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if in_tag || c.is_whitespace() => (),
+            _ => {
+                last_end = i + c.len_utf8();
+                if !TERMINATORS.contains(&c) {
+                    continue;
+                }
+                let mut full_width = FULL_WIDTH_TERMINATORS.contains(&c);
+                // Runs such as "?!" or "..." and closing quotes belong to
+                // the sentence.
+                while let Some(&(j, next)) = chars.peek() {
+                    if !TERMINATORS.contains(&next) && !CLOSERS.contains(&next) {
+                        break;
+                    }
+                    full_width |= FULL_WIDTH_TERMINATORS.contains(&next);
+                    last_end = j + next.len_utf8();
+                    chars.next();
+                }
+                let ends = match chars.peek() {
+                    None => true,
+                    Some((_, next)) => {
+                        full_width
+                            || next.is_whitespace()
+                            || (text_mode == TextMode::Ssml && *next == '<')
+                    }
+                };
+                if ends && !continues(rest, i, last_end) {
+                    return last_end;
+                }
+            }
+        }
+    }
+    last_end
+}
+
+/// Whether the run of terminators from `start` to `end` of `text` leaves
+/// the sentence going: a full stop or ellipsis before a lowercase letter,
+/// or the full stop of an abbreviation.
+fn continues(text: &str, start: usize, end: usize) -> bool {
+    let run = &text[start..end];
+    let run = run.trim_end_matches(CLOSERS);
+    if !run.chars().all(|c| c == '.' || c == '…') {
+        return false;
+    }
+    if text[end..]
+        .trim_start()
+        .starts_with(|c: char| c.is_lowercase())
+    {
+        return true;
+    }
+    let word_start = text[..start]
+        .rfind(|c: char| !c.is_alphanumeric() && c != '.')
+        .map_or(0, |i| {
+            i + text[i..].chars().next().map_or(1, char::len_utf8)
+        });
+    run == "." && ABBREVIATIONS.contains(&text[word_start..start].to_lowercase().as_str())
+}
+
+/// Whether `text` ends with sentence-ending punctuation, not counting
+/// closing quotes or brackets and trailing whitespace.
+pub(crate) fn ends_sentence(text: &str) -> bool {
+    text.trim_end()
+        .trim_end_matches(CLOSERS)
+        .ends_with(TERMINATORS)
+}
+
+/// The byte ranges of the sentences in `text`, without the whitespace
+/// between them.
+pub(crate) fn split_sentences(text: &str, text_mode: TextMode) -> Vec<Range<usize>> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    loop {
+        start = text.len() - text[start..].trim_start().len();
+        let len = sentence_len(text, start, text_mode);
+        if len == 0 {
+            return sentences;
+        }
+        sentences.push(start..start + len);
+        start += len;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sentence(text: &str, start: usize) -> &str {
+        &text[start..start + sentence_len(text, start, TextMode::Plain)]
+    }
+
+    #[test]
+    fn plain_sentences() {
+        let text = "Hello world. Goodbye world";
+        assert_eq!(sentence(text, 0), "Hello world.");
+        assert_eq!(sentence(text, 13), "Goodbye world");
+
+        let text = "Pi is 3.14, see example.com! \"Really?!\" Yes…  ";
+        assert_eq!(sentence(text, 0), "Pi is 3.14, see example.com!");
+        assert_eq!(sentence(text, 29), "\"Really?!\"");
+        assert_eq!(sentence(text, 40), "Yes…");
+
+        let text = "你好。世界！";
+        assert_eq!(sentence(text, 0), "你好。");
+        assert_eq!(sentence(text, 9), "世界！");
+
+        assert_eq!(sentence_len(text, 1, TextMode::Plain), 0);
+        assert_eq!(sentence_len(text, 100, TextMode::Plain), 0);
+    }
+
+    #[test]
+    fn abbreviations_and_ellipses_continue() {
+        let text = "Dr. Smith met Mrs. Jones, e.g. at noon. Well... maybe… not. Done.";
+        assert_eq!(sentence(text, 0), "Dr. Smith met Mrs. Jones, e.g. at noon.");
+        assert_eq!(sentence(text, 40), "Well... maybe… not.");
+
+        // Even where the next word would start one.
+        let text = "Ask the Dr. Oops. The end... Really.";
+        assert_eq!(sentence(text, 0), "Ask the Dr. Oops.");
+        assert_eq!(sentence(text, 18), "The end...");
+    }
+
+    #[test]
+    fn sentences_are_split() {
+        let text = "  One. Two?! \"Three.\"  Four";
+        let sentences: Vec<_> = split_sentences(text, TextMode::Plain)
+            .into_iter()
+            .map(|range| &text[range])
+            .collect();
+        assert_eq!(sentences, ["One.", "Two?!", "\"Three.\"", "Four"]);
+        assert!(split_sentences(" \n ", TextMode::Plain).is_empty());
+    }
+
+    #[test]
+    fn ssml_sentences() {
+        let text = "<speak><s>Use <say-as interpret-as=\"characters\">a.b</say-as> now.</s> Then <break time=\"1s\"/>stop</speak>";
+        let len = sentence_len(text, 10, TextMode::Ssml);
+        assert_eq!(
+            &text[10..10 + len],
+            "Use <say-as interpret-as=\"characters\">a.b</say-as> now."
+        );
+        let start = text.find("Then").unwrap();
+        let len = sentence_len(text, start, TextMode::Ssml);
+        assert_eq!(&text[start..start + len], "Then <break time=\"1s\"/>stop");
+    }
+}
@@ -0,0 +1,156 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Finding the silence espeak pads speech with, and splitting the rest of
+//! the audio by sentence.
+//!
+//! Audio is measured in windows of [`SILENCE_WINDOW`]; a window whose RMS
+//! level is below [`SILENCE_THRESHOLD_DB`] counts as silent.
+
+use std::ops::Range;
+use std::time::Duration;
+
+/// RMS level, in dB relative to full scale, below which audio counts as
+/// silence.
+pub const SILENCE_THRESHOLD_DB: f32 = -50.0;
+
+/// Length of the windows audio is measured in.
+pub const SILENCE_WINDOW: Duration = Duration::from_millis(10);
+
+/// Where the time in a synthesized utterance goes. The parts add up to
+/// `total`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DurationBreakdown {
+    pub total: Duration,
+    /// Silence before the speech starts.
+    pub leading_silence: Duration,
+    /// Silence after the speech ends.
+    pub trailing_silence: Duration,
+    /// The speech in between, split where each sentence starts. Pauses
+    /// between sentences count towards the sentence before.
+    pub sentences: Vec<Duration>,
+}
+
+impl DurationBreakdown {
+    /// Break down `samples`, given the sample indices at which sentences
+    /// start.
+    pub(crate) fn new(samples: &[i16], sample_rate: u32, sentence_starts: &[usize]) -> Self {
+        let duration = |samples: usize| {
+            Duration::from_nanos(samples as u64 * 1_000_000_000 / sample_rate as u64)
+        };
+        let speech = audible_range(samples, sample_rate);
+        // The first sentence starts with the speech, whatever silence its
+        // event was reported in.
+        let mut bounds = vec![speech.start];
+        bounds.extend(
+            sentence_starts
+                .iter()
+                .skip(1)
+                .map(|start| (*start).clamp(speech.start, speech.end)),
+        );
+        bounds.push(speech.end);
+        DurationBreakdown {
+            total: duration(samples.len()),
+            leading_silence: duration(speech.start),
+            trailing_silence: duration(samples.len() - speech.end),
+            sentences: bounds
+                .windows(2)
+                .map(|pair| duration(pair[1].saturating_sub(pair[0])))
+                .collect(),
+        }
+    }
+}
+
+/// The range of `samples` from the first window that isn't silent to the
+/// end of the last. Empty, at the end of `samples`, if all of it is silent.
+pub(crate) fn audible_range(samples: &[i16], sample_rate: u32) -> Range<usize> {
+    let window = ((sample_rate as u64 * SILENCE_WINDOW.as_millis() as u64 / 1000) as usize).max(1);
+    let threshold = i16::MAX as f64 * 10f64.powf(SILENCE_THRESHOLD_DB as f64 / 20.0);
+    let audible = |chunk: &[i16]| {
+        let sum: f64 = chunk.iter().map(|s| (*s as f64) * (*s as f64)).sum();
+        (sum / chunk.len() as f64).sqrt() >= threshold
+    };
+
+    let windows: Vec<Range<usize>> = (0..samples.len())
+        .step_by(window)
+        .map(|start| start..(start + window).min(samples.len()))
+        .collect();
+    let first = windows
+        .iter()
+        .find(|range| audible(&samples[(*range).clone()]));
+    let last = windows
+        .iter()
+        .rev()
+        .find(|range| audible(&samples[(*range).clone()]));
+    match (first, last) {
+        (Some(first), Some(last)) => first.start..last.end,
+        _ => samples.len()..samples.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One sample per millisecond keeps the arithmetic easy to follow.
+    const RATE: u32 = 1000;
+
+    fn audio(parts: &[(usize, i16)]) -> Vec<i16> {
+        parts
+            .iter()
+            .flat_map(|(len, level)| std::iter::repeat_n(*level, *len))
+            .collect()
+    }
+
+    #[test]
+    fn silence_is_found_by_window() {
+        let samples = audio(&[(30, 0), (50, 8000), (25, 20)]);
+        assert_eq!(audible_range(&samples, RATE), 30..80);
+
+        // Quiet audio in a window with loud audio counts as audible.
+        let samples = audio(&[(35, 0), (50, 8000), (25, 0)]);
+        assert_eq!(audible_range(&samples, RATE), 30..90);
+
+        let silent = audio(&[(40, 50)]);
+        assert_eq!(audible_range(&silent, RATE), 40..40);
+        assert_eq!(audible_range(&[], RATE), 0..0);
+    }
+
+    #[test]
+    fn parts_add_up() {
+        let ms = Duration::from_millis;
+        let samples = audio(&[(20, 0), (100, 8000), (40, 0), (60, -8000), (30, 0)]);
+        let breakdown = DurationBreakdown::new(&samples, RATE, &[0, 150]);
+        assert_eq!(
+            breakdown,
+            DurationBreakdown {
+                total: ms(250),
+                leading_silence: ms(20),
+                trailing_silence: ms(30),
+                sentences: vec![ms(130), ms(70)],
+            }
+        );
+
+        let silent = DurationBreakdown::new(&audio(&[(40, 0)]), RATE, &[0]);
+        assert_eq!(silent.leading_silence, ms(40));
+        assert_eq!(silent.sentences, [ms(0)]);
+    }
+}
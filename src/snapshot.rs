@@ -0,0 +1,206 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! A dump of espeak's global state, meant to be pasted into bug reports.
+
+use crate::{
+    espeak_lock, init, try_list_voices, OutputMode, PoisonlessLock, Voice, ACTIVE_SYNTHESES,
+    OUTPUT_MODE,
+};
+use espeak_rs_sys::*;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::sync::atomic::Ordering;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParameterSnapshot {
+    pub name: String,
+    /// The value espeak is currently using.
+    pub current: i32,
+    /// The value espeak would use if nothing had set it.
+    pub default: i32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StateSnapshot {
+    /// Version of this crate.
+    pub crate_version: String,
+    /// Cargo features this crate was built with.
+    pub features: Vec<String>,
+    /// Version reported by the linked espeak-ng.
+    pub espeak_version: String,
+    /// Where espeak-ng loads its voices and dictionaries from.
+    pub data_path: String,
+    pub sample_rate: u32,
+    /// Audio output mode espeak was initialized with.
+    pub output_mode: String,
+    /// Name of the voice espeak last selected.
+    pub current_voice: Option<String>,
+    pub parameters: Vec<ParameterSnapshot>,
+    /// Every language covered by an installed voice.
+    pub languages: Vec<String>,
+    /// Utterances whose synthesis threads are running or waiting to run.
+    pub active_syntheses: usize,
+    /// Why espeak couldn't be initialized, in which case only the crate
+    /// fields are filled in.
+    pub init_error: Option<String>,
+}
+
+const PARAMETERS: &[(&str, espeak_PARAMETER)] = &[
+    ("rate", espeak_PARAMETER_espeakRATE),
+    ("volume", espeak_PARAMETER_espeakVOLUME),
+    ("pitch", espeak_PARAMETER_espeakPITCH),
+    ("range", espeak_PARAMETER_espeakRANGE),
+    ("punctuation", espeak_PARAMETER_espeakPUNCTUATION),
+    ("capitals", espeak_PARAMETER_espeakCAPITALS),
+    ("word_gap", espeak_PARAMETER_espeakWORDGAP),
+    ("intonation", espeak_PARAMETER_espeakINTONATION),
+    ("ssml_break_mul", espeak_PARAMETER_espeakSSML_BREAK_MUL),
+    ("emphasis", espeak_PARAMETER_espeakEMPHASIS),
+    ("line_length", espeak_PARAMETER_espeakLINELENGTH),
+];
+
+/// Every feature in Cargo.toml but `default`, and whether it's enabled.
+const FEATURES: &[(&str, bool)] = &[
+    ("core", cfg!(feature = "core")),
+    ("corpus-tools", cfg!(feature = "corpus-tools")),
+    ("dsp", cfg!(feature = "dsp")),
+    ("http-example", cfg!(feature = "http-example")),
+    ("mmap", cfg!(feature = "mmap")),
+    ("online", cfg!(feature = "online")),
+    ("playback", cfg!(feature = "playback")),
+    ("serde", cfg!(feature = "serde")),
+    ("subprocess-pool", cfg!(feature = "subprocess-pool")),
+    ("test-tone", cfg!(feature = "test-tone")),
+    ("tracing", cfg!(feature = "tracing")),
+];
+
+fn enabled_features() -> Vec<String> {
+    FEATURES
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| String::from(*name))
+        .collect()
+}
+
+fn string_from_ptr(ptr: *const c_char) -> String {
+    if ptr.is_null() {
+        String::default()
+    } else {
+        let cstr = unsafe { CStr::from_ptr(ptr) };
+        cstr.to_string_lossy().into_owned()
+    }
+}
+
+/// Collect everything useful for diagnosing "it sounds wrong" reports.
+///
+/// This takes the espeak lock, so it waits for an utterance that is being
+/// synthesized to finish rather than reading espeak's state mid-synthesis.
+pub fn debug_snapshot() -> StateSnapshot {
+    let sample_rate = match init() {
+        Ok(sample_rate) => sample_rate,
+        Err(error) => {
+            return StateSnapshot {
+                crate_version: String::from(env!("CARGO_PKG_VERSION")),
+                features: enabled_features(),
+                espeak_version: String::default(),
+                data_path: String::default(),
+                sample_rate: 0,
+                output_mode: String::from(OUTPUT_MODE.plock().name()),
+                current_voice: None,
+                parameters: Vec::new(),
+                languages: Vec::new(),
+                active_syntheses: ACTIVE_SYNTHESES.load(Ordering::SeqCst),
+                init_error: Some(error.to_string()),
+            }
+        }
+    };
+    let mut languages: Vec<String> = try_list_voices()
+        .unwrap_or_default()
+        .into_iter()
+        .flat_map(|voice| voice.languages.into_iter().map(|lang| lang.name))
+        .collect();
+    languages.sort();
+    languages.dedup();
+
+    let lock = espeak_lock();
+    let output_mode = lock
+        .as_ref()
+        .map_or(OutputMode::Synchronous, |state| state.output_mode);
+    let mut data_path: *const c_char = std::ptr::null();
+    let espeak_version = string_from_ptr(unsafe { espeak_Info(&mut data_path) });
+    let data_path = string_from_ptr(data_path);
+
+    let current_voice = unsafe {
+        let voice = espeak_GetCurrentVoice();
+        if voice.is_null() {
+            None
+        } else {
+            Some(Voice::from_espeak_voice(*voice).name)
+        }
+    };
+
+    let parameters = PARAMETERS
+        .iter()
+        .map(|(name, param)| ParameterSnapshot {
+            name: String::from(*name),
+            current: unsafe { espeak_GetParameter(*param, 1) },
+            default: unsafe { espeak_GetParameter(*param, 0) },
+        })
+        .collect();
+
+    StateSnapshot {
+        crate_version: String::from(env!("CARGO_PKG_VERSION")),
+        features: enabled_features(),
+        espeak_version,
+        data_path,
+        sample_rate,
+        output_mode: String::from(output_mode.name()),
+        current_voice,
+        parameters,
+        languages,
+        active_syntheses: ACTIVE_SYNTHESES.load(Ordering::SeqCst),
+        init_error: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn features_match_the_manifest() {
+        let manifest = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"));
+        let mut declared: Vec<&str> = manifest
+            .lines()
+            .skip_while(|line| line.trim() != "[features]")
+            .skip(1)
+            .take_while(|line| !line.starts_with('['))
+            .filter_map(|line| line.split_once('=').map(|(name, _)| name.trim()))
+            .filter(|name| !name.starts_with('#') && *name != "default")
+            .collect();
+        declared.sort_unstable();
+        let known: Vec<&str> = FEATURES.iter().map(|(name, _)| *name).collect();
+        assert_eq!(known, declared);
+    }
+}
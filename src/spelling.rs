@@ -0,0 +1,346 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Spelling out codes with a phonetic alphabet, such as "Alpha Bravo One"
+//! for `AB1`.
+//!
+//! A [`SpellingAlphabet`] gives the word to say for each character. The
+//! default is the NATO alphabet, with digits left for the voice to name
+//! in its own language and English names for common symbols. Languages
+//! with their own alphabet, like the German one, can have it set with
+//! [`set_language_alphabet`].
+//!
+//! [`Speaker::speak_phonetic_alphabet`](crate::Speaker::speak_phonetic_alphabet)
+//! reports each word as an [`Event::Word`](crate::Event::Word) covering the
+//! character it spells.
+//!
+//! [`Speaker::spell`](crate::Speaker::spell) instead has the voice name
+//! each character itself, "R, U, S, T" for "Rust".
+
+use crate::table::escape;
+use crate::text_map::TextMap;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::RwLock;
+use std::time::Duration;
+
+/// The pause between characters in [`Speaker::spell`](crate::Speaker::spell).
+pub const DEFAULT_SPELLING_GAP: Duration = Duration::from_millis(250);
+
+/// The NATO phonetic alphabet.
+pub const NATO_ALPHABET: [(char, &str); 26] = [
+    ('a', "Alpha"),
+    ('b', "Bravo"),
+    ('c', "Charlie"),
+    ('d', "Delta"),
+    ('e', "Echo"),
+    ('f', "Foxtrot"),
+    ('g', "Golf"),
+    ('h', "Hotel"),
+    ('i', "India"),
+    ('j', "Juliett"),
+    ('k', "Kilo"),
+    ('l', "Lima"),
+    ('m', "Mike"),
+    ('n', "November"),
+    ('o', "Oscar"),
+    ('p', "Papa"),
+    ('q', "Quebec"),
+    ('r', "Romeo"),
+    ('s', "Sierra"),
+    ('t', "Tango"),
+    ('u', "Uniform"),
+    ('v', "Victor"),
+    ('w', "Whiskey"),
+    ('x', "X-ray"),
+    ('y', "Yankee"),
+    ('z', "Zulu"),
+];
+
+/// Names for symbols common in codes and confirmation strings.
+pub const DEFAULT_SYMBOL_NAMES: [(char, &str); 12] = [
+    ('-', "Dash"),
+    ('.', "Dot"),
+    ('/', "Slash"),
+    ('@', "At"),
+    ('#', "Hash"),
+    ('_', "Underscore"),
+    ('+', "Plus"),
+    ('*', "Star"),
+    ('&', "Ampersand"),
+    (':', "Colon"),
+    ('=', "Equals"),
+    ('$', "Dollar"),
+];
+
+/// The word to say for each character. Letters are looked up in
+/// lowercase. Characters without a word are said as they are, and
+/// whitespace is skipped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpellingAlphabet {
+    words: HashMap<char, String>,
+}
+
+impl Default for SpellingAlphabet {
+    /// [`NATO_ALPHABET`] and [`DEFAULT_SYMBOL_NAMES`].
+    fn default() -> SpellingAlphabet {
+        NATO_ALPHABET
+            .iter()
+            .chain(&DEFAULT_SYMBOL_NAMES)
+            .fold(SpellingAlphabet::empty(), |alphabet, (c, word)| {
+                alphabet.with(*c, word)
+            })
+    }
+}
+
+impl SpellingAlphabet {
+    /// An alphabet that says every character as it is.
+    pub fn empty() -> SpellingAlphabet {
+        SpellingAlphabet {
+            words: HashMap::new(),
+        }
+    }
+
+    /// Say `word` for `c`, replacing any word it had.
+    pub fn with(mut self, c: char, word: &str) -> SpellingAlphabet {
+        self.words.insert(fold(c), String::from(word));
+        self
+    }
+
+    /// The word to say for `c`, if it has one.
+    pub fn word(&self, c: char) -> Option<&str> {
+        self.words.get(&fold(c)).map(String::as_str)
+    }
+}
+
+/// `c` in lowercase, if that's a single character.
+fn fold(c: char) -> char {
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(lower), None) => lower,
+        _ => c,
+    }
+}
+
+/// Alphabets set for particular languages, by language tag.
+static LANGUAGE_ALPHABETS: RwLock<Vec<(String, SpellingAlphabet)>> = RwLock::new(Vec::new());
+
+/// Use `alphabet` for voices speaking `language`, a tag such as `de` or
+/// `en-us`. An alphabet for `de` also covers `de-at` unless it has its own.
+pub fn set_language_alphabet(language: &str, alphabet: SpellingAlphabet) {
+    let mut alphabets = match LANGUAGE_ALPHABETS.write() {
+        Ok(alphabets) => alphabets,
+        Err(e) => e.into_inner(),
+    };
+    let language = language.to_ascii_lowercase();
+    alphabets.retain(|(tag, _)| *tag != language);
+    alphabets.push((language, alphabet));
+}
+
+/// The alphabet to use for `language`: the one set for it or its primary
+/// subtag, or the default alphabet.
+pub fn language_alphabet(language: &str) -> SpellingAlphabet {
+    let alphabets = match LANGUAGE_ALPHABETS.read() {
+        Ok(alphabets) => alphabets,
+        Err(e) => e.into_inner(),
+    };
+    let language = language.to_ascii_lowercase();
+    let primary = language.split('-').next().unwrap_or_default();
+    for tag in [&*language, primary] {
+        if let Some((_, alphabet)) = alphabets.iter().find(|(t, _)| t == tag) {
+            return alphabet.clone();
+        }
+    }
+    SpellingAlphabet::default()
+}
+
+/// Replace each character of `text` with its word, separated by commas so
+/// the voice pauses between them. Each word, with the separator after it,
+/// maps back to the character it spells.
+pub(crate) fn spell(text: &str, alphabet: &SpellingAlphabet) -> (String, TextMap) {
+    let mut spoken = String::new();
+    let mut map = TextMap::default();
+    for (start, c) in text.char_indices() {
+        if c.is_whitespace() {
+            continue;
+        }
+        if !spoken.is_empty() {
+            spoken.push_str(", ");
+        }
+        let spoken_start = spoken.len();
+        match alphabet.word(c) {
+            Some(word) => spoken.push_str(word),
+            None => spoken.push(c),
+        }
+        map.push(spoken_start..spoken.len(), start..start + c.len_utf8());
+    }
+    (spoken, map)
+}
+
+/// SSML naming each grapheme of `text` in turn, with `gap` between them.
+/// Each grapheme's `say-as` element maps back to the grapheme.
+pub(crate) fn spell_characters(text: &str, gap: Duration) -> (String, TextMap) {
+    let mut spoken = String::from("<speak>");
+    let mut map = TextMap::default();
+    let mut first = true;
+    for range in graphemes(text) {
+        let grapheme = &text[range.clone()];
+        if grapheme.chars().all(char::is_whitespace) {
+            continue;
+        }
+        if !first {
+            spoken.push_str(&format!("<break time=\"{}ms\"/>", gap.as_millis()));
+        }
+        first = false;
+        let spoken_start = spoken.len();
+        spoken.push_str("<say-as interpret-as=\"characters\">");
+        spoken.push_str(&escape(grapheme));
+        spoken.push_str("</say-as>");
+        map.push(spoken_start..spoken.len(), range);
+    }
+    spoken.push_str("</speak>");
+    (spoken, map)
+}
+
+/// The byte ranges of the user-perceived characters of `text`: each
+/// character along with the combining marks, variation selectors and
+/// emoji modifiers after it, emoji joined by zero width joiners, and flags
+/// made of two regional indicators. A close approximation of Unicode's
+/// extended grapheme clusters for what gets spelled out.
+pub(crate) fn graphemes(text: &str) -> Vec<Range<usize>> {
+    let mut graphemes: Vec<Range<usize>> = Vec::new();
+    let mut joined = false;
+    let mut regional_indicators = 0;
+    for (start, c) in text.char_indices() {
+        let end = start + c.len_utf8();
+        let regional_indicator = ('\u{1F1E6}'..='\u{1F1FF}').contains(&c);
+        let extends = joined || extends(c) || (regional_indicator && regional_indicators % 2 == 1);
+        match graphemes.last_mut() {
+            Some(last) if extends => last.end = end,
+            _ => {
+                graphemes.push(start..end);
+                regional_indicators = 0;
+            }
+        }
+        if regional_indicator {
+            regional_indicators += 1;
+        }
+        joined = c == '\u{200D}';
+    }
+    graphemes
+}
+
+/// Whether `c` belongs to the grapheme before it.
+fn extends(c: char) -> bool {
+    matches!(c,
+        '\u{0300}'..='\u{036F}'
+        | '\u{0483}'..='\u{0489}'
+        | '\u{0591}'..='\u{05BD}'
+        | '\u{0610}'..='\u{061A}'
+        | '\u{064B}'..='\u{065F}'
+        | '\u{0670}'
+        | '\u{0900}'..='\u{0903}'
+        | '\u{093A}'..='\u{094F}'
+        | '\u{0951}'..='\u{0957}'
+        | '\u{0962}'..='\u{0963}'
+        | '\u{1AB0}'..='\u{1AFF}'
+        | '\u{1DC0}'..='\u{1DFF}'
+        | '\u{200C}'..='\u{200D}'
+        | '\u{20D0}'..='\u{20FF}'
+        | '\u{3099}'..='\u{309A}'
+        | '\u{FE00}'..='\u{FE0F}'
+        | '\u{FE20}'..='\u{FE2F}'
+        | '\u{1F3FB}'..='\u{1F3FF}'
+        | '\u{E0020}'..='\u{E007F}'
+        | '\u{E0100}'..='\u{E01EF}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Event;
+
+    #[test]
+    fn characters_are_spelled() {
+        let alphabet = SpellingAlphabet::default().with('ß', "Eszett");
+        let (spoken, _) = spell("Ab-1 ß?", &alphabet);
+        assert_eq!(spoken, "Alpha, Bravo, Dash, 1, Eszett, ?");
+        let (spoken, _) = spell("ab", &SpellingAlphabet::empty());
+        assert_eq!(spoken, "a, b");
+    }
+
+    #[test]
+    fn graphemes_are_found() {
+        let text = "ne\u{301}😀👍🏽👩\u{200D}💻🇫🇷🇩🇪 नि";
+        let found: Vec<&str> = graphemes(text).into_iter().map(|g| &text[g]).collect();
+        assert_eq!(
+            found,
+            [
+                "n",
+                "e\u{301}",
+                "😀",
+                "👍🏽",
+                "👩\u{200D}💻",
+                "🇫🇷",
+                "🇩🇪",
+                " ",
+                "नि"
+            ]
+        );
+        assert!(graphemes("").is_empty());
+    }
+
+    #[test]
+    fn characters_are_named() {
+        let (spoken, map) = spell_characters("a <é", Duration::from_millis(100));
+        assert_eq!(
+            spoken,
+            "<speak><say-as interpret-as=\"characters\">a</say-as>\
+             <break time=\"100ms\"/><say-as interpret-as=\"characters\">&lt;</say-as>\
+             <break time=\"100ms\"/><say-as interpret-as=\"characters\">é</say-as></speak>"
+        );
+        let start = spoken.rfind('é').unwrap();
+        let mut word = Event::Word(start, 'é'.len_utf8());
+        map.correct(&mut word);
+        assert_eq!(word, Event::Word(3, 2));
+    }
+
+    #[test]
+    fn words_map_to_their_characters() {
+        let (spoken, map) = spell("A 1é", &SpellingAlphabet::default());
+        let words: Vec<Event> = spoken
+            .split(", ")
+            .scan(0, |start, word| {
+                let event = Event::Word(*start, word.len());
+                *start += word.len() + 2;
+                Some(event)
+            })
+            .map(|mut event| {
+                map.correct(&mut event);
+                event
+            })
+            .collect();
+        assert_eq!(
+            words,
+            [Event::Word(0, 1), Event::Word(2, 1), Event::Word(3, 2)]
+        );
+    }
+}
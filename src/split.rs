@@ -0,0 +1,145 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Splitting long text into pieces espeak can take in one `espeak_Synth`.
+
+use crate::{EspeakError, TextMode};
+
+/// Default for [`SpeakerParams::synth_text_budget`](crate::SpeakerParams::synth_text_budget):
+/// the most bytes of text handed to a single `espeak_Synth` call.
+pub const DEFAULT_SYNTH_TEXT_BUDGET: usize = 8192;
+
+/// Split `text` into pieces of at most `budget` bytes, returning each
+/// piece's byte offset into `text`.
+///
+/// Pieces end after a sentence or top-level SSML element if possible,
+/// otherwise after whitespace, otherwise at the last character that fits.
+/// A budget smaller than the next character still takes that character.
+/// SSML is only split between top-level elements; a longer run without
+/// such a point is an error.
+pub(crate) fn split_text(
+    text: &str,
+    budget: usize,
+    text_mode: TextMode,
+) -> Result<Vec<(usize, &str)>, EspeakError> {
+    let budget = budget.max(1);
+    let allowed = match text_mode {
+        TextMode::Plain => None,
+        TextMode::Ssml => Some(ssml_split_points(text)),
+    };
+    let can_split = |i: usize| match &allowed {
+        None => true,
+        Some(allowed) => allowed[i],
+    };
+
+    let mut pieces = Vec::<(usize, &str)>::new();
+    let mut start = 0usize;
+    while text.len() - start > budget {
+        let mut limit = start + budget;
+        while !text.is_char_boundary(limit) {
+            limit -= 1;
+        }
+        if limit == start {
+            limit += text[start..].chars().next().map_or(1, char::len_utf8);
+        }
+        let window = &text[start..limit];
+
+        let mut sentence_end = None;
+        let mut whitespace_end = None;
+        let mut prev: Option<char> = None;
+        for (i, c) in window.char_indices() {
+            let at = start + i;
+            if c == '>' && text_mode == TextMode::Ssml && can_split(at + 1) {
+                // The end of a top-level SSML element.
+                sentence_end = Some(at + 1);
+            }
+            if c.is_whitespace() && can_split(at + c.len_utf8()) {
+                whitespace_end = Some(at + c.len_utf8());
+                if matches!(prev, Some('.') | Some('!') | Some('?')) {
+                    sentence_end = whitespace_end;
+                }
+            }
+            prev = Some(c);
+        }
+
+        let end = match sentence_end.or(whitespace_end) {
+            Some(end) => end,
+            None if can_split(limit) => limit,
+            None => {
+                return Err(EspeakError::TextTooLong {
+                    len: text.len(),
+                    budget,
+                })
+            }
+        };
+        pieces.push((start, &text[start..end]));
+        start = end;
+    }
+    if start < text.len() || pieces.is_empty() {
+        pieces.push((start, &text[start..]));
+    }
+    Ok(pieces)
+}
+
+/// For each byte position, whether it lies between top-level SSML
+/// elements, outside of any tag.
+fn ssml_split_points(text: &str) -> Vec<bool> {
+    let bytes = text.as_bytes();
+    let mut allowed = vec![false; bytes.len() + 1];
+    let mut depth = 0usize;
+    let mut i = 0usize;
+    while i < bytes.len() {
+        allowed[i] = depth == 0;
+        if bytes[i] != b'<' {
+            i += 1;
+            continue;
+        }
+        let tag_end = match text[i..].find('>') {
+            Some(offset) => i + offset,
+            None => break,
+        };
+        let tag = &text[i + 1..tag_end];
+        if tag.starts_with('/') {
+            depth = depth.saturating_sub(1);
+        } else if !(tag.ends_with('/') || tag.starts_with('!') || tag.starts_with('?')) {
+            depth += 1;
+        }
+        i = tag_end + 1;
+    }
+    allowed[bytes.len()] = depth == 0;
+    allowed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiny_budget_takes_a_char_at_a_time() {
+        let text = "héllo 日本";
+        let pieces = split_text(text, 1, TextMode::Plain).unwrap();
+        let chars: Vec<(usize, &str)> = text
+            .char_indices()
+            .map(|(i, c)| (i, &text[i..i + c.len_utf8()]))
+            .collect();
+        assert_eq!(pieces, chars);
+    }
+}
@@ -0,0 +1,399 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Runtime discovery of the SSML subset understood by espeak-ng.
+//!
+//! Support varies between espeak-ng releases, and tags espeak doesn't
+//! recognize may end up read aloud. [`ssml_capabilities`] synthesizes a tiny
+//! snippet for each tag and checks whether any of the markup leaked into
+//! the spoken words.
+//!
+//! [`sanitize_ssml`] then takes the tags that aren't supported out of a
+//! document, keeping what they contain.
+
+use crate::text_map::TextMap;
+use crate::{espeak_version, Event, SpeakerParams, SpeakerSource, TextMode};
+use std::sync::OnceLock;
+
+const PROBE_WORD: &str = "hello";
+
+/// The SSML tags known to work with the linked espeak-ng.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SsmlCapabilities {
+    /// The espeak-ng version the probes ran against.
+    pub version: String,
+    pub supported_tags: Vec<String>,
+    /// Whether `<phoneme alphabet="ipa" ph="...">` replaces the
+    /// pronunciation of its content, rather than just being tolerated.
+    pub supports_phoneme_alphabet_ipa: bool,
+    pub supports_mark: bool,
+    pub supports_break: bool,
+    pub supports_prosody: bool,
+    pub supports_say_as: bool,
+}
+
+impl SsmlCapabilities {
+    pub fn supports(&self, tag: &str) -> bool {
+        self.supported_tags.iter().any(|t| t == tag)
+    }
+}
+
+/// Tag name, opening markup and closing markup for each probe.
+const TAG_PROBES: &[(&str, &str, &str)] = &[
+    ("audio", "<audio src=\"probe.wav\">", "</audio>"),
+    ("break", "<break time=\"1ms\"/>", ""),
+    ("emphasis", "<emphasis>", "</emphasis>"),
+    ("mark", "<mark name=\"probe\"/>", ""),
+    ("p", "<p>", "</p>"),
+    (
+        "phoneme",
+        "<phoneme alphabet=\"ipa\" ph=\"a\">",
+        "</phoneme>",
+    ),
+    ("prosody", "<prosody rate=\"fast\">", "</prosody>"),
+    ("s", "<s>", "</s>"),
+    (
+        "say-as",
+        "<say-as interpret-as=\"characters\">",
+        "</say-as>",
+    ),
+    ("sub", "<sub alias=\"hello\">", "</sub>"),
+    ("voice", "<voice gender=\"female\">", "</voice>"),
+];
+
+/// Probe espeak for each SSML tag this crate knows about.
+///
+/// Each probe is a short synthesis, so this takes a moment; callers that
+/// need the result repeatedly should keep it around.
+pub fn ssml_capabilities() -> SsmlCapabilities {
+    let supported_tags: Vec<String> = TAG_PROBES
+        .iter()
+        .filter(|(_, open, close)| probe_ssml(open, close))
+        .map(|(tag, _, _)| String::from(*tag))
+        .collect();
+    let has = |tag: &str| supported_tags.iter().any(|t| t == tag);
+
+    SsmlCapabilities {
+        version: espeak_version(),
+        supports_phoneme_alphabet_ipa: has("phoneme") && phoneme_ipa_honored(),
+        supports_mark: has("mark"),
+        supports_break: has("break"),
+        supports_prosody: has("prosody"),
+        supports_say_as: has("say-as"),
+        supported_tags,
+    }
+}
+
+/// Speak `{open}hello{close}` as SSML and report whether only the word
+/// "hello" was spoken, i.e. no part of the markup was read aloud.
+pub fn probe_ssml(open: &str, close: &str) -> bool {
+    let text = format!("<speak>{}{}{}</speak>", open, PROBE_WORD, close);
+    let word_start = "<speak>".len() + open.len();
+    let word_end = word_start + PROBE_WORD.len();
+
+    let (words, _) = synthesize(&text, TextMode::Ssml);
+    !words.is_empty()
+        && words
+            .iter()
+            .all(|(start, len)| *start >= word_start && start + len <= word_end)
+}
+
+/// A single vowel spoken in place of "hello" is much shorter than the word.
+fn phoneme_ipa_honored() -> bool {
+    let (_, plain_samples) = synthesize(PROBE_WORD, TextMode::Plain);
+    let (_, phoneme_samples) = synthesize(
+        "<speak><phoneme alphabet=\"ipa\" ph=\"a\">hello</phoneme></speak>",
+        TextMode::Ssml,
+    );
+    phoneme_samples * 10 < plain_samples * 7
+}
+
+fn synthesize(text: &str, text_mode: TextMode) -> (Vec<(usize, usize)>, usize) {
+    let mut words = Vec::<(usize, usize)>::new();
+    let source = SpeakerSource::with_text_mode(text, "en", SpeakerParams::new(), text_mode);
+    let sample_count = source
+        .with_callback(|evt| {
+            if let Event::Word(start, len) = evt {
+                words.push((start, len));
+            }
+        })
+        .count();
+    (words, sample_count)
+}
+
+/// A tag [`sanitize_ssml`] took out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemovedTag {
+    pub name: String,
+    /// Byte position of the opening tag in the input.
+    pub position: usize,
+}
+
+/// Take the tags espeak doesn't support, according to `capabilities`, out
+/// of the SSML `input`, so that they aren't read aloud. Their content is
+/// kept, except that a `<sub>` is replaced by its alias. The `<speak>`
+/// root, comments, declarations and entities are left as they are, and a
+/// `<` that doesn't start a tag is escaped. Returns the sanitized SSML and
+/// the tags removed, in order.
+pub fn sanitize_ssml(input: &str, capabilities: &SsmlCapabilities) -> (String, Vec<RemovedTag>) {
+    let (sanitized, _, removed) = sanitize(input, capabilities);
+    (sanitized, removed)
+}
+
+/// The capabilities of the linked espeak-ng, probed on first use.
+pub(crate) fn cached_capabilities() -> &'static SsmlCapabilities {
+    static CAPABILITIES: OnceLock<SsmlCapabilities> = OnceLock::new();
+    CAPABILITIES.get_or_init(ssml_capabilities)
+}
+
+/// [`sanitize_ssml`], also mapping positions in the output back to the
+/// input.
+pub(crate) fn sanitize(
+    input: &str,
+    capabilities: &SsmlCapabilities,
+) -> (String, TextMap, Vec<RemovedTag>) {
+    let mut output = String::with_capacity(input.len());
+    let mut map = TextMap::default();
+    let mut removed = Vec::new();
+    // An element being replaced as a whole: its name, how deeply nested
+    // in itself the parser is, where it started in the input and where
+    // its replacement started in the output.
+    let mut replacing: Option<(&str, usize, usize, usize)> = None;
+    let mut i = 0;
+    while let Some(offset) = input[i..].find('<') {
+        if replacing.is_none() {
+            output.push_str(&input[i..i + offset]);
+        }
+        i += offset;
+        let Some(markup) = Markup::parse(&input[i..]) else {
+            if replacing.is_none() {
+                let start = output.len();
+                output.push_str("&lt;");
+                map.push(start..output.len(), i..i + 1);
+            }
+            i += 1;
+            continue;
+        };
+        let range = i..i + markup.len;
+        i = range.end;
+        let Some(tag) = markup.tag else {
+            if replacing.is_none() {
+                output.push_str(&input[range]);
+            }
+            continue;
+        };
+
+        if let Some((name, depth, start, spoken_start)) = &mut replacing {
+            if tag.name == *name && !tag.self_closing {
+                match tag.closing {
+                    true => *depth -= 1,
+                    false => *depth += 1,
+                }
+            }
+            if *depth == 0 {
+                map.push(*spoken_start..output.len(), *start..range.end);
+                replacing = None;
+            }
+            continue;
+        }
+        if tag.name == "speak" || capabilities.supports(tag.name) {
+            output.push_str(&input[range]);
+            continue;
+        }
+        if !tag.closing {
+            removed.push(RemovedTag {
+                name: String::from(tag.name),
+                position: range.start,
+            });
+        }
+        let spoken_start = output.len();
+        match tag.attribute("alias") {
+            Some(alias) if tag.name == "sub" && !tag.closing && !tag.self_closing => {
+                output.push_str(alias);
+                replacing = Some((tag.name, 1, range.start, spoken_start));
+            }
+            _ => map.push(spoken_start..spoken_start, range),
+        }
+    }
+    match replacing {
+        Some((_, _, start, spoken_start)) => {
+            map.push(spoken_start..output.len(), start..input.len())
+        }
+        None => output.push_str(&input[i..]),
+    }
+    (output, map, removed)
+}
+
+/// Something between `<` and `>`.
+struct Markup<'a> {
+    /// Bytes from the `<` to the `>`, inclusive.
+    len: usize,
+    /// `None` for comments, declarations and processing instructions.
+    tag: Option<Tag<'a>>,
+}
+
+struct Tag<'a> {
+    name: &'a str,
+    closing: bool,
+    self_closing: bool,
+    /// Everything after the name.
+    attributes: &'a str,
+}
+
+impl<'a> Markup<'a> {
+    /// Parse the markup `text` starts with, if the `<` starting it opens
+    /// any. Quoted attribute values may contain `>`.
+    fn parse(text: &'a str) -> Option<Markup<'a>> {
+        if text.starts_with("<!--") {
+            let len = text.find("-->")? + 3;
+            return Some(Markup { len, tag: None });
+        }
+        if text.starts_with("<!") || text.starts_with("<?") {
+            let len = text.find('>')? + 1;
+            return Some(Markup { len, tag: None });
+        }
+        let closing = text[1..].starts_with('/');
+        let name_start = if closing { 2 } else { 1 };
+        let name_len = text[name_start..]
+            .find(|c: char| !(c.is_alphanumeric() || matches!(c, '-' | '_' | ':' | '.')))
+            .unwrap_or(text.len() - name_start);
+        if !text[name_start..].starts_with(|c: char| c.is_alphabetic() || c == '_') {
+            return None;
+        }
+        let name_end = name_start + name_len;
+        let mut quote = None;
+        let close = text[name_end..].char_indices().find_map(|(i, c)| {
+            match (quote, c) {
+                (None, '"' | '\'') => quote = Some(c),
+                (Some(q), _) if c == q => quote = None,
+                (None, '>') => return Some(name_end + i),
+                (None, '<') => return Some(usize::MAX),
+                _ => (),
+            }
+            None
+        })?;
+        if close == usize::MAX {
+            // Another tag starts before this one ends, so it wasn't one.
+            return None;
+        }
+        let self_closing = text[..close].ends_with('/');
+        let attributes_end = if self_closing { close - 1 } else { close };
+        Some(Markup {
+            len: close + 1,
+            tag: Some(Tag {
+                name: &text[name_start..name_end],
+                closing,
+                self_closing,
+                attributes: &text[name_end..attributes_end.max(name_end)],
+            }),
+        })
+    }
+}
+
+impl<'a> Tag<'a> {
+    /// The value of the attribute `name`, as written.
+    fn attribute(&self, name: &str) -> Option<&'a str> {
+        let mut rest = self.attributes;
+        loop {
+            rest = rest.trim_start();
+            let name_end = rest.find(|c: char| c == '=' || c.is_whitespace())?;
+            let attribute = &rest[..name_end];
+            rest = rest[name_end..]
+                .trim_start()
+                .strip_prefix('=')?
+                .trim_start();
+            let quote = rest.chars().next().filter(|c| matches!(c, '"' | '\''))?;
+            let value_end = rest[1..].find(quote)? + 1;
+            let value = &rest[1..value_end];
+            if attribute == name {
+                return Some(value);
+            }
+            rest = &rest[value_end + 1..];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::Range;
+
+    /// The range of the input that `spoken` in the output came from.
+    fn original(map: &TextMap, spoken: Range<usize>) -> Range<usize> {
+        let mut event = Event::Word(spoken.start, spoken.len());
+        map.correct(&mut event);
+        match event {
+            Event::Word(start, len) => start..start + len,
+            _ => unreachable!(),
+        }
+    }
+
+    fn capabilities(tags: &[&str]) -> SsmlCapabilities {
+        SsmlCapabilities {
+            version: String::new(),
+            supported_tags: tags.iter().map(|tag| String::from(*tag)).collect(),
+            supports_phoneme_alphabet_ipa: false,
+            supports_mark: false,
+            supports_break: tags.contains(&"break"),
+            supports_prosody: false,
+            supports_say_as: false,
+        }
+    }
+
+    #[test]
+    fn unsupported_tags_are_removed() {
+        let input = "<?xml version=\"1.0\"?><speak>Hi <voice gender=\"female\">there\
+                     <break time=\"1s\"/> &amp; <phoneme ph='a>b'>you</phoneme></voice>\
+                     <!-- <voice> --></speak>";
+        let (output, removed) = sanitize_ssml(input, &capabilities(&["break"]));
+        assert_eq!(
+            output,
+            "<?xml version=\"1.0\"?><speak>Hi there<break time=\"1s\"/> &amp; you\
+             <!-- <voice> --></speak>"
+        );
+        let names: Vec<_> = removed.iter().map(|tag| tag.name.as_str()).collect();
+        assert_eq!(names, ["voice", "phoneme"]);
+        assert_eq!(removed[0].position, input.find("<voice").unwrap());
+    }
+
+    #[test]
+    fn informal_ssml_is_tolerated() {
+        let input = "<speak>1 < 2 <sub alias=\"World Wide Web\">WWW <sub>x</sub></sub>, <3 <b>bold</b> <unclosed</speak>";
+        let (output, map, removed) = sanitize(input, &capabilities(&[]));
+        assert_eq!(
+            output,
+            "<speak>1 &lt; 2 World Wide Web, &lt;3 bold &lt;unclosed</speak>"
+        );
+        let names: Vec<_> = removed.iter().map(|tag| tag.name.as_str()).collect();
+        assert_eq!(names, ["sub", "b"]);
+
+        let alias = output.find("World").unwrap();
+        let sub = input.find("<sub").unwrap();
+        let sub_end = input.find(", <3").unwrap();
+        assert_eq!(original(&map, alias..alias + 5), sub..sub_end);
+        let bold = output.find("bold").unwrap();
+        let bold_in_input = input.find("bold").unwrap();
+        assert_eq!(
+            original(&map, bold..bold + 4),
+            bold_in_input..bold_in_input + 4
+        );
+    }
+}
@@ -0,0 +1,309 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Speaking text as it arrives, for
+//! [`Speaker::speak_stream`](crate::Speaker::speak_stream).
+//!
+//! Fragments are gathered until they make up whole sentences, which are
+//! synthesized one after another into the same source. A sentence counts
+//! as whole once more text follows it. When no fragment has arrived for a
+//! while, what has is spoken up to its last whitespace, or all of it if it
+//! ends a sentence, so that a word split across fragments isn't read as
+//! two. The rest is spoken once the sender is dropped.
+
+use crate::cancel::Job;
+use crate::channel::{chunk_channel, ChunkSender};
+use crate::offsets::OffsetCorrector;
+use crate::sentences::{ends_sentence, split_sentences};
+use crate::split::split_text;
+use crate::turns::Turn;
+use crate::{
+    espeak_lock, init, is_asynchronous, reinit, spawn_synthesis, EspeakError, Event,
+    ParamApplyError, PoisonlessLock, SpeakerParams, SpeakerSource, SynthContext, SynthJob,
+    TextMode,
+};
+use std::ffi::CString;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How long [`Speaker::speak_stream`](crate::Speaker::speak_stream) waits
+/// for more text before speaking an unfinished sentence.
+pub const STREAM_FLUSH_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Text received but not yet spoken.
+#[derive(Default)]
+struct Pending {
+    text: String,
+}
+
+impl Pending {
+    fn push(&mut self, fragment: &str) {
+        self.text.push_str(&fragment.replace('\0', ""));
+    }
+
+    /// Take the sentences that more text follows, along with the
+    /// whitespace before them.
+    fn take_sentences(&mut self) -> Option<String> {
+        let sentences = split_sentences(&self.text, TextMode::Plain);
+        let end = sentences.iter().rev().nth(1)?.end;
+        self.take(end)
+    }
+
+    /// Take what can be spoken before more text comes: all of it if it
+    /// ends a sentence, or up to the last whitespace otherwise.
+    fn take_words(&mut self) -> Option<String> {
+        let end = match ends_sentence(&self.text) {
+            true => self.text.len(),
+            false => self.text.rfind(char::is_whitespace)?,
+        };
+        self.take(end)
+    }
+
+    fn take_all(&mut self) -> Option<String> {
+        self.take(self.text.len())
+    }
+
+    fn take(&mut self, end: usize) -> Option<String> {
+        if self.text[..end].trim().is_empty() {
+            return None;
+        }
+        Some(self.text.drain(..end).collect())
+    }
+}
+
+/// Everything the synthesis thread needs for a stream.
+struct StreamJob {
+    rx: Receiver<String>,
+    timeout: Duration,
+    voice_name: CString,
+    params: SpeakerParams,
+    sample_rate: u32,
+    /// The text taken on so far, shared with the source.
+    text: Arc<Mutex<String>>,
+    param_errors: Arc<Mutex<Vec<ParamApplyError>>>,
+    job: Arc<Job>,
+    /// Whether the job has been moved out of the queue.
+    running: bool,
+}
+
+/// Make the source for [`Speaker::speak_stream`](crate::Speaker::speak_stream).
+pub(crate) fn stream_source(
+    rx: Receiver<String>,
+    voice_name: &str,
+    params: SpeakerParams,
+    timeout: Duration,
+) -> Result<SpeakerSource, EspeakError> {
+    let voice_name = CString::new(if voice_name.is_empty() {
+        "en"
+    } else {
+        voice_name
+    })
+    .map_err(|e| EspeakError::InvalidVoiceName {
+        nul_position: e.nul_position(),
+    })?;
+    let sample_rate = init()?;
+    let ahead = params
+        .synth_ahead
+        .map(|ahead| (ahead.as_secs_f64() * sample_rate as f64) as u64);
+    let (tx, chunk_rx, backlog) = chunk_channel(params.channel_capacity, ahead);
+    let offsets = OffsetCorrector::new("", TextMode::Plain);
+    let mut source = SpeakerSource::from_channel(chunk_rx, backlog, sample_rate, offsets);
+    source.voice_name = String::from(voice_name.to_str().unwrap_or_default());
+    let text = Arc::<Mutex<String>>::default();
+    source.streamed_text = Some(text.clone());
+
+    let job = StreamJob {
+        rx,
+        timeout,
+        voice_name,
+        params,
+        sample_rate,
+        text,
+        param_errors: source.param_errors.clone(),
+        job: source.job.clone(),
+        running: false,
+    };
+    source.job.queue(tx.clone());
+    spawn_synthesis(tx, move |tx| job.run(tx));
+    Ok(source)
+}
+
+impl StreamJob {
+    fn run(mut self, tx: ChunkSender) {
+        let mut context = SynthContext {
+            tx,
+            sample_rate: self.sample_rate,
+            text_offset: 0,
+            char_starts: Vec::new(),
+            audio_offset_ms: 0,
+            samples_sent: 0,
+            started: false,
+            cancelled: false,
+            terminated: false,
+            job: self.job.clone(),
+            asynchronous: false,
+            last_piece: false,
+            held: None,
+            #[cfg(test)]
+            drop_audio: false,
+        };
+        let mut pending = Pending::default();
+        let completed = loop {
+            let (text, closed) = match self.rx.recv_timeout(self.timeout) {
+                Ok(fragment) => {
+                    pending.push(&fragment);
+                    (pending.take_sentences(), false)
+                }
+                Err(RecvTimeoutError::Timeout) => (pending.take_words(), false),
+                Err(RecvTimeoutError::Disconnected) => (pending.take_all(), true),
+            };
+            if self.job.is_cancelled() {
+                break false;
+            }
+            if let Some(text) = text {
+                if !self.speak(&mut context, &text) {
+                    break false;
+                }
+            }
+            if closed {
+                break true;
+            }
+        };
+        if !self.running && !self.job.start() {
+            // Cancelled before anything was spoken, and already reported.
+            return;
+        }
+        if completed && !context.cancelled {
+            let audio_ms = (context.samples_sent * 1000 / self.sample_rate as u64) as u32;
+            let terminated = Event::Terminated { audio_ms };
+            context.send_now((Vec::new(), vec![(audio_ms, terminated)]));
+        }
+        if self.job.is_cancelled() {
+            let _ = context.tx.send_error(EspeakError::Cancelled);
+        }
+        self.job.finish();
+    }
+
+    /// Take on `text` and synthesize it, in its turn like any other
+    /// utterance. Returns whether the stream can go on.
+    fn speak(&mut self, context: &mut SynthContext, text: &str) -> bool {
+        let offset = {
+            let mut streamed = self.text.plock();
+            let offset = streamed.len();
+            streamed.push_str(text);
+            offset + text.len() - text.trim_start().len()
+        };
+        let pieces = match split_text(text.trim(), self.params.synth_text_budget, TextMode::Plain) {
+            Ok(pieces) => pieces,
+            Err(error) => {
+                let _ = context.tx.send_error(error);
+                return false;
+            }
+        };
+        let job = SynthJob {
+            pieces: pieces
+                .into_iter()
+                .map(|(start, piece)| {
+                    let piece = CString::new(piece).expect("text has no NUL bytes");
+                    (offset + start, piece)
+                })
+                .collect(),
+            pauses: Vec::new(),
+            voice_name: self.voice_name.clone(),
+            params: self.params.clone(),
+            text_mode: TextMode::Plain,
+            sample_rate: self.sample_rate,
+            param_errors: self.param_errors.clone(),
+            job: self.job.clone(),
+            turn: Turn::take(),
+            continued: true,
+            character: None,
+        };
+
+        job.turn.wait();
+        let lock = espeak_lock();
+        if !self.running && !self.job.start() {
+            return false;
+        }
+        self.running = true;
+        if self.job.is_cancelled() {
+            return false;
+        }
+        let lock = match reinit(lock) {
+            Ok(lock) => lock,
+            Err(error) => {
+                let _ = context.tx.send_error(error);
+                return false;
+            }
+        };
+        context.asynchronous = is_asynchronous(&lock);
+        match job.configure() {
+            Ok(()) => job.synthesize(context, job.synth_flags()),
+            Err(error) => {
+                let _ = context.tx.send_error(error);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending(text: &str) -> Pending {
+        Pending {
+            text: String::from(text),
+        }
+    }
+
+    #[test]
+    fn sentences_wait_for_what_follows() {
+        let mut text = pending("Hello world.");
+        assert_eq!(text.take_sentences(), None);
+        text.push(" Bye");
+        assert_eq!(text.take_sentences().as_deref(), Some("Hello world."));
+        assert_eq!(text.text, " Bye");
+
+        let mut text = pending("One. Two! Thr");
+        assert_eq!(text.take_sentences().as_deref(), Some("One. Two!"));
+        // An abbreviation doesn't end the sentence.
+        let mut text = pending("Ask Dr. Smith");
+        assert_eq!(text.take_sentences(), None);
+    }
+
+    #[test]
+    fn words_are_not_split() {
+        let mut text = pending("Hello wor");
+        assert_eq!(text.take_words().as_deref(), Some("Hello"));
+        assert_eq!(text.take_words(), None);
+        text.push("ld");
+        assert_eq!(text.take_words(), None);
+        text.push("?");
+        assert_eq!(text.take_words().as_deref(), Some(" world?"));
+
+        let mut text = pending("  ");
+        assert_eq!(text.take_all(), None);
+        text.push("Bye\u{0}");
+        assert_eq!(text.take_all().as_deref(), Some("  Bye"));
+    }
+}
@@ -0,0 +1,203 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Tables read out cell by cell, with an event as each cell is reached.
+//!
+//! The cells are spoken as SSML, with a `<mark/>` before each one. Marks
+//! carrying this module's prefix are turned into [`Event::Cell`] by the
+//! source speaking the table.
+
+use crate::Event;
+
+/// Prefix of the mark names generated for cells.
+const CELL_MARK: &str = "espeak-rs-cell:";
+
+/// The default way each cell is read: its header, then its value.
+pub const DEFAULT_CELL_TEMPLATE: &str = "{header} {value}";
+
+/// Rows of cells to be read aloud, as passed to
+/// [`Speaker::speak_table`](crate::Speaker::speak_table).
+///
+/// Each row is read as a sentence, its cells separated by commas. A cell is
+/// read by filling in `{header}` and `{value}` in the cell template.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableSpeech {
+    headers: Vec<String>,
+    rows: Vec<Vec<String>>,
+    cell_template: String,
+}
+
+impl TableSpeech {
+    pub fn new<H, R, C>(headers: H, rows: R) -> TableSpeech
+    where
+        H: IntoIterator,
+        H::Item: Into<String>,
+        R: IntoIterator,
+        R::Item: IntoIterator<Item = C>,
+        C: ToString,
+    {
+        TableSpeech {
+            headers: headers.into_iter().map(Into::into).collect(),
+            rows: rows
+                .into_iter()
+                .map(|row| row.into_iter().map(|cell| cell.to_string()).collect())
+                .collect(),
+            cell_template: String::from(DEFAULT_CELL_TEMPLATE),
+        }
+    }
+
+    /// Read each cell using `template`, in which `{header}` and `{value}`
+    /// are replaced by the cell's column header and value.
+    pub fn with_cell_template(mut self, template: &str) -> TableSpeech {
+        self.cell_template = String::from(template);
+        self
+    }
+
+    /// The text that will be spoken, without markup.
+    pub fn text(&self) -> String {
+        self.render(|_, _, cell| cell)
+    }
+
+    /// The SSML that is synthesized, with a mark before each cell.
+    pub(crate) fn ssml(&self) -> String {
+        let text = self.render(|row, col, cell| {
+            format!(
+                "<mark name=\"{}{}:{}\"/>{}",
+                CELL_MARK,
+                row,
+                col,
+                escape(&cell)
+            )
+        });
+        format!("<speak>{}</speak>", text)
+    }
+
+    fn render<F>(&self, mut cell: F) -> String
+    where
+        F: FnMut(usize, usize, String) -> String,
+    {
+        let rows: Vec<String> = self
+            .rows
+            .iter()
+            .enumerate()
+            .map(|(row, values)| {
+                let cells: Vec<String> = values
+                    .iter()
+                    .enumerate()
+                    .map(|(col, value)| {
+                        let header = self.headers.get(col).map_or("", String::as_str);
+                        cell(row, col, fill(&self.cell_template, header, value))
+                    })
+                    .collect();
+                cells.join(", ") + "."
+            })
+            .collect();
+        rows.join(" ")
+    }
+}
+
+/// Replace the placeholders in `template` in a single pass, so
+/// placeholders appearing in the header or value are left alone.
+fn fill(template: &str, header: &str, value: &str) -> String {
+    let mut filled = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        filled.push_str(&rest[..open]);
+        rest = &rest[open..];
+        if let Some(after) = rest.strip_prefix("{header}") {
+            filled.push_str(header);
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("{value}") {
+            filled.push_str(value);
+            rest = after;
+        } else {
+            filled.push('{');
+            rest = &rest[1..];
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Turn a mark generated for a cell into [`Event::Cell`].
+pub(crate) fn cell_event(event: &mut Event) {
+    let Event::Mark(name) = event else {
+        return;
+    };
+    let Some((row, col)) = name
+        .strip_prefix(CELL_MARK)
+        .and_then(|cell| cell.split_once(':'))
+    else {
+        return;
+    };
+    if let (Ok(row), Ok(col)) = (row.parse(), col.parse()) {
+        *event = Event::Cell { row, col };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> TableSpeech {
+        TableSpeech::new(["CPU", "Memory"], [[87, 54], [12, 3]])
+    }
+
+    #[test]
+    fn cells_are_filled_in() {
+        assert_eq!(table().text(), "CPU 87, Memory 54. CPU 12, Memory 3.");
+        assert_eq!(
+            table()
+                .with_cell_template("{header} {value} percent")
+                .text(),
+            "CPU 87 percent, Memory 54 percent. CPU 12 percent, Memory 3 percent."
+        );
+        assert_eq!(fill("{value}{x}", "{value}", "{header}"), "{header}{x}");
+    }
+
+    #[test]
+    fn cells_are_marked() {
+        let table = TableSpeech::new(["A&B"], [["<1>"]]);
+        assert_eq!(
+            table.ssml(),
+            "<speak><mark name=\"espeak-rs-cell:0:0\"/>A&amp;B &lt;1&gt;.</speak>"
+        );
+    }
+
+    #[test]
+    fn marks_become_cells() {
+        let mut event = Event::Mark(format!("{}1:2", CELL_MARK));
+        cell_event(&mut event);
+        assert_eq!(event, Event::Cell { row: 1, col: 2 });
+
+        for name in ["other", "espeak-rs-cell:1", "espeak-rs-cell:a:2"] {
+            let mut event = Event::Mark(String::from(name));
+            cell_event(&mut event);
+            assert_eq!(event, Event::Mark(String::from(name)));
+        }
+    }
+}
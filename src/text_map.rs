@@ -0,0 +1,115 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Mapping positions in rewritten text back to the text the caller gave.
+//!
+//! When the crate changes the text before handing it to espeak, the
+//! positions espeak reports refer to the changed text. A [`TextMap`]
+//! records which ranges were changed, and a word reported anywhere inside
+//! a changed range covers the whole original range.
+
+use crate::Event;
+use std::ops::Range;
+
+#[derive(Debug, Clone)]
+struct Replacement {
+    /// Where the replacement is in the rewritten text.
+    spoken: Range<usize>,
+    /// What it replaced in the original text.
+    original: Range<usize>,
+}
+
+/// Maps positions in rewritten text back to the original.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TextMap {
+    replacements: Vec<Replacement>,
+}
+
+impl TextMap {
+    /// Record that `original` was replaced by `spoken`. Replacements must
+    /// be pushed in order.
+    pub(crate) fn push(&mut self, spoken: Range<usize>, original: Range<usize>) {
+        self.replacements.push(Replacement { spoken, original });
+    }
+
+    pub(crate) fn correct(&self, event: &mut Event) {
+        if self.replacements.is_empty() {
+            return;
+        }
+        match event {
+            Event::Word(start, len) => {
+                let end = self.map_end(*start + *len);
+                *start = self.map_start(*start);
+                *len = end.saturating_sub(*start);
+            }
+            Event::Sentence { start, .. } => *start = self.map_start(*start),
+            _ => (),
+        }
+    }
+
+    /// Map a position at which something starts. Inside a replacement,
+    /// that's the start of the URL it replaced.
+    pub(crate) fn map_start(&self, position: usize) -> usize {
+        let mut mapped = position;
+        for replacement in &self.replacements {
+            if position < replacement.spoken.start {
+                break;
+            }
+            if position < replacement.spoken.end {
+                return replacement.original.start;
+            }
+            mapped = position - replacement.spoken.end + replacement.original.end;
+        }
+        mapped
+    }
+
+    /// Map a position in the original text to the rewritten text. Inside a
+    /// replaced range, that's the start of its replacement.
+    pub(crate) fn map_to_spoken(&self, position: usize) -> usize {
+        let mut mapped = position;
+        for replacement in &self.replacements {
+            if position < replacement.original.start {
+                break;
+            }
+            if position < replacement.original.end {
+                return replacement.spoken.start;
+            }
+            mapped = position - replacement.original.end + replacement.spoken.end;
+        }
+        mapped
+    }
+
+    /// Map a position at which something ends. Inside a replacement,
+    /// that's the end of the URL it replaced.
+    fn map_end(&self, position: usize) -> usize {
+        let mut mapped = position;
+        for replacement in &self.replacements {
+            if position <= replacement.spoken.start {
+                break;
+            }
+            if position <= replacement.spoken.end {
+                return replacement.original.end;
+            }
+            mapped = position - replacement.spoken.end + replacement.original.end;
+        }
+        mapped
+    }
+}
@@ -0,0 +1,83 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Deterministic stand-in audio for [`SpeakerParams::test_tone_mode`].
+//!
+//! espeak still runs so timing and events stay real, but each sample is
+//! replaced with a square wave whose pitch depends only on the index of
+//! the word being spoken.
+//!
+//! [`SpeakerParams::test_tone_mode`]: crate::SpeakerParams::test_tone_mode
+
+use crate::Event;
+use std::collections::VecDeque;
+
+const AMPLITUDE: i16 = 4096;
+
+pub(crate) struct ToneGenerator {
+    sample_rate: u32,
+    samples_seen: usize,
+    word_index: usize,
+    word_starts: VecDeque<usize>,
+}
+
+impl ToneGenerator {
+    pub(crate) fn new(sample_rate: u32) -> ToneGenerator {
+        ToneGenerator {
+            sample_rate,
+            samples_seen: 0,
+            word_index: 0,
+            word_starts: VecDeque::new(),
+        }
+    }
+
+    /// Overwrite a freshly received chunk of samples in place.
+    pub(crate) fn process(&mut self, samples: &mut [i16], events: &[(u32, Event)]) {
+        for (audio_position, event) in events {
+            if let Event::Word(_, _) = event {
+                let at_sample = (*audio_position as u64 * self.sample_rate as u64 / 1000) as usize;
+                self.word_starts.push_back(at_sample);
+            }
+        }
+
+        for sample in samples.iter_mut() {
+            while let Some(&start) = self.word_starts.front() {
+                if start > self.samples_seen {
+                    break;
+                }
+                self.word_starts.pop_front();
+                self.word_index += 1;
+            }
+            *sample = self.tone_sample();
+            self.samples_seen += 1;
+        }
+    }
+
+    fn tone_sample(&self) -> i16 {
+        // Half-periods from 20 to 48 samples, cycling every 8 words.
+        let half_period = 20 + 4 * (self.word_index % 8);
+        if (self.samples_seen / half_period) & 1 == 0 {
+            AMPLITUDE
+        } else {
+            -AMPLITUDE
+        }
+    }
+}
@@ -0,0 +1,192 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! The rewrites applied to text before it reaches espeak.
+//!
+//! Each stage rewrites a copy of the text and records a `TextMap` from its
+//! output back to its input. Positions espeak reports are taken back
+//! through the maps in reverse order.
+
+use crate::caps::lowercase_caps;
+use crate::digits::separate_digits;
+use crate::phonemes::inline_phonemes;
+use crate::pronunciations::apply_pronunciations;
+use crate::punctuation::restore_punctuation;
+use crate::ssml::{cached_capabilities, sanitize};
+use crate::text_map::TextMap;
+use crate::urls::rewrite_urls;
+use crate::{CapsPolicy, Event, PunctuationEdit, Speaker, SpeakerParams, TextMode, UrlPolicy};
+
+/// What [`dry_run_transform`] would hand to espeak, and how it got there.
+#[derive(Debug, Clone)]
+pub struct TransformReport {
+    /// The text as espeak receives it.
+    pub effective_text: String,
+    /// Maps byte positions between the original and the effective text.
+    pub offset_map: OffsetMap,
+    /// Names of the stages that ran, in order: `"punctuation"`,
+    /// `"pronunciations"`, `"inline_phonemes"`, `"caps"`, `"urls"` and
+    /// `"digits"` for plain text, or `"ssml_sanitize"` for SSML.
+    pub applied_stages: Vec<&'static str>,
+    /// What [`SpeakerParams::punctuation_repair`] inserted and
+    /// capitalized, at positions in the caller's text.
+    pub punctuation_edits: Vec<PunctuationEdit>,
+}
+
+impl TransformReport {
+    /// The report for text rewritten by the single stage `name`.
+    pub(crate) fn rewritten(name: &'static str, (effective_text, map): (String, TextMap)) -> Self {
+        TransformReport {
+            effective_text,
+            offset_map: OffsetMap { maps: vec![map] },
+            applied_stages: vec![name],
+            punctuation_edits: Vec::new(),
+        }
+    }
+}
+
+/// Maps byte positions between the caller's text and the text espeak
+/// received.
+///
+/// A position inside a rewritten range maps to the start of the range it
+/// replaced.
+#[derive(Debug, Clone, Default)]
+pub struct OffsetMap {
+    /// One map per stage, in the order the stages ran.
+    maps: Vec<TextMap>,
+}
+
+impl OffsetMap {
+    /// Map a position in the effective text to the caller's text.
+    pub fn to_original(&self, position: usize) -> usize {
+        self.maps
+            .iter()
+            .rev()
+            .fold(position, |position, map| map.map_start(position))
+    }
+
+    /// Map a position in the caller's text to the effective text.
+    pub fn to_effective(&self, position: usize) -> usize {
+        self.maps
+            .iter()
+            .fold(position, |position, map| map.map_to_spoken(position))
+    }
+
+    pub(crate) fn correct(&self, event: &mut Event) {
+        for map in self.maps.iter().rev() {
+            map.correct(event);
+        }
+    }
+}
+
+/// Run `text` through the rewrites `params` enable for `text_mode`, to be
+/// spoken by the named voice.
+///
+/// SSML is passed through unchanged, unless it is to be sanitized.
+pub(crate) fn transform(
+    text: &str,
+    voice_name: &str,
+    params: &SpeakerParams,
+    text_mode: TextMode,
+) -> TransformReport {
+    let mut report = TransformReport {
+        effective_text: String::from(text),
+        offset_map: OffsetMap::default(),
+        applied_stages: Vec::new(),
+        punctuation_edits: Vec::new(),
+    };
+    if let (TextMode::Plain, Some(repair)) = (text_mode, &params.punctuation_repair) {
+        // First, so that the edits are at positions in the caller's text.
+        let (repaired, map, edits) =
+            restore_punctuation(text, repair, params.allow_inline_phonemes);
+        report.effective_text = repaired;
+        report.offset_map.maps.push(map);
+        report.applied_stages.push("punctuation");
+        report.punctuation_edits = edits;
+    }
+    let mut apply = |name, stage: &dyn Fn(&str) -> (String, TextMap)| {
+        let (rewritten, map) = stage(&report.effective_text);
+        report.effective_text = rewritten;
+        report.offset_map.maps.push(map);
+        report.applied_stages.push(name);
+    };
+    if text_mode != TextMode::Plain {
+        if params.sanitize_ssml {
+            apply("ssml_sanitize", &|text| {
+                let (sanitized, map, _) = sanitize(text, cached_capabilities());
+                (sanitized, map)
+            });
+        }
+        return report;
+    }
+    if !params.pronunciations.is_empty() {
+        // First, so that replacements can use inline phonemes.
+        apply("pronunciations", &|text| {
+            apply_pronunciations(text, &params.pronunciations, params.allow_inline_phonemes)
+        });
+    }
+    if params.allow_inline_phonemes {
+        apply("inline_phonemes", &inline_phonemes);
+    }
+    if params.caps_policy != CapsPolicy::AsIs {
+        apply("caps", &|text| {
+            lowercase_caps(
+                text,
+                params.caps_policy,
+                &params.acronyms,
+                voice_name,
+                params.allow_inline_phonemes,
+            )
+        });
+    }
+    if params.url_policy != UrlPolicy::ReadFull {
+        apply("urls", &|text| rewrite_urls(text, &params.url_policy));
+    }
+    if params.say_digits {
+        // After the URLs, which digits would otherwise break up.
+        apply("digits", &|text| {
+            separate_digits(text, params.allow_inline_phonemes)
+        });
+    }
+    report
+}
+
+/// Show what speaking `text` with `speaker` would hand to espeak, without
+/// synthesizing anything.
+pub fn dry_run_transform(text: &str, speaker: &Speaker) -> TransformReport {
+    let params = speaker.current_params();
+    transform(
+        text,
+        &speaker.voice_name,
+        &params,
+        TextMode::from_params(&params),
+    )
+}
+
+/// `text` cut to at most `max` bytes, for logging.
+#[cfg(feature = "tracing")]
+pub(crate) fn truncated(text: &str, max: usize) -> &str {
+    let mut end = text.len().min(max);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
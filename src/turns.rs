@@ -0,0 +1,94 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Taking turns at synthesis in the order utterances were requested.
+//!
+//! espeak synthesizes one utterance at a time, under the espeak lock. A
+//! `Mutex` makes no promise of fairness, so a thread speaking short
+//! utterances in a loop could keep winning it while another thread's
+//! utterance waited for seconds. Each utterance instead takes a [`Turn`]
+//! when it's requested, and its synthesis thread waits for that turn
+//! before taking the lock.
+
+use crate::PoisonlessLock;
+use std::collections::BTreeSet;
+use std::sync::{Condvar, Mutex};
+
+struct Queue {
+    /// The ticket the next turn gets.
+    next: u64,
+    /// The ticket whose turn it is.
+    serving: u64,
+    /// Tickets given up before their turn came, to be skipped.
+    abandoned: BTreeSet<u64>,
+}
+
+static QUEUE: Mutex<Queue> = Mutex::new(Queue {
+    next: 0,
+    serving: 0,
+    abandoned: BTreeSet::new(),
+});
+static TURN_CHANGED: Condvar = Condvar::new();
+
+/// A place in the synthesis queue. Dropping it passes the turn on, or
+/// gives up the place if the turn hasn't come yet.
+pub(crate) struct Turn {
+    ticket: u64,
+}
+
+impl Turn {
+    /// Queue up behind every turn taken so far.
+    pub(crate) fn take() -> Turn {
+        let mut queue = QUEUE.plock();
+        let ticket = queue.next;
+        queue.next += 1;
+        Turn { ticket }
+    }
+
+    /// Block until every earlier turn is over.
+    pub(crate) fn wait(&self) {
+        let mut queue = QUEUE.plock();
+        while queue.serving != self.ticket {
+            queue = TURN_CHANGED
+                .wait(queue)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+}
+
+impl Drop for Turn {
+    fn drop(&mut self) {
+        let mut queue = QUEUE.plock();
+        if queue.serving != self.ticket {
+            queue.abandoned.insert(self.ticket);
+            return;
+        }
+        queue.serving += 1;
+        loop {
+            let serving = queue.serving;
+            if !queue.abandoned.remove(&serving) {
+                break;
+            }
+            queue.serving += 1;
+        }
+        TURN_CHANGED.notify_all();
+    }
+}
@@ -0,0 +1,214 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Rewriting URLs and email addresses before the text reaches espeak.
+//!
+//! espeak spells most of a URL out character by character. Depending on
+//! the [`UrlPolicy`], each one is replaced in a copy of the text, and a
+//! `TextMap` takes the positions espeak reports in that copy back to
+//! the original text, so a word spoken in place of a URL covers the URL.
+
+use crate::text_map::TextMap;
+
+/// What to say in place of URLs and email addresses in plain text.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(
+    any(feature = "serde", feature = "subprocess-pool"),
+    derive(serde::Serialize, serde::Deserialize)
+)]
+#[non_exhaustive]
+pub enum UrlPolicy {
+    /// Leave them for espeak to read in full.
+    #[default]
+    ReadFull,
+    /// Only say the domain, e.g. "link to example dot com". The wording
+    /// is English; use `Placeholder` for other languages.
+    Domain,
+    /// Leave them out of the audio.
+    Skip,
+    /// Say the given text instead.
+    Placeholder(String),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Url,
+    Email,
+}
+
+/// Rewrite the URLs and email addresses in `text` according to `policy`.
+pub(crate) fn rewrite_urls(text: &str, policy: &UrlPolicy) -> (String, TextMap) {
+    let mut map = TextMap::default();
+    if *policy == UrlPolicy::ReadFull {
+        return (String::from(text), map);
+    }
+    let mut rewritten = String::with_capacity(text.len());
+    let mut copied = 0;
+    for (start, end) in tokens(text) {
+        let (start, end) = trim_punctuation(text, start, end);
+        let token = &text[start..end];
+        let Some(kind) = classify(token) else {
+            continue;
+        };
+        rewritten.push_str(&text[copied..start]);
+        let spoken_start = rewritten.len();
+        rewritten.push_str(&spoken_form(token, kind, policy));
+        map.push(spoken_start..rewritten.len(), start..end);
+        copied = end;
+    }
+    rewritten.push_str(&text[copied..]);
+    (rewritten, map)
+}
+
+/// Byte ranges of the whitespace-separated tokens in `text`.
+fn tokens(text: &str) -> Vec<(usize, usize)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                tokens.push((s, i));
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => (),
+        }
+    }
+    if let Some(s) = start {
+        tokens.push((s, text.len()));
+    }
+    tokens
+}
+
+/// Narrow a token to exclude surrounding brackets, quotes and sentence
+/// punctuation, which espeak should still see.
+fn trim_punctuation(text: &str, start: usize, end: usize) -> (usize, usize) {
+    let token = &text[start..end];
+    let trimmed = token.trim_start_matches(|c| "([{<\"'".contains(c));
+    let start = start + token.len() - trimmed.len();
+    let trimmed = trimmed.trim_end_matches(|c| ".,;:!?)]}>\"'".contains(c));
+    (start, start + trimmed.len())
+}
+
+fn classify(token: &str) -> Option<Kind> {
+    let lower = token.to_ascii_lowercase();
+    let after_prefix = ["http://", "https://", "ftp://", "www."]
+        .iter()
+        .find_map(|prefix| lower.strip_prefix(prefix));
+    if let Some(rest) = after_prefix {
+        let is_url = !rest.is_empty() && host(token).contains('.');
+        return is_url.then_some(Kind::Url);
+    }
+    let (local, domain) = token.split_once('@')?;
+    let valid_domain = domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && domain
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '.' || c == '-');
+    let valid_local = !local.is_empty() && !local.contains('/');
+    (valid_local && valid_domain).then_some(Kind::Email)
+}
+
+/// The host part of a URL, without credentials, port or a `www.` prefix.
+fn host(url: &str) -> &str {
+    let rest = match url.find("://") {
+        Some(i) => &url[i + 3..],
+        None => url,
+    };
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority.rsplit('@').next().unwrap_or_default();
+    let host = host.split(':').next().unwrap_or_default();
+    match host.get(..4) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("www.") => &host[4..],
+        _ => host,
+    }
+}
+
+fn spoken_form(token: &str, kind: Kind, policy: &UrlPolicy) -> String {
+    match policy {
+        UrlPolicy::ReadFull => String::from(token),
+        UrlPolicy::Skip => String::new(),
+        UrlPolicy::Placeholder(placeholder) => placeholder.clone(),
+        UrlPolicy::Domain => {
+            let domain = match kind {
+                Kind::Url => host(token),
+                Kind::Email => token.split_once('@').map_or("", |(_, domain)| domain),
+            };
+            let spoken = domain
+                .split('.')
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<_>>()
+                .join(" dot ");
+            match kind {
+                Kind::Url => format!("link to {}", spoken),
+                Kind::Email => format!("email address at {}", spoken),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Event;
+
+    const TEXT: &str = "See (https://www.example.com/a/b?x=1), or mail me@example.org.";
+
+    #[test]
+    fn domain_is_spoken() {
+        let (rewritten, _) = rewrite_urls(TEXT, &UrlPolicy::Domain);
+        assert_eq!(
+            rewritten,
+            "See (link to example dot com), or mail email address at example dot org."
+        );
+    }
+
+    #[test]
+    fn positions_map_back() {
+        let (rewritten, map) = rewrite_urls(TEXT, &UrlPolicy::Placeholder(String::from("a link")));
+        assert_eq!(rewritten, "See (a link), or mail a link.");
+        let url = TEXT.find("https").unwrap();
+        let url_len = "https://www.example.com/a/b?x=1".len();
+        let email = TEXT.find("me@").unwrap();
+
+        let mut link = Event::Word(rewritten.find("link").unwrap(), 4);
+        map.correct(&mut link);
+        assert_eq!(link, Event::Word(url, url_len));
+
+        let mut mail = Event::Word(rewritten.find("mail").unwrap(), 4);
+        map.correct(&mut mail);
+        assert_eq!(mail, Event::Word(TEXT.find("mail").unwrap(), 4));
+
+        let mut second = Event::Word(rewritten.rfind('a').unwrap(), 6);
+        map.correct(&mut second);
+        assert_eq!(second, Event::Word(email, "me@example.org".len()));
+    }
+
+    #[test]
+    fn skipped_urls_keep_later_positions() {
+        let (rewritten, map) = rewrite_urls(TEXT, &UrlPolicy::Skip);
+        assert_eq!(rewritten, "See (), or mail .");
+        let mut or = Event::Word(rewritten.find("or").unwrap(), 2);
+        map.correct(&mut or);
+        assert_eq!(or, Event::Word(TEXT.find(", or").unwrap() + 2, 2));
+    }
+}
@@ -0,0 +1,266 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Voices from directories outside espeak-ng's installed data.
+//!
+//! espeak reads all of its data from a single directory, so the crate
+//! assembles a merged copy: every entry of the installed `espeak-ng-data`
+//! is linked into a temporary directory, except `voices`, which is rebuilt
+//! from the installed voices with the user directories laid over it. espeak
+//! is then pointed at the merged copy.
+//!
+//! The same copy holds dictionaries compiled by
+//! [`compile_dictionary`](crate::compile_dictionary), so they don't touch
+//! the installed data.
+
+use crate::{current_data_path, espeak_lock, init, EspeakError, InitState, PoisonlessLock};
+use espeak_rs_sys::*;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::raw::c_int;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[derive(Default)]
+struct UserVoices {
+    dirs: Vec<PathBuf>,
+    /// The installed data directory, found before espeak was pointed
+    /// elsewhere.
+    stock_data: Option<PathBuf>,
+    /// Whether espeak has been re-initialized with the merged data.
+    merged: bool,
+    /// Whether the merged copy is needed even without user directories,
+    /// since dictionaries were compiled into it.
+    overlay: bool,
+    warnings: Vec<String>,
+}
+
+static USER_VOICES: Mutex<UserVoices> = Mutex::new(UserVoices {
+    dirs: Vec::new(),
+    stock_data: None,
+    merged: false,
+    overlay: false,
+    warnings: Vec::new(),
+});
+
+/// Make the voices in `path` available alongside the installed ones.
+///
+/// `path` is laid out like espeak-ng-data's `voices` directory, so for
+/// example a variant goes in `path/!v/`. Where a file has the same name
+/// as an installed one, the user's file is used and a warning is recorded
+/// in [`user_voice_warnings`]. Directories added later take precedence.
+pub fn add_user_voice_dir<P: AsRef<Path>>(path: P) -> Result<(), EspeakError> {
+    USER_VOICES.plock().dirs.push(path.as_ref().to_path_buf());
+    reload_voices()
+}
+
+/// Pick up files added to or removed from the user voice directories
+/// since espeak was initialized or last reloaded. Changes to the contents
+/// of existing files are seen without reloading.
+pub fn reload_voices() -> Result<(), EspeakError> {
+    init()?;
+    let mut lock = espeak_lock();
+    match lock.as_mut() {
+        Some(state) => merge(state),
+        None => Ok(()),
+    }
+}
+
+/// Installed voices that were replaced by a user's file of the same name,
+/// as of the last merge.
+pub fn user_voice_warnings() -> Vec<String> {
+    USER_VOICES.plock().warnings.clone()
+}
+
+/// Rebuild the merged data directory and point espeak at it. Must be
+/// called with the espeak lock held, after espeak has been initialized.
+pub(crate) fn merge(state: &mut InitState) -> Result<(), EspeakError> {
+    let mut user_voices = USER_VOICES.plock();
+    if user_voices.dirs.is_empty() && !user_voices.overlay {
+        return Ok(());
+    }
+    let stock_data = match &user_voices.stock_data {
+        Some(path) => path.clone(),
+        None => {
+            let path = current_data_path();
+            user_voices.stock_data = Some(path.clone());
+            path
+        }
+    };
+
+    let root = merged_root();
+    let mut warnings = Vec::new();
+    build(&root, &stock_data, &user_voices.dirs, &mut warnings).map_err(|e| {
+        EspeakError::VoiceDirectory {
+            path: root.clone(),
+            message: e.to_string(),
+        }
+    })?;
+    user_voices.warnings = warnings;
+
+    if !user_voices.merged {
+        let root_cstr = CString::new(root.to_string_lossy().into_owned()).map_err(|e| {
+            EspeakError::VoiceDirectory {
+                path: root.clone(),
+                message: e.to_string(),
+            }
+        })?;
+        let result = unsafe {
+            espeak_Terminate();
+            espeak_Initialize(
+                state.output_mode.espeak_output(),
+                0,
+                root_cstr.as_ptr(),
+                espeakINITIALIZE_DONT_EXIT as c_int,
+            )
+        };
+        state.sample_rate = result
+            .try_into()
+            .map_err(|_| EspeakError::InitFailed(result))?;
+        user_voices.merged = true;
+    }
+    Ok(())
+}
+
+/// Point espeak at the merged copy even if there are no user directories,
+/// and return its `espeak-ng-data`. Must be called with the espeak lock
+/// held, after espeak has been initialized.
+pub(crate) fn overlay_data(state: &mut InitState) -> Result<PathBuf, EspeakError> {
+    USER_VOICES.plock().overlay = true;
+    merge(state)?;
+    Ok(merged_root().join("espeak-ng-data"))
+}
+
+/// The installed data directory, whether or not espeak has been pointed
+/// at the merged copy. Must be called with the espeak lock held.
+pub(crate) fn stock_data() -> PathBuf {
+    USER_VOICES
+        .plock()
+        .stock_data
+        .clone()
+        .unwrap_or_else(current_data_path)
+}
+
+/// Remove the merged copy, once espeak has been terminated. The user
+/// directories are merged again when espeak is next initialized.
+pub(crate) fn remove_overlay() -> Result<(), EspeakError> {
+    let mut user_voices = USER_VOICES.plock();
+    user_voices.merged = false;
+    user_voices.overlay = false;
+    let root = merged_root();
+    match fs::remove_dir_all(&root) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(EspeakError::DataNotWritable {
+            path: root,
+            message: e.to_string(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// espeak is given the directory containing `espeak-ng-data`.
+fn merged_root() -> PathBuf {
+    std::env::temp_dir().join(format!("espeak-rs-data-{}", std::process::id()))
+}
+
+fn build(
+    root: &Path,
+    stock_data: &Path,
+    user_dirs: &[PathBuf],
+    warnings: &mut Vec<String>,
+) -> io::Result<()> {
+    let data = root.join("espeak-ng-data");
+    fs::create_dir_all(&data)?;
+    for entry in fs::read_dir(stock_data)? {
+        let entry = entry?;
+        let target = data.join(entry.file_name());
+        if entry.file_name() != "voices" && fs::symlink_metadata(&target).is_err() {
+            link(&entry.path(), &target)?;
+        }
+    }
+
+    let voices = data.join("voices");
+    if voices.exists() {
+        fs::remove_dir_all(&voices)?;
+    }
+    fs::create_dir(&voices)?;
+    overlay(&stock_data.join("voices"), &voices, Path::new(""), None)?;
+    for dir in user_dirs {
+        overlay(dir, &voices, Path::new(""), Some(warnings))?;
+    }
+    Ok(())
+}
+
+/// Link the files under `from` into `to`, recreating its directories.
+/// With `warnings`, files that replace existing ones are reported by their
+/// path within `voices`, starting from `name`.
+fn overlay(
+    from: &Path,
+    to: &Path,
+    name: &Path,
+    mut warnings: Option<&mut Vec<String>>,
+) -> io::Result<()> {
+    if !from.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let source = entry.path();
+        let target = to.join(entry.file_name());
+        let name = name.join(entry.file_name());
+        if source.is_dir() {
+            fs::create_dir_all(&target)?;
+            overlay(&source, &target, &name, warnings.as_deref_mut())?;
+            continue;
+        }
+        if fs::symlink_metadata(&target).is_ok() {
+            if let Some(warnings) = warnings.as_deref_mut() {
+                warnings.push(format!(
+                    "{} replaces voice file {}",
+                    source.display(),
+                    name.display()
+                ));
+            }
+            fs::remove_file(&target)?;
+        }
+        link(&source, &target)?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn link(source: &Path, target: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(source, target)
+}
+
+#[cfg(not(unix))]
+fn link(source: &Path, target: &Path) -> io::Result<()> {
+    if source.is_dir() {
+        fs::create_dir_all(target)?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            link(&entry.path(), &target.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        fs::copy(source, target).map(|_| ())
+    }
+}
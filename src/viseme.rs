@@ -0,0 +1,263 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Mouth shapes for lip sync, from phoneme events.
+//!
+//! [`Viseme`] is the common 15-shape set used by Oculus and Microsoft
+//! avatars. A [`VisemeMap`] assigns one to each espeak phoneme mnemonic,
+//! which are based on the Kirshenbaum ASCII IPA scheme. The default map
+//! covers the phonemes most languages share; languages needing more can
+//! have their own map set with [`set_language_map`].
+//!
+//! [`SpeakerSource::with_viseme_callback`](crate::SpeakerSource::with_viseme_callback)
+//! reports visemes as the audio reaches them.
+
+use crate::try_list_voices;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// A mouth shape. The set is fixed, so unlike the crate's other enums
+/// this one isn't `#[non_exhaustive]`, and a `match` can map each shape
+/// to an animation without a wildcard arm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Viseme {
+    /// Silence, mouth closed or at rest.
+    Sil,
+    /// p, b, m
+    Pp,
+    /// f, v
+    Ff,
+    /// The th sounds.
+    Th,
+    /// t, d
+    Dd,
+    /// k, g, and h
+    Kk,
+    /// ch, j, sh and the s in "measure"
+    Ch,
+    /// s, z
+    Ss,
+    /// n, l and ng
+    Nn,
+    /// r
+    Rr,
+    /// Open vowels, as in "father" and "cat"
+    Aa,
+    /// Mid front and central vowels, as in "bed" and "about"
+    E,
+    /// Close front vowels and y, as in "sit" and "see"
+    I,
+    /// Rounded back vowels, as in "thought"
+    O,
+    /// Close rounded vowels and w, as in "boot"
+    U,
+}
+
+/// The default viseme of each phoneme mnemonic.
+const DEFAULT_VISEMES: &[(&str, Viseme)] = &[
+    ("_", Viseme::Sil),
+    ("p", Viseme::Pp),
+    ("b", Viseme::Pp),
+    ("m", Viseme::Pp),
+    ("f", Viseme::Ff),
+    ("v", Viseme::Ff),
+    ("T", Viseme::Th),
+    ("D", Viseme::Th),
+    ("t", Viseme::Dd),
+    ("d", Viseme::Dd),
+    ("k", Viseme::Kk),
+    ("g", Viseme::Kk),
+    ("h", Viseme::Kk),
+    ("x", Viseme::Kk),
+    ("tS", Viseme::Ch),
+    ("dZ", Viseme::Ch),
+    ("S", Viseme::Ch),
+    ("Z", Viseme::Ch),
+    ("s", Viseme::Ss),
+    ("z", Viseme::Ss),
+    ("n", Viseme::Nn),
+    ("N", Viseme::Nn),
+    ("l", Viseme::Nn),
+    ("r", Viseme::Rr),
+    ("R", Viseme::Rr),
+    ("a", Viseme::Aa),
+    ("A", Viseme::Aa),
+    ("&", Viseme::Aa),
+    ("V", Viseme::Aa),
+    ("e", Viseme::E),
+    ("E", Viseme::E),
+    ("@", Viseme::E),
+    ("3", Viseme::E),
+    ("i", Viseme::I),
+    ("I", Viseme::I),
+    ("j", Viseme::I),
+    ("y", Viseme::I),
+    ("o", Viseme::O),
+    ("O", Viseme::O),
+    ("0", Viseme::O),
+    ("u", Viseme::U),
+    ("U", Viseme::U),
+    ("w", Viseme::U),
+];
+
+/// Marks espeak adds to a mnemonic for length, stress or variants, which
+/// don't change the mouth shape.
+const MODIFIERS: &[char] = &[':', ';', '#', '-', '\'', ',', '!'];
+
+/// Assigns a [`Viseme`] to each phoneme mnemonic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VisemeMap {
+    visemes: HashMap<String, Viseme>,
+}
+
+impl Default for VisemeMap {
+    fn default() -> VisemeMap {
+        VisemeMap {
+            visemes: DEFAULT_VISEMES
+                .iter()
+                .map(|(phoneme, viseme)| (String::from(*phoneme), *viseme))
+                .collect(),
+        }
+    }
+}
+
+impl VisemeMap {
+    /// A map with no entries, so that every phoneme is [`Viseme::Sil`].
+    pub fn empty() -> VisemeMap {
+        VisemeMap {
+            visemes: HashMap::new(),
+        }
+    }
+
+    /// Map `phoneme` to `viseme`, replacing any previous entry.
+    pub fn with(mut self, phoneme: &str, viseme: Viseme) -> VisemeMap {
+        self.visemes.insert(String::from(phoneme), viseme);
+        self
+    }
+
+    /// The viseme for a phoneme mnemonic. A mnemonic without an entry of
+    /// its own is looked up without its modifiers, then by its first two
+    /// characters and then its first, so that `A:` and `aI` follow `a`.
+    /// Phonemes that still aren't found are [`Viseme::Sil`].
+    pub fn viseme(&self, phoneme: &str) -> Viseme {
+        if let Some(viseme) = self.visemes.get(phoneme) {
+            return *viseme;
+        }
+        let bare: String = phoneme.chars().filter(|c| !MODIFIERS.contains(c)).collect();
+        for key in [&*bare, prefix(&bare, 2), prefix(&bare, 1)] {
+            if let Some(viseme) = self.visemes.get(key) {
+                return *viseme;
+            }
+        }
+        Viseme::Sil
+    }
+}
+
+/// The first `chars` characters of `text`.
+fn prefix(text: &str, chars: usize) -> &str {
+    text.char_indices()
+        .nth(chars)
+        .map_or(text, |(i, _)| &text[..i])
+}
+
+/// Maps set for particular languages, by language tag.
+static LANGUAGE_MAPS: RwLock<Vec<(String, VisemeMap)>> = RwLock::new(Vec::new());
+
+/// Use `map` for voices speaking `language`, a tag such as `en` or
+/// `en-us`. A map for `en` also covers `en-us` unless it has its own.
+pub fn set_language_map(language: &str, map: VisemeMap) {
+    let mut maps = match LANGUAGE_MAPS.write() {
+        Ok(maps) => maps,
+        Err(e) => e.into_inner(),
+    };
+    let language = language.to_ascii_lowercase();
+    maps.retain(|(tag, _)| *tag != language);
+    maps.push((language, map));
+}
+
+/// The map to use for `language`: the one set for it or its primary
+/// subtag, or the default map.
+pub fn language_map(language: &str) -> VisemeMap {
+    let maps = match LANGUAGE_MAPS.read() {
+        Ok(maps) => maps,
+        Err(e) => e.into_inner(),
+    };
+    let language = language.to_ascii_lowercase();
+    let primary = language.split('-').next().unwrap_or_default();
+    for tag in [&*language, primary] {
+        if let Some((_, map)) = maps.iter().find(|(t, _)| t == tag) {
+            return map.clone();
+        }
+    }
+    VisemeMap::default()
+}
+
+/// The language of the voice `voice_name` selects: the first language of
+/// the installed voice by that name or identifier, or else taken from the
+/// name itself, such as `en-US` from `gmw/en-US` or `en` from `en+klatt`.
+pub(crate) fn voice_language(voice_name: &str) -> String {
+    let name = voice_name.split('+').next().unwrap_or_default();
+    let installed = try_list_voices()
+        .unwrap_or_default()
+        .into_iter()
+        .find_map(|voice| {
+            let matches = voice.name == name || voice.identifier == name;
+            matches.then(|| voice.languages.into_iter().next())?
+        });
+    if let Some(language) = installed {
+        return language.name;
+    }
+    match name.rsplit('/').next().unwrap_or_default() {
+        "" => String::from("en"),
+        language => String::from(language),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phonemes_are_mapped() {
+        let map = VisemeMap::default();
+        let visemes: Vec<Viseme> = ["p", "A:", "m", "@", "tS", "aI", "'oU", "_:", "?"]
+            .iter()
+            .map(|phoneme| map.viseme(phoneme))
+            .collect();
+        assert_eq!(
+            visemes,
+            [
+                Viseme::Pp,
+                Viseme::Aa,
+                Viseme::Pp,
+                Viseme::E,
+                Viseme::Ch,
+                Viseme::Aa,
+                Viseme::O,
+                Viseme::Sil,
+                Viseme::Sil,
+            ]
+        );
+        assert_eq!(VisemeMap::empty().viseme("p"), Viseme::Sil);
+        assert_eq!(map.with("tS", Viseme::Ss).viseme("tS"), Viseme::Ss);
+    }
+}
@@ -0,0 +1,210 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Streaming WAV encoding of a [`SpeakerSource`], and WAV files of an
+//! [`AudioBuffer`].
+
+use crate::{AudioBuffer, EspeakError, SpeakerSource};
+use rodio::Source;
+use std::fs;
+use std::path::Path;
+
+/// Samples per chunk after the header, about 185ms at 22050Hz.
+const SAMPLES_PER_CHUNK: usize = 4096;
+
+/// Size used for the RIFF and data lengths, since the final length isn't
+/// known while streaming. Most players treat it as "until end of stream".
+const STREAMING_LEN: u32 = u32::MAX;
+
+/// An iterator of byte chunks making up a WAV file: first a 44-byte header,
+/// then little-endian 16-bit mono PCM as it is synthesized.
+///
+/// Each chunk converts cheaply into a `bytes::Bytes` or an HTTP body frame.
+/// Dropping the stream drops the source, which stops synthesis.
+pub struct WavChunkStream {
+    source: SpeakerSource,
+    header_sent: bool,
+}
+
+impl WavChunkStream {
+    pub(crate) fn new(source: SpeakerSource) -> WavChunkStream {
+        WavChunkStream {
+            source,
+            header_sent: false,
+        }
+    }
+}
+
+/// Length of the header [`wav_header`] writes.
+pub(crate) const WAV_HEADER_LEN: usize = 44;
+
+/// A canonical 44-byte PCM WAV header.
+pub(crate) fn wav_header(sample_rate: u32, channels: u16, data_len: u32) -> Vec<u8> {
+    let bits_per_sample = 16u16;
+    let block_align = channels * bits_per_sample / 8;
+    let byte_rate = sample_rate * block_align as u32;
+    let riff_len = data_len.saturating_add(36);
+
+    let mut header = Vec::<u8>::with_capacity(44);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&riff_len.to_le_bytes());
+    header.extend_from_slice(b"WAVE");
+    header.extend_from_slice(b"fmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&channels.to_le_bytes());
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    header.extend_from_slice(&byte_rate.to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&bits_per_sample.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_len.to_le_bytes());
+    header
+}
+
+/// The sample rate and data length in bytes of a header written by
+/// [`wav_header`] for mono audio. The length is `None` for a streamed file,
+/// which doesn't know it.
+pub(crate) fn parse_wav_header(header: &[u8]) -> Result<(u32, Option<usize>), String> {
+    let header = header
+        .get(..WAV_HEADER_LEN)
+        .ok_or_else(|| format!("shorter than the {}-byte header", WAV_HEADER_LEN))?;
+    let u16_at = |i: usize| u16::from_le_bytes([header[i], header[i + 1]]);
+    let u32_at =
+        |i: usize| u32::from_le_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+    if &header[0..4] != b"RIFF" || &header[8..16] != b"WAVEfmt " || &header[36..40] != b"data" {
+        return Err("not a canonical WAV header".to_string());
+    }
+    if u32_at(16) != 16 || u16_at(20) != 1 || u16_at(22) != 1 || u16_at(34) != 16 {
+        return Err("not 16-bit mono PCM".to_string());
+    }
+    let data_len = match u32_at(40) {
+        STREAMING_LEN => None,
+        len => Some(len as usize),
+    };
+    Ok((u32_at(24), data_len))
+}
+
+/// Where the events of the WAV file `path` are kept: beside it, with the
+/// extension `events.json`.
+#[cfg(feature = "serde")]
+pub(crate) fn sidecar_path(path: &Path) -> std::path::PathBuf {
+    path.with_extension("events.json")
+}
+
+pub(crate) fn file_error(path: &Path, message: impl ToString) -> EspeakError {
+    EspeakError::AudioFile {
+        path: path.to_path_buf(),
+        message: message.to_string(),
+    }
+}
+
+impl AudioBuffer {
+    /// Write the audio to `path` as a WAV file. With the `serde` feature,
+    /// the events are written beside it, to the same name with the
+    /// extension `events.json`.
+    pub fn save_wav(&self, path: impl AsRef<Path>) -> Result<(), EspeakError> {
+        let path = path.as_ref();
+        let data_len = u32::try_from(self.samples.len() * 2)
+            .ok()
+            .filter(|len| *len != STREAMING_LEN)
+            .ok_or_else(|| file_error(path, "too long for a WAV file"))?;
+        let mut bytes = wav_header(self.sample_rate, 1, data_len);
+        bytes.reserve(self.samples.len() * 2);
+        for sample in &self.samples {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+        fs::write(path, bytes).map_err(|e| file_error(path, e))?;
+        #[cfg(feature = "serde")]
+        {
+            let sidecar = sidecar_path(path);
+            let json =
+                serde_json::to_string_pretty(&self.events).expect("Failed to serialize events");
+            fs::write(&sidecar, json).map_err(|e| file_error(&sidecar, e))?;
+        }
+        Ok(())
+    }
+
+    /// Read a WAV file written by [`save_wav`](AudioBuffer::save_wav) or
+    /// [`WavChunkStream`], with the events beside it if there are any and
+    /// the `serde` feature is enabled.
+    pub fn load_wav(path: impl AsRef<Path>) -> Result<AudioBuffer, EspeakError> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|e| file_error(path, e))?;
+        let (sample_rate, data_len) = parse_wav_header(&bytes).map_err(|e| file_error(path, e))?;
+        let data = &bytes[WAV_HEADER_LEN..];
+        let data = match data_len {
+            Some(len) => data.get(..len).ok_or_else(|| {
+                file_error(path, format!("{} bytes of data, not {}", data.len(), len))
+            })?,
+            None => data,
+        };
+        let mut buffer = AudioBuffer::new(sample_rate);
+        buffer.samples = data
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        buffer.events = load_sidecar(path)?;
+        Ok(buffer)
+    }
+}
+
+/// The events saved beside the WAV file `path`, or none if there's no
+/// sidecar.
+#[cfg(feature = "serde")]
+pub(crate) fn load_sidecar(path: &Path) -> Result<Vec<crate::TimedEvent>, EspeakError> {
+    let sidecar = sidecar_path(path);
+    match fs::read_to_string(&sidecar) {
+        Ok(json) => serde_json::from_str(&json).map_err(|e| file_error(&sidecar, e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(file_error(&sidecar, e)),
+    }
+}
+
+#[cfg(not(feature = "serde"))]
+pub(crate) fn load_sidecar(_path: &Path) -> Result<Vec<crate::TimedEvent>, EspeakError> {
+    Ok(Vec::new())
+}
+
+impl Iterator for WavChunkStream {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        if !self.header_sent {
+            self.header_sent = true;
+            return Some(wav_header(
+                self.source.sample_rate(),
+                self.source.channels(),
+                STREAMING_LEN,
+            ));
+        }
+
+        let mut chunk = Vec::<u8>::with_capacity(SAMPLES_PER_CHUNK * 2);
+        for sample in self.source.by_ref().take(SAMPLES_PER_CHUNK) {
+            chunk.extend_from_slice(&sample.to_le_bytes());
+        }
+        if chunk.is_empty() {
+            None
+        } else {
+            Some(chunk)
+        }
+    }
+}
@@ -0,0 +1,314 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Per-word statistics of an utterance, for
+//! [`Speaker::word_stats`](crate::Speaker::word_stats).
+//!
+//! espeak doesn't say when it reads a word in another language, as the
+//! English voice does with Cyrillic text, but the phonemes give it away:
+//! after switching it speaks phonemes from the other language's table.
+//! The phonemes a voice has are read from espeak's `phontab`, as the
+//! voice's own table and those it includes, and the phonemes of each word
+//! that aren't among them are counted as foreign. Languages share many
+//! mnemonics, so this is a lower bound.
+
+use crate::{current_data_path, espeak_lock, try_list_voices, EspeakError, Event};
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::PathBuf;
+
+/// Bytes of a table's name in `phontab`.
+const TABLE_NAME_LEN: usize = 32;
+/// Bytes of each phoneme in `phontab`: its mnemonic, flags, program,
+/// code, types and lengths.
+const PHONEME_LEN: usize = 16;
+
+/// What was spoken for one word.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WordStat {
+    /// The word, as reported by its [`Event::Word`].
+    pub word: String,
+    /// Where the word is in the text, in bytes.
+    pub range: Range<usize>,
+    /// Phonemes spoken for the word.
+    pub phonemes: usize,
+    /// Phonemes not in the voice's phoneme table, suggesting espeak read
+    /// the word in another language.
+    pub foreign_phonemes: usize,
+}
+
+/// A phoneme table from `phontab`.
+#[derive(Debug, PartialEq)]
+struct PhonemeTable {
+    name: String,
+    /// The index of the table this one builds on, plus one, or `0`.
+    includes: usize,
+    mnemonics: Vec<String>,
+}
+
+/// Read the tables in `data`, the contents of `phontab`. `None` if it's cut
+/// short.
+fn parse_tables(data: &[u8]) -> Option<Vec<PhonemeTable>> {
+    let count = *data.first()? as usize;
+    let mut tables = Vec::with_capacity(count);
+    let mut rest = data.get(4..)?;
+    for _ in 0..count {
+        let phonemes = *rest.first()? as usize;
+        let includes = *rest.get(1)? as usize;
+        let name = rest.get(4..4 + TABLE_NAME_LEN)?;
+        let name = name.split(|b| *b == 0).next().unwrap_or_default();
+        rest = &rest[4 + TABLE_NAME_LEN..];
+        let entries = rest.get(..phonemes * PHONEME_LEN)?;
+        // The first entry of each table is unused.
+        let mnemonics = entries
+            .chunks(PHONEME_LEN)
+            .skip(1)
+            .map(|entry| {
+                // Up to four characters, the first in the low byte.
+                let mnemonic = u32::from_ne_bytes([entry[0], entry[1], entry[2], entry[3]]);
+                let bytes = mnemonic.to_le_bytes();
+                let len = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+                String::from_utf8_lossy(&bytes[..len]).into_owned()
+            })
+            .filter(|mnemonic| !mnemonic.is_empty())
+            .collect();
+        tables.push(PhonemeTable {
+            name: String::from_utf8_lossy(name).into_owned(),
+            includes,
+            mnemonics,
+        });
+        rest = &rest[entries.len()..];
+    }
+    Some(tables)
+}
+
+/// The mnemonics of the table named `name` and the tables it builds on.
+fn inventory(tables: &[PhonemeTable], name: &str) -> Option<HashSet<String>> {
+    let mut index = tables.iter().position(|table| table.name == name)?;
+    let mut mnemonics = HashSet::new();
+    // Bounded, in case the tables include each other in a loop.
+    for _ in 0..tables.len() {
+        let table = &tables[index];
+        mnemonics.extend(table.mnemonics.iter().cloned());
+        match table.includes.checked_sub(1) {
+            Some(included) if included < tables.len() => index = included,
+            _ => break,
+        }
+    }
+    Some(mnemonics)
+}
+
+/// Names the phoneme table of `voice_name` may have, best first: the
+/// voice's own name and languages, then each without its region.
+fn table_names(voice_name: &str, languages: &[String]) -> Vec<String> {
+    let mut names = Vec::new();
+    let voice_name = voice_name.split('+').next().unwrap_or_default();
+    for name in [voice_name]
+        .into_iter()
+        .chain(languages.iter().map(String::as_str))
+    {
+        let mut name = name.to_lowercase();
+        loop {
+            if !names.contains(&name) {
+                names.push(name.clone());
+            }
+            match name.rfind('-') {
+                Some(dash) => name.truncate(dash),
+                None => break,
+            }
+        }
+    }
+    names
+}
+
+/// The phoneme mnemonics `voice_name` speaks with when it doesn't switch
+/// languages. espeak must be initialized.
+pub(crate) fn phoneme_inventory(voice_name: &str) -> Result<HashSet<String>, EspeakError> {
+    let voice_name = if voice_name.is_empty() {
+        "en"
+    } else {
+        voice_name
+    };
+    let base = voice_name.split('+').next().unwrap_or_default();
+    let languages: Vec<String> = try_list_voices()?
+        .into_iter()
+        .filter(|voice| {
+            let identifier = voice.identifier.rsplit('/').next().unwrap_or_default();
+            [voice.name.as_str(), voice.identifier.as_str(), identifier]
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(base))
+        })
+        .flat_map(|voice| voice.languages.into_iter().map(|language| language.name))
+        .collect();
+    let path: PathBuf = {
+        let _lock = espeak_lock();
+        current_data_path().join("phontab")
+    };
+    let not_found = || EspeakError::DataNotFound {
+        searched_paths: vec![path.clone()],
+    };
+    let data = std::fs::read(&path).map_err(|_| not_found())?;
+    let tables = parse_tables(&data).ok_or_else(not_found)?;
+    table_names(voice_name, &languages)
+        .iter()
+        .find_map(|name| inventory(&tables, name))
+        .ok_or_else(|| EspeakError::VoiceNotFound {
+            name: String::from(voice_name),
+        })
+}
+
+/// Attribute each phoneme in `events` to the word reported before it.
+pub(crate) fn word_stats(
+    text: &str,
+    events: impl IntoIterator<Item = Event>,
+    inventory: &HashSet<String>,
+) -> Vec<WordStat> {
+    let mut stats = Vec::<WordStat>::new();
+    for event in events {
+        match event {
+            Event::Word(start, len) => stats.push(WordStat {
+                word: text.get(start..start + len).unwrap_or_default().to_string(),
+                range: start..start + len,
+                phonemes: 0,
+                foreign_phonemes: 0,
+            }),
+            Event::Phoneme { name, .. } => {
+                if let Some(stat) = stats.last_mut() {
+                    stat.phonemes += 1;
+                    if !inventory.contains(&name) {
+                        stat.foreign_phonemes += 1;
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(name: &str, includes: u8, mnemonics: &[&str]) -> Vec<u8> {
+        let mut data = vec![mnemonics.len() as u8 + 1, includes, 0, 0];
+        let mut name_bytes = name.as_bytes().to_vec();
+        name_bytes.resize(TABLE_NAME_LEN, 0);
+        data.extend(name_bytes);
+        data.extend([0; PHONEME_LEN]);
+        for mnemonic in mnemonics {
+            let mut bytes = mnemonic.as_bytes().to_vec();
+            bytes.resize(4, 0);
+            let packed = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            data.extend(packed.to_ne_bytes());
+            data.extend([7; PHONEME_LEN - 4]);
+        }
+        data
+    }
+
+    fn phontab(tables: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = vec![tables.len() as u8, 0, 0, 0];
+        for table in tables {
+            data.extend(table);
+        }
+        data
+    }
+
+    fn set(mnemonics: &[&str]) -> HashSet<String> {
+        mnemonics.iter().map(|m| m.to_string()).collect()
+    }
+
+    #[test]
+    fn tables_build_on_each_other() {
+        let data = phontab(&[
+            table("base1", 0, &["_", "a", "t"]),
+            table("en", 1, &["aI", "@U"]),
+            table("en-us", 2, &["3:"]),
+            table("de", 1, &["y:", "aI"]),
+        ]);
+        let tables = parse_tables(&data).unwrap();
+        assert_eq!(tables.len(), 4);
+        assert_eq!(tables[2].name, "en-us");
+        assert_eq!(
+            inventory(&tables, "en-us"),
+            Some(set(&["_", "a", "t", "aI", "@U", "3:"]))
+        );
+        assert_eq!(
+            inventory(&tables, "de"),
+            Some(set(&["_", "a", "t", "y:", "aI"]))
+        );
+        assert_eq!(inventory(&tables, "fr"), None);
+
+        assert_eq!(parse_tables(&data[..data.len() - 1]), None);
+        assert_eq!(parse_tables(&[]), None);
+    }
+
+    #[test]
+    fn table_names_fall_back_to_the_language() {
+        assert_eq!(
+            table_names("en-GB-x-rp+klatt", &[]),
+            ["en-gb-x-rp", "en-gb-x", "en-gb", "en"]
+        );
+        let languages = [String::from("pt-br"), String::from("pt")];
+        assert_eq!(
+            table_names("Portuguese_(Brazil)", &languages),
+            ["portuguese_(brazil)", "pt-br", "pt"]
+        );
+    }
+
+    #[test]
+    fn phonemes_go_to_the_word_before() {
+        let phoneme = |name: &str| Event::Phoneme {
+            name: String::from(name),
+            id: 0,
+        };
+        let events = [
+            Event::Start,
+            phoneme("_"),
+            Event::Word(0, 2),
+            phoneme("h"),
+            phoneme("aI"),
+            Event::Word(3, 12),
+            phoneme("m"),
+            phoneme("o"),
+            phoneme("s^"),
+            Event::Terminated { audio_ms: 0 },
+        ];
+        let stats = word_stats("Hi Москва", events, &set(&["_", "h", "aI", "m"]));
+        assert_eq!(
+            stats,
+            [
+                WordStat {
+                    word: String::from("Hi"),
+                    range: 0..2,
+                    phonemes: 2,
+                    foreign_phonemes: 0,
+                },
+                WordStat {
+                    word: String::from("Москва"),
+                    range: 3..15,
+                    phonemes: 3,
+                    foreign_phonemes: 2,
+                },
+            ]
+        );
+    }
+}
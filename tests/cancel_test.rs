@@ -0,0 +1,129 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+#[cfg(test)]
+mod tests {
+    use espeak_rs::*;
+    use std::time::{Duration, Instant};
+
+    fn long_text() -> String {
+        "Hello world. This is a rather long utterance. ".repeat(100)
+    }
+
+    /// The events of a source, and its error, once it has been read to
+    /// the end.
+    fn finish(source: SpeakerSource) -> (Vec<Event>, Option<EspeakError>, usize) {
+        let mut events = Vec::new();
+        let mut source = source.with_callback(|event| events.push(event));
+        let samples = source.by_ref().count();
+        let error = source.error().cloned();
+        drop(source);
+        (events, error, samples)
+    }
+
+    #[test]
+    fn queued_jobs_are_skipped() {
+        let started = Instant::now();
+        let (events, error, _) = finish(Speaker::new().speak(&long_text()));
+        let synthesis = started.elapsed();
+        assert_eq!(events.last(), Some(&Event::End));
+        assert_eq!(error, None);
+
+        // A full channel keeps the first job synthesizing, holding espeak,
+        // until it is read.
+        let mut speaker = Speaker::new();
+        speaker.params.channel_capacity = Some(1);
+        let mut first = speaker.speak(&long_text());
+        first.by_ref().take(100).for_each(drop);
+        let second = speaker.speak(&long_text());
+        let third = speaker.speak(&long_text());
+
+        let token = second.cancel_token();
+        assert_ne!(token.id(), third.cancel_token().id());
+        token.cancel();
+        assert!(token.is_cancelled());
+        let (events, error, samples) = finish(second);
+        assert_eq!(events, vec![Event::Cancelled]);
+        assert_eq!(error, Some(EspeakError::Cancelled));
+        assert_eq!(samples, 0);
+
+        drop(first);
+        let started = Instant::now();
+        let mut third = third;
+        assert!(third.next().is_some());
+        assert!(
+            started.elapsed() < synthesis.max(Duration::from_millis(50)),
+            "the third job waited {:?}, the second would take {:?}",
+            started.elapsed(),
+            synthesis
+        );
+        let (events, error, _) = finish(third);
+        assert_eq!(events.last(), Some(&Event::End));
+        assert_eq!(error, None);
+    }
+
+    #[test]
+    fn running_jobs_are_stopped() {
+        let mut source = Speaker::new().speak(&long_text());
+        source.by_ref().take(100).for_each(drop);
+        let token = source.cancel_token();
+        token.cancel();
+        let (events, error, samples) = finish(source);
+        assert_eq!(events.last(), Some(&Event::Cancelled));
+        assert_eq!(error, Some(EspeakError::Cancelled));
+        assert!(samples < 22050 * 10);
+
+        // Cancelling a finished job does nothing.
+        let source = Speaker::new().speak("Hello world");
+        let token = source.cancel_token();
+        let (events, error, _) = finish(source);
+        token.cancel();
+        assert!(!token.is_cancelled());
+        assert_eq!(events.last(), Some(&Event::End));
+        assert_eq!(error, None);
+    }
+
+    #[test]
+    fn cancelling_races_with_starting() {
+        let (expected, _, expected_samples) = finish(Speaker::new().speak("Hello world"));
+        let mut speaker = Speaker::new();
+        speaker.params.rate = Some(400.into());
+        speaker.params.pitch = Some(90.into());
+        for _ in 0..20 {
+            let source = speaker.speak("Hello world");
+            source.cancel_token().cancel();
+            let (events, error, _) = finish(source);
+            match error {
+                None => assert_eq!(events.last(), Some(&Event::End)),
+                Some(error) => {
+                    assert_eq!(error, EspeakError::Cancelled);
+                    assert_eq!(events.last(), Some(&Event::Cancelled));
+                }
+            }
+        }
+        // Each job either ran or was skipped whole, leaving espeak set up
+        // for the next one.
+        let (events, error, samples) = finish(Speaker::new().speak("Hello world"));
+        assert_eq!(events, expected);
+        assert_eq!(samples, expected_samples);
+        assert_eq!(error, None);
+    }
+}
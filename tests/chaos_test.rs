@@ -0,0 +1,111 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+#[cfg(test)]
+mod tests {
+    use espeak_rs::{debug_snapshot, Event, Speaker};
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    const THREADS: usize = 4;
+    /// Per thread. Kept low enough for CI; set `ESPEAK_RS_CHAOS_ITERATIONS`
+    /// to soak for longer.
+    const ITERATIONS: usize = 250;
+    const TEXT: &str = "A source is created, read from for a moment and dropped, \
+        over and over, from several threads at once.";
+
+    fn iterations() -> usize {
+        std::env::var("ESPEAK_RS_CHAOS_ITERATIONS")
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(ITERATIONS)
+    }
+
+    /// Threads in this process, where the OS says.
+    fn thread_count() -> Option<usize> {
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+        let line = status.lines().find(|line| line.starts_with("Threads:"))?;
+        line["Threads:".len()..].trim().parse().ok()
+    }
+
+    /// Wait for `done`, failing after a generous timeout.
+    fn wait_for(what: &str, mut done: impl FnMut() -> bool) {
+        let start = Instant::now();
+        while !done() {
+            assert!(
+                start.elapsed() < Duration::from_secs(30),
+                "timed out waiting for {}",
+                what
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn sources_dropped_early_under_contention() {
+        // Settle whatever threads initialization starts.
+        Speaker::new().speak("Warm up.").for_each(drop);
+        wait_for("the warm-up to end", || {
+            debug_snapshot().active_syntheses == 0
+        });
+        let baseline = thread_count();
+
+        let iterations = iterations();
+        let barrier = Arc::new(Barrier::new(THREADS));
+        let threads: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    let speaker = Speaker::new();
+                    barrier.wait();
+                    for _ in 0..iterations {
+                        let source = speaker.speak(TEXT);
+                        assert_eq!(source.take(100).count(), 100);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().expect("a chaos thread panicked");
+        }
+
+        wait_for("synthesis threads to end", || {
+            debug_snapshot().active_syntheses == 0
+        });
+        if let Some(baseline) = baseline {
+            wait_for("the thread count to return to its baseline", || {
+                thread_count().is_some_and(|count| count <= baseline)
+            });
+        }
+
+        // Nothing was left locked, poisoned or half-configured.
+        let mut events = Vec::new();
+        let mut source = Speaker::new()
+            .speak("Still speaking.")
+            .with_callback(|event| events.push(event));
+        assert!(source.by_ref().count() > 0);
+        assert_eq!(source.error(), None);
+        drop(source);
+        assert!(events.contains(&Event::Word(0, 5)), "{:?}", events);
+        assert_eq!(events.last(), Some(&Event::End));
+    }
+}
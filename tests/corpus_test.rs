@@ -0,0 +1,49 @@
+#![cfg(feature = "corpus-tools")]
+
+#[cfg(test)]
+mod tests {
+    use espeak_rs::corpus::{self, Change, CorpusItem, CorpusReport};
+
+    #[test]
+    fn default_corpus_round_trips() {
+        let items = corpus::default_corpus();
+        let report = corpus::run(&items);
+        assert_eq!(report.entries.len(), items.len());
+        for entry in &report.entries {
+            assert!(entry.sample_count > 0, "{:?} produced no audio", entry.item);
+            assert!(entry.word_events > 0, "{:?} produced no words", entry.item);
+            assert!(!entry.phonemes.is_empty());
+        }
+
+        let parsed = CorpusReport::from_json(&report.to_json()).unwrap();
+        assert_eq!(parsed, report);
+        assert!(corpus::diff(&report, &parsed).is_empty());
+    }
+
+    #[test]
+    fn diff_reports_changes() {
+        let items = [CorpusItem::new("en", "Hello world")];
+        let old = corpus::run(&items);
+
+        let mut new = old.clone();
+        new.entries[0].duration_ms += new.entries[0].duration_ms / 100;
+        assert!(corpus::diff(&old, &new).is_empty());
+
+        new.entries[0].duration_ms = old.entries[0].duration_ms * 2;
+        new.entries[0].phonemes.push('x');
+        new.entries[0].word_events += 1;
+        new.entries
+            .push(corpus::run(&[CorpusItem::new("fr", "Bonjour")]).entries[0].clone());
+
+        let diff = corpus::diff(&old, &new);
+        assert_eq!(diff.items.len(), 2);
+        assert_eq!(diff.items[0].changes.len(), 3);
+        assert!(matches!(diff.items[0].changes[0], Change::Duration { .. }));
+        assert!(matches!(diff.items[0].changes[1], Change::Phonemes { .. }));
+        assert!(matches!(
+            diff.items[0].changes[2],
+            Change::WordEvents { .. }
+        ));
+        assert_eq!(diff.items[1].changes, vec![Change::Added]);
+    }
+}
@@ -0,0 +1,28 @@
+#[cfg(test)]
+mod tests {
+    use espeak_rs::{try_list_voices, EspeakError, Speaker};
+    use std::fs;
+
+    // Kept in its own test binary so that espeak hasn't been initialized
+    // with the real data yet.
+    #[test]
+    fn missing_data_is_reported() {
+        let dir = std::env::temp_dir().join(format!("espeak-rs-no-data-{}", std::process::id()));
+        let data = dir.join("espeak-ng-data");
+        fs::create_dir_all(&data).unwrap();
+        std::env::set_var("ESPEAK_DATA_PATH", &dir);
+
+        match try_list_voices() {
+            Err(EspeakError::DataNotFound { searched_paths }) => {
+                assert!(searched_paths.contains(&data), "{:?}", searched_paths)
+            }
+            other => panic!("expected DataNotFound, got {:?}", other),
+        }
+        assert!(matches!(
+            Speaker::try_new(),
+            Err(EspeakError::DataNotFound { .. })
+        ));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
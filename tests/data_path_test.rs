@@ -0,0 +1,27 @@
+#[cfg(test)]
+mod tests {
+    use espeak_rs::{info, set_data_path, shutdown, MissingDataPath, PathSource, Speaker};
+
+    // Kept in its own test binary, as it initializes espeak again.
+    #[test]
+    fn explicit_path_comes_first() {
+        let found = info().unwrap();
+        assert_ne!(found.data_path_source, PathSource::Explicit);
+
+        shutdown().unwrap();
+        set_data_path(Some(found.data_path.clone()), MissingDataPath::Fail);
+        let explicit = info().unwrap();
+        assert_eq!(explicit.data_path_source, PathSource::Explicit);
+        assert_eq!(explicit.data_path, found.data_path);
+        assert!(Speaker::new().speak("Hello").count() > 0);
+
+        shutdown().unwrap();
+        set_data_path(Some(found.data_path.join("nowhere")), MissingDataPath::Skip);
+        assert_eq!(info().unwrap().data_path_source, found.data_path_source);
+
+        shutdown().unwrap();
+        set_data_path(Some(found.data_path.join("nowhere")), MissingDataPath::Fail);
+        assert!(info().is_err());
+        set_data_path(None, MissingDataPath::Skip);
+    }
+}
@@ -0,0 +1,102 @@
+#[cfg(test)]
+mod tests {
+    use espeak_rs::{compile_dictionary, info, set_data_path, shutdown, MissingDataPath, Speaker};
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::time::SystemTime;
+
+    /// The size and mtime of every file under `dir`.
+    fn contents(dir: &Path) -> HashMap<PathBuf, (u64, SystemTime)> {
+        let mut files = HashMap::new();
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let metadata = fs::metadata(&path).unwrap();
+            if metadata.is_dir() {
+                files.extend(contents(&path));
+            } else {
+                files.insert(path, (metadata.len(), metadata.modified().unwrap()));
+            }
+        }
+        files
+    }
+
+    fn copy_dir(from: &Path, to: &Path) {
+        fs::create_dir_all(to).unwrap();
+        for entry in fs::read_dir(from).unwrap() {
+            let path = entry.unwrap().path();
+            let target = to.join(path.file_name().unwrap());
+            if path.is_dir() {
+                copy_dir(&path, &target);
+            } else {
+                fs::copy(&path, &target).unwrap();
+            }
+        }
+    }
+
+    /// Set or clear the read-only flag on `dir` and everything under it.
+    /// Directories are made read-only after, and writable before, their
+    /// contents.
+    fn set_readonly(dir: &Path, readonly: bool) {
+        let set = |path: &Path| {
+            let mut permissions = fs::metadata(path).unwrap().permissions();
+            permissions.set_readonly(readonly);
+            fs::set_permissions(path, permissions).unwrap();
+        };
+        if !readonly {
+            set(dir);
+        }
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                set_readonly(&path, readonly);
+            } else {
+                set(&path);
+            }
+        }
+        if readonly {
+            set(dir);
+        }
+    }
+
+    // Kept in its own test binary since it changes the data espeak reads
+    // for the rest of the process.
+    #[test]
+    fn dictionaries_compile_into_an_overlay() {
+        let temp = std::env::temp_dir().join(format!("espeak-rs-dict-{}", std::process::id()));
+        let source = temp.join("dictsource");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("en_list"), "rustacean\tr'VstEIS@n\n").unwrap();
+        fs::write(source.join("en_rules"), ".group a\n\ta\t\ta\n").unwrap();
+
+        // Compile against a read-only copy of the data, so that a write to
+        // it fails instead of changing the installed data.
+        let stock = temp.join("espeak-ng-data");
+        copy_dir(&info().unwrap().data_path, &stock);
+        set_readonly(&stock, true);
+        set_data_path(Some(stock.clone()), MissingDataPath::Fail);
+        shutdown().unwrap();
+        assert_eq!(info().unwrap().data_path, stock);
+        let before = contents(&stock);
+
+        let compiled = compile_dictionary("en", &source, false).unwrap();
+        assert!(!compiled.starts_with(&stock));
+        assert!(fs::metadata(&compiled).unwrap().is_file());
+        let overlay = info().unwrap().data_path;
+        assert_ne!(overlay, stock);
+        assert!(Speaker::new().speak("rustacean").count() > 0);
+        assert_eq!(contents(&stock), before);
+
+        shutdown().unwrap();
+        assert!(!overlay.exists());
+        // espeak comes back on next use, from the given data.
+        assert_eq!(info().unwrap().data_path, stock);
+        assert!(Speaker::new().speak("Hello").count() > 0);
+        assert_eq!(contents(&stock), before);
+
+        shutdown().unwrap();
+        set_data_path(None, MissingDataPath::Skip);
+        set_readonly(&stock, false);
+        fs::remove_dir_all(&temp).unwrap();
+    }
+}
@@ -0,0 +1,76 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+#[cfg(test)]
+mod tests {
+    use espeak_rs::Speaker;
+    use std::sync::{Arc, Barrier, Mutex};
+    use std::thread;
+
+    const JOBS: usize = 50;
+
+    /// The longest run of jobs in `order` finished by the same thread,
+    /// up to when either thread is done.
+    fn longest_run(order: &[usize]) -> usize {
+        let mut done = [0; 2];
+        let (mut longest, mut run) = (0, 0);
+        for (i, id) in order.iter().enumerate() {
+            run = if i > 0 && order[i - 1] == *id {
+                run + 1
+            } else {
+                1
+            };
+            longest = longest.max(run);
+            done[*id] += 1;
+            if done[*id] == JOBS {
+                break;
+            }
+        }
+        longest
+    }
+
+    #[test]
+    fn threads_take_turns() {
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let barrier = Arc::new(Barrier::new(2));
+        let threads: Vec<_> = (0..2)
+            .map(|id| {
+                let order = order.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    let speaker = Speaker::new();
+                    barrier.wait();
+                    for _ in 0..JOBS {
+                        assert!(speaker.speak("Hi").count() > 0);
+                        order.lock().unwrap().push(id);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let order = order.lock().unwrap();
+        assert_eq!(order.len(), JOBS * 2);
+        assert!(longest_run(&order) <= 3, "{:?}", order);
+    }
+}
@@ -0,0 +1,66 @@
+#[cfg(test)]
+mod tests {
+    use espeak_rs::{global_defaults, ParamValue, Speaker, SpeakerParams};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    fn with_rate(rate: i32) -> SpeakerParams {
+        let mut params = SpeakerParams::new();
+        params.rate = Some(rate.into());
+        params
+    }
+
+    fn params_with(value: i32) -> SpeakerParams {
+        let mut params = SpeakerParams::new();
+        params.rate = Some(value.into());
+        params.volume = Some(value.into());
+        params.pitch = Some(value.into());
+        params.range = Some(value.into());
+        params.word_gap = Some(value);
+        params
+    }
+
+    // Kept in its own test binary since the defaults are process-wide.
+    #[test]
+    fn global_defaults_apply_to_new_speakers() {
+        global_defaults().set(with_rate(80));
+        assert_eq!(
+            Speaker::new_with_global_defaults().params.rate,
+            Some(80.into())
+        );
+        assert_eq!(Speaker::new().params.rate, None);
+
+        let mut following = Speaker::new();
+        following.follow_global(true);
+        let fixed = Speaker::new_with_global_defaults();
+        global_defaults().set(with_rate(400));
+        assert_eq!(fixed.params.rate, Some(80.into()));
+        let slow = fixed.speak("Hello world").count();
+        let fast = following.speak("Hello world").count();
+        assert!(fast < slow, "{} < {}", fast, slow);
+
+        let done = AtomicBool::new(false);
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                for value in 0..2_000 {
+                    global_defaults().set(params_with(value));
+                }
+                done.store(true, Ordering::SeqCst);
+            });
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    while !done.load(Ordering::SeqCst) {
+                        let params = Speaker::new_with_global_defaults().params;
+                        let fields = [
+                            params.volume,
+                            params.pitch,
+                            params.range,
+                            params.word_gap.map(ParamValue::Absolute),
+                        ];
+                        assert!(fields.iter().all(|field| *field == params.rate));
+                    }
+                });
+            }
+        });
+    }
+}
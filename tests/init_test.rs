@@ -0,0 +1,26 @@
+#[cfg(test)]
+mod tests {
+    use espeak_rs::rodio::Source;
+    use espeak_rs::Speaker;
+    use std::sync::Barrier;
+    use std::thread;
+
+    // Kept in its own test binary so that this is the process's first use
+    // of espeak.
+    #[test]
+    fn concurrent_first_use_agrees_on_sample_rate() {
+        let barrier = Barrier::new(16);
+        let sample_rates: Vec<u32> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..16)
+                .map(|_| {
+                    scope.spawn(|| {
+                        barrier.wait();
+                        Speaker::new().speak("Hi").sample_rate()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert!(sample_rates.iter().all(|rate| *rate == 22050));
+    }
+}
@@ -1,8 +1,21 @@
 #[cfg(test)]
 mod tests {
-    use espeak_rs::{list_voices, Event, Gender, Speaker};
-    use rodio::Source;
+    use espeak_rs::rodio::Source;
+    #[cfg(feature = "serde")]
+    use espeak_rs::TimedEvent;
+    #[cfg(feature = "dsp")]
+    use espeak_rs::{compare_voices, EventRecording, GAIN_RAMP};
+    use espeak_rs::{
+        dry_run_transform, list_voices, probe_ssml, sanitize_ssml, ssml_capabilities,
+        synthesis_key, try_list_voices, Capitals, CapsPolicy, EspeakError, Event, Gender,
+        ParamValue, Punctuation, PunctuationEdit, PunctuationRepair, Rate, Segment, Speaker,
+        SpeakerParams, SpeakerSource, SynthFlags, TableSpeech, UrlPolicy, Voice,
+    };
     use std::cell::Cell;
+    use std::ops::ControlFlow;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::{Duration, Instant};
 
     macro_rules! assert_within {
         ($left:expr, $right:expr, $range:expr $(,)?) => {{
@@ -30,7 +43,7 @@ mod tests {
         assert_within!(count, 21748usize, 500);
 
         // Higher speech rate generates less samples
-        speaker.params.rate = Some(400);
+        speaker.params.rate = Some(400.into());
         let source = speaker.speak("Hello, world");
         let count = source.count();
         assert_within!(count, 6902usize, 500);
@@ -87,14 +100,20 @@ mod tests {
         for _sample in source {
             current_sample.set(current_sample.get() + 1);
         }
+        let audio_ms = match events[events.len() - 2].1 {
+            Event::Terminated { audio_ms } => audio_ms,
+            ref event => panic!("expected Terminated before End, got {:?}", event),
+        };
+        assert_within!(audio_ms as usize, 40786usize * 1000 / 22050, 2);
         let expected = [
             (0usize, Event::Start),
-            (0usize, Event::Sentence(0)),
+            (0usize, Event::Sentence { start: 0, len: 12 }),
             (0usize, Event::Word(0, 5)),
             (6769usize, Event::Word(6, 5)),
-            (22675usize, Event::Sentence(13)),
+            (22675usize, Event::Sentence { start: 13, len: 13 }),
             (22675usize, Event::Word(13, 7)),
             (31355usize, Event::Word(21, 5)),
+            (40786usize, Event::Terminated { audio_ms }),
             (40786usize, Event::End),
         ];
 
@@ -104,4 +123,1622 @@ mod tests {
             assert_within!(*at_sample, expected[i].0, 25);
         }
     }
+
+    #[test]
+    fn text_mode_is_per_utterance() {
+        fn word_starts(source: espeak_rs::SpeakerSource) -> Vec<usize> {
+            let mut starts = Vec::<usize>::new();
+            let source = source.with_callback(|evt| {
+                if let Event::Word(start, _) = evt {
+                    starts.push(start);
+                }
+            });
+            source.for_each(drop);
+            starts
+        }
+
+        let text = "<mark name=\"here\"/>Hello";
+        let tag_len = text.find("Hello").unwrap();
+        let speaker = Speaker::new();
+        for _ in 0..2 {
+            let ssml = word_starts(speaker.speak_ssml(text));
+            assert!(!ssml.is_empty());
+            assert!(ssml.iter().all(|start| *start >= tag_len));
+
+            let plain = word_starts(speaker.speak_plain(text));
+            assert!(plain.iter().any(|start| *start < tag_len));
+        }
+    }
+
+    #[test]
+    fn bounded_channel_backlog() {
+        let mut speaker = Speaker::new();
+        speaker.params.channel_capacity = Some(1);
+        let mut source = speaker.speak("Hello world. Goodbye world. Hello again, world.");
+
+        let mut blocked = false;
+        for _ in 0..50 {
+            thread::sleep(Duration::from_millis(10));
+            let backlog = source.backlog();
+            assert!(backlog.chunks_queued <= 2);
+            if backlog.producer_blocked {
+                blocked = true;
+                break;
+            }
+        }
+        assert!(blocked);
+
+        source.by_ref().for_each(drop);
+        let backlog = source.backlog();
+        assert!(!backlog.producer_blocked);
+        assert_eq!(backlog.chunks_queued, 0);
+        assert_eq!(backlog.samples_queued, 0);
+    }
+
+    #[test]
+    fn raw_synth_flags() {
+        let mut speaker = Speaker::new();
+        let default: Vec<i16> = speaker.speak("Hello, world").collect();
+
+        // The sentence pause espeak adds at the end of the text.
+        speaker.params.extra_synth_flags = espeak_rs_sys::espeakENDPAUSE;
+        let count = speaker.speak("Hello, world").count();
+        assert!(count > default.len(), "{} {}", count, default.len());
+
+        speaker.params.override_synth_flags = Some(espeak_rs_sys::espeakCHARS_AUTO);
+        let overridden: Vec<i16> = speaker.speak("Hello, world").collect();
+        assert_eq!(overridden, default);
+    }
+
+    #[test]
+    fn probes_ssml_support() {
+        let capabilities = ssml_capabilities();
+        assert!(!capabilities.version.is_empty());
+        assert!(capabilities.supports_mark);
+        assert!(capabilities.supports_break);
+        assert!(capabilities.supports("mark"));
+        assert!(!probe_ssml("<bogus-tag>", "</bogus-tag>"));
+    }
+
+    #[test]
+    fn first_word_slices_text() {
+        fn first_word(source: espeak_rs::SpeakerSource) -> Option<(usize, usize)> {
+            let mut first = None;
+            let source = source.with_callback(|evt| {
+                if let Event::Word(start, len) = evt {
+                    first.get_or_insert((start, len));
+                }
+            });
+            source.for_each(drop);
+            first
+        }
+
+        let speaker = Speaker::new();
+        for (text, ssml) in [
+            ("   Hello world", false),
+            ("\"Hello,\" she said", false),
+            ("<speak><s>Hello world</s></speak>", true),
+        ] {
+            let source = if ssml {
+                speaker.speak_ssml(text)
+            } else {
+                speaker.speak_plain(text)
+            };
+            let (start, len) = first_word(source).unwrap();
+            assert_eq!(&text[start..start + len], "Hello", "{:?}", text);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "dsp")]
+    fn chunked_wav_stream() {
+        let speaker = Speaker::new();
+        let expected = speaker.speak("Hello, world").count();
+
+        let bytes: Vec<u8> = speaker
+            .speak("Hello, world")
+            .into_chunked_wav_stream()
+            .flatten()
+            .collect();
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(u16::from_le_bytes([bytes[22], bytes[23]]), 1);
+        assert_eq!(
+            u32::from_le_bytes([bytes[24], bytes[25], bytes[26], bytes[27]]),
+            22050
+        );
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!((bytes.len() - 44) / 2, expected);
+    }
+
+    #[test]
+    #[cfg(feature = "dsp")]
+    fn dropping_wav_stream_stops_synthesis() {
+        let speaker = Speaker::new();
+        let long_text = "This sentence is repeated for a long time. ".repeat(1000);
+        let mut stream = speaker.speak(&long_text).into_chunked_wav_stream();
+        stream.next().unwrap();
+        stream.next().unwrap();
+        drop(stream);
+
+        // Synthesis holds espeak until it stops, so another utterance only
+        // finishes promptly if the dropped one was cancelled.
+        let start = Instant::now();
+        assert!(speaker.speak("Goodbye").count() > 0);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    #[cfg(feature = "dsp")]
+    fn gain_control_ducks_smoothly() {
+        fn rms(samples: &[i16]) -> f64 {
+            let sum: f64 = samples.iter().map(|s| (*s as f64).powi(2)).sum();
+            (sum / samples.len() as f64).sqrt()
+        }
+
+        let text = "Hello world. Goodbye world";
+        let speaker = Speaker::new();
+        let plain: Vec<i16> = speaker.speak(text).collect();
+        let half = plain.len() / 2;
+
+        let (tx, rx) = mpsc::channel::<f32>();
+        let mut ducked = Vec::<i16>::new();
+        for (i, sample) in speaker.speak(text).with_gain_control(rx).enumerate() {
+            if i == half {
+                tx.send(0.5).unwrap();
+            }
+            ducked.push(sample);
+        }
+        assert_eq!(ducked.len(), plain.len());
+        assert_eq!(&ducked[..half], &plain[..half]);
+        let ratio = rms(&ducked[half..]) / rms(&plain[half..]);
+        assert!((ratio - 0.5).abs() < 0.05, "ratio {}", ratio);
+
+        // Slam the gain between extremes; the applied gain must still ramp.
+        let (tx, rx) = mpsc::channel::<f32>();
+        let step = 1.0 / (22050.0 * GAIN_RAMP.as_secs_f64());
+        let mut last: Option<(usize, f64)> = None;
+        for (i, sample) in speaker.speak(text).with_gain_control(rx).enumerate() {
+            if i % 500 == 0 {
+                tx.send(if i % 1000 == 0 { 0.0 } else { 1.0 }).unwrap();
+            }
+            if plain[i].abs() < 2000 {
+                continue;
+            }
+            let gain = sample as f64 / plain[i] as f64;
+            if let Some((j, last_gain)) = last {
+                assert!((gain - last_gain).abs() <= (i - j) as f64 * step + 0.001);
+            }
+            last = Some((i, gain));
+        }
+    }
+
+    #[test]
+    fn nul_bytes_are_reported() {
+        let mut speaker = Speaker::new();
+        assert_eq!(
+            speaker.try_speak("Hello\0world").err(),
+            Some(EspeakError::InvalidText { nul_position: 5 })
+        );
+        assert!(speaker.speak("Hello\0world").count() > 0);
+
+        // Rewrites can bring NUL bytes in, at the word they replace.
+        speaker.add_pronunciation("nginx", "engine\0x");
+        assert_eq!(
+            speaker.try_speak("Run nginx").err(),
+            Some(EspeakError::InvalidText { nul_position: 4 })
+        );
+        speaker.params.pronunciations.clear();
+        speaker.params.url_policy = UrlPolicy::Placeholder(String::from("a\0link"));
+        assert_eq!(
+            speaker.try_speak("See https://example.com").err(),
+            Some(EspeakError::InvalidText { nul_position: 4 })
+        );
+        speaker.params.url_policy = UrlPolicy::ReadFull;
+
+        let mut events = Vec::<Event>::new();
+        let source = speaker
+            .try_speak("")
+            .unwrap()
+            .with_callback(|evt| events.push(evt));
+        source.for_each(drop);
+        assert_eq!(events.last(), Some(&Event::End));
+
+        speaker.set_voice(&Voice {
+            name: String::from("en\0us"),
+            identifier: String::from("gmw/en-US"),
+            age: 0,
+            gender: Gender::Male,
+            languages: Vec::new(),
+        });
+        assert_eq!(
+            speaker.try_speak("Hello").err(),
+            Some(EspeakError::InvalidVoiceName { nul_position: 2 })
+        );
+    }
+
+    #[test]
+    fn unknown_voice_is_reported() {
+        let mut speaker = Speaker::new();
+        speaker.set_voice(&Voice {
+            name: String::from("xx-does-not-exist"),
+            identifier: String::from("xx-does-not-exist"),
+            age: 0,
+            gender: Gender::Male,
+            languages: Vec::new(),
+        });
+        let mut source = speaker.speak("Hello, world");
+        assert_eq!(source.by_ref().count(), 0);
+        assert_eq!(
+            source.error(),
+            Some(&EspeakError::VoiceNotFound {
+                name: String::from("xx-does-not-exist")
+            })
+        );
+    }
+
+    #[test]
+    fn long_text_is_split() {
+        let mut text = String::new();
+        let mut n = 0;
+        while text.len() < 50_000 {
+            n += 1;
+            text.push_str(&format!("This is sentence number {}. ", n));
+        }
+        let last_sentence = text.trim_end().rfind("This").unwrap();
+
+        let mut last_word = 0usize;
+        let mut starts = 0usize;
+        let speaker = Speaker::new();
+        let source = speaker.speak(&text).with_callback(|evt| match evt {
+            Event::Word(start, _) => last_word = start,
+            Event::Start => starts += 1,
+            _ => (),
+        });
+        source.for_each(drop);
+        assert_eq!(starts, 1);
+        assert!(
+            last_word >= last_sentence,
+            "{} < {}",
+            last_word,
+            last_sentence
+        );
+    }
+
+    #[test]
+    fn long_unbroken_token_finishes() {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let text = "a".repeat(50_000);
+            let count = Speaker::new().speak(&text).count();
+            tx.send(count).unwrap();
+        });
+        assert!(rx.recv_timeout(Duration::from_secs(300)).is_ok());
+    }
+
+    #[test]
+    fn errors_are_std_errors() {
+        assert_eq!(try_list_voices().unwrap().len(), list_voices().len());
+
+        let error: Box<dyn std::error::Error> = Box::new(EspeakError::VoiceNotFound {
+            name: String::from("xx"),
+        });
+        assert_eq!(error.to_string(), "voice \"xx\" not found");
+    }
+
+    #[test]
+    fn events_are_broadcast() {
+        let speaker = Speaker::new();
+        let source = speaker.speak("Hello world. Goodbye world");
+        let bus = source.events_broadcast();
+        let subscribers: Vec<_> = (0..3)
+            .map(|_| {
+                let receiver = bus.subscribe();
+                thread::spawn(move || receiver.collect::<Vec<_>>())
+            })
+            .collect();
+        let slow = bus.subscribe_with_capacity(2);
+        drop(bus.subscribe());
+        let sample_count = source.count();
+
+        let expected = [
+            Event::Start,
+            Event::Sentence { start: 0, len: 12 },
+            Event::Word(0, 5),
+            Event::Word(6, 5),
+            Event::Sentence { start: 13, len: 13 },
+            Event::Word(13, 7),
+            Event::Word(21, 5),
+            Event::End,
+        ];
+        for subscriber in subscribers {
+            let received = subscriber.join().unwrap();
+            let events: Vec<Event> = received
+                .iter()
+                .map(|timed| timed.event.clone())
+                .filter(|event| !matches!(event, Event::Terminated { .. }))
+                .collect();
+            assert_eq!(events, expected);
+            let end = received.last().unwrap().offset.as_millis() as usize;
+            assert_within!(end, sample_count * 1000 / 22050, 1);
+        }
+
+        // The slow subscriber also got `Terminated`, and kept it and `End`.
+        assert_eq!(slow.dropped(), expected.len() as u64 - 1);
+        let kept: Vec<Event> = slow.map(|timed| timed.event).collect();
+        assert!(
+            matches!(kept[..], [Event::Terminated { .. }, Event::End]),
+            "{:?}",
+            kept
+        );
+    }
+
+    #[test]
+    fn url_policies() {
+        let text = "Read more at https://www.example.com/articles/2024/05/a-rather-long-slug?utm_source=newsletter&utm_medium=email today.";
+        let url = "https://www.example.com/articles/2024/05/a-rather-long-slug?utm_source=newsletter&utm_medium=email";
+        let url_start = text.find(url).unwrap();
+
+        let speak = |policy: UrlPolicy| {
+            let mut speaker = Speaker::new();
+            speaker.params.url_policy = policy;
+            let mut words = Vec::new();
+            let samples = speaker
+                .speak(text)
+                .with_callback(|event| {
+                    if let Event::Word(start, len) = event {
+                        words.push((start, len));
+                    }
+                })
+                .count();
+            for (start, len) in &words {
+                assert!(text.get(*start..*start + *len).is_some(), "{:?}", words);
+            }
+            assert_eq!(
+                words.last().map(|(start, _)| &text[*start..]),
+                Some("today.")
+            );
+            (samples, words)
+        };
+
+        let (full, _) = speak(UrlPolicy::ReadFull);
+        let (domain, domain_words) = speak(UrlPolicy::Domain);
+        let (placeholder, placeholder_words) =
+            speak(UrlPolicy::Placeholder(String::from("a link")));
+        let (skip, skip_words) = speak(UrlPolicy::Skip);
+        assert!(full > domain, "{} > {}", full, domain);
+        assert!(domain > placeholder, "{} > {}", domain, placeholder);
+        assert!(placeholder > skip, "{} > {}", placeholder, skip);
+
+        assert!(domain_words.contains(&(url_start, url.len())));
+        assert!(placeholder_words.contains(&(url_start, url.len())));
+        assert!(!skip_words.iter().any(|(start, _)| *start == url_start));
+    }
+
+    #[test]
+    fn long_paragraph_is_not_truncated() {
+        let mut text = String::new();
+        while text.len() < 5_000 {
+            text.push_str("The quick brown fox jumps over the lazy dog. ");
+        }
+        let mut speaker = Speaker::new();
+        // Hand espeak the whole paragraph in one call.
+        speaker.params.synth_text_budget = usize::MAX;
+        let mut words = 0usize;
+        speaker
+            .speak(&text)
+            .with_callback(|evt| {
+                if let Event::Word(_, _) = evt {
+                    words += 1;
+                }
+            })
+            .for_each(drop);
+        assert_eq!(words, text.split_whitespace().count());
+    }
+
+    #[test]
+    fn inline_phonemes() {
+        let speak = |text: &str| {
+            let mut speaker = Speaker::new();
+            speaker.params.allow_inline_phonemes = true;
+            let mut words = Vec::new();
+            let samples = speaker
+                .speak(text)
+                .with_callback(|event| {
+                    if let Event::Word(start, len) = event {
+                        words.push((start, len));
+                    }
+                })
+                .count();
+            (samples, words)
+        };
+
+        let text = "say [[h@'loU]] now";
+        let (escaped, words) = speak(text);
+        assert_eq!(words.len(), 3, "{:?}", words);
+        assert_eq!(&text[words[1].0..words[1].0 + words[1].1], "[[h@'loU]]");
+
+        let (plain, _) = speak("say hello now");
+        assert_within!(escaped, plain, plain / 5);
+    }
+
+    #[test]
+    fn synth_flags() {
+        let speak = |text: &str, flags: SynthFlags| {
+            let mut speaker = Speaker::new();
+            speaker.params.flags = flags;
+            let source = speaker.speak(text);
+            let sample_rate = source.sample_rate() as usize;
+            (source.count(), sample_rate)
+        };
+
+        let mut phonemes = SynthFlags::new();
+        phonemes.phonemes_input = true;
+        let (brackets, _) = speak("say [[h@'loU]] now", phonemes);
+        let (plain, _) = speak("say hello now", SynthFlags::new());
+        assert_within!(brackets, plain, plain / 5);
+
+        let mut end_pause = SynthFlags::new();
+        end_pause.end_pause = true;
+        let (paused, sample_rate) = speak("Hello world", end_pause);
+        let (unpaused, _) = speak("Hello world", SynthFlags::new());
+        assert!(
+            paused > unpaused + sample_rate / 20,
+            "{} {}",
+            paused,
+            unpaused
+        );
+    }
+
+    #[test]
+    fn params_are_validated() {
+        let mut speaker = Speaker::new();
+        speaker.params.rate = Some(500.into());
+        let expected = EspeakError::ParamOutOfRange {
+            name: "rate",
+            value: 500,
+            range: SpeakerParams::RATE_RANGE,
+        };
+        assert_eq!(speaker.params.validate(), Err(expected.clone()));
+        assert_eq!(speaker.try_speak("Hello").err(), Some(expected.clone()));
+
+        let mut source = speaker.speak("Hello");
+        assert_eq!(source.by_ref().count(), 0);
+        assert_eq!(source.error(), Some(&expected));
+
+        speaker.params.rate = Some((*SpeakerParams::RATE_RANGE.end()).into());
+        speaker.params.pitch = Some((-1).into());
+        assert!(matches!(
+            speaker.params.validate(),
+            Err(EspeakError::ParamOutOfRange { name: "pitch", .. })
+        ));
+    }
+
+    #[test]
+    fn ring_reads_never_block() {
+        let text = "Hello world. Goodbye world";
+        let expected = Speaker::new().speak(text).count();
+        let (producer, mut consumer) = Speaker::new()
+            .speak(text)
+            .into_ring(Duration::from_millis(50));
+
+        let mut buffer = [0i16; 64];
+        let mut total = 0;
+        let mut slowest = Duration::ZERO;
+        let mut events = Vec::new();
+        while !consumer.is_finished() {
+            let started = Instant::now();
+            total += consumer.read(&mut buffer);
+            slowest = slowest.max(started.elapsed());
+            while let Some((position, event)) = consumer.poll_event() {
+                assert!(position <= total as u64);
+                events.push(event);
+            }
+        }
+        while let Some((_, event)) = consumer.poll_event() {
+            events.push(event);
+        }
+
+        assert!(producer.is_finished());
+        assert_eq!(total, expected);
+        assert!(consumer.underruns() > 0);
+        assert_eq!(consumer.dropped_events(), 0);
+        assert!(slowest < Duration::from_millis(1), "{:?}", slowest);
+        assert_eq!(events.first(), Some(&Event::Start));
+        assert_eq!(events.last(), Some(&Event::End));
+    }
+
+    #[test]
+    fn phoneme_events() {
+        let phonemes = |enabled: bool| {
+            let mut speaker = Speaker::new();
+            speaker.params.phoneme_events = enabled;
+            let mut names = Vec::new();
+            speaker
+                .speak("hello")
+                .with_callback(|event| {
+                    if let Event::Phoneme { name, .. } = event {
+                        names.push(name);
+                    }
+                })
+                .for_each(drop);
+            names
+        };
+
+        let names = phonemes(true);
+        assert!(!names.is_empty());
+        assert!(names.iter().all(|name| !name.is_empty()), "{:?}", names);
+        assert!(phonemes(false).is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "dsp")]
+    fn voices_are_compared() {
+        let text = "Hello world";
+        let gap = Duration::from_millis(300);
+        let voices: Vec<Voice> = list_voices()
+            .into_iter()
+            .filter(|voice| ["roa/fr-CH", "gmw/en-US", "gmw/de"].contains(&&*voice.identifier))
+            .collect();
+        assert_eq!(voices.len(), 3);
+
+        let buffer = compare_voices(text, &voices, gap);
+        let marks: Vec<_> = buffer
+            .events
+            .iter()
+            .filter_map(|timed| match &timed.event {
+                Event::Mark(name) => Some((timed.offset, name.clone())),
+                _ => None,
+            })
+            .collect();
+        let names: Vec<_> = marks.iter().map(|(_, name)| name.clone()).collect();
+        let expected: Vec<_> = voices.iter().map(|voice| voice.name.clone()).collect();
+        assert_eq!(names, expected);
+        assert!(marks.windows(2).all(|pair| pair[0].0 < pair[1].0));
+
+        let rate = buffer.sample_rate as usize;
+        let parts: usize = voices
+            .iter()
+            .map(|voice| {
+                let mut speaker = Speaker::new();
+                speaker.set_voice(voice);
+                speaker.speak(&format!("Voice: {}", voice.name)).count()
+                    + speaker.speak(text).count()
+            })
+            .sum();
+        let gaps = (voices.len() * 2 - 1) * gap.as_millis() as usize * rate / 1000;
+        assert_within!(buffer.samples.len(), parts + gaps, rate / 100);
+    }
+
+    #[test]
+    fn ssml_marks() {
+        let mut events = Vec::new();
+        Speaker::new()
+            .speak_ssml("Hello <mark name='mid'/> world")
+            .with_callback(|event| match event {
+                Event::Word(..) | Event::Mark(_) => events.push(event),
+                _ => (),
+            })
+            .for_each(drop);
+
+        assert_eq!(events.len(), 3, "{:?}", events);
+        assert!(matches!(events[0], Event::Word(..)));
+        assert_eq!(events[1], Event::Mark(String::from("mid")));
+        assert!(matches!(events[2], Event::Word(..)));
+    }
+
+    #[test]
+    fn effective_text() {
+        let text = "Say \\[[ and see https://example.com/page, then hello.";
+        let mut speaker = Speaker::new();
+        speaker.params.allow_inline_phonemes = true;
+        speaker.params.url_policy = UrlPolicy::Domain;
+
+        let report = dry_run_transform(text, &speaker);
+        assert_eq!(
+            report.effective_text,
+            "Say [ [ and see link to example dot com, then hello."
+        );
+        assert_eq!(report.applied_stages, ["inline_phonemes", "urls"]);
+
+        let original = text.find("hello").unwrap();
+        let effective = report.effective_text.find("hello").unwrap();
+        assert_eq!(report.offset_map.to_effective(original), effective);
+        assert_eq!(report.offset_map.to_original(effective), original);
+        let url = text.find("https").unwrap();
+        let link = report.effective_text.find("link").unwrap();
+        assert_eq!(report.offset_map.to_effective(url + 4), link);
+
+        let source = speaker.speak(text);
+        assert_eq!(source.effective_text(), report.effective_text);
+        assert!(dry_run_transform(text, &Speaker::new())
+            .applied_stages
+            .is_empty());
+    }
+
+    #[test]
+    fn timed_callback_offsets() {
+        let read = Cell::new(0usize);
+        let mut events = Vec::new();
+        let source = Speaker::new()
+            .speak("Hello world. Goodbye world")
+            .with_timed_callback(|timed| events.push((read.get(), timed)));
+        let rate = source.sample_rate() as usize;
+        for _ in source {
+            read.set(read.get() + 1);
+        }
+
+        assert_eq!(events.first().map(|(_, t)| &t.event), Some(&Event::Start));
+        assert_eq!(events.last().map(|(_, t)| &t.event), Some(&Event::End));
+        assert!(events.windows(2).all(|w| w[0].1.offset <= w[1].1.offset));
+        for (at_sample, timed) in &events {
+            let counted = (*at_sample * 1000 / rate) as u128;
+            assert_within!(timed.offset.as_millis(), counted, 2);
+        }
+    }
+
+    #[test]
+    fn end_carries_audio_length() {
+        let mut speaker = Speaker::new();
+        speaker.params.synth_text_budget = 16;
+        let mut terminated = Vec::new();
+        let mut source = speaker
+            .speak("Hello world. Goodbye world. Hello again.")
+            .with_timed_callback(|timed| {
+                if let Event::Terminated { audio_ms } = timed.event {
+                    terminated.push(audio_ms);
+                }
+            });
+        let samples = source.by_ref().count();
+        drop(source);
+
+        assert_eq!(terminated.len(), 1, "{:?}", terminated);
+        assert_within!(terminated[0] as usize, samples * 1000 / 22050, 2);
+
+        let mut source = Speaker::new().speak("Hello");
+        assert!(!source.completed());
+        source.by_ref().for_each(drop);
+        assert!(source.completed());
+    }
+
+    #[test]
+    #[cfg(feature = "dsp")]
+    fn duration_breakdown() {
+        let breakdown = Speaker::new()
+            .speak("Hello world. Goodbye world")
+            .duration_breakdown()
+            .unwrap();
+        assert_eq!(breakdown.sentences.len(), 2);
+        assert!(breakdown.leading_silence > Duration::ZERO);
+        let parts = breakdown.leading_silence
+            + breakdown.sentences.iter().sum::<Duration>()
+            + breakdown.trailing_silence;
+        assert_within!(
+            parts.as_micros(),
+            breakdown.total.as_micros(),
+            Duration::from_millis(1).as_micros()
+        );
+    }
+
+    #[test]
+    fn non_ascii_words() {
+        let words = |voice: &str, text: &str| {
+            let voice = list_voices()
+                .into_iter()
+                .find(|v| v.identifier == voice)
+                .unwrap();
+            let mut speaker = Speaker::new();
+            speaker.set_voice(&voice);
+            let mut events = Vec::new();
+            speaker
+                .speak(text)
+                .with_callback(|event| {
+                    if let Event::Word(..) = event {
+                        events.push(event);
+                    }
+                })
+                .for_each(drop);
+            events
+        };
+
+        let text = "مرحبا بالعالم الجميل";
+        let events = words("sem/ar", text);
+        let slices: Vec<_> = events.iter().map(|e| e.word_in(text)).collect();
+        assert_eq!(slices, [Some("مرحبا"), Some("بالعالم"), Some("الجميل")]);
+        assert_eq!(events[1].char_range(text), Some(6..13));
+
+        let text = "I 💖 crabs 🦀 and Rust";
+        let slices: Vec<_> = words("gmw/en-US", text)
+            .iter()
+            .map(|e| e.word_in(text).unwrap())
+            .filter(|word| word.is_ascii())
+            .collect();
+        assert_eq!(slices, ["I", "crabs", "and", "Rust"]);
+    }
+
+    #[test]
+    fn word_positions_agree() {
+        let text = "Hello café, 東京 and 🦀 crabs naïve";
+        let chars: Vec<char> = text.chars().collect();
+        let utf16: Vec<u16> = text.encode_utf16().collect();
+        let (tx, rx) = mpsc::channel();
+        let mut source = Speaker::new()
+            .speak(text)
+            .with_callback(move |event| tx.send(event).unwrap());
+        source.by_ref().for_each(drop);
+        let events: Vec<Event> = rx.try_iter().collect();
+        let mut words = 0;
+        for event in &events {
+            let Some(position) = source.position(event) else {
+                continue;
+            };
+            let word = source.resolve(event).unwrap();
+            assert_eq!(&text[position.byte.clone()], word);
+            assert_eq!(
+                chars[position.char.clone()].iter().collect::<String>(),
+                word
+            );
+            assert_eq!(String::from_utf16(&utf16[position.utf16]).unwrap(), word);
+            if matches!(event, Event::Word(..)) {
+                assert_eq!(event.char_range(text), Some(position.char));
+                words += 1;
+            }
+        }
+        assert!(words >= 5, "{:?}", events);
+        assert_eq!(source.position(&Event::End), None);
+    }
+
+    #[test]
+    fn words_are_resolved() {
+        let mut words = Vec::new();
+        let mut iter = Speaker::new()
+            .speak("Hello wörld. Goodbye")
+            .iter_audio_and_events();
+        while let Some((_, events)) = iter.next() {
+            for event in events.unwrap_or_default() {
+                if let Some(word) = iter.resolve(&event) {
+                    words.push(String::from(word));
+                }
+            }
+        }
+        assert_eq!(
+            words,
+            ["Hello wörld.", "Hello", "wörld", "Goodbye", "Goodbye"]
+        );
+
+        let mut words = Vec::new();
+        let mut iter = Speaker::new()
+            .speak_ssml("<speak>Salt <emphasis>&amp; pepper</emphasis></speak>")
+            .iter_audio_and_events();
+        let mut sentences = Vec::new();
+        while let Some((_, events)) = iter.next() {
+            for event in events.unwrap_or_default() {
+                let resolved = iter.resolve(&event).map(String::from);
+                match event {
+                    Event::Word(..) => words.extend(resolved),
+                    Event::Sentence { .. } => sentences.extend(resolved),
+                    _ => assert_eq!(resolved, None),
+                }
+            }
+        }
+        assert_eq!(words.first().map(String::as_str), Some("Salt"));
+        assert_eq!(words.last().map(String::as_str), Some("pepper"));
+        assert_eq!(sentences, ["Salt <emphasis>&amp; pepper"]);
+    }
+
+    #[test]
+    fn synthesis_is_paced() {
+        let mut speaker = Speaker::new();
+        speaker.params.synth_ahead = Some(Duration::from_secs(1));
+        let mut source = speaker.speak(&"Hello world. ".repeat(15));
+        let rate = source.sample_rate() as usize;
+        // A second ahead, plus up to one chunk from espeak.
+        let limit = rate + rate / 5;
+
+        // Nothing is consumed yet, as if playback were paused.
+        thread::sleep(Duration::from_millis(300));
+        let paused = source.backlog();
+        assert!(paused.producer_blocked);
+        assert!(paused.samples_queued <= limit, "{:?}", paused);
+
+        // Consume at ten times real time.
+        let step = rate / 10;
+        let mut most_queued = 0;
+        while source.by_ref().take(step).count() == step {
+            most_queued = most_queued.max(source.backlog().samples_queued);
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(most_queued > 0);
+        assert!(most_queued <= limit, "{} > {}", most_queued, limit);
+    }
+
+    #[test]
+    fn events_without_audio() {
+        let text = "The quick brown fox jumps over the lazy dog. ".repeat(20);
+        let speaker = Speaker::new();
+
+        let started = Instant::now();
+        let mut by_sample = Vec::new();
+        let mut iter = speaker.speak(&text).iter_audio_and_events();
+        for (_, events) in &mut iter {
+            by_sample.extend(events.unwrap_or_default());
+        }
+        let sample_time = started.elapsed();
+
+        let started = Instant::now();
+        let timed: Vec<_> = speaker.speak(&text).into_events().collect();
+        let events_time = started.elapsed();
+
+        let events: Vec<_> = timed.iter().map(|timed| timed.event.clone()).collect();
+        assert_eq!(events.len(), by_sample.len() + 1);
+        assert_eq!(events[..by_sample.len()], by_sample[..]);
+        assert_eq!(events.last(), Some(&Event::End));
+        assert!(timed.windows(2).all(|w| w[0].offset <= w[1].offset));
+        assert!(
+            events_time < sample_time,
+            "{:?} >= {:?}",
+            events_time,
+            sample_time
+        );
+    }
+
+    #[test]
+    fn table_cells() {
+        let table = TableSpeech::new(["CPU", "Memory"], [[87, 54], [12, 3]])
+            .with_cell_template("{header} {value} percent");
+        assert_eq!(
+            table.text(),
+            "CPU 87 percent, Memory 54 percent. CPU 12 percent, Memory 3 percent."
+        );
+
+        let cells: Vec<_> = Speaker::new()
+            .speak_table(&table)
+            .into_events()
+            .filter(|timed| matches!(timed.event, Event::Cell { .. }))
+            .collect();
+        let order: Vec<_> = cells.iter().map(|timed| timed.event.clone()).collect();
+        assert_eq!(
+            order,
+            [
+                Event::Cell { row: 0, col: 0 },
+                Event::Cell { row: 0, col: 1 },
+                Event::Cell { row: 1, col: 0 },
+                Event::Cell { row: 1, col: 1 },
+            ]
+        );
+        assert!(cells.windows(2).all(|w| w[0].offset < w[1].offset));
+    }
+
+    #[test]
+    fn callback_stops_speech() {
+        let text = "Stop me after the first word, long before this sentence ends.";
+        let full = Speaker::new().speak(text).count();
+
+        let mut events = Vec::new();
+        let mut source = Speaker::new().speak(text).with_callback_control(|event| {
+            let word = matches!(event, Event::Word(..));
+            events.push(event);
+            if word {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        let played = source.by_ref().count();
+        assert_eq!(source.next(), None);
+        assert_eq!(source.error(), None);
+        drop(source);
+
+        assert!(played * 10 < full, "{} of {} samples played", played, full);
+        assert!(matches!(events.last(), Some(Event::Word(..))));
+        assert!(!events.contains(&Event::End));
+    }
+
+    #[test]
+    fn adapters_compose() {
+        let text = "Hello world. Goodbye";
+        let mut called = Vec::new();
+        let mut yielded = Vec::new();
+        let samples = Speaker::new()
+            .speak(text)
+            .with_callback(|event| called.push(event))
+            .iter_audio_and_events()
+            .inspect(|(_, events)| yielded.extend(events.iter().flatten().cloned()))
+            .count();
+        // The end comes after the last sample, so only the callback sees it.
+        assert_eq!(called.pop(), Some(Event::End));
+        assert_eq!(called, yielded);
+
+        let mut called = Vec::new();
+        let mut yielded = Vec::new();
+        let iter = Speaker::new()
+            .speak(text)
+            .iter_audio_and_events()
+            .with_callback(|event| called.push(event));
+        for (_, events) in iter {
+            yielded.extend(events.into_iter().flatten());
+        }
+        // The end comes after the last sample, so only the callback sees it.
+        assert_eq!(called.pop(), Some(Event::End));
+        assert_eq!(called, yielded);
+
+        let mut source = Speaker::new().speak(text).with_callback(|_| ());
+        let head = source.by_ref().take(1000).count();
+        let mut iter = source.into_inner().iter_audio_and_events();
+        let middle = iter.by_ref().take(1000).count();
+        let tail = iter.into_inner().count();
+        assert_eq!(head + middle + tail, samples);
+    }
+
+    #[test]
+    fn capitals_read_as_words() {
+        let text = "READ THIS IMPORTANT NOTICE from NASA";
+        let mut speaker = Speaker::new();
+        speaker.params.caps_policy = CapsPolicy::LowercaseLongRuns(4);
+        let source = speaker.speak(text);
+        assert_eq!(
+            source.effective_text(),
+            "read this important notice from NASA"
+        );
+        let mut words = Vec::new();
+        let capitals = source
+            .with_callback(|event| {
+                if let Event::Word(start, len) = event {
+                    words.push(&text[start..start + len]);
+                }
+            })
+            .count();
+        assert_eq!(words, text.split(' ').collect::<Vec<_>>());
+
+        let lowercase = Speaker::new()
+            .speak("read this important notice from NASA")
+            .count();
+        assert_within!(capitals, lowercase, lowercase / 20);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn events_serialize() {
+        let events: Vec<TimedEvent> = Speaker::new().speak("Hello world").into_events().collect();
+        let json = serde_json::to_string(&events).unwrap();
+        assert!(json.contains(r#""event":{"Word":[0,5]}"#), "{}", json);
+        let parsed: Vec<TimedEvent> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, events);
+
+        let voice = &list_voices()[0];
+        let json = serde_json::to_value(voice).unwrap();
+        assert_eq!(json["name"], voice.name.as_str());
+        let parsed: Voice = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.identifier, voice.identifier);
+        assert_eq!(parsed.gender, voice.gender);
+    }
+
+    #[test]
+    #[cfg(feature = "dsp")]
+    fn recordings_replay() {
+        let source = Speaker::new().speak("Hello world. Goodbye world");
+        let (buffer, recording) = EventRecording::capture(source);
+        assert_eq!(buffer.events, recording.events);
+        assert_eq!(buffer.duration(), recording.duration);
+        let words: Vec<_> = recording
+            .events
+            .iter()
+            .filter_map(|timed| recording.resolve(&timed.event))
+            .collect();
+        assert!(words.contains(&"Goodbye"));
+
+        #[cfg(feature = "serde")]
+        let recording = EventRecording::from_json(&recording.to_json()).unwrap();
+
+        let started = Instant::now();
+        let replayed: Vec<_> = recording
+            .replay(2.0)
+            .map(|timed| (started.elapsed(), timed))
+            .collect();
+        let events: Vec<_> = replayed.iter().map(|(_, timed)| timed.clone()).collect();
+        assert_eq!(events, recording.events);
+        for pair in replayed.windows(2) {
+            let (first_at, first) = &pair[0];
+            let (second_at, second) = &pair[1];
+            let gap = (second.offset - first.offset).as_millis() as i64 / 2;
+            let replayed_gap = (*second_at - *first_at).as_millis() as i64;
+            assert_within!(replayed_gap, gap, 20);
+        }
+
+        let mut replay = recording.replay(100.0);
+        let seek = recording.events[recording.events.len() / 2].offset;
+        replay.seek(seek);
+        assert!(replay.all(|timed| timed.offset >= seek));
+    }
+
+    #[test]
+    fn synthesis_keys() {
+        let text = "Hello <b>world</b>";
+        let mut speaker = Speaker::new();
+        let key = synthesis_key(&speaker, text);
+        assert_eq!(synthesis_key(&Speaker::new(), text), key);
+        assert_ne!(synthesis_key(&speaker, "Hello world"), key);
+
+        speaker.params.rate = Some(200.into());
+        let faster = synthesis_key(&speaker, text);
+        assert_ne!(faster, key);
+        speaker.params.rate = Some(201.into());
+        assert_ne!(synthesis_key(&speaker, text), faster);
+        speaker.params.rate = None;
+        assert_eq!(synthesis_key(&speaker, text), key);
+
+        speaker.params.flags.ssml = true;
+        assert_ne!(synthesis_key(&speaker, text), key);
+
+        speaker.params.flags.ssml = false;
+        speaker.params.channel_capacity = Some(1);
+        assert_eq!(synthesis_key(&speaker, text), key);
+    }
+
+    #[test]
+    fn speakers_are_built() {
+        let english = list_voices()
+            .into_iter()
+            .find(|voice| voice.identifier == "gmw/en-GB-x-rp")
+            .unwrap();
+        let speaker = Speaker::builder()
+            .voice(&english)
+            .rate(Rate::wpm(180).unwrap())
+            .pitch(50)
+            .build()
+            .unwrap();
+        assert_eq!(speaker.params.rate, Some(180.into()));
+        assert_eq!(speaker.params.pitch, Some(50.into()));
+        assert!(speaker.speak("Hello").count() > 0);
+
+        let speaker = Speaker::builder().voice_name("en-GB").ssml(true).build();
+        assert!(speaker.unwrap().speak("Hello <break/> world").count() > 0);
+    }
+
+    #[test]
+    fn builder_validates() {
+        match Speaker::builder().pitch(150).build() {
+            Err(EspeakError::ParamOutOfRange { name, value, .. }) => {
+                assert_eq!((name, value), ("pitch", 150))
+            }
+            other => panic!("expected ParamOutOfRange, got {:?}", other.err()),
+        }
+        match Speaker::builder().voice_name("no-such-voice").build() {
+            Err(EspeakError::VoiceNotFound { name }) => assert_eq!(name, "no-such-voice"),
+            other => panic!("expected VoiceNotFound, got {:?}", other.err()),
+        }
+        assert!(matches!(
+            Speaker::builder().voice_name("en\0").build(),
+            Err(EspeakError::InvalidVoiceName { nul_position: 2 })
+        ));
+    }
+
+    #[test]
+    fn punctuation_is_read() {
+        let count = |punctuation, chars: Option<&str>| {
+            let mut speaker = Speaker::new();
+            speaker.params.punctuation = Some(punctuation);
+            speaker.params.punctuation_chars = chars.map(String::from);
+            speaker.speak("Hello, world!").count()
+        };
+        let none = count(Punctuation::None, None);
+        let all = count(Punctuation::All, None);
+        assert!(all > none * 3 / 2, "{} vs {}", all, none);
+        let some = count(Punctuation::Some, Some(","));
+        assert!(some > none && some < all, "{} {} {}", none, some, all);
+
+        assert_eq!(
+            Punctuation::from_raw(Punctuation::All.as_raw()),
+            Some(Punctuation::All)
+        );
+        assert_eq!(Punctuation::from_raw(7), None);
+    }
+
+    #[test]
+    fn rates_are_checked() {
+        for wpm in [30, 79, 451, -1] {
+            let error = Rate::try_from(wpm).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("rate of {} is outside 80..=450", wpm)
+            );
+        }
+        assert!(Rate::wpm(1000).is_err());
+        assert_eq!(Rate::wpm(175).unwrap().as_wpm(), 175);
+
+        let count = |rate: Rate| {
+            let speaker = Speaker::builder().rate(rate).build().unwrap();
+            speaker.speak("Hello world, how are you today").count()
+        };
+        let slowest = count(Rate::wpm(80).unwrap());
+        assert_eq!(count(Rate::clamped(30)), slowest);
+        assert_eq!(count(Rate::clamped(i32::MIN)), slowest);
+        let fastest = count(Rate::wpm(450).unwrap());
+        assert_eq!(count(Rate::clamped(1000)), fastest);
+        assert!(slowest > fastest * 2);
+    }
+
+    #[test]
+    fn capitals_are_announced() {
+        let count = |capitals| {
+            let mut speaker = Speaker::new();
+            speaker.params.capitals = Some(capitals);
+            speaker.speak("ABC").count()
+        };
+        let off = count(Capitals::Off);
+        let spelling = count(Capitals::Spelling);
+        assert!(spelling > off * 3 / 2, "{} vs {}", spelling, off);
+
+        for capitals in [Capitals::SoundIcon, Capitals::PitchRaise(20)] {
+            assert_eq!(Capitals::from_raw(capitals.as_raw()), Some(capitals));
+        }
+        assert_eq!(Capitals::from_raw(-1), None);
+        let mut speaker = Speaker::new();
+        speaker.params.capitals = Some(Capitals::PitchRaise(2));
+        assert!(matches!(
+            speaker.try_speak("ABC"),
+            Err(EspeakError::ParamOutOfRange {
+                name: "capitals",
+                value: 2,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn long_lines_are_broken() {
+        let words = ["server", "request", "handled", "status", "okay", "latency"];
+        let text: String = words
+            .iter()
+            .cycle()
+            .take(90)
+            .copied()
+            .collect::<Vec<_>>()
+            .join(" ");
+        assert!(text.len() >= 600);
+        let sentences = |line_length| {
+            let mut speaker = Speaker::new();
+            speaker.params.line_length = line_length;
+            let mut sentences = 0;
+            speaker
+                .speak(&text)
+                .with_callback(|event| {
+                    if let Event::Sentence { .. } = event {
+                        sentences += 1;
+                    }
+                })
+                .for_each(drop);
+            sentences
+        };
+        assert_eq!(sentences(None), 1);
+        assert!(sentences(Some(60)) > 1);
+    }
+
+    #[test]
+    fn event_invariants_hold_across_voices() {
+        let sentences = [
+            ("en", "The weather is lovely today."),
+            ("ar", "الطقس جميل اليوم."),
+            ("cmn", "今天天气很好。"),
+            ("ja", "今日はいい天気です。"),
+            ("hi", "आज मौसम बहुत अच्छा है।"),
+            ("fi", "Tänään on kaunis sää."),
+            ("de", "Das Wetter ist heute schön."),
+            ("fr", "Il fait très beau aujourd'hui."),
+            ("ru", "Сегодня прекрасная погода."),
+            ("tr", "Bugün hava çok güzel."),
+        ];
+        for (voice, text) in sentences {
+            let speak = || SpeakerSource::new(text, voice, SpeakerParams::new());
+            assert!(speak().count() > 0, "{}: no audio", voice);
+            let timed: Vec<_> = speak().into_events().collect();
+            let events: Vec<_> = timed.iter().map(|timed| &timed.event).collect();
+            let count = |f: fn(&Event) -> bool| events.iter().filter(|e| f(e)).count();
+            assert_eq!(count(|e| *e == Event::Start), 1, "{}: {:?}", voice, events);
+            assert_eq!(count(|e| *e == Event::End), 1, "{}: {:?}", voice, events);
+            assert_eq!(events.last(), Some(&&Event::End), "{}", voice);
+            assert!(
+                count(|e| matches!(e, Event::Sentence { .. })) >= 1,
+                "{}",
+                voice
+            );
+            assert!(count(|e| matches!(e, Event::Word(..))) >= 1, "{}", voice);
+            assert!(
+                timed.windows(2).all(|w| w[0].offset <= w[1].offset),
+                "{}: {:?}",
+                voice,
+                timed
+            );
+
+            let mut last_start = 0;
+            for event in events {
+                let Event::Word(start, len) = *event else {
+                    continue;
+                };
+                assert!(
+                    text.get(start..start + len).is_some(),
+                    "{}: word {}+{} in {} bytes",
+                    voice,
+                    start,
+                    len,
+                    text.len()
+                );
+                assert!(start >= last_start, "{}: {} < {}", voice, start, last_start);
+                last_start = start;
+            }
+        }
+    }
+
+    #[test]
+    fn sentences_are_spoken_separately() {
+        let text = "Dr. Smith arrived late... but nobody minded. \
+            Then the meeting began! Was it over?";
+        let speaker = Speaker::new();
+        let sentences = speaker.speak_sentences(text);
+        let texts: Vec<_> = sentences.iter().map(|(text, _)| text.as_str()).collect();
+        assert_eq!(
+            texts,
+            [
+                "Dr. Smith arrived late... but nobody minded.",
+                "Then the meeting began!",
+                "Was it over?"
+            ]
+        );
+
+        let mut starts = Vec::new();
+        speaker
+            .speak_plain(text)
+            .with_callback(|event| {
+                if let Event::Sentence { start, len } = event {
+                    starts.push(&text[start..start + len]);
+                }
+            })
+            .for_each(drop);
+        assert_eq!(starts, texts);
+
+        let whole = speaker.speak_plain(text).count() as f64;
+        let separate: usize = sentences.into_iter().map(|(_, s)| s.count()).sum();
+        let ratio = separate as f64 / whole;
+        assert!((0.85..1.2).contains(&ratio), "{}", ratio);
+    }
+
+    #[test]
+    fn segments_include_silence() {
+        let speaker = Speaker::new();
+        let sample_rate = speaker.speak("").sample_rate() as f64;
+        let seconds = |source: espeak_rs::SpeakerSource| source.count() as f64 / sample_rate;
+        let parts = seconds(speaker.speak("Your code is")) + seconds(speaker.speak("1 2 3 4"));
+
+        let pause = Duration::from_millis(800);
+        let (tx, rx) = mpsc::channel();
+        let source = speaker
+            .speak_segments(&[
+                Segment::Text("Your code is"),
+                Segment::Silence(pause),
+                Segment::Text(" 1 2 3 4"),
+                Segment::Silence(pause),
+            ])
+            .with_timed_callback(move |timed| tx.send(timed).unwrap());
+        let total = source.count() as f64 / sample_rate;
+        let expected = parts + 2.0 * pause.as_secs_f64();
+        assert!(
+            (total - expected).abs() < 0.1 * expected,
+            "{} != {}",
+            total,
+            expected
+        );
+
+        let text = "Your code is 1 2 3 4";
+        let events: Vec<_> = rx.try_iter().collect();
+        let first_digit = events
+            .iter()
+            .find_map(|timed| match timed.event {
+                Event::Word(start, len) if &text[start..start + len] == "1" => Some(timed.offset),
+                _ => None,
+            })
+            .unwrap();
+        assert!(first_digit > pause, "{:?}", events);
+        assert_eq!(events.iter().filter(|t| t.event == Event::End).count(), 1);
+        let Some(Event::Terminated { audio_ms }) = events
+            .iter()
+            .map(|timed| timed.event.clone())
+            .find(|event| matches!(event, Event::Terminated { .. }))
+        else {
+            panic!("{:?}", events);
+        };
+        assert_within!(audio_ms as u64, (total * 1000.0) as u64, 50);
+    }
+
+    #[test]
+    fn unsupported_ssml_is_sanitized() {
+        let words = |speaker: &Speaker, text: &str| {
+            let mut words = Vec::new();
+            let samples = speaker
+                .speak_ssml(text)
+                .with_callback(|event| {
+                    if let Event::Word(..) = event {
+                        words.push(event.word_in(text).unwrap().to_string());
+                    }
+                })
+                .count();
+            assert!(samples > 0);
+            words
+        };
+
+        let input = "<speak>Good <voice gender=\"female\">morning</voice> all</speak>";
+        let mut capabilities = ssml_capabilities();
+        capabilities.supported_tags.retain(|tag| tag != "voice");
+        let (sanitized, removed) = sanitize_ssml(input, &capabilities);
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].name, "voice");
+        let speaker = Speaker::new();
+        let spoken = words(&speaker, &sanitized);
+        assert!(!spoken.is_empty());
+        assert!(
+            spoken.iter().all(|word| !word.contains(['<', '>'])),
+            "{:?}",
+            spoken
+        );
+
+        // Applied on the fly, positions still refer to the input.
+        let input = "<speak>Good <bogus-tag level=\"2\">morning</bogus-tag> all</speak>";
+        let mut speaker = Speaker::new();
+        speaker.params.sanitize_ssml = true;
+        assert_eq!(words(&speaker, input), ["Good", "morning", "all"]);
+    }
+
+    #[test]
+    fn streamed_fragments_are_spoken_as_words() {
+        let (tx, rx) = mpsc::channel();
+        let mut words = Vec::new();
+        let mut source = Speaker::new().speak_stream(rx).with_callback(|event| {
+            if let Event::Word(start, len) = event {
+                words.push(start..start + len);
+            }
+        });
+        for fragment in ["Hel", "lo wor", "ld. Bye."] {
+            tx.send(String::from(fragment)).unwrap();
+        }
+        drop(tx);
+        source.by_ref().for_each(drop);
+        let source = source.into_inner();
+        assert!(source.error().is_none(), "{:?}", source.error());
+        let text = source.effective_text();
+        assert_eq!(text, "Hello world. Bye.");
+        let words: Vec<_> = words.into_iter().map(|range| &text[range]).collect();
+        assert_eq!(words, ["Hello", "world", "Bye"]);
+    }
+
+    #[test]
+    fn numbers_can_be_read_as_digits() {
+        let text = "The code is 1000000.";
+        let mut speaker = Speaker::new();
+        let mut words = Vec::new();
+        let as_number = speaker.speak(text).count();
+        speaker.params.say_digits = true;
+        let as_digits = speaker
+            .speak(text)
+            .with_callback(|event| {
+                if let Event::Word(start, len) = event {
+                    words.push(&text[start..start + len]);
+                }
+            })
+            .count();
+        // "one zero zero zero zero zero zero" rather than "one million".
+        assert!(as_digits > as_number * 3 / 2, "{} {}", as_digits, as_number);
+        assert_eq!(
+            words,
+            ["The", "code", "is", "1", "0", "0", "0", "0", "0", "0"]
+        );
+    }
+
+    #[test]
+    fn single_characters_start_quickly() {
+        let speaker = Speaker::new();
+        speaker.speak("a").for_each(drop);
+        let time_to_first_sample = |source: SpeakerSource| {
+            let start = Instant::now();
+            let sample = source.into_iter().next();
+            assert!(sample.is_some());
+            start.elapsed()
+        };
+        let mut as_char = Duration::MAX;
+        let mut as_text = Duration::MAX;
+        for _ in 0..5 {
+            let started = Instant::now();
+            let source = speaker.speak_char('a');
+            as_char = as_char.min(started.elapsed() + time_to_first_sample(source));
+            let started = Instant::now();
+            let source = speaker.speak("a");
+            as_text = as_text.min(started.elapsed() + time_to_first_sample(source));
+        }
+        assert!(as_char < as_text, "{:?} {:?}", as_char, as_text);
+
+        let mut words = Vec::new();
+        let source = speaker.speak_char('é').with_callback(|event| {
+            if let Event::Word(start, len) = event {
+                words.push((start, len));
+            }
+        });
+        assert!(source.filter(|sample| *sample != 0).count() > 0);
+        assert!(
+            words.iter().all(|word| *word == (0, 'é'.len_utf8())),
+            "{:?}",
+            words
+        );
+
+        // Outside the BMP, which a 16-bit wchar_t can't hold.
+        match speaker.try_speak_char('😀') {
+            Ok(source) => assert!(source.filter(|sample| *sample != 0).count() > 0),
+            Err(error) => assert!(matches!(error, EspeakError::UnsupportedChar { .. })),
+        }
+        assert!(matches!(
+            speaker.try_speak_char('\0'),
+            Err(EspeakError::InvalidText { .. })
+        ));
+    }
+
+    #[test]
+    fn foreign_words_have_foreign_phonemes() {
+        let speaker = Speaker::new();
+        let stats = speaker
+            .word_stats("The Zeitgeist of the time was hopeful.")
+            .unwrap();
+        let words: Vec<_> = stats.iter().map(|stat| stat.word.as_str()).collect();
+        assert_eq!(
+            words,
+            ["The", "Zeitgeist", "of", "the", "time", "was", "hopeful"]
+        );
+        assert!(stats.iter().all(|stat| stat.phonemes > 0), "{:?}", stats);
+        let zeitgeist = stats[1].foreign_phonemes;
+        assert!(
+            stats.iter().all(|stat| stat.foreign_phonemes <= zeitgeist),
+            "{:?}",
+            stats
+        );
+
+        // The English voice switches to Russian for Cyrillic text.
+        let stats = speaker.word_stats("The word Москва means Moscow.").unwrap();
+        let moscow = stats.iter().find(|stat| stat.word == "Москва").unwrap();
+        assert!(moscow.foreign_phonemes > 0, "{:?}", stats);
+        assert_eq!(stats[0].foreign_phonemes, 0, "{:?}", stats);
+    }
+
+    #[test]
+    fn pronunciations_keep_original_offsets() {
+        let text = "Run nginx, PostgreSQL and Postgres on my server.";
+        let mut speaker = Speaker::new();
+        speaker.add_pronunciation("nginx", "engine x");
+        speaker.add_pronunciation("PostgreSQL", "post gress Q L");
+        speaker.add_pronunciation("postgres", "post gress");
+        speaker.add_pronunciation("serve", "sirv");
+        let mut words = Vec::new();
+        speaker
+            .speak(text)
+            .with_callback(|event| {
+                if let Event::Word(start, len) = event {
+                    words.push(&text[start..start + len]);
+                }
+            })
+            .for_each(drop);
+        words.dedup();
+        assert_eq!(
+            words,
+            [
+                "Run",
+                "nginx",
+                "PostgreSQL",
+                "and",
+                "Postgres",
+                "on",
+                "my",
+                "server"
+            ]
+        );
+        let report = dry_run_transform(text, &speaker);
+        assert_eq!(
+            report.effective_text,
+            "Run engine x, post gress Q L and post gress on my server."
+        );
+    }
+
+    #[test]
+    fn estimated_durations_match_synthesis() {
+        let text = "Estimates are handy for progress bars. They should be close.";
+        for rate in [None, Some(ParamValue::Relative(200))] {
+            let mut speaker = Speaker::new();
+            speaker.params.rate = rate;
+            let source = speaker.speak(text);
+            let sample_rate = source.sample_rate() as f64;
+            let counted = Duration::from_secs_f64(source.count() as f64 / sample_rate);
+            let estimate = speaker.estimate_duration(text);
+            let difference = estimate.as_secs_f64() - counted.as_secs_f64();
+            assert!(difference.abs() < 0.05, "{:?} {:?}", estimate, counted);
+
+            let fast = speaker.estimate_duration_fast(text);
+            assert!(
+                fast > counted / 3 && fast < counted * 3,
+                "{:?} {:?}",
+                fast,
+                counted
+            );
+        }
+        let mut fast = Speaker::new();
+        fast.params.rate = Some(ParamValue::Relative(200));
+        assert!(fast.estimate_duration(text) < Speaker::new().estimate_duration(text));
+        assert!(fast.estimate_duration_fast(text) < Speaker::new().estimate_duration_fast(text));
+    }
+
+    #[test]
+    fn fast_estimates_follow_relative_rates() {
+        let text = "one two three four five six seven eight nine ten eleven twelve";
+        let normal = Speaker::new();
+        let normal_ratio = normal.estimate_duration_fast(text).as_secs_f64()
+            / normal.estimate_duration(text).as_secs_f64();
+        // 300% of the normal rate is clamped to the fastest, 450.
+        for (percent, rate) in [(50, 87), (150, 262), (300, 450)] {
+            let mut relative = Speaker::new();
+            relative.params.rate = Some(ParamValue::Relative(percent));
+            let mut absolute = Speaker::new();
+            absolute.params.rate = Some(ParamValue::Absolute(rate));
+            let fast = relative.estimate_duration_fast(text);
+            assert_eq!(fast, absolute.estimate_duration_fast(text));
+
+            let synthesized = relative.estimate_duration(text);
+            let ratio = fast.as_secs_f64() / synthesized.as_secs_f64();
+            assert!(
+                (ratio / normal_ratio - 1.0).abs() < 0.35,
+                "{}%: {:?} {:?}",
+                percent,
+                fast,
+                synthesized
+            );
+        }
+    }
+
+    #[test]
+    fn punctuation_is_restored() {
+        // 60 words, as a speech recognizer might transcribe them.
+        let text = "so yesterday we drove out to the lake in the morning because the forecast \
+                    said it would rain later and when we got there the water was completely \
+                    still and there was nobody else around so we set up the tent near the pier \
+                    then we went for a long swim but the water was much colder than we expected";
+        assert_eq!(text.split_whitespace().count(), 60);
+        let speak = |speaker: &Speaker| {
+            let mut sentences = Vec::new();
+            let samples: Vec<i16> = speaker
+                .speak(text)
+                .with_callback(|event| {
+                    if let Event::Sentence { start, .. } = event {
+                        sentences.push(start);
+                    }
+                })
+                .collect();
+            (samples, sentences)
+        };
+        let mut speaker = Speaker::new();
+        let (unsegmented, _) = speak(&speaker);
+        speaker.params.punctuation_repair = Some(PunctuationRepair::default());
+        let (segmented, sentences) = speak(&speaker);
+        assert!(sentences.len() >= 2, "{:?}", sentences);
+        for start in sentences {
+            // Each sentence starts at a word of the caller's text.
+            assert!(start == 0 || text[..start].ends_with(' '), "{}", start);
+        }
+        assert_ne!(segmented, unsegmented);
+
+        let report = dry_run_transform(text, &speaker);
+        assert_eq!(report.applied_stages, ["punctuation"]);
+        assert!(report.effective_text.starts_with("So yesterday"));
+        assert_eq!(
+            report.punctuation_edits[0],
+            PunctuationEdit::Capitalized { position: 0 }
+        );
+        let periods = report
+            .punctuation_edits
+            .iter()
+            .filter(|edit| matches!(edit, PunctuationEdit::Inserted { mark: '.', .. }))
+            .count();
+        assert!(periods >= 1, "{:?}", report.punctuation_edits);
+    }
 }
@@ -0,0 +1,90 @@
+#[cfg(test)]
+mod tests {
+    use espeak_rs::{debug_snapshot, info, set_output_mode, Event, OutputMode, Speaker};
+    use std::cell::Cell;
+
+    /// What the core checks observe of one utterance.
+    #[derive(Debug)]
+    struct Run {
+        events: Vec<(usize, Event)>,
+        samples: usize,
+    }
+
+    fn speak(speaker: &Speaker, text: &str, ssml: bool) -> Run {
+        let mut events = Vec::new();
+        let samples = Cell::new(0);
+        let source = if ssml {
+            speaker.speak_ssml(text)
+        } else {
+            speaker.speak(text)
+        };
+        let source = source.with_callback(|event| {
+            if !matches!(event, Event::Terminated { .. }) {
+                events.push((samples.get(), event))
+            }
+        });
+        for _ in source {
+            samples.set(samples.get() + 1);
+        }
+        Run {
+            events,
+            samples: samples.get(),
+        }
+    }
+
+    fn suite() -> Vec<Run> {
+        let mut speaker = Speaker::new();
+        let mut runs = vec![
+            speak(&speaker, "Hello world. Goodbye world", false),
+            speak(&speaker, "Hello <mark name='mid'/> world", true),
+            speak(&speaker, "", false),
+        ];
+        speaker.params.rate = Some(300.into());
+        speaker.params.phoneme_events = true;
+        runs.push(speak(&speaker, "Hello world", false));
+        runs
+    }
+
+    #[test]
+    fn modes_are_equivalent() {
+        set_output_mode(OutputMode::Synchronous).unwrap();
+        assert_eq!(info().unwrap().output_mode, OutputMode::Synchronous);
+        let synchronous = suite();
+
+        set_output_mode(OutputMode::Retrieval).unwrap();
+        let info = info().unwrap();
+        assert_eq!(info.output_mode, OutputMode::Retrieval);
+        assert_eq!(debug_snapshot().output_mode, "retrieval");
+        let retrieval = suite();
+
+        for (sync_run, retrieval_run) in synchronous.iter().zip(&retrieval) {
+            let tolerance = info.sample_rate as usize / 100;
+            let sync_events: Vec<&Event> = sync_run.events.iter().map(|(_, e)| e).collect();
+            let events: Vec<&Event> = retrieval_run.events.iter().map(|(_, e)| e).collect();
+            assert_eq!(events, sync_events);
+            assert!(sync_run.samples.abs_diff(retrieval_run.samples) <= tolerance);
+            for ((at, _), (sync_at, _)) in retrieval_run.events.iter().zip(&sync_run.events) {
+                assert!(at.abs_diff(*sync_at) <= tolerance, "{} vs {}", at, sync_at);
+            }
+        }
+        assert_eq!(
+            synchronous[0].events.last().map(|(_, e)| e),
+            Some(&Event::End)
+        );
+
+        // Dropping a source stops espeak's thread too, and the next
+        // utterance is unaffected.
+        let mut source = Speaker::new().speak(&"Hello world. ".repeat(50));
+        source.by_ref().take(1000).for_each(drop);
+        drop(source);
+        let again = speak(&Speaker::new(), "Hello world. Goodbye world", false);
+        assert_eq!(again.events.len(), retrieval[0].events.len());
+        assert_eq!(again.events.last().map(|(_, e)| e), Some(&Event::End));
+
+        set_output_mode(OutputMode::Synchronous).unwrap();
+        assert_eq!(
+            espeak_rs::info().unwrap().output_mode,
+            OutputMode::Synchronous
+        );
+    }
+}
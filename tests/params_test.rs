@@ -0,0 +1,120 @@
+#[cfg(test)]
+mod tests {
+    use espeak_rs::{rodio::Source, ParamValue, Speaker, SpeakerParams};
+    use espeak_rs_sys::espeak_PARAMETER_espeakEMPHASIS as EMPHASIS;
+    use std::sync::{Mutex, MutexGuard};
+    use std::time::Duration;
+
+    /// The tests read back espeak's global params, so they can't run
+    /// alongside each other.
+    fn serial() -> MutexGuard<'static, ()> {
+        static SERIAL: Mutex<()> = Mutex::new(());
+        SERIAL
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    const fn absolute(value: i32) -> Option<ParamValue> {
+        Some(ParamValue::Absolute(value))
+    }
+
+    #[test]
+    fn current_params_are_read_back() {
+        let _serial = serial();
+        let defaults = SpeakerParams::espeak_defaults();
+        assert_eq!(
+            (defaults.rate, defaults.pitch),
+            (absolute(175), absolute(50))
+        );
+        let current = SpeakerParams::current();
+        assert_eq!((current.rate, current.pitch), (absolute(175), absolute(50)));
+        assert!(current.volume.is_some() && current.word_gap.is_some());
+
+        let mut speaker = Speaker::new();
+        speaker.params.rate = Some(300.into());
+        assert!(speaker.speak("Hello").count() > 0);
+        assert_eq!(SpeakerParams::current().rate, absolute(300));
+        assert_eq!(SpeakerParams::current().pitch, absolute(50));
+        assert_eq!(SpeakerParams::espeak_defaults().rate, absolute(175));
+    }
+
+    #[test]
+    fn relative_params_scale_the_defaults() {
+        let _serial = serial();
+        let text = "Hello world, this is a test of relative rates.";
+        let count = |rate| {
+            let mut speaker = Speaker::new();
+            speaker.params.rate = rate;
+            speaker.speak(text).count() as f64
+        };
+        let default = count(None);
+        let same = count(Some(ParamValue::Relative(100)));
+        let double = count(Some(ParamValue::Relative(200)));
+        assert!(
+            (same / default - 1.0).abs() < 0.05,
+            "{} vs {}",
+            same,
+            default
+        );
+        let ratio = double / default;
+        assert!((0.4..0.65).contains(&ratio), "{} vs {}", double, default);
+
+        let mut speaker = Speaker::new();
+        speaker.params.rate = Some(ParamValue::Relative(100));
+        assert!(speaker.speak("Hello").count() > 0);
+        assert_eq!(SpeakerParams::current().rate, absolute(175));
+        speaker.params.rate = Some(ParamValue::Relative(120));
+        assert!(speaker.speak("Hello").count() > 0);
+        assert_eq!(SpeakerParams::current().rate, absolute(210));
+        // Clamped to the fastest rate.
+        speaker.params.rate = Some(ParamValue::Relative(300));
+        assert!(speaker.speak("Hello").count() > 0);
+        assert_eq!(SpeakerParams::current().rate, absolute(450));
+
+        speaker.params.rate = Some(ParamValue::Relative(-5));
+        assert!(speaker.try_speak("Hello").is_err());
+    }
+
+    #[test]
+    fn raw_params_are_applied() {
+        let _serial = serial();
+        let default = SpeakerParams::espeak_defaults();
+        let mut speaker = Speaker::new();
+        speaker.params.set_raw(EMPHASIS, 1, false);
+        speaker.params.set_raw(EMPHASIS, 2, false);
+        assert_eq!(speaker.params.raw_params, [(EMPHASIS, 2, false)]);
+        let source = speaker.speak("Hello world");
+        assert!(source.count() > 0);
+        assert_eq!(SpeakerParams::current_raw(EMPHASIS), 2);
+        assert_eq!(SpeakerParams::current().rate, default.rate);
+
+        // Other speakers aren't affected.
+        assert!(Speaker::new().speak("Hello").count() > 0);
+        assert_eq!(SpeakerParams::current_raw(EMPHASIS), 0);
+    }
+
+    #[test]
+    fn word_gaps_are_in_tens_of_ms() {
+        let _serial = serial();
+        let mut params = SpeakerParams::new();
+        params.set_word_gap(Duration::from_millis(504));
+        assert_eq!(params.word_gap, Some(50));
+        assert_eq!(params.word_gap_duration(), Some(Duration::from_millis(500)));
+        params.set_word_gap(Duration::from_secs(3600));
+        assert_eq!(params.word_gap, Some(*SpeakerParams::WORD_GAP_RANGE.end()));
+
+        let samples = |gap| {
+            let mut speaker = Speaker::new();
+            if let Some(gap) = gap {
+                speaker.params.set_word_gap(gap);
+            }
+            let source = speaker.speak("Hello world");
+            let sample_rate = source.sample_rate() as f64;
+            (source.count() as f64, sample_rate)
+        };
+        let (default, sample_rate) = samples(None);
+        let (gapped, _) = samples(Some(Duration::from_millis(500)));
+        let added = (gapped - default) / sample_rate;
+        assert!((0.35..0.65).contains(&added), "added {}s", added);
+    }
+}
@@ -0,0 +1,80 @@
+#![cfg(feature = "playback")]
+
+#[cfg(test)]
+mod tests {
+    use espeak_rs::playback::{
+        open_sink, speak_and_wait, speak_and_wait_or_discard, PlaybackError,
+    };
+    use espeak_rs::{EspeakError, Event, Speaker};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn missing_device_is_reported() {
+        if open_sink().is_ok() {
+            // There's an audio device; nothing to check without playing.
+            return;
+        }
+        let source = Speaker::new().speak("Hello");
+        match speak_and_wait(source) {
+            Err(PlaybackError::NoOutputDevice(_)) => (),
+            other => panic!("expected NoOutputDevice, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn discard_fallback_fires_callbacks() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let received = events.clone();
+        let source = Speaker::new()
+            .speak("Hello world. Goodbye world")
+            .with_callback(move |event| {
+                if !matches!(event, Event::Terminated { .. }) {
+                    received.lock().unwrap().push(event);
+                }
+            });
+        speak_and_wait_or_discard(source).unwrap();
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                Event::Start,
+                Event::Sentence { start: 0, len: 12 },
+                Event::Word(0, 5),
+                Event::Word(6, 5),
+                Event::Sentence { start: 13, len: 13 },
+                Event::Word(13, 7),
+                Event::Word(21, 5),
+                Event::End,
+            ]
+        );
+    }
+
+    #[test]
+    fn play_reports_a_missing_device() {
+        if open_sink().is_ok() {
+            return;
+        }
+        match Speaker::new().play("Hello") {
+            Err(EspeakError::NoOutputDevice { .. }) => (),
+            other => panic!("expected NoOutputDevice, got {:?}", other),
+        }
+    }
+
+    #[test]
+    #[ignore = "needs an audio device"]
+    fn play_blocks_until_spoken() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let received = events.clone();
+        Speaker::new()
+            .play_with_callback("Hello world", move |event| {
+                received.lock().unwrap().push(event)
+            })
+            .unwrap();
+        assert_eq!(events.lock().unwrap().last(), Some(&Event::End));
+        Speaker::new().play("Goodbye").unwrap();
+        assert!(matches!(
+            Speaker::new().play("Nul\0"),
+            Err(EspeakError::InvalidText { .. })
+        ));
+    }
+}
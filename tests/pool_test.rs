@@ -0,0 +1,132 @@
+#![cfg(feature = "subprocess-pool")]
+
+#[cfg(test)]
+mod tests {
+    use espeak_rs::pool::PooledSynthesizer;
+    use espeak_rs::{EspeakError, Speaker};
+    use std::io;
+    use std::process::Command;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    const WORKER: &str = env!("CARGO_BIN_EXE_espeak-rs-worker");
+
+    fn run_jobs(pool: &PooledSynthesizer, n_jobs: usize) -> Duration {
+        let speaker = Speaker::new();
+        let start = Instant::now();
+        thread::scope(|scope| {
+            for i in 0..n_jobs {
+                let speaker = &speaker;
+                scope.spawn(move || {
+                    let text = format!("This is job number {}, spoken in parallel.", i);
+                    let synthesis = pool.synthesize(speaker, &text).unwrap();
+                    assert!(!synthesis.samples.is_empty());
+                });
+            }
+        });
+        start.elapsed()
+    }
+
+    #[test]
+    fn more_workers_are_faster() {
+        let single = PooledSynthesizer::with_worker_path(WORKER, 1).unwrap();
+        let quad = PooledSynthesizer::with_worker_path(WORKER, 4).unwrap();
+        let single_time = run_jobs(&single, 20);
+        let quad_time = run_jobs(&quad, 20);
+        assert!(
+            quad_time < single_time,
+            "{:?} vs {:?}",
+            quad_time,
+            single_time
+        );
+    }
+
+    #[test]
+    fn killed_worker_is_respawned() {
+        let pool = PooledSynthesizer::with_worker_path(WORKER, 1).unwrap();
+        let speaker = Speaker::new();
+        let expected = pool.synthesize(&speaker, "Hello, world").unwrap();
+
+        for pid in pool.worker_ids() {
+            Command::new("kill")
+                .arg("-9")
+                .arg(pid.to_string())
+                .status()
+                .unwrap();
+        }
+        thread::sleep(Duration::from_millis(100));
+
+        let synthesis = pool.synthesize(&speaker, "Hello, world").unwrap();
+        assert_eq!(synthesis, expected);
+    }
+
+    #[test]
+    fn failed_respawn_leaves_slot_empty() {
+        let dir = std::env::temp_dir().join(format!("espeak-rs-pool-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let worker = dir.join("espeak-rs-worker");
+        std::fs::copy(WORKER, &worker).unwrap();
+
+        let pool = PooledSynthesizer::with_worker_path(&worker, 1).unwrap();
+        let speaker = Speaker::new();
+        let expected = pool.synthesize(&speaker, "Hello, world").unwrap();
+
+        std::fs::remove_file(&worker).unwrap();
+        for pid in pool.worker_ids() {
+            Command::new("kill")
+                .arg("-9")
+                .arg(pid.to_string())
+                .status()
+                .unwrap();
+        }
+        thread::sleep(Duration::from_millis(100));
+
+        assert!(pool.synthesize(&speaker, "Hello, world").is_err());
+        // The dead worker was not put back.
+        assert!(pool.worker_ids().is_empty());
+
+        // Once the binary is back the slot is refilled on the next job.
+        std::fs::copy(WORKER, &worker).unwrap();
+        let synthesis = pool.synthesize(&speaker, "Hello, world").unwrap();
+        assert_eq!(synthesis, expected);
+        assert_eq!(pool.worker_ids().len(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn workers_report_errors() {
+        let pool = PooledSynthesizer::with_worker_path(WORKER, 1).unwrap();
+        let workers = pool.worker_ids();
+        let mut speaker = Speaker::new();
+        speaker.params.rate = Some(500.into());
+        let error = pool.synthesize(&speaker, "Hello, world").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Other);
+        let error = error
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<EspeakError>());
+        assert!(
+            matches!(
+                error,
+                Some(EspeakError::ParamOutOfRange { name: "rate", .. })
+            ),
+            "{:?}",
+            error
+        );
+        // The worker is still serving jobs.
+        assert_eq!(pool.worker_ids(), workers);
+        speaker.params.rate = None;
+        assert!(pool.synthesize(&speaker, "Hello, world").is_ok());
+    }
+
+    #[test]
+    fn workers_use_all_params() {
+        let pool = PooledSynthesizer::with_worker_path(WORKER, 1).unwrap();
+        let mut speaker = Speaker::new();
+        speaker.params.synth_text_budget = 16;
+        speaker.params.say_digits = true;
+        let text = "Call 555 0100. Ask for the front desk, then wait.";
+        let synthesis = pool.synthesize(&speaker, text).unwrap();
+        let in_process: Vec<i16> = speaker.speak(text).collect();
+        assert_eq!(synthesis.samples, in_process);
+    }
+}
@@ -0,0 +1,168 @@
+#[cfg(test)]
+mod tests {
+    use espeak_rs::schedule::{plan, run, run_with_clock};
+    use espeak_rs::{Event, Speaker, TimedEvent};
+    use std::sync::mpsc;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn events() -> Vec<(Duration, Event)> {
+        vec![
+            (ms(0), Event::Start),
+            (ms(100), Event::Word(0, 5)),
+            (ms(200), Event::Word(6, 5)),
+            (ms(300), Event::End),
+        ]
+    }
+
+    /// A clock the test moves by hand, giving the timer thread a moment to
+    /// notice each move.
+    struct MockClock(Arc<Mutex<Duration>>);
+
+    impl MockClock {
+        fn set(&self, position: Duration) {
+            *self.0.lock().unwrap() = position;
+            thread::sleep(ms(30));
+        }
+    }
+
+    fn mock_schedule() -> (
+        MockClock,
+        espeak_rs::schedule::Schedule,
+        mpsc::Receiver<(Duration, Event)>,
+    ) {
+        let position = Arc::new(Mutex::new(Duration::ZERO));
+        let clock = position.clone();
+        let fired_at = position.clone();
+        let (tx, rx) = mpsc::channel();
+        let schedule = run_with_clock(
+            events(),
+            move || *clock.lock().unwrap(),
+            move |event| tx.send((*fired_at.lock().unwrap(), event)).unwrap(),
+        );
+        (MockClock(position), schedule, rx)
+    }
+
+    #[test]
+    fn fires_at_simulated_times() {
+        let (clock, schedule, rx) = mock_schedule();
+        for step in 0..=8 {
+            clock.set(ms(step * 50));
+        }
+        schedule.join();
+
+        let fired: Vec<_> = rx.iter().collect();
+        assert_eq!(
+            fired
+                .iter()
+                .map(|(_, event)| event.clone())
+                .collect::<Vec<_>>(),
+            events()
+                .into_iter()
+                .map(|(_, event)| event)
+                .collect::<Vec<_>>()
+        );
+        for ((at, _), (due, _)) in fired.iter().zip(events()) {
+            assert!(*at >= due && *at - due < ms(50), "{:?} for {:?}", at, due);
+        }
+    }
+
+    #[test]
+    fn backward_jump_does_not_refire() {
+        let (clock, schedule, rx) = mock_schedule();
+        clock.set(ms(150));
+        clock.set(ms(50));
+        clock.set(ms(120));
+        assert_eq!(rx.try_iter().count(), 2);
+        clock.set(ms(400));
+        schedule.join();
+        assert_eq!(rx.try_iter().count(), 2);
+    }
+
+    #[test]
+    fn cancel_stops_events() {
+        let (clock, schedule, rx) = mock_schedule();
+        clock.set(ms(100));
+        schedule.cancel();
+        clock.set(ms(400));
+        schedule.join();
+        assert_eq!(rx.try_iter().count(), 2);
+    }
+
+    #[test]
+    fn runs_on_the_system_clock() {
+        let start = Instant::now();
+        let timed = events()
+            .into_iter()
+            .map(|(offset, event)| TimedEvent { offset, event });
+        let (tx, rx) = mpsc::channel();
+        run(plan(timed, start), start, move |event| {
+            tx.send((start.elapsed(), event)).unwrap()
+        })
+        .join();
+        let fired: Vec<_> = rx.iter().collect();
+        assert_eq!(fired.len(), 4);
+        for ((at, _), (due, _)) in fired.iter().zip(events()) {
+            assert!(*at >= due, "{:?} < {:?}", at, due);
+        }
+    }
+
+    #[test]
+    fn paced_events_wait_out_pauses() {
+        let text = "Hello world, this is paced speech.";
+        let mut expected = Vec::new();
+        Speaker::new()
+            .speak(text)
+            .with_timed_callback(|timed| expected.push(timed))
+            .for_each(drop);
+        let end = expected.last().unwrap().offset;
+        let pause_at = expected[expected.len() / 2].offset;
+
+        let position = Arc::new(Mutex::new(Duration::ZERO));
+        let clock = MockClock(position.clone());
+        let fired_at = position.clone();
+        let (tx, rx) = mpsc::channel();
+        let (mut source, schedule) = Speaker::new().speak(text).with_paced_callback(
+            move || *position.lock().unwrap(),
+            move |event| tx.send((*fired_at.lock().unwrap(), event)).unwrap(),
+        );
+        // Like a player buffering ahead, pull everything before playing.
+        source.by_ref().for_each(drop);
+        drop(source);
+        clock.set(Duration::ZERO);
+        let mut fired: Vec<_> = rx.try_iter().collect();
+        assert!(fired.len() < expected.len());
+
+        let mut at = Duration::ZERO;
+        while at < pause_at {
+            at = (at + ms(20)).min(pause_at);
+            clock.set(at);
+        }
+        fired.extend(rx.try_iter());
+        let paused = fired.len();
+        assert!(paused < expected.len());
+        for _ in 0..5 {
+            clock.set(pause_at);
+            assert_eq!(rx.try_iter().count(), 0, "an event fired while paused");
+        }
+
+        while at < end {
+            at += ms(20);
+            clock.set(at);
+        }
+        schedule.join();
+        fired.extend(rx.try_iter());
+        let events: Vec<_> = fired.iter().map(|(_, event)| event.clone()).collect();
+        let expected_events: Vec<_> = expected.iter().map(|t| t.event.clone()).collect();
+        assert_eq!(events, expected_events);
+        for ((at, _), timed) in fired.iter().zip(&expected) {
+            assert!(*at >= timed.offset, "{:?} fired at {:?}", timed, at);
+        }
+        assert!(fired[paused..].iter().all(|(at, _)| *at > pause_at));
+    }
+}
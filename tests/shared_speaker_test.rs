@@ -0,0 +1,75 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2022 Eitan Isaacson <eitan@monotonous.org>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+#[cfg(test)]
+mod tests {
+    use espeak_rs::{Rate, Speaker};
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    const THREADS: usize = 8;
+    const RATES: [u16; 4] = [120, 175, 250, 350];
+
+    fn text(i: usize) -> String {
+        format!("Thread number {} is speaking, at its own pace.", i + 1)
+    }
+
+    #[test]
+    fn shared_speakers_match_single_threaded_output() {
+        let speakers: Arc<Vec<Speaker>> = Arc::new(
+            RATES
+                .iter()
+                .map(|wpm| {
+                    Speaker::builder()
+                        .rate(Rate::wpm(*wpm).unwrap())
+                        .build()
+                        .unwrap()
+                })
+                .collect(),
+        );
+        let baseline: Vec<usize> = (0..THREADS)
+            .map(|i| speakers[i % RATES.len()].speak(&text(i)).count())
+            .collect();
+        // The rates took effect.
+        assert!(baseline[0] > baseline[3] * 2, "{:?}", baseline);
+
+        let barrier = Arc::new(Barrier::new(THREADS));
+        let threads: Vec<_> = (0..THREADS)
+            .map(|i| {
+                let speakers = speakers.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    // Each speaker is shared by two threads.
+                    let speaker = &speakers[i % RATES.len()];
+                    barrier.wait();
+                    (0..3)
+                        .map(|_| speaker.speak(&text(i)).count())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        for (i, thread) in threads.into_iter().enumerate() {
+            for count in thread.join().unwrap() {
+                assert_eq!(count, baseline[i], "thread {}", i);
+            }
+        }
+    }
+}
@@ -0,0 +1,27 @@
+#![cfg(feature = "serde")]
+
+#[cfg(test)]
+mod tests {
+    use espeak_rs::{debug_snapshot, StateSnapshot};
+
+    #[test]
+    fn snapshot_round_trips() {
+        let snapshot = debug_snapshot();
+        assert!(!snapshot.espeak_version.is_empty());
+        for name in [
+            "rate",
+            "volume",
+            "pitch",
+            "range",
+            "punctuation",
+            "capitals",
+            "word_gap",
+        ] {
+            assert!(snapshot.parameters.iter().any(|p| p.name == name));
+        }
+
+        let json = serde_json::to_string_pretty(&snapshot).unwrap();
+        let parsed: StateSnapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, snapshot);
+    }
+}
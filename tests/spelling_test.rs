@@ -0,0 +1,86 @@
+#[cfg(test)]
+mod tests {
+    use espeak_rs::spelling::{language_alphabet, set_language_alphabet, SpellingAlphabet};
+    use espeak_rs::{list_voices, Capitals, Event, Speaker};
+
+    #[test]
+    fn codes_are_spelled() {
+        let speaker = Speaker::new();
+        let source = speaker.speak_phonetic_alphabet("AB1");
+        assert_eq!(source.effective_text(), "Alpha, Bravo, 1");
+        let mut words = Vec::new();
+        let spelled = source
+            .with_callback(|event| {
+                if let Event::Word(..) = event {
+                    words.push(event)
+                }
+            })
+            .count();
+        assert_eq!(
+            words,
+            [Event::Word(0, 1), Event::Word(1, 1), Event::Word(2, 1)]
+        );
+        let plain = speaker.speak("AB1").count();
+        assert!(spelled > plain * 2, "{} vs {}", spelled, plain);
+    }
+
+    #[test]
+    fn alphabets_are_per_language() {
+        let german = list_voices()
+            .into_iter()
+            .find(|voice| voice.identifier == "gmw/de")
+            .unwrap();
+        let mut speaker = Speaker::new();
+        speaker.set_voice(&german);
+        assert_eq!(
+            speaker.speak_phonetic_alphabet("ab").effective_text(),
+            "Alpha, Bravo"
+        );
+
+        set_language_alphabet("de", SpellingAlphabet::default().with('a', "Anton"));
+        assert_eq!(language_alphabet("de-AT").word('A'), Some("Anton"));
+        assert_eq!(language_alphabet("en").word('A'), Some("Alpha"));
+        assert_eq!(
+            speaker.speak_phonetic_alphabet("ab").effective_text(),
+            "Anton, Bravo"
+        );
+    }
+
+    #[test]
+    fn characters_are_named_one_by_one() {
+        let words = |speaker: &Speaker, text: &str| {
+            let mut words = Vec::new();
+            let samples = speaker
+                .spell(text)
+                .with_callback(|event| {
+                    if let Event::Word(..) = event {
+                        words.push(event)
+                    }
+                })
+                .count();
+            (words, samples)
+        };
+
+        let mut speaker = Speaker::new();
+        let (rust, plain_samples) = words(&speaker, "Rust");
+        assert_eq!(
+            rust,
+            [
+                Event::Word(0, 1),
+                Event::Word(1, 1),
+                Event::Word(2, 1),
+                Event::Word(3, 1)
+            ]
+        );
+
+        // Accents and skin tones stay with their character.
+        let text = "ne\u{301} 😀👍🏽";
+        let (spelled, _) = words(&speaker, text);
+        let spelled: Vec<_> = spelled.iter().map(|e| e.word_in(text).unwrap()).collect();
+        assert_eq!(spelled, ["n", "e\u{301}", "😀", "👍🏽"]);
+
+        speaker.params.capitals = Some(Capitals::Spelling);
+        let (_, announced_samples) = words(&speaker, "Rust");
+        assert!(announced_samples > plain_samples);
+    }
+}
@@ -0,0 +1,42 @@
+#![cfg(feature = "test-tone")]
+
+#[cfg(test)]
+mod tests {
+    use espeak_rs::{Event, Speaker};
+    use std::cell::Cell;
+
+    fn run(speaker: &Speaker, text: &str) -> (Vec<i16>, Vec<(usize, Event)>) {
+        let mut samples = Vec::<i16>::new();
+        let mut events = Vec::<(usize, Event)>::new();
+        let current_sample: Cell<usize> = Cell::new(0);
+        let source = speaker
+            .speak(text)
+            .with_callback(|evt| events.push((current_sample.get(), evt)));
+        for sample in source {
+            samples.push(sample);
+            current_sample.set(current_sample.get() + 1);
+        }
+        (samples, events)
+    }
+
+    #[test]
+    fn tone_mode_is_deterministic() {
+        let text = "Hello world. Goodbye world";
+        let mut speaker = Speaker::new();
+        let (_, speech_events) = run(&speaker, text);
+
+        speaker.params.test_tone_mode = true;
+        let (first, tone_events) = run(&speaker, text);
+        let (second, _) = run(&speaker, text);
+        assert!(!first.is_empty());
+        assert_eq!(first, second);
+
+        assert_eq!(tone_events.len(), speech_events.len());
+        for ((tone_at, tone_evt), (speech_at, speech_evt)) in
+            tone_events.iter().zip(speech_events.iter())
+        {
+            assert_eq!(tone_evt, speech_evt);
+            assert!(tone_at.abs_diff(*speech_at) <= 25);
+        }
+    }
+}
@@ -0,0 +1,50 @@
+#[cfg(test)]
+mod tests {
+    use espeak_rs::{
+        add_user_voice_dir, list_variants, list_voices, reload_voices, user_voice_warnings,
+        SpeakerParams, SpeakerSource, TextMode,
+    };
+    use std::fs;
+
+    // Kept in its own test binary since it changes the data espeak reads
+    // for the rest of the process.
+    #[test]
+    fn user_variants_are_merged() {
+        let voices_before = list_voices().len();
+        let user_dir =
+            std::env::temp_dir().join(format!("espeak-rs-voices-{}", std::process::id()));
+        fs::create_dir_all(user_dir.join("!v")).unwrap();
+        fs::write(
+            user_dir.join("!v").join("rusttest"),
+            "name RustTest\nlanguage variant\npitch 80 120\n",
+        )
+        .unwrap();
+
+        add_user_voice_dir(&user_dir).unwrap();
+        assert!(list_variants().iter().any(|v| v.name == "RustTest"));
+        assert_eq!(list_voices().len(), voices_before);
+        assert!(user_voice_warnings().is_empty());
+
+        let mut source = SpeakerSource::try_with_text_mode(
+            "Hello",
+            "en+rusttest",
+            SpeakerParams::new(),
+            TextMode::Plain,
+        )
+        .unwrap();
+        assert!(source.by_ref().count() > 0);
+        assert_eq!(source.error(), None);
+
+        // Shadowing an installed variant is reported.
+        fs::write(
+            user_dir.join("!v").join("klatt"),
+            "name Klatt Override\nlanguage variant\nklatt 1\n",
+        )
+        .unwrap();
+        reload_voices().unwrap();
+        assert!(list_variants().iter().any(|v| v.name == "Klatt Override"));
+        assert_eq!(user_voice_warnings().len(), 1);
+
+        fs::remove_dir_all(&user_dir).unwrap();
+    }
+}
@@ -0,0 +1,66 @@
+#[cfg(test)]
+mod tests {
+    use espeak_rs::viseme::{set_language_map, Viseme, VisemeMap};
+    use espeak_rs::{list_voices, Event, Speaker};
+    use std::time::Duration;
+
+    fn visemes(speaker: &Speaker, text: &str) -> Vec<(Duration, Viseme)> {
+        let mut visemes = Vec::new();
+        speaker
+            .speak(text)
+            .with_viseme_callback(|offset, viseme| visemes.push((offset, viseme)))
+            .for_each(drop);
+        visemes
+    }
+
+    #[test]
+    fn bilabials_are_found() {
+        let mut speaker = Speaker::new();
+        speaker.params.phoneme_events = true;
+        let words: Vec<Duration> = speaker
+            .speak("papa mama")
+            .into_events()
+            .filter(|timed| matches!(timed.event, Event::Word(..)))
+            .map(|timed| timed.offset)
+            .collect();
+        assert_eq!(words.len(), 2);
+
+        let shapes = visemes(&speaker, "papa mama");
+        let bilabials: Vec<Duration> = shapes
+            .iter()
+            .filter(|(_, viseme)| *viseme == Viseme::Pp)
+            .map(|(offset, _)| *offset)
+            .collect();
+        assert_eq!(bilabials.len(), 4, "{:?}", shapes);
+        assert!(bilabials[0] >= words[0] && bilabials[0] < words[0] + Duration::from_millis(100));
+        assert!(bilabials[1] < words[1]);
+        assert!(bilabials[2] >= words[1] && bilabials[2] < words[1] + Duration::from_millis(100));
+        assert!(shapes.windows(2).all(|w| w[0].0 <= w[1].0));
+        assert_eq!(shapes.last().map(|(_, viseme)| *viseme), Some(Viseme::Sil));
+
+        speaker.params.phoneme_events = false;
+        assert_eq!(
+            visemes(&speaker, "papa mama")
+                .into_iter()
+                .map(|(_, viseme)| viseme)
+                .collect::<Vec<_>>(),
+            [Viseme::Sil]
+        );
+    }
+
+    #[test]
+    fn maps_are_per_language() {
+        let german = list_voices()
+            .into_iter()
+            .find(|voice| voice.identifier == "gmw/de")
+            .unwrap();
+        let mut speaker = Speaker::new();
+        speaker.set_voice(&german);
+        speaker.params.phoneme_events = true;
+
+        set_language_map("de", VisemeMap::default().with("m", Viseme::Ff));
+        let shapes = visemes(&speaker, "Mama");
+        assert!(shapes.iter().any(|(_, viseme)| *viseme == Viseme::Ff));
+        assert!(!shapes.iter().any(|(_, viseme)| *viseme == Viseme::Pp));
+    }
+}